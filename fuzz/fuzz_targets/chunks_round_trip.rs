@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use png_decode_encode::{chunk::Chunk, png::Png};
+
+// Well-formed chunks in any order: the parser may reject the file, but must
+// not panic, and whatever it accepts must write back out unchanged.
+fuzz_target!(|chunks: Vec<Chunk>| {
+    let bytes = Png::from_chunks(chunks).as_bytes();
+    if let Ok(png) = Png::try_from(bytes.as_slice()) {
+        assert_eq!(png.as_bytes(), bytes);
+    }
+});
@@ -0,0 +1,643 @@
+use std::{fmt::Display, time::Duration};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    error::PngError,
+    ihdr::Ihdr,
+    pixels::{self, ImageBuffer},
+    png::Png
+};
+
+const ACTL_CHUNK_TYPE: ChunkType = ChunkType(u32::from_be_bytes(*b"acTL"));
+const FCTL_CHUNK_TYPE: ChunkType = ChunkType(u32::from_be_bytes(*b"fcTL"));
+const FDAT_CHUNK_TYPE: ChunkType = ChunkType(u32::from_be_bytes(*b"fdAT"));
+
+// acTL: marks the file as an animated PNG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    // 0 loops forever.
+    pub num_plays: u32
+}
+
+// What happens to the frame region before the next frame is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisposeOp {
+    None = 0,
+    // Cleared to fully transparent black.
+    Background = 1,
+    // Reverted to what was there before this frame.
+    Previous = 2
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendOp {
+    // The frame replaces the region, alpha included.
+    Source = 0,
+    // The frame is composited over the region.
+    Over = 1
+}
+
+// fcTL: geometry and timing of one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameControl {
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    // 0 means 1/100 of a second.
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp
+}
+
+// fdAT: image data of a frame after the first, like IDAT with a sequence number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameData {
+    pub sequence_number: u32,
+    pub data: Vec<u8>
+}
+
+// A frame with its zlib stream, from IDAT or from its fdAT chunks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub control: FrameControl,
+    pub data: Vec<u8>
+}
+
+impl FrameControl {
+    pub fn delay(&self) -> Duration {
+        let den = if self.delay_den == 0 { 100 } else { self.delay_den };
+        Duration::from_secs_f64(self.delay_num as f64 / den as f64)
+    }
+}
+
+// The delay fraction closest to 1/fps seconds that fits in fcTL's two u16s,
+// with the smallest denominator among equally close ones.
+pub fn frame_delay(fps: f64) -> Result<(u16, u16), PngError> {
+    if !fps.is_finite() || fps <= 0.0 {
+        return Err(PngError::InvalidArgument("frame rate must be a positive number"));
+    }
+    let mut best: Option<(u16, u16, f64)> = None;
+    for den in 1..=u16::MAX {
+        let num = (den as f64 / fps).round();
+        if num < 1.0 || num > u16::MAX as f64 {
+            continue;
+        }
+        let error = (num / den as f64 - 1.0 / fps).abs();
+        if best.is_none_or(|(_, _, best_error)| error < best_error) {
+            best = Some((num as u16, den, error));
+        }
+        if error == 0.0 {
+            break;
+        }
+    }
+    best.map(|(num, den, _)| (num, den)).ok_or(PngError::InvalidArgument("frame rate is out of range"))
+}
+
+impl TryFrom<u8> for DisposeOp {
+    type Error = PngError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(DisposeOp::None),
+            1 => Ok(DisposeOp::Background),
+            2 => Ok(DisposeOp::Previous),
+            _ => Err(invalid("fcTL", "unknown dispose op"))
+        }
+    }
+}
+
+impl TryFrom<u8> for BlendOp {
+    type Error = PngError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(BlendOp::Source),
+            1 => Ok(BlendOp::Over),
+            _ => Err(invalid("fcTL", "unknown blend op"))
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for AnimationControl {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_chunk(chunk, ACTL_CHUNK_TYPE, 8)?;
+        let num_frames = u32_at(data, 0);
+        if num_frames == 0 {
+            return Err(invalid("acTL", "an animation needs at least one frame"));
+        }
+        Ok(AnimationControl { num_frames, num_plays: u32_at(data, 4) })
+    }
+}
+
+impl From<AnimationControl> for Chunk {
+    fn from(value: AnimationControl) -> Self {
+        let mut data = value.num_frames.to_be_bytes().to_vec();
+        data.extend(value.num_plays.to_be_bytes());
+        Chunk::new(ACTL_CHUNK_TYPE, data)
+    }
+}
+
+impl TryFrom<&Chunk> for FrameControl {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = expect_chunk(chunk, FCTL_CHUNK_TYPE, 26)?;
+        let frame = FrameControl {
+            sequence_number: u32_at(data, 0),
+            width: u32_at(data, 4),
+            height: u32_at(data, 8),
+            x_offset: u32_at(data, 12),
+            y_offset: u32_at(data, 16),
+            delay_num: u16::from_be_bytes([data[20], data[21]]),
+            delay_den: u16::from_be_bytes([data[22], data[23]]),
+            dispose_op: DisposeOp::try_from(data[24])?,
+            blend_op: BlendOp::try_from(data[25])?
+        };
+        if frame.width == 0 || frame.height == 0 {
+            return Err(invalid("fcTL", "frame must not be empty"));
+        }
+        Ok(frame)
+    }
+}
+
+impl From<FrameControl> for Chunk {
+    fn from(value: FrameControl) -> Self {
+        let mut data = Vec::with_capacity(26);
+        for field in [value.sequence_number, value.width, value.height, value.x_offset, value.y_offset] {
+            data.extend(field.to_be_bytes());
+        }
+        data.extend(value.delay_num.to_be_bytes());
+        data.extend(value.delay_den.to_be_bytes());
+        data.extend([value.dispose_op as u8, value.blend_op as u8]);
+        Chunk::new(FCTL_CHUNK_TYPE, data)
+    }
+}
+
+impl TryFrom<&Chunk> for FrameData {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if *chunk.chunk_type() != FDAT_CHUNK_TYPE {
+            return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+        }
+        let data = chunk.data();
+        if data.len() < 4 {
+            return Err(invalid("fdAT", "missing sequence number"));
+        }
+        Ok(FrameData { sequence_number: u32_at(data, 0), data: data[4..].to_vec() })
+    }
+}
+
+impl From<FrameData> for Chunk {
+    fn from(value: FrameData) -> Self {
+        let mut data = value.sequence_number.to_be_bytes().to_vec();
+        data.extend(value.data);
+        Chunk::new(FDAT_CHUNK_TYPE, data)
+    }
+}
+
+impl Display for AnimationControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.num_plays {
+            0 => write!(f, "{} frames, loops forever", self.num_frames),
+            plays => write!(f, "{} frames, plays {} times", self.num_frames, plays)
+        }
+    }
+}
+
+impl Png {
+    pub fn is_animated(&self) -> bool {
+        self.chunk_by_type("acTL").is_some()
+    }
+
+    pub fn animation_control(&self) -> Result<Option<AnimationControl>, PngError> {
+        self.chunk_as("acTL")
+    }
+
+    pub fn frame_controls(&self) -> Result<Vec<FrameControl>, PngError> {
+        self.chunks_by_type("fcTL").into_iter().map(FrameControl::try_from).collect()
+    }
+
+    // Gives every frame a delay of 1/fps seconds, rewriting the fcTL chunks
+    // where they are. Returns how many frames changed.
+    pub fn set_frame_rate(&mut self, fps: f64) -> Result<usize, PngError> {
+        if !self.is_animated() {
+            return Err(PngError::InvalidArgument("not an animated PNG"));
+        }
+        self.validate_animation()?;
+        let (delay_num, delay_den) = frame_delay(fps)?;
+        let mut changed = 0;
+        for chunk in self.chunks_by_type_mut("fcTL") {
+            let control = FrameControl::try_from(&*chunk)?;
+            let retimed = FrameControl { delay_num, delay_den, ..control };
+            if retimed != control {
+                *chunk = retimed.into();
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+
+    // Rewrites acTL where it is; 0 loops forever.
+    pub fn set_num_plays(&mut self, num_plays: u32) -> Result<AnimationControl, PngError> {
+        let control = AnimationControl { num_plays, ..self.animation_control()?.ok_or(PngError::InvalidArgument("not an animated PNG"))? };
+        if let Some(chunk) = self.chunks_by_type_mut("acTL").into_iter().next() {
+            *chunk = control.into();
+        }
+        Ok(control)
+    }
+
+    // Groups the image data by frame. When the first fcTL comes before IDAT
+    // the default image is the first frame; otherwise it is not part of the
+    // animation and is skipped.
+    pub fn frames(&self) -> Result<Vec<Frame>, PngError> {
+        self.validate_animation()?;
+        let mut frames: Vec<Frame> = Vec::new();
+        for chunk in self.chunks() {
+            match &chunk.chunk_type().bytes() {
+                b"fcTL" => frames.push(Frame { control: FrameControl::try_from(chunk)?, data: Vec::new() }),
+                b"IDAT" => if let Some(frame) = frames.last_mut() {
+                    frame.data.extend(chunk.data());
+                },
+                b"fdAT" => if let Some(frame) = frames.last_mut() {
+                    frame.data.extend(&chunk.data()[4..]);
+                },
+                _ => {}
+            }
+        }
+        Ok(frames)
+    }
+
+    // Decodes a frame on its own, at its own size, using the image's color
+    // type, palette and transparency.
+    pub fn decode_frame(&self, frame: &Frame) -> Result<ImageBuffer, PngError> {
+        let ihdr = Ihdr { width: frame.control.width, height: frame.control.height, ..self.header()? };
+        let mut chunks: Vec<Chunk> = vec![ihdr.into()];
+        chunks.extend(self.chunks().iter().filter(|chunk| matches!(&chunk.chunk_type().bytes(), b"PLTE" | b"tRNS")).cloned());
+        chunks.push(Chunk::new(ChunkType(u32::from_be_bytes(*b"IDAT")), frame.data.clone()));
+        chunks.push(Chunk::new(ChunkType(u32::from_be_bytes(*b"IEND")), Vec::new()));
+        pixels::decode_rgba(&Png::from_chunks(chunks))
+    }
+
+    // Every frame as it is shown: drawn on the full canvas over what the
+    // previous frames left behind after their dispose ops.
+    pub fn composited_frames(&self) -> Result<Vec<(FrameControl, ImageBuffer)>, PngError> {
+        let mut composited = Vec::new();
+        self.for_each_composited_frame(|_, control, canvas| {
+            composited.push((control, canvas.clone()));
+            Ok(())
+        })?;
+        Ok(composited)
+    }
+
+    // `composited_frames` one frame at a time, so only the canvas is held.
+    // The canvas is allocated once the animation is valid and the default
+    // image has decoded, which proves the IHDR's size is backed by data.
+    pub fn for_each_composited_frame(
+        &self,
+        mut on_frame: impl FnMut(usize, FrameControl, &ImageBuffer) -> Result<(), PngError>
+    ) -> Result<(), PngError> {
+        let frames = self.frames()?;
+        let (width, height) = pixels::decode_rgba(self).map(|image| (image.width, image.height))?;
+        let mut canvas = ImageBuffer::new(width, height, vec![[0; 4]; width as usize * height as usize])?;
+        for (index, frame) in frames.iter().enumerate() {
+            let control = frame.control;
+            let image = self.decode_frame(frame)?;
+            let previous = (control.dispose_op == DisposeOp::Previous && index > 0).then(|| canvas.clone());
+            for (x, y, color) in image.pixels() {
+                let (x, y) = (control.x_offset + x, control.y_offset + y);
+                let color = match control.blend_op {
+                    BlendOp::Source => color,
+                    BlendOp::Over => blend_over(canvas.pixel(x, y), color)
+                };
+                canvas.set_pixel(x, y, color)?;
+            }
+            on_frame(index, control, &canvas)?;
+            // Previous on the first frame has nothing to revert to, so the
+            // spec treats it as Background.
+            match (control.dispose_op, previous) {
+                (DisposeOp::None, _) => {},
+                (DisposeOp::Previous, Some(previous)) => canvas = previous,
+                (DisposeOp::Background | DisposeOp::Previous, _) => {
+                    for y in control.y_offset..control.y_offset + control.height {
+                        for x in control.x_offset..control.x_offset + control.width {
+                            canvas.set_pixel(x, y, [0; 4])?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Checks the animation chunks against each other and the IHDR: fcTL and
+    // fdAT sequence numbers count up from 0 without gaps, the number of fcTL
+    // chunks matches acTL, frames fit in the canvas, and a first frame that
+    // is the default image covers all of it. Images without acTL must not
+    // have fcTL or fdAT either.
+    pub fn validate_animation(&self) -> Result<(), PngError> {
+        let Some(control) = self.animation_control()? else {
+            if self.chunks().iter().any(|chunk| [FCTL_CHUNK_TYPE, FDAT_CHUNK_TYPE].contains(chunk.chunk_type())) {
+                return Err(invalid("acTL", "fcTL or fdAT found without acTL"));
+            }
+            return Ok(());
+        };
+        let ihdr = self.header()?;
+        let mut expected_sequence = 0;
+        let mut frame_count = 0;
+        let mut seen_idat = false;
+        let mut frame_has_data = true;
+        for chunk in self.chunks() {
+            match &chunk.chunk_type().bytes() {
+                b"acTL" if seen_idat => return Err(invalid("acTL", "must come before the image data")),
+                b"IDAT" => seen_idat = true,
+                b"fcTL" => {
+                    let frame = FrameControl::try_from(chunk)?;
+                    if frame.sequence_number != expected_sequence {
+                        return Err(invalid("fcTL", "sequence number out of order"));
+                    }
+                    if frame.x_offset.checked_add(frame.width).is_none_or(|right| right > ihdr.width)
+                        || frame.y_offset.checked_add(frame.height).is_none_or(|bottom| bottom > ihdr.height) {
+                        return Err(invalid("fcTL", "frame extends past the image"));
+                    }
+                    if !seen_idat && (frame.x_offset, frame.y_offset, frame.width, frame.height) != (0, 0, ihdr.width, ihdr.height) {
+                        return Err(invalid("fcTL", "a first frame used as the default image must cover it"));
+                    }
+                    if !frame_has_data {
+                        return Err(invalid("fcTL", "previous frame has no image data"));
+                    }
+                    frame_has_data = !seen_idat && frame_count == 0;
+                    expected_sequence += 1;
+                    frame_count += 1;
+                },
+                b"fdAT" => {
+                    let data = FrameData::try_from(chunk)?;
+                    if data.sequence_number != expected_sequence {
+                        return Err(invalid("fdAT", "sequence number out of order"));
+                    }
+                    if frame_count == 0 || !seen_idat {
+                        return Err(invalid("fdAT", "must follow the image data and an fcTL"));
+                    }
+                    frame_has_data = true;
+                    expected_sequence += 1;
+                },
+                _ => {}
+            }
+        }
+        if !frame_has_data {
+            return Err(invalid("fcTL", "last frame has no image data"));
+        }
+        if frame_count != control.num_frames {
+            return Err(invalid("acTL", "frame count does not match the fcTL chunks"));
+        }
+        Ok(())
+    }
+}
+
+// `top` composited over `bottom` with straight alpha.
+fn blend_over(bottom: [u8; 4], top: [u8; 4]) -> [u8; 4] {
+    let (top_alpha, bottom_alpha) = (top[3] as u32, bottom[3] as u32);
+    let alpha = top_alpha * 255 + bottom_alpha * (255 - top_alpha);
+    if alpha == 0 {
+        return [0; 4];
+    }
+    let mut color = [0, 0, 0, ((alpha + 127) / 255) as u8];
+    for i in 0..3 {
+        color[i] = ((top[i] as u32 * top_alpha * 255 + bottom[i] as u32 * bottom_alpha * (255 - top_alpha) + alpha / 2) / alpha) as u8;
+    }
+    color
+}
+
+fn expect_chunk(chunk: &Chunk, chunk_type: ChunkType, len: usize) -> Result<&[u8], PngError> {
+    if *chunk.chunk_type() != chunk_type {
+        return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+    }
+    match chunk.data().len() == len {
+        true => Ok(chunk.data()),
+        false => Err(PngError::InvalidChunkData { chunk_type: chunk_type.to_string(), reason: "wrong length" })
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn invalid(chunk_type: &str, reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: chunk_type.to_string(), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::{ColorType, Ihdr};
+    use std::str::FromStr;
+
+    fn frame_control(sequence_number: u32, width: u32, height: u32) -> FrameControl {
+        FrameControl {
+            sequence_number,
+            width,
+            height,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: DisposeOp::Background,
+            blend_op: BlendOp::Over
+        }
+    }
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    // Two frames: the default image, then a 2x2 fdAT frame.
+    fn testing_chunks() -> Vec<Chunk> {
+        vec![
+            Ihdr::new(4, 4, 8, ColorType::Rgba).unwrap().into(),
+            AnimationControl { num_frames: 2, num_plays: 0 }.into(),
+            frame_control(0, 4, 4).into(),
+            chunk("IDAT", vec![1, 2]),
+            chunk("IDAT", vec![3]),
+            frame_control(1, 2, 2).into(),
+            FrameData { sequence_number: 2, data: vec![4, 5] }.into(),
+            chunk("IEND", Vec::new()),
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let control = AnimationControl { num_frames: 3, num_plays: 2 };
+        assert_eq!(AnimationControl::try_from(&Chunk::from(control)).unwrap(), control);
+        let frame = FrameControl { x_offset: 5, y_offset: 6, dispose_op: DisposeOp::Previous, ..frame_control(7, 1, 2) };
+        let fctl: Chunk = frame.into();
+        assert_eq!(fctl.data().len(), 26);
+        assert_eq!(FrameControl::try_from(&fctl).unwrap(), frame);
+        let data = FrameData { sequence_number: 9, data: vec![1, 2, 3] };
+        assert_eq!(FrameData::try_from(&Chunk::from(data.clone())).unwrap(), data);
+        assert_eq!(frame.delay(), Duration::from_millis(100));
+        assert_eq!(control.to_string(), "3 frames, plays 2 times");
+    }
+
+    #[test]
+    fn test_invalid_chunks() {
+        assert!(AnimationControl::try_from(&chunk("acTL", vec![0; 8])).is_err());
+        let mut fctl = Chunk::from(frame_control(0, 1, 1)).data().to_vec();
+        fctl[24] = 3;
+        assert!(FrameControl::try_from(&chunk("fcTL", fctl)).is_err());
+        assert!(FrameData::try_from(&chunk("fdAT", vec![0, 0])).is_err());
+    }
+
+    #[test]
+    fn test_frames() {
+        let png = Png::from_chunks(testing_chunks());
+        assert!(png.is_animated());
+        png.validate_animation().unwrap();
+        let frames = png.frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].data, [1, 2, 3]);
+        assert_eq!(frames[1].data, [4, 5]);
+        assert_eq!(frames[1].control.width, 2);
+    }
+
+    #[test]
+    fn test_sequence_gaps() {
+        let mut chunks = testing_chunks();
+        chunks[6] = FrameData { sequence_number: 3, data: vec![4, 5] }.into();
+        assert!(Png::from_chunks(chunks).validate_animation().is_err());
+    }
+
+    #[test]
+    fn test_frame_count_and_geometry() {
+        let mut chunks = testing_chunks();
+        chunks[1] = AnimationControl { num_frames: 3, num_plays: 0 }.into();
+        assert!(Png::from_chunks(chunks).validate_animation().is_err());
+
+        let mut chunks = testing_chunks();
+        chunks[5] = FrameControl { x_offset: 3, ..frame_control(1, 2, 2) }.into();
+        assert!(Png::from_chunks(chunks).validate_animation().is_err());
+
+        let mut chunks = testing_chunks();
+        chunks[2] = frame_control(0, 2, 2).into();
+        assert!(Png::from_chunks(chunks).validate_animation().is_err());
+    }
+
+    #[test]
+    fn test_frame_without_data() {
+        let mut chunks = testing_chunks();
+        chunks.remove(6);
+        assert!(Png::from_chunks(chunks).validate_animation().is_err());
+    }
+
+    #[test]
+    fn test_default_image_outside_animation() {
+        let chunks = vec![
+            Ihdr::new(4, 4, 8, ColorType::Rgba).unwrap().into(),
+            AnimationControl { num_frames: 1, num_plays: 1 }.into(),
+            chunk("IDAT", vec![1]),
+            frame_control(0, 3, 3).into(),
+            FrameData { sequence_number: 1, data: vec![2] }.into(),
+            chunk("IEND", Vec::new()),
+        ];
+        let png = Png::from_chunks(chunks);
+        let frames = png.frames().unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data, [2]);
+    }
+
+    // A 4x4 animation: an opaque red default image, then a 2x2 frame at (1, 1)
+    // whose left column is blue and right column is transparent.
+    fn encoded_animation(dispose_op: DisposeOp, blend_op: BlendOp) -> Png {
+        let encode = |image: ImageBuffer| Png::from_rgba(&image, ColorType::Rgba).unwrap().image_data();
+        let red = encode(ImageBuffer::new(4, 4, vec![[255, 0, 0, 255]; 16]).unwrap());
+        let blue = encode(ImageBuffer::new(2, 2, vec![[0, 0, 255, 255], [0, 0, 0, 0], [0, 0, 255, 255], [0, 0, 0, 0]]).unwrap());
+        Png::from_chunks(vec![
+            Ihdr::new(4, 4, 8, ColorType::Rgba).unwrap().into(),
+            AnimationControl { num_frames: 2, num_plays: 0 }.into(),
+            FrameControl { dispose_op, ..frame_control(0, 4, 4) }.into(),
+            chunk("IDAT", red),
+            FrameControl { x_offset: 1, y_offset: 1, blend_op, ..frame_control(1, 2, 2) }.into(),
+            FrameData { sequence_number: 2, data: blue }.into(),
+            chunk("IEND", Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_composited_frames() {
+        let frames = encoded_animation(DisposeOp::None, BlendOp::Over).composited_frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].1.pixel(1, 1), [255, 0, 0, 255]);
+        assert_eq!(frames[1].1.pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(frames[1].1.pixel(1, 1), [0, 0, 255, 255]);
+        assert_eq!(frames[1].1.pixel(2, 1), [255, 0, 0, 255]);
+
+        let frames = encoded_animation(DisposeOp::None, BlendOp::Source).composited_frames().unwrap();
+        assert_eq!(frames[1].1.pixel(2, 1), [0, 0, 0, 0]);
+
+        let frames = encoded_animation(DisposeOp::Background, BlendOp::Over).composited_frames().unwrap();
+        assert_eq!(frames[1].1.pixel(0, 0), [0, 0, 0, 0]);
+        assert_eq!(frames[1].1.pixel(1, 1), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_composited_frames_need_backed_canvas() {
+        let red = Png::from_rgba(&ImageBuffer::new(1, 1, vec![[255, 0, 0, 255]]).unwrap(), ColorType::Rgba).unwrap().image_data();
+        let png = Png::from_chunks(vec![
+            Ihdr::new(60000, 60000, 8, ColorType::Rgba).unwrap().into(),
+            AnimationControl { num_frames: 1, num_plays: 0 }.into(),
+            chunk("IDAT", red.clone()),
+            frame_control(0, 1, 1).into(),
+            FrameData { sequence_number: 1, data: red }.into(),
+            chunk("IEND", Vec::new()),
+        ]);
+        assert!(png.validate_animation().is_ok());
+        assert!(matches!(png.composited_frames(), Err(PngError::InvalidImageData(_))));
+    }
+
+    #[test]
+    fn test_blend_over() {
+        assert_eq!(blend_over([255, 0, 0, 255], [0, 0, 255, 0]), [255, 0, 0, 255]);
+        assert_eq!(blend_over([255, 0, 0, 255], [0, 0, 255, 255]), [0, 0, 255, 255]);
+        assert_eq!(blend_over([0, 0, 0, 0], [10, 20, 30, 128]), [10, 20, 30, 128]);
+        assert_eq!(blend_over([255, 255, 255, 255], [0, 0, 0, 128]), [127, 127, 127, 255]);
+    }
+
+    #[test]
+    fn test_frame_delay() {
+        assert_eq!(frame_delay(24.0).unwrap(), (1, 24));
+        assert_eq!(frame_delay(0.5).unwrap(), (2, 1));
+        assert_eq!(frame_delay(29.97).unwrap(), (100, 2997));
+        assert!(frame_delay(0.0).is_err());
+        assert!(frame_delay(f64::NAN).is_err());
+        assert!(frame_delay(1e-9).is_err());
+    }
+
+    #[test]
+    fn test_retime_and_loops() {
+        let mut png = Png::from_chunks(testing_chunks());
+        let frame_data: Vec<Vec<u8>> = png.frames().unwrap().into_iter().map(|frame| frame.data).collect();
+        assert_eq!(png.set_frame_rate(24.0).unwrap(), 2);
+        assert!(png.frame_controls().unwrap().iter().all(|frame| (frame.delay_num, frame.delay_den) == (1, 24)));
+        assert_eq!(png.set_frame_rate(24.0).unwrap(), 0);
+        assert_eq!(png.set_num_plays(3).unwrap(), AnimationControl { num_frames: 2, num_plays: 3 });
+        assert_eq!(png.animation_control().unwrap().unwrap().num_plays, 3);
+        assert_eq!(png.frames().unwrap().into_iter().map(|frame| frame.data).collect::<Vec<_>>(), frame_data);
+        assert!(png.is_pixel_data_untouched());
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "acTL");
+        png.validate_animation().unwrap();
+
+        let mut still = Png::from_chunks(vec![chunk("IDAT", Vec::new()), chunk("IEND", Vec::new())]);
+        assert!(still.set_frame_rate(24.0).is_err());
+        assert!(still.set_num_plays(0).is_err());
+    }
+
+    #[test]
+    fn test_not_animated() {
+        let png = Png::from_chunks(vec![chunk("IDAT", Vec::new()), chunk("IEND", Vec::new())]);
+        assert!(!png.is_animated());
+        png.validate_animation().unwrap();
+        let stray = Png::from_chunks(vec![frame_control(0, 1, 1).into(), chunk("IEND", Vec::new())]);
+        assert!(stray.validate_animation().is_err());
+    }
+}
@@ -4,7 +4,50 @@ use clap::{Parser, Subcommand};
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
-    pub command: Commands
+    pub command: Commands,
+
+    #[arg(long, global = true)]
+    pub no_software: bool,
+
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    // Set tIME to the current time whenever a chunk is added or removed
+    #[arg(long, global = true)]
+    pub touch: bool,
+
+    // Move chunks into the order the spec requires and merge IDAT chunks when reading a file to modify
+    #[arg(long, global = true)]
+    pub normalize: bool,
+
+    // zlib level for written image data: fast, default, best or 0 to 9
+    #[arg(long, global = true)]
+    pub compression: Option<String>,
+
+    // Seed for random choices such as chunk names, to make runs reproducible
+    #[arg(long, global = true)]
+    pub seed: Option<u64>,
+
+    // File of "allow PATTERN" and "deny PATTERN" lines for the text keywords
+    // that may be written
+    #[arg(long, global = true)]
+    pub keyword_policy: Option<String>,
+
+    // Only write text chunks with keywords matching this glob; can be repeated
+    #[arg(long, global = true)]
+    pub allow_keyword: Vec<String>,
+
+    // Refuse to write text chunks with keywords matching this glob; can be repeated
+    #[arg(long, global = true)]
+    pub deny_keyword: Vec<String>,
+
+    // Refuse input files larger than this many bytes
+    #[arg(long, global = true)]
+    pub max_input_bytes: Option<u64>,
+
+    // Refuse images whose header declares more pixels than this, before decoding them
+    #[arg(long, global = true)]
+    pub max_pixels: Option<u64>
 }
 
 #[derive(Debug, Subcommand)]
@@ -12,26 +55,430 @@ pub enum Commands {
     #[command(arg_required_else_help = true)]
     Encode {
         file: String,
+        // A chunk type, or "random" for a random private ancillary type
         chunk: String,
         message: String,
-        output_file: Option<String>
+        output_file: Option<String>,
+        #[arg(long)]
+        integrity: bool,
+        // Treat `file` as a glob pattern and split the message across every match
+        #[arg(long, conflicts_with = "output_file")]
+        spread: bool,
+        // Extra Reed-Solomon shards; the message survives losing that many carriers
+        #[arg(long, requires = "spread", default_value_t = 0)]
+        parity: usize,
+        // Put a header with the format and tool version in front of the message
+        #[arg(long)]
+        envelope: bool,
+        // Where the message goes: chunk, trailer (after IEND), pixels, or auto
+        // to pick one from the message size and the image. Any mode puts an
+        // envelope around the message; `chunk` is ignored outside chunk mode.
+        #[arg(long, conflicts_with = "spread")]
+        mode: Option<String>,
+        // With --mode auto, prefer places that do not show in chunk listings
+        #[arg(long, requires = "mode")]
+        stealth: bool,
+        // Experimental: also hide the message in the pixels with error
+        // correction, to survive optimizers that drop chunks or re-encode the
+        // image. `chunk` must be ancillary and safe to copy
+        #[arg(long, conflicts_with_all = ["spread", "mode", "envelope", "integrity"])]
+        robust: bool
     },
 
     #[command(arg_required_else_help = true)]
     Decode {
         file: String,
-        chunk: String
+        chunk: String,
+        // Treat `file` as a glob pattern and rebuild a message spread across the matches
+        #[arg(long)]
+        gather: bool,
+        // Describe the message envelope instead of printing the message
+        #[arg(long, conflicts_with = "gather")]
+        inspect: bool,
+        // Look for a message `encode --mode` put in this place, or auto to try
+        // the chunk, the trailer and the pixels in turn
+        #[arg(long, conflicts_with_all = ["gather", "inspect"])]
+        mode: Option<String>,
+        // Read a message `encode --robust` hid, from the pixels when the chunk is gone
+        #[arg(long, conflicts_with_all = ["gather", "inspect", "mode"])]
+        robust: bool
     },
 
     #[command(arg_required_else_help = true)]
     Remove {
         file: String,
-        chunk: String
+        chunk: String,
+        // Remove every chunk of the type instead of the first
+        #[arg(long, conflicts_with = "nth")]
+        all: bool,
+        // Remove this occurrence of the type, 0 being the first
+        #[arg(long)]
+        nth: Option<usize>
+    },
+
+    #[command(arg_required_else_help = true)]
+    Strip {
+        file: String,
+        // Comma separated chunk types, e.g. tEXt,zTXt,iTXt
+        #[arg(required_unless_present = "vendor")]
+        types: Option<String>,
+        // Also strip chunks written by known programs, such as Fireworks'
+        // prVW and mkBF or ImageMagick's vpAg
+        #[arg(long)]
+        vendor: bool
     },
 
     #[command(arg_required_else_help = true)]
     Print {
+        file: String,
+        // Show what each standard chunk is and where the spec allows it
+        #[arg(long)]
+        describe: bool,
+        // Compare each stored CRC with one computed from the chunk, reading past mismatches
+        #[arg(long)]
+        verify: bool,
+        // With --verify, also show a BLAKE3 hash of each chunk's data
+        #[arg(long, requires = "verify")]
+        hash: bool
+    },
+
+    #[command(arg_required_else_help = true)]
+    Optimize {
+        file: String,
+        output_file: Option<String>,
+        #[arg(long)]
+        trials: bool,
+        #[arg(long)]
+        json: bool,
+        // Drop an alpha channel that can be expressed as a tRNS color key
+        #[arg(long)]
+        synthesize_trns: bool,
+        // Split the image data in two halves indexed by an Apple iDOT chunk,
+        // so decoders can inflate them in parallel
+        #[arg(long)]
+        emit_idot: bool
+    },
+
+    #[command(arg_required_else_help = true)]
+    Verify {
+        file: String,
+        #[arg(long)]
+        strong: bool,
+        // Also check conformance to a spec edition: png-1.2 or png-3
+        #[arg(long)]
+        profile: Option<String>
+    },
+
+    #[command(arg_required_else_help = true)]
+    Info {
+        file: String
+    },
+
+    #[command(arg_required_else_help = true)]
+    Metadata {
+        file: String,
+        output_file: Option<String>,
+        // Print resolution, stored in pHYs as pixels per meter
+        #[arg(long)]
+        dpi: Option<f64>,
+        // Last modification time as RFC 3339, e.g. 2023-01-02T03:04:05Z
+        #[arg(long)]
+        time: Option<String>,
+        // Recount palette usage into hIST
+        #[arg(long)]
+        histogram: bool,
+        // Embed a GIMP palette as sPLT, named after the file
+        #[arg(long)]
+        suggested_palette: Option<String>,
+        // Set a text entry, as KEYWORD=TEXT; can be repeated
+        #[arg(long = "set", value_name = "KEYWORD=TEXT")]
+        set: Vec<String>,
+        // Keep every other chunk at its byte offset: overwrite the old entry at
+        // the same size, or append after the image data, and touch nothing else
+        #[arg(long, requires = "set", conflicts_with_all = ["dpi", "time", "histogram", "suggested_palette"])]
+        minimal_rewrite: bool
+    },
+
+    #[command(arg_required_else_help = true)]
+    Fingerprint {
+        file: String
+    },
+
+    // Print perceptual hashes and dominant colors of the image as JSON
+    #[command(arg_required_else_help = true)]
+    Signature {
         file: String
+    },
+
+    #[command(arg_required_else_help = true)]
+    Convert {
+        file: String,
+        output_file: Option<String>,
+        #[arg(long, required_unless_present = "colorkey", conflicts_with = "colorkey")]
+        palette: Option<String>,
+        // Fail on colors that are not in the palette
+        #[arg(long, required_unless_present_any = ["nearest", "colorkey"], conflicts_with_all = ["nearest", "colorkey"])]
+        exact: bool,
+        // Map colors that are not in the palette onto the closest entry
+        #[arg(long, conflicts_with = "colorkey")]
+        nearest: bool,
+        // Color to make transparent, e.g. '#FF00FF', through tRNS or an
+        // alpha channel
+        #[arg(long)]
+        colorkey: Option<String>
+    },
+
+    #[command(arg_required_else_help = true)]
+    Quantize {
+        file: String,
+        output_file: Option<String>,
+        // Most palette entries to use, from 1 to 256
+        #[arg(long, default_value_t = 256)]
+        colors: usize
+    },
+
+    #[command(arg_required_else_help = true)]
+    Scale {
+        file: String,
+        output_file: Option<String>,
+        #[arg(long)]
+        factor: u32,
+        // Turn every pixel into a factor x factor block
+        #[arg(long, required_unless_present = "scale2x", conflicts_with = "scale2x")]
+        nearest: bool,
+        // Smooth diagonal edges with Scale2x/Scale3x
+        #[arg(long)]
+        scale2x: bool
+    },
+
+    #[command(arg_required_else_help = true)]
+    Analyze {
+        file: String,
+        #[arg(long)]
+        filters: bool,
+        // The zlib header and deflate blocks of the image data
+        #[arg(long)]
+        zlib: bool
+    },
+
+    #[command(arg_required_else_help = true)]
+    Compare {
+        file: String,
+        other_file: String,
+        // psnr or ssim
+        #[arg(long)]
+        metric: Option<String>,
+        // Fail when the metric is below this value
+        #[arg(long, requires = "metric")]
+        min: Option<f64>
+    },
+
+    // Compare the chunks and pixels of two files
+    #[command(arg_required_else_help = true)]
+    Diff {
+        file: String,
+        other_file: String,
+        // Also write a side-by-side HTML report with blended, flickering and
+        // highlighted pixel comparisons
+        #[arg(long)]
+        html: Option<String>
+    },
+
+    #[command(arg_required_else_help = true)]
+    EncryptImage {
+        file: String,
+        output_file: Option<String>,
+        #[arg(long)]
+        password: String
+    },
+
+    #[command(arg_required_else_help = true)]
+    DecryptImage {
+        file: String,
+        output_file: Option<String>,
+        #[arg(long)]
+        password: String
+    },
+
+    // Hide a message in the low bits of the pixels with ±1 embedding
+    #[command(arg_required_else_help = true)]
+    HidePixels {
+        file: String,
+        message: String,
+        output_file: Option<String>,
+        // Spread the message over the image in an order derived from the password
+        #[arg(long)]
+        password: Option<String>
+    },
+
+    #[command(arg_required_else_help = true)]
+    RevealPixels {
+        file: String,
+        #[arg(long)]
+        password: Option<String>
+    },
+
+    #[command(arg_required_else_help = true)]
+    Redact {
+        file: String,
+        output_file: Option<String>,
+        // x,y,width,height; may be repeated
+        #[arg(long, required = true)]
+        rect: Vec<String>,
+        // Block size; blacks out the region when not given
+        #[arg(long)]
+        pixelate: Option<u32>
+    },
+
+    #[command(arg_required_else_help = true)]
+    Lint {
+        file: String,
+        #[arg(long)]
+        strip_legacy: bool
+    },
+
+    // Write the image as a browser or tool shows it, following that
+    // consumer's choice of color chunks, to debug color shifts
+    #[command(arg_required_else_help = true)]
+    Render {
+        file: String,
+        output_file: String,
+        // chrome, firefox or imagemagick
+        #[arg(long)]
+        emulate: String
+    },
+
+    // Report what lint finds; with --fix make conflicting color chunks agree
+    // by the spec's precedence, writing back unless an output file is given
+    #[command(arg_required_else_help = true)]
+    Doctor {
+        file: String,
+        output_file: Option<String>,
+        #[arg(long)]
+        fix: bool
+    },
+
+    // Fix bad CRCs, misplaced chunks and data after IEND, writing a new file
+    #[command(arg_required_else_help = true)]
+    Repair {
+        file: String,
+        output_file: String,
+        // Drop ancillary chunks that cannot be read instead of failing
+        #[arg(long)]
+        drop_unreadable: bool
+    },
+
+    // Write a deliberately broken copy of a file, for testing other decoders
+    #[command(arg_required_else_help = true)]
+    Generate {
+        output_file: String,
+        // bad-crc, truncated, misordered or bad-filter
+        #[arg(long)]
+        corrupt: String,
+        // The valid file to start from
+        #[arg(long)]
+        from: String
+    },
+
+    // Print the optional capabilities of this build as JSON
+    Features,
+
+    // Run operations separated by "," on one image, reading and writing it
+    // once, e.g. `chain strip tEXt , optimize --level 9 < in.png > out.png`
+    #[command(arg_required_else_help = true)]
+    Chain {
+        // "-" reads standard input
+        #[arg(short, long, default_value = "-")]
+        input: String,
+        // "-" writes standard output
+        #[arg(short, long, default_value = "-")]
+        output: String,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        steps: Vec<String>
+    },
+
+    // Animated PNG tools
+    #[command(arg_required_else_help = true)]
+    Apng {
+        #[command(subcommand)]
+        command: ApngCommand
+    }
+
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ApngCommand {
+    // Render every frame, as it is shown, into a grid labelled with frame
+    // numbers and delays
+    #[command(arg_required_else_help = true)]
+    ContactSheet {
+        file: String,
+        #[arg(long, default_value_t = 5)]
+        columns: u32,
+        #[arg(long)]
+        out: String
+    },
+
+    // Give every frame the same delay, rewriting only the fcTL chunks
+    #[command(arg_required_else_help = true)]
+    Retime {
+        file: String,
+        output_file: Option<String>,
+        // Frames per second; fractional rates such as 29.97 are allowed
+        #[arg(long)]
+        fps: f64
+    },
+
+    // Set how many times the animation plays, rewriting only the acTL chunk
+    #[command(arg_required_else_help = true)]
+    Loops {
+        file: String,
+        // 0 loops forever
+        count: u32,
+        output_file: Option<String>
+    }
+}
+
+// One operation of a `chain` command.
+#[derive(Parser, Debug)]
+#[command(no_binary_name = true)]
+pub struct ChainStep {
+    #[command(subcommand)]
+    pub operation: Operation
+}
+
+#[derive(Debug, Subcommand, PartialEq)]
+pub enum Operation {
+    Strip {
+        types: String
+    },
+    Remove {
+        chunk: String
+    },
+    Encode {
+        chunk: String,
+        message: String
+    },
+    // Move chunks into the order the spec requires and merge IDAT chunks
+    Normalize,
+    Optimize {
+        // zlib level, 0 to 9
+        #[arg(long, default_value_t = 9)]
+        level: u32,
+        // Try every filter type instead of keeping the current filters
+        #[arg(long)]
+        trials: bool
+    },
+    Quantize {
+        #[arg(long, default_value_t = 256)]
+        colors: usize
     }
+}
 
+// Splits `chain` arguments on "," and parses each group as an operation.
+pub fn parse_chain(steps: &[String]) -> Result<Vec<Operation>, clap::Error> {
+    steps
+        .split(|step| step == ",")
+        .map(|group| ChainStep::try_parse_from(group).map(|step| step.operation))
+        .collect()
 }
@@ -0,0 +1,168 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{chunk::Chunk, chunk_type::{ChunkType, ChunkTypeSet}, error::PngError, ihdr::{ColorType, Ihdr}, png::Png};
+
+// bKGD: like tRNS the layout depends on the IHDR color type, so it is parsed
+// with `Background::parse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    PaletteIndex(u8),
+    // Gray sample value, for grayscale images with or without alpha.
+    Gray(u16),
+    // RGB sample values, for truecolor images with or without alpha.
+    Rgb(u16, u16, u16)
+}
+
+impl Background {
+    pub fn parse(chunk: &Chunk, color_type: ColorType) -> Result<Background, PngError> {
+        if chunk.chunk_type().bytes() != *b"bKGD" {
+            return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+        }
+        let data = chunk.data();
+        let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+        match color_type {
+            ColorType::Indexed if data.len() == 1 => Ok(Background::PaletteIndex(data[0])),
+            ColorType::Indexed => Err(invalid("expected 1 byte")),
+            ColorType::Grayscale | ColorType::GrayscaleAlpha if data.len() == 2 => Ok(Background::Gray(sample(0))),
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => Err(invalid("expected 2 bytes")),
+            ColorType::Rgb | ColorType::Rgba if data.len() == 6 => Ok(Background::Rgb(sample(0), sample(2), sample(4))),
+            ColorType::Rgb | ColorType::Rgba => Err(invalid("expected 6 bytes"))
+        }
+    }
+
+    // Whether this kind of bKGD fits an image of `color_type`.
+    pub fn matches(&self, color_type: ColorType) -> bool {
+        matches!(
+            (self, color_type),
+            (Background::PaletteIndex(_), ColorType::Indexed)
+                | (Background::Gray(_), ColorType::Grayscale | ColorType::GrayscaleAlpha)
+                | (Background::Rgb(..), ColorType::Rgb | ColorType::Rgba)
+        )
+    }
+
+    // Samples must fit in the image bit depth.
+    fn fits(&self, ihdr: &Ihdr) -> bool {
+        let max = ((1u32 << ihdr.bit_depth) - 1) as u16;
+        match *self {
+            Background::PaletteIndex(_) => true,
+            Background::Gray(gray) => gray <= max,
+            Background::Rgb(r, g, b) => r <= max && g <= max && b <= max
+        }
+    }
+}
+
+impl From<Background> for Chunk {
+    fn from(value: Background) -> Self {
+        let data = match value {
+            Background::PaletteIndex(index) => vec![index],
+            Background::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Background::Rgb(r, g, b) => [r, g, b].iter().flat_map(|sample| sample.to_be_bytes()).collect()
+        };
+        Chunk::new(ChunkType::from_str("bKGD").unwrap(), data)
+    }
+}
+
+impl Display for Background {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Background::PaletteIndex(index) => write!(f, "palette index {}", index),
+            Background::Gray(gray) => write!(f, "gray {}", gray),
+            Background::Rgb(r, g, b) => write!(f, "rgb({}, {}, {})", r, g, b)
+        }
+    }
+}
+
+impl Png {
+    pub fn background_color(&self) -> Result<Option<Background>, PngError> {
+        match self.chunk_by_type("bKGD") {
+            Some(chunk) => Background::parse(chunk, self.header()?.color_type).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    // Replaces the bKGD chunk, or inserts one after PLTE and before the image data.
+    pub fn set_background_color(&mut self, background: Background) -> Result<(), PngError> {
+        let ihdr = self.header()?;
+        if !background.matches(ihdr.color_type) {
+            return Err(invalid("does not match the image color type"));
+        }
+        if !background.fits(&ihdr) {
+            return Err(invalid("sample value does not fit the bit depth"));
+        }
+        if let Background::PaletteIndex(index) = background {
+            let entries = self.palette()?.map_or(0, |palette| palette.len());
+            if index as usize >= entries {
+                return Err(invalid("palette index out of range"));
+            }
+        }
+        self.place_chunk(background.into(), &ChunkTypeSet::of(&[b"IDAT"]));
+        Ok(())
+    }
+
+    pub fn remove_background_color(&mut self) -> Option<Chunk> {
+        self.remove_chunk("bKGD").ok()
+    }
+}
+
+fn invalid(reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: String::from("bKGD"), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::{Palette, Rgb};
+
+    fn testing_png(bit_depth: u8, color_type: ColorType) -> Png {
+        Png::from_chunks(vec![
+            Ihdr::new(1, 1, bit_depth, color_type).unwrap().into(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_parse_by_color_type() {
+        let chunk = Chunk::new(ChunkType::from_str("bKGD").unwrap(), vec![0, 1, 0, 2, 0, 3]);
+        assert_eq!(Background::parse(&chunk, ColorType::Rgba).unwrap(), Background::Rgb(1, 2, 3));
+        assert!(Background::parse(&chunk, ColorType::GrayscaleAlpha).is_err());
+        assert!(Background::parse(&chunk, ColorType::Indexed).is_err());
+        let gray = Chunk::new(ChunkType::from_str("bKGD").unwrap(), vec![1, 0]);
+        assert_eq!(Background::parse(&gray, ColorType::GrayscaleAlpha).unwrap(), Background::Gray(256));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for (background, color_type) in [
+            (Background::PaletteIndex(3), ColorType::Indexed),
+            (Background::Gray(65535), ColorType::Grayscale),
+            (Background::Rgb(1, 2, 3), ColorType::Rgb)
+        ] {
+            let chunk: Chunk = background.into();
+            assert_eq!(Background::parse(&chunk, color_type).unwrap(), background);
+        }
+    }
+
+    #[test]
+    fn test_set_background_color() {
+        let mut png = testing_png(8, ColorType::Rgba);
+        assert_eq!(png.background_color().unwrap(), None);
+        png.set_background_color(Background::Rgb(255, 255, 255)).unwrap();
+        assert_eq!(png.background_color().unwrap(), Some(Background::Rgb(255, 255, 255)));
+        assert!(png.set_background_color(Background::Rgb(256, 0, 0)).is_err());
+        assert!(png.set_background_color(Background::Gray(0)).is_err());
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "bKGD", "IDAT", "IEND"]);
+        assert!(png.remove_background_color().is_some());
+    }
+
+    #[test]
+    fn test_palette_index_limited_by_palette() {
+        let mut png = testing_png(4, ColorType::Indexed);
+        assert!(png.set_background_color(Background::PaletteIndex(0)).is_err());
+        png.set_palette(Palette::new(vec![Rgb::new(0, 0, 0), Rgb::new(1, 1, 1)]).unwrap());
+        assert!(png.set_background_color(Background::PaletteIndex(2)).is_err());
+        png.set_background_color(Background::PaletteIndex(1)).unwrap();
+        assert!(testing_png(1, ColorType::Grayscale).set_background_color(Background::Gray(2)).is_err());
+    }
+}
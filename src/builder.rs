@@ -0,0 +1,157 @@
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    error::PngError,
+    ihdr::Ihdr,
+    png::Png,
+    time::ModificationTime
+};
+
+// Builds a Png from a header or an existing file. Steps that fail are kept
+// until `build`, which reports the first of them, so a chain of steps needs
+// one check at the end instead of one per step.
+pub struct PngBuilder {
+    png: Png,
+    error: Option<PngError>
+}
+
+impl PngBuilder {
+    // An image with no image data yet; add it with `with_pixels` or IDAT
+    // chunks.
+    pub fn from_header(header: Ihdr) -> PngBuilder {
+        let iend = Chunk::new(ChunkType(u32::from_be_bytes(*b"IEND")), Vec::new());
+        PngBuilder::from_png(Png::from_chunks(vec![header.into(), iend]))
+    }
+
+    pub fn from_png(png: Png) -> PngBuilder {
+        PngBuilder { png, error: None }
+    }
+
+    // Packed rows for the header's size and format, which replace any image
+    // data already there. Interlaced headers are not supported.
+    pub fn with_pixels(self, data: &[u8]) -> PngBuilder {
+        self.then(|png| {
+            let header = png.header()?;
+            if header.interlaced {
+                return Err(PngError::InvalidArgument("with_pixels writes non-interlaced image data"));
+            }
+            let encoded = Png::from_pixels(header.width, header.height, header.color_type, header.bit_depth, data)?;
+            png.retain_chunks(|chunk| chunk.chunk_type().bytes() != *b"IDAT");
+            for chunk in encoded.into_iter().filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT") {
+                png.append_chunk(chunk);
+            }
+            Ok(())
+        })
+    }
+
+    // A tEXt chunk, or iTXt for text Latin-1 cannot hold, replacing any text
+    // with the same keyword.
+    pub fn with_text(self, keyword: &str, text: &str) -> PngBuilder {
+        self.then(|png| png.set_text(keyword, text))
+    }
+
+    // Adds `chunk` before IEND; `build` moves it to where the spec wants it.
+    pub fn with_chunk(self, chunk: Chunk) -> PngBuilder {
+        self.then(|png| {
+            png.append_chunk(chunk);
+            Ok(())
+        })
+    }
+
+    // Removes every chunk of `chunk_type`, if there are any.
+    pub fn without_type(self, chunk_type: &str) -> PngBuilder {
+        self.then(|png| {
+            let _ = png.remove_chunks(chunk_type).or_else(|err| match err {
+                PngError::ChunkNotFound(_) => Ok(Vec::new()),
+                err => Err(err)
+            })?;
+            Ok(())
+        })
+    }
+
+    pub fn with_time(self, time: ModificationTime) -> PngBuilder {
+        self.then(|png| {
+            png.set_modification_time(time);
+            Ok(())
+        })
+    }
+
+    pub fn with_time_now(self) -> PngBuilder {
+        self.with_time(ModificationTime::now())
+    }
+
+    // Puts the chunks in spec order if a step left them out of it, then
+    // checks the result: a valid header, image data, no chunk that may only
+    // appear once appearing twice, and consistent animation chunks.
+    pub fn build(self) -> Result<Png, PngError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        let mut png = self.png;
+        if png.validate_order().is_err() {
+            png.normalize();
+        }
+        png.header()?;
+        if png.chunk_by_type("IDAT").is_none() {
+            return Err(PngError::ChunkNotFound(String::from("IDAT")));
+        }
+        png.validate_order()?;
+        png.validate_animation()?;
+        Ok(png)
+    }
+
+    fn then(mut self, step: impl FnOnce(&mut Png) -> Result<(), PngError>) -> PngBuilder {
+        if self.error.is_none() {
+            self.error = step(&mut self.png).err();
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ihdr::ColorType, pixels};
+    use std::str::FromStr;
+
+    fn header() -> Ihdr {
+        Ihdr::new(2, 1, 8, ColorType::Rgb).unwrap()
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_build_from_header() {
+        let gamma = Chunk::new(ChunkType::from_str("gAMA").unwrap(), 45455u32.to_be_bytes().to_vec());
+        let png = PngBuilder::from_header(header())
+            .with_pixels(&[1, 2, 3, 4, 5, 6])
+            .with_text("Title", "first")
+            .with_text("Title", "second")
+            .with_chunk(gamma)
+            .build()
+            .unwrap();
+        // gAMA moved ahead of the image data.
+        assert_eq!(chunk_types(&png), ["IHDR", "gAMA", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(png.text("Title").as_deref(), Some("second"));
+        assert_eq!(pixels::decode_rgba(&png).unwrap().pixel(1, 0), [4, 5, 6, 255]);
+    }
+
+    #[test]
+    fn test_build_from_png() {
+        let png = PngBuilder::from_header(header()).with_pixels(&[0; 6]).with_text("Comment", "x").with_time_now().build().unwrap();
+        let edited = PngBuilder::from_png(png).without_type("tEXt").without_type("zTXt").build().unwrap();
+        assert_eq!(chunk_types(&edited), ["IHDR", "IDAT", "tIME", "IEND"]);
+    }
+
+    #[test]
+    fn test_first_error_is_reported() {
+        let result = PngBuilder::from_header(header()).with_text("", "no keyword").with_pixels(&[0; 5]).build();
+        assert!(matches!(result, Err(PngError::InvalidChunkData { .. })));
+        assert!(matches!(PngBuilder::from_header(header()).build(), Err(PngError::ChunkNotFound(_))));
+        let twice = Chunk::new(ChunkType::from_str("gAMA").unwrap(), vec![0; 4]);
+        let result = PngBuilder::from_header(header()).with_pixels(&[0; 6]).with_chunk(twice.clone()).with_chunk(twice).build();
+        assert!(matches!(result, Err(PngError::InvalidChunkOrder { .. })));
+    }
+}
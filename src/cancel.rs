@@ -0,0 +1,64 @@
+use std::{
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    time::{Duration, Instant}
+};
+
+use crate::error::PngError;
+
+// Lets a host abort a long decode, either by calling `cancel` from another
+// thread or by setting a deadline. Clones share the same cancelled flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn with_deadline(deadline: Instant) -> CancellationToken {
+        CancellationToken { cancelled: Arc::default(), deadline: Some(deadline) }
+    }
+
+    pub fn with_timeout(timeout: Duration) -> CancellationToken {
+        CancellationToken::with_deadline(Instant::now() + timeout)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    // Called from decoding loops; returns `PngError::Cancelled` once cancelled.
+    pub fn check(&self) -> Result<(), PngError> {
+        if self.is_cancelled() {
+            return Err(PngError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_is_shared_between_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(clone.check().is_ok());
+        token.cancel();
+        assert!(matches!(clone.check(), Err(PngError::Cancelled)));
+    }
+
+    #[test]
+    fn test_deadline() {
+        assert!(CancellationToken::with_timeout(Duration::ZERO).is_cancelled());
+        assert!(!CancellationToken::with_timeout(Duration::from_secs(3600)).is_cancelled());
+    }
+}
@@ -0,0 +1,101 @@
+use crc::{Algorithm, Crc, Digest};
+
+use crate::chunk_type::ChunkType;
+
+// The CRC-32 of ISO 3309 that PNG uses.
+pub(crate) const PNG_CRC_ALGO: Algorithm<u32> = Algorithm {
+    width: 32,
+    poly: 0x04C11DB7,
+    init: 0xFFFFFFFF,
+    refin: true,
+    refout: true,
+    xorout: 0xFFFFFFFF,
+    check: 0xCBF43926,
+    residue: 0x00000000
+};
+
+// Built once instead of for every checksum.
+static TABLE: Crc<u32> = Crc::<u32>::new(&PNG_CRC_ALGO);
+
+// A running PNG CRC. Chunks are checksummed by feeding the type and then the
+// data, so neither has to be copied into one buffer first.
+pub trait Crc32: Default {
+    fn update(&mut self, bytes: &[u8]);
+    fn finalize(self) -> u32;
+}
+
+// Portable table-driven CRC from the crc crate.
+pub struct TableCrc32(Digest<'static, u32>);
+
+// crc32fast, which uses SSE4.2 and PCLMULQDQ on x86 and the CRC instructions
+// on aarch64 when the CPU has them, and falls back to slicing-by-16.
+#[cfg(feature = "crc32fast")]
+#[derive(Default)]
+pub struct FastCrc32(crc32fast::Hasher);
+
+#[cfg(feature = "crc32fast")]
+pub type DefaultCrc32 = FastCrc32;
+#[cfg(not(feature = "crc32fast"))]
+pub type DefaultCrc32 = TableCrc32;
+
+impl Default for TableCrc32 {
+    fn default() -> Self {
+        TableCrc32(TABLE.digest())
+    }
+}
+
+impl Crc32 for TableCrc32 {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finalize(self) -> u32 {
+        self.0.finalize()
+    }
+}
+
+#[cfg(feature = "crc32fast")]
+impl Crc32 for FastCrc32 {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finalize(self) -> u32 {
+        self.0.finalize()
+    }
+}
+
+// The CRC of a chunk's type and data, with the backend `C`.
+pub fn chunk_crc_with<C: Crc32>(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    let mut crc = C::default();
+    crc.update(&chunk_type.bytes());
+    crc.update(data);
+    crc.finalize()
+}
+
+pub fn chunk_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    chunk_crc_with::<DefaultCrc32>(chunk_type, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::str::FromStr;
+
+    #[test]
+    fn test_backends_agree() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let data = b"This is where your secret message will be!";
+        assert_eq!(chunk_crc_with::<TableCrc32>(&chunk_type, data), 2882656334);
+        assert_eq!(chunk_crc(&chunk_type, data), 2882656334);
+        let long: alloc::vec::Vec<u8> = (0..100_000u32).map(|i| (i * 31 % 251) as u8).collect();
+        assert_eq!(chunk_crc_with::<TableCrc32>(&chunk_type, &long), chunk_crc(&chunk_type, &long));
+    }
+
+    #[test]
+    fn test_check_value() {
+        let mut crc = DefaultCrc32::default();
+        crc.update(b"123456789");
+        assert_eq!(crc.finalize(), PNG_CRC_ALGO.check);
+    }
+}
@@ -1,21 +1,9 @@
-use std::{fmt::{Display, Debug}, io};
+use alloc::{string::{String, ToString}, vec::Vec};
+use core::fmt::{Display, Debug};
 
-use crc::{Crc, Algorithm};
+use crate::{checksum, chunk_type::ChunkType, error::PngError};
 
-use crate::chunk_type::ChunkType;
-
-const PNG_CRC_ALGO: Algorithm<u32> = Algorithm { 
-            width: 32, 
-            poly: 0x04C11DB7, 
-            init: 0xFFFFFFFF, 
-            refin: true, 
-            refout: true, 
-            xorout: 0xFFFFFFFF, 
-            check: 0xCBF43926, 
-            residue: 0x00000000 
-};
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Chunk {
     pub data_length: u32,
     pub chunk_type: ChunkType,
@@ -25,7 +13,7 @@ pub struct Chunk {
 
 impl Chunk {
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        let crc_chksm = get_crc_checksum(&chunk_type, &data);
+        let crc_chksm = checksum::chunk_crc(&chunk_type, &data);
         Chunk {
            data_length: data.len() as u32,
            chunk_type,
@@ -40,15 +28,28 @@ impl Chunk {
         &self.chunk_type
     }
     pub fn data(&self) -> &[u8] {
-        &self.message_bytes.as_ref()
+        self.message_bytes.as_ref()
     }
     pub fn crc(&self) -> u32 {
         self.crc
     }
-    pub fn data_as_string(&self) -> io::Result<String> {
-        Ok(std::str::from_utf8(&self.message_bytes)
-            .expect("cannot convert utf8 to str")
-            .to_string())
+    // The CRC of the type and data, which differs from `crc` when the chunk
+    // was read with `from_bytes_unchecked` from a corrupt file.
+    pub fn computed_crc(&self) -> u32 {
+        checksum::chunk_crc(&self.chunk_type, &self.message_bytes)
+    }
+    // For chunks parsed with `ParseOptions::defer_crc_check`, which leaves
+    // the check to whoever reads the chunk.
+    pub fn verify_crc(&self) -> Result<(), PngError> {
+        crc_matches(&self.chunk_type, self.crc, self.computed_crc())
+    }
+    // Parses a chunk like `try_from` but keeps the stored CRC even when it
+    // does not match.
+    pub fn from_bytes_unchecked(value: &[u8]) -> Result<Chunk, PngError> {
+        ChunkRef::from_bytes_unchecked(value).map(Chunk::from)
+    }
+    pub fn data_as_string(&self) -> Result<String, PngError> {
+        Ok(core::str::from_utf8(&self.message_bytes)?.to_string())
     }
     pub fn as_bytes(&self) -> Vec<u8> {
         let chunk: Vec<u8> = self.length()
@@ -63,51 +64,98 @@ impl Chunk {
     }
 }
 
-impl TryFrom<&[u8]> for Chunk {
-    type Error = &'static str;
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let data_length_raw: [u8; 4] = value[0..=3].try_into().expect("failed to get data length");
-        let chunk_type_raw: [u8; 4] = value[4..=7].try_into().expect("failed to get chunk_type");
-        let crc_raw: [u8; 4] = value[value.len()-4..value.len()].try_into().expect("failed to get chunk_type");
-        let mut message_data_raw: Vec<u8> = Vec::new(); 
-        if value.len() > 12 {
-            for i in value[8..value.len()-4].iter() {
-                message_data_raw.push(*i);
-            }
+// A chunk whose data is borrowed from the buffer it was parsed from rather
+// than copied out of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+    chunk_type: ChunkType,
+    data: &'a [u8],
+    crc: u32
+}
+
+impl<'a> ChunkRef<'a> {
+    pub fn length(&self) -> u32 {
+        self.data.len() as u32
+    }
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+    pub fn computed_crc(&self) -> u32 {
+        checksum::chunk_crc(&self.chunk_type, self.data)
+    }
+    pub fn verify_crc(&self) -> Result<(), PngError> {
+        crc_matches(&self.chunk_type, self.crc, self.computed_crc())
+    }
+    // Like `Chunk::from_bytes_unchecked`, keeping a CRC that does not match.
+    pub fn from_bytes_unchecked(value: &'a [u8]) -> Result<ChunkRef<'a>, PngError> {
+        if value.len() < 12 {
+            return Err(PngError::TruncatedChunk { offset: 0 });
         }
-        if u32::from_be_bytes(crc_raw) != get_crc_checksum(&ChunkType(u32::from_be_bytes(chunk_type_raw)), &message_data_raw) {
-            return Err("crc checksum failed");
+        let data_length = u32::from_be_bytes(value[0..4].try_into().unwrap());
+        let chunk_type_raw: [u8; 4] = value[4..8].try_into().unwrap();
+        let crc = u32::from_be_bytes(value[value.len()-4..].try_into().unwrap());
+        let data = &value[8..value.len()-4];
+        if data_length as usize != data.len() {
+            return Err(PngError::LengthMismatch { declared: data_length, actual: data.len() });
         }
-        Ok(Chunk { 
-            data_length: u32::from_be_bytes(data_length_raw), 
-            chunk_type: ChunkType(u32::from_be_bytes(chunk_type_raw)),
-            message_bytes: message_data_raw, 
-            crc: u32::from_be_bytes(crc_raw) 
-        })
+        Ok(ChunkRef { chunk_type: ChunkType::try_from(chunk_type_raw)?, data, crc })
     }
 }
 
-impl Display for Chunk {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+    type Error = PngError;
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let chunk = ChunkRef::from_bytes_unchecked(value)?;
+        chunk.verify_crc()?;
+        Ok(chunk)
     }
 }
 
-fn get_crc_checksum(chunk_type: &ChunkType, data: &Vec<u8>) -> u32 {
-        let maybe_crc = Crc::<u32>::new(&PNG_CRC_ALGO); 
-        let mut chunk_type_and_data_bytes: Vec<u8> = Vec::from(u32::to_be_bytes(chunk_type.0));
-        for i in data.clone() {
-            chunk_type_and_data_bytes.push(i);
+// Copies the data, keeping the stored CRC.
+impl From<ChunkRef<'_>> for Chunk {
+    fn from(value: ChunkRef<'_>) -> Self {
+        Chunk {
+            data_length: value.length(),
+            chunk_type: value.chunk_type,
+            message_bytes: value.data.to_vec(),
+            crc: value.crc
         }
-        maybe_crc.checksum(&chunk_type_and_data_bytes) 
     }
+}
 
+impl TryFrom<&[u8]> for Chunk {
+    type Error = PngError;
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let chunk = Chunk::from_bytes_unchecked(value)?;
+        chunk.verify_crc()?;
+        Ok(chunk)
+    }
+}
+
+fn crc_matches(chunk_type: &ChunkType, stored: u32, computed: u32) -> Result<(), PngError> {
+    match stored == computed {
+        true => Ok(()),
+        false => Err(PngError::CrcMismatch { chunk_type: chunk_type.to_string(), stored, computed })
+    }
+}
+
+impl Display for Chunk {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
-    use std::str::FromStr;
+    use core::str::FromStr;
 
     fn testing_chunk() -> Chunk {
         let data_length: u32 = 42;
@@ -210,6 +258,60 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_truncated_chunk_from_bytes() {
+        let chunk = Chunk::try_from([0, 0, 0, 42, 82, 117].as_ref());
+        assert!(matches!(chunk, Err(PngError::TruncatedChunk { .. })));
+    }
+
+    #[test]
+    fn test_chunk_length_mismatch_from_bytes() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        chunk_data[3] = 40;
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+        assert!(matches!(chunk, Err(PngError::LengthMismatch { declared: 40, actual: 42 })));
+    }
+
+    #[test]
+    fn test_invalid_chunk_type_from_bytes() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        chunk_data[5] = b'1';
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+        assert!(matches!(chunk, Err(PngError::InvalidChunkType(_))));
+    }
+
+    #[test]
+    fn test_chunk_ref_borrows_data() {
+        let chunk_data = testing_chunk().as_bytes();
+        let chunk = ChunkRef::try_from(chunk_data.as_ref()).unwrap();
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert_eq!(chunk.crc(), 2882656334);
+        assert!(core::ptr::eq(chunk.data(), &chunk_data[8..50]));
+        assert_eq!(Chunk::from(chunk).as_bytes(), chunk_data);
+
+        let mut bad_crc = chunk_data.clone();
+        bad_crc[50] ^= 1;
+        assert!(matches!(ChunkRef::try_from(bad_crc.as_ref()), Err(PngError::CrcMismatch { .. })));
+        assert_ne!(ChunkRef::from_bytes_unchecked(&bad_crc).unwrap().crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_verify_crc() {
+        let mut chunk_data = testing_chunk().as_bytes();
+        assert!(Chunk::from_bytes_unchecked(&chunk_data).unwrap().verify_crc().is_ok());
+        chunk_data[10] ^= 1;
+        let chunk = Chunk::from_bytes_unchecked(&chunk_data).unwrap();
+        assert!(matches!(chunk.verify_crc(), Err(PngError::CrcMismatch { stored: 2882656334, .. })));
+        assert!(ChunkRef::from_bytes_unchecked(&chunk_data).unwrap().verify_crc().is_err());
+    }
+
+    #[test]
+    fn test_invalid_utf8_data_as_string() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0xff, 0xfe]);
+        assert!(matches!(chunk.data_as_string(), Err(PngError::Utf8(_))));
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -1,11 +1,94 @@
-use std::{str::FromStr, num::ParseIntError, fmt::Display};
+use alloc::{string::{String, ToString}, vec::Vec};
+use core::{str::FromStr, fmt::Display};
 
-#[derive(Debug, PartialEq, Eq)]
+#[cfg(feature = "std")]
+use rand::Rng;
+
+use crate::error::PngError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ChunkType(pub u32); 
 
-#[derive(Debug)]
-pub enum ChunkError {
-    Parsing(ParseIntError)
+// Why `ChunkType::from_str` rejected a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkTypeError {
+    // The length of the string in bytes.
+    WrongLength(usize),
+    // The first character that is not an ASCII letter.
+    InvalidCharacter(char)
+}
+
+// Where the spec allows a chunk to appear, relative to PLTE and IDAT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    First,
+    BeforePlte,
+    // After PLTE when there is one, and before IDAT.
+    AfterPlte,
+    BeforeIdat,
+    // Consecutive, with nothing in between.
+    Idat,
+    AfterIdat,
+    Anywhere,
+    Last
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StandardType {
+    pub chunk_type: ChunkType,
+    pub description: &'static str,
+    pub placement: Placement,
+    // Whether a file may have more than one.
+    pub multiple: bool
+}
+
+const fn standard(name: &[u8; 4], description: &'static str, placement: Placement, multiple: bool) -> StandardType {
+    StandardType { chunk_type: ChunkType(u32::from_be_bytes(*name)), description, placement, multiple }
+}
+
+// The PNG third edition chunks, the registered extensions and APNG, in
+// recommended file order.
+const STANDARD_TYPES: [StandardType; 32] = [
+    standard(b"IHDR", "image header", Placement::First, false),
+    standard(b"cICP", "coding-independent code points", Placement::BeforePlte, false),
+    standard(b"mDCV", "mastering display color volume", Placement::BeforePlte, false),
+    standard(b"cLLI", "content light level", Placement::BeforePlte, false),
+    standard(b"cHRM", "primary chromaticities and white point", Placement::BeforePlte, false),
+    standard(b"gAMA", "image gamma", Placement::BeforePlte, false),
+    standard(b"iCCP", "embedded ICC profile", Placement::BeforePlte, false),
+    standard(b"sBIT", "significant bits", Placement::BeforePlte, false),
+    standard(b"sRGB", "standard RGB color space", Placement::BeforePlte, false),
+    standard(b"PLTE", "palette", Placement::BeforeIdat, false),
+    standard(b"tRNS", "transparency", Placement::AfterPlte, false),
+    standard(b"bKGD", "background color", Placement::AfterPlte, false),
+    standard(b"hIST", "palette histogram", Placement::AfterPlte, false),
+    standard(b"pHYs", "physical pixel dimensions", Placement::BeforeIdat, false),
+    standard(b"sPLT", "suggested palette", Placement::BeforeIdat, true),
+    standard(b"eXIf", "exchangeable image file profile", Placement::BeforeIdat, false),
+    standard(b"oFFs", "image offset", Placement::BeforeIdat, false),
+    standard(b"pCAL", "calibration of pixel values", Placement::BeforeIdat, false),
+    standard(b"sCAL", "physical scale of image subject", Placement::BeforeIdat, false),
+    standard(b"sTER", "stereo image indicator", Placement::BeforeIdat, false),
+    standard(b"acTL", "animation control", Placement::BeforeIdat, false),
+    standard(b"fcTL", "frame control", Placement::Anywhere, true),
+    standard(b"IDAT", "image data", Placement::Idat, true),
+    standard(b"fdAT", "frame data", Placement::AfterIdat, true),
+    standard(b"tIME", "last modification time", Placement::Anywhere, false),
+    standard(b"tEXt", "textual data", Placement::Anywhere, true),
+    standard(b"zTXt", "compressed textual data", Placement::Anywhere, true),
+    standard(b"iTXt", "international textual data", Placement::Anywhere, true),
+    standard(b"gIFg", "GIF graphic control extension", Placement::Anywhere, true),
+    standard(b"gIFx", "GIF application extension", Placement::Anywhere, true),
+    standard(b"gIFt", "GIF plain text extension", Placement::Anywhere, true),
+    standard(b"IEND", "image trailer", Placement::Last, false),
+];
+
+// A small copyable set of chunk types, kept sorted so equal sets compare and
+// hash equal. Holds up to `CAPACITY` types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkTypeSet {
+    types: [u32; ChunkTypeSet::CAPACITY],
+    len: usize
 }
 
 impl ChunkType {
@@ -13,29 +96,20 @@ impl ChunkType {
         u32::to_be_bytes(self.0)
     }
     
+    // Any four bytes, for the rare caller that needs a type the spec does
+    // not allow. `try_from` checks the bytes.
+    pub const fn new_unchecked(bytes: [u8; 4]) -> ChunkType {
+        ChunkType(u32::from_be_bytes(bytes))
+    }
+
+    // Letters only, with the reserved bit clear (third letter uppercase).
     pub fn is_valid(&self) -> bool {
-        let bytes = u32::to_be_bytes(self.0).into_iter();
-        for (i, byte) in bytes.enumerate() {
-            match byte {
-                _ if i == 2 && !byte.is_ascii_uppercase() => return false,
-                _ if !byte.is_ascii_lowercase() && 
-                    !byte.is_ascii_uppercase() => return false,
-                _ => {}
-            }
-        }
-        true
+        let bytes = self.bytes();
+        bytes.iter().all(u8::is_ascii_alphabetic) && bytes[2].is_ascii_uppercase()
     }
+
     pub fn is_err(&self) -> bool {
-        let bytes = u32::to_be_bytes(self.0).into_iter();
-        for (i, byte) in bytes.enumerate() {
-            match byte {
-                _ if i == 2 && !byte.is_ascii_uppercase() => return true,
-                _ if !byte.is_ascii_lowercase() && 
-                    !byte.is_ascii_uppercase() => return true,
-                _ => {}
-            }
-        }
-        false
+        !self.is_valid()
     }
 
     fn set_bitness(&self, bit_position: usize) -> bool {
@@ -62,49 +136,237 @@ impl ChunkType {
     pub fn is_safe_to_copy(&self) -> bool {
         self.is_valid() && !self.set_bitness(3)
     }
+
+    pub fn standard_types() -> impl Iterator<Item = &'static StandardType> {
+        STANDARD_TYPES.iter()
+    }
+
+    pub fn standard(&self) -> Option<&'static StandardType> {
+        STANDARD_TYPES.iter().find(|standard| standard.chunk_type == *self)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn random_private_ancillary<R: Rng + ?Sized>(rng: &mut R) -> ChunkType {
+        let bytes = [
+            rng.gen_range(b'a'..=b'z'),
+            rng.gen_range(b'a'..=b'z'),
+            rng.gen_range(b'A'..=b'Z'),
+            if rng.gen() { rng.gen_range(b'a'..=b'z') } else { rng.gen_range(b'A'..=b'Z') }
+        ];
+        ChunkType(u32::from_be_bytes(bytes))
+    }
     
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = &'static str;
+    type Error = PngError;
+    // Rejects bytes that are not ASCII letters. A lowercase third letter,
+    // which the current spec reserves, is allowed so that files from a later
+    // edition still parse; `is_valid` reports it.
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
-        Ok(ChunkType(u32::from_be_bytes(value)))
+        if !value.iter().all(u8::is_ascii_alphabetic) {
+            return Err(PngError::InvalidChunkType(String::from_utf8_lossy(&value).into_owned()));
+        }
+        Ok(ChunkType::new_unchecked(value))
     }
 }
 
+// Exactly four ASCII letters, like `try_from`.
 impl FromStr for ChunkType {
-    type Err = ParseIntError;
+    type Err = ChunkTypeError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut chars: [u8; 4] = [0u8; 4];
-        for (i, char) in s.chars().enumerate() {
-            chars[i] = char as u8;
+        let bytes: [u8; 4] = s.as_bytes().try_into().map_err(|_| ChunkTypeError::WrongLength(s.len()))?;
+        match s.chars().find(|char| !char.is_ascii_alphabetic()) {
+            Some(char) => Err(ChunkTypeError::InvalidCharacter(char)),
+            None => Ok(ChunkType::new_unchecked(bytes))
         }
-        Ok(ChunkType(u32::from_be_bytes(chars)))
     }
 }
 
-impl Display for ChunkType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let bytes = self.0.to_be_bytes();
-        let chunk_str = match std::str::from_utf8(&bytes) {
-            Ok(val) => val,
-            Err(err) => panic!("invalid utf8 {}", err) 
+impl Display for ChunkTypeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ChunkTypeError::WrongLength(len) => write!(f, "a chunk type is 4 letters, not {} bytes", len),
+            ChunkTypeError::InvalidCharacter(char) => write!(f, "{:?} is not an ASCII letter", char)
+        }
+    }
+}
+
+impl core::error::Error for ChunkTypeError {}
+
+impl Display for Placement {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let text = match self {
+            Placement::First => "first",
+            Placement::BeforePlte => "before PLTE and IDAT",
+            Placement::AfterPlte => "after PLTE, before IDAT",
+            Placement::BeforeIdat => "before IDAT",
+            Placement::Idat => "consecutive",
+            Placement::AfterIdat => "after IDAT",
+            Placement::Anywhere => "anywhere",
+            Placement::Last => "last"
         };
-        write!(f, "{}", chunk_str)
+        f.pad(text)
+    }
+}
+
+impl Display for ChunkType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.bytes()))
+    }
+}
+
+impl ChunkTypeSet {
+    pub const CAPACITY: usize = 32;
+
+    pub const fn new() -> ChunkTypeSet {
+        ChunkTypeSet { types: [0; ChunkTypeSet::CAPACITY], len: 0 }
+    }
+
+    // Builds a set at compile time; panics if `types` has more than CAPACITY entries.
+    pub const fn of(types: &[&[u8; 4]]) -> ChunkTypeSet {
+        let mut set = ChunkTypeSet::new();
+        let mut i = 0;
+        while i < types.len() {
+            set = set.with(u32::from_be_bytes(*types[i]));
+            i += 1;
+        }
+        set
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn contains(&self, chunk_type: ChunkType) -> bool {
+        let mut i = 0;
+        while i < self.len {
+            if self.types[i] == chunk_type.0 {
+                return true;
+            }
+            i += 1;
+        }
+        false
+    }
+
+    // Returns whether `chunk_type` was newly added.
+    pub fn insert(&mut self, chunk_type: ChunkType) -> Result<bool, PngError> {
+        if self.contains(chunk_type) {
+            return Ok(false);
+        }
+        if self.len == ChunkTypeSet::CAPACITY {
+            return Err(PngError::InvalidArgument("too many chunk types in one set"));
+        }
+        *self = self.with(chunk_type.0);
+        Ok(true)
+    }
+
+    pub fn remove(&mut self, chunk_type: ChunkType) -> bool {
+        match self.types[..self.len].iter().position(|value| *value == chunk_type.0) {
+            Some(index) => {
+                self.types.copy_within(index + 1..self.len, index);
+                self.len -= 1;
+                self.types[self.len] = 0;
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = ChunkType> + '_ {
+        self.types[..self.len].iter().map(|value| ChunkType(*value))
+    }
+
+    pub fn union(&self, other: &ChunkTypeSet) -> Result<ChunkTypeSet, PngError> {
+        let mut set = *self;
+        for chunk_type in other.iter() {
+            set.insert(chunk_type)?;
+        }
+        Ok(set)
+    }
+
+    pub fn intersection(&self, other: &ChunkTypeSet) -> ChunkTypeSet {
+        let mut set = *self;
+        for chunk_type in self.iter() {
+            if !other.contains(chunk_type) {
+                set.remove(chunk_type);
+            }
+        }
+        set
+    }
+
+    // Inserts a value that is not yet present, keeping the types sorted.
+    const fn with(mut self, value: u32) -> ChunkTypeSet {
+        let mut i = self.len;
+        while i > 0 && self.types[i - 1] >= value {
+            if self.types[i - 1] == value {
+                return self;
+            }
+            i -= 1;
+        }
+        assert!(self.len < ChunkTypeSet::CAPACITY, "too many chunk types in one set");
+        let mut j = self.len;
+        while j > i {
+            self.types[j] = self.types[j - 1];
+            j -= 1;
+        }
+        self.types[i] = value;
+        self.len += 1;
+        self
     }
 }
 
-impl Display for ChunkError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)       
+impl Default for ChunkTypeSet {
+    fn default() -> Self {
+        ChunkTypeSet::new()
+    }
+}
+
+impl FromIterator<ChunkType> for ChunkTypeSet {
+    // Panics past CAPACITY types; use `insert` to handle that as an error.
+    fn from_iter<T: IntoIterator<Item = ChunkType>>(iter: T) -> Self {
+        let mut set = ChunkTypeSet::new();
+        for chunk_type in iter {
+            set.insert(chunk_type).expect("too many chunk types in one set");
+        }
+        set
+    }
+}
+
+// Parses a comma separated list such as "tEXt,zTXt, iTXt".
+impl FromStr for ChunkTypeSet {
+    type Err = PngError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut set = ChunkTypeSet::new();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let bytes: [u8; 4] = name.as_bytes().try_into().map_err(|_| PngError::InvalidChunkType(name.to_string()))?;
+            let chunk_type = ChunkType(u32::from_be_bytes(bytes));
+            if !chunk_type.is_valid() {
+                return Err(PngError::InvalidChunkType(name.to_string()));
+            }
+            set.insert(chunk_type)?;
+        }
+        Ok(set)
+    }
+}
+
+impl Display for ChunkTypeSet {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let names: Vec<String> = self.iter().map(|chunk_type| chunk_type.to_string()).collect();
+        write!(f, "{}", names.join(","))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::convert::TryFrom;
-    use std::str::FromStr;
+    use core::convert::TryFrom;
+    use core::str::FromStr;
+    use rand::{rngs::StdRng, SeedableRng};
 
     #[test]
     pub fn test_chunk_type_from_bytes() {
@@ -121,6 +383,18 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    pub fn test_chunk_type_from_str_errors() {
+        assert_eq!(ChunkType::from_str("RuStacean"), Err(ChunkTypeError::WrongLength(9)));
+        assert_eq!(ChunkType::from_str("Ru"), Err(ChunkTypeError::WrongLength(2)));
+        assert_eq!(ChunkType::from_str("ruSé"), Err(ChunkTypeError::WrongLength(5)));
+        assert_eq!(ChunkType::from_str("Ru1t"), Err(ChunkTypeError::InvalidCharacter('1')));
+        assert_eq!(ChunkType::from_str("aéb"), Err(ChunkTypeError::InvalidCharacter('é')));
+        assert_eq!(ChunkTypeError::WrongLength(2).to_string(), "a chunk type is 4 letters, not 2 bytes");
+        // Letters with the reserved bit set still parse; `is_valid` reports them.
+        assert!(!ChunkType::from_str("Rust").unwrap().is_valid());
+    }
+
     #[test]
     pub fn test_chunk_type_is_critical() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -180,16 +454,56 @@ mod tests {
         let chunk = ChunkType::from_str("Rust").unwrap();
         assert!(!chunk.is_valid());
 
-        let chunk = ChunkType::from_str("Ru1t").unwrap();
+        let chunk = ChunkType::new_unchecked(*b"Ru1t");
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_try_from_rejects_non_letters() {
+        assert!(matches!(ChunkType::try_from([82, 117, 0xff, 116]), Err(PngError::InvalidChunkType(_))));
+        assert!(ChunkType::try_from(*b"Ru1t").is_err());
+        assert!(ChunkType::try_from(*b"Ru t").is_err());
+        let reserved = ChunkType::try_from(*b"Rust").unwrap();
+        assert!(reserved.is_err() && !reserved.is_valid());
+        assert!(ChunkType::new_unchecked(*b"Ru1t").is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
         assert_eq!(&chunk.to_string(), "RuSt");
     }
 
+    #[test]
+    pub fn test_random_private_ancillary() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let chunk = ChunkType::random_private_ancillary(&mut rng);
+            assert!(chunk.is_valid());
+            assert!(!chunk.is_critical());
+            assert!(!chunk.is_public());
+            assert!(chunk.is_reserved_bit_valid());
+        }
+    }
+
+    #[test]
+    pub fn test_random_private_ancillary_is_deterministic() {
+        let mut first = StdRng::seed_from_u64(42);
+        let mut second = StdRng::seed_from_u64(42);
+        for _ in 0..10 {
+            assert_eq!(
+                ChunkType::random_private_ancillary(&mut first),
+                ChunkType::random_private_ancillary(&mut second)
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_non_utf8_chunk_type_string() {
+        let chunk = ChunkType::new_unchecked([0xff, 117, 83, 116]);
+        assert_eq!(&chunk.to_string(), "\u{fffd}uSt");
+    }
+
     #[test]
     pub fn test_chunk_type_trait_impls() {
         let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
@@ -197,5 +511,60 @@ mod tests {
         let _chunk_string = format!("{}", chunk_type_1);
         let _are_chunks_equal = chunk_type_1 == chunk_type_2;
     }
-}
 
+    #[test]
+    pub fn test_chunk_type_set_is_sorted() {
+        let a: ChunkTypeSet = "zTXt,tEXt,iTXt".parse().unwrap();
+        let b = ChunkTypeSet::of(&[b"iTXt", b"tEXt", b"zTXt", b"tEXt"]);
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 3);
+        assert_eq!(a.to_string(), "iTXt,tEXt,zTXt");
+    }
+
+    #[test]
+    pub fn test_chunk_type_set_operations() {
+        let text = ChunkTypeSet::of(&[b"tEXt", b"zTXt"]);
+        let other = ChunkTypeSet::of(&[b"zTXt", b"eXIf"]);
+        assert!(text.contains(ChunkType::from_str("tEXt").unwrap()));
+        assert!(!text.contains(ChunkType::from_str("eXIf").unwrap()));
+        assert_eq!(text.union(&other).unwrap().len(), 3);
+        assert_eq!(text.intersection(&other), ChunkTypeSet::of(&[b"zTXt"]));
+        let mut set = text;
+        assert!(set.remove(ChunkType::from_str("tEXt").unwrap()));
+        assert!(!set.remove(ChunkType::from_str("tEXt").unwrap()));
+        assert_eq!(set.iter().collect::<Vec<ChunkType>>(), [ChunkType::from_str("zTXt").unwrap()]);
+    }
+
+    #[test]
+    pub fn test_chunk_type_set_capacity() {
+        let mut set = ChunkTypeSet::new();
+        for i in 0..ChunkTypeSet::CAPACITY as u8 {
+            assert!(set.insert(ChunkType(u32::from_be_bytes([b'a', b'a' + i / 26, b'A', b'a' + i % 26]))).unwrap());
+        }
+        assert!(set.insert(ChunkType::from_str("tEXt").unwrap()).is_err());
+        assert!(!set.insert(ChunkType::from_str("aaAa").unwrap()).unwrap());
+    }
+
+    #[test]
+    pub fn test_chunk_type_set_from_invalid_str() {
+        assert!("tEXt,toolong".parse::<ChunkTypeSet>().is_err());
+        assert!("tExt".parse::<ChunkTypeSet>().is_err());
+        assert!("".parse::<ChunkTypeSet>().unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_standard_types() {
+        let names: Vec<String> = ChunkType::standard_types().map(|standard| standard.chunk_type.to_string()).collect();
+        assert_eq!(names.first().map(String::as_str), Some("IHDR"));
+        assert_eq!(names.last().map(String::as_str), Some("IEND"));
+        assert!(ChunkType::standard_types().all(|standard| standard.chunk_type.is_valid()));
+        let unique: ChunkTypeSet = ChunkType::standard_types().map(|standard| standard.chunk_type).collect();
+        assert_eq!(unique.len(), names.len());
+
+        let trns = ChunkType::from_str("tRNS").unwrap().standard().unwrap();
+        assert_eq!(trns.placement, Placement::AfterPlte);
+        assert_eq!(trns.placement.to_string(), "after PLTE, before IDAT");
+        assert!(!trns.multiple);
+        assert!(ChunkType::from_str("ruSt").unwrap().standard().is_none());
+    }
+}
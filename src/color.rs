@@ -0,0 +1,454 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::{ChunkType, ChunkTypeSet},
+    error::PngError,
+    png::Png,
+    text::{decode_latin1, encode_latin1, validate_keyword},
+    zlib
+};
+
+// Color-space chunks must come before PLTE and the image data.
+const COLOR_SPACE_BEFORE: ChunkTypeSet = ChunkTypeSet::of(&[b"PLTE", b"IDAT"]);
+// How far gAMA and cHRM values may be from the sRGB ones, in 1/100000, and
+// still count as the same. Encoders round them differently.
+const GAMMA_TOLERANCE: u32 = 100;
+const CHROMATICITY_TOLERANCE: u32 = 100;
+
+// gAMA: image gamma times 100000.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gamma(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticity {
+    // CIE x and y times 100000.
+    pub x: u32,
+    pub y: u32
+}
+
+// cHRM: chromaticities of the white point and the three primaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticities {
+    pub white: Chromaticity,
+    pub red: Chromaticity,
+    pub green: Chromaticity,
+    pub blue: Chromaticity
+}
+
+// sRGB: the image is in the sRGB color space with this rendering intent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual = 0,
+    RelativeColorimetric = 1,
+    Saturation = 2,
+    AbsoluteColorimetric = 3
+}
+
+// iCCP: an embedded ICC profile, stored inflated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IccProfile {
+    pub name: String,
+    pub profile: Vec<u8>
+}
+
+// Color chunks that disagree, so decoders that follow different chunks show
+// different colors. The spec's precedence is iCCP, then sRGB, then gAMA and
+// cHRM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorConflict {
+    // iCCP overrides sRGB, but decoders without ICC support use sRGB.
+    IccProfileWithSrgb,
+    // gAMA is not the sRGB gamma, for decoders that ignore sRGB.
+    SrgbGamma(Gamma),
+    // cHRM is not the sRGB primaries, for decoders that ignore sRGB.
+    SrgbChromaticities(Chromaticities)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColorInfo {
+    pub gamma: Option<Gamma>,
+    pub chromaticities: Option<Chromaticities>,
+    pub rendering_intent: Option<RenderingIntent>,
+    pub icc_profile: Option<IccProfile>
+}
+
+impl Gamma {
+    // The gAMA the spec recommends alongside an sRGB chunk.
+    pub const SRGB: Gamma = Gamma(45455);
+
+    pub fn from_f64(gamma: f64) -> Result<Gamma, PngError> {
+        Ok(Gamma(to_fixed_point("gAMA", gamma)?))
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0 as f64 / 100000.0
+    }
+}
+
+impl TryFrom<&Chunk> for Gamma {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        match chunk_data(chunk, b"gAMA")? {
+            [a, b, c, d] => Ok(Gamma(u32::from_be_bytes([*a, *b, *c, *d]))),
+            _ => Err(invalid("gAMA", "expected 4 bytes"))
+        }
+    }
+}
+
+impl From<Gamma> for Chunk {
+    fn from(value: Gamma) -> Self {
+        Chunk::new(ChunkType::from_str("gAMA").unwrap(), value.0.to_be_bytes().to_vec())
+    }
+}
+
+impl Display for Gamma {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
+
+impl Chromaticity {
+    pub fn from_f64(x: f64, y: f64) -> Result<Chromaticity, PngError> {
+        Ok(Chromaticity { x: to_fixed_point("cHRM", x)?, y: to_fixed_point("cHRM", y)? })
+    }
+}
+
+impl Display for Chromaticity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.x as f64 / 100000.0, self.y as f64 / 100000.0)
+    }
+}
+
+impl Chromaticities {
+    // The sRGB (Rec. 709, D65) values the spec recommends alongside an sRGB chunk.
+    pub const SRGB: Chromaticities = Chromaticities {
+        white: Chromaticity { x: 31270, y: 32900 },
+        red: Chromaticity { x: 64000, y: 33000 },
+        green: Chromaticity { x: 30000, y: 60000 },
+        blue: Chromaticity { x: 15000, y: 6000 }
+    };
+}
+
+impl Chromaticity {
+    fn is_near(&self, other: &Chromaticity) -> bool {
+        self.x.abs_diff(other.x) <= CHROMATICITY_TOLERANCE && self.y.abs_diff(other.y) <= CHROMATICITY_TOLERANCE
+    }
+}
+
+impl Chromaticities {
+    fn is_near(&self, other: &Chromaticities) -> bool {
+        self.white.is_near(&other.white) && self.red.is_near(&other.red)
+            && self.green.is_near(&other.green) && self.blue.is_near(&other.blue)
+    }
+}
+
+impl TryFrom<&Chunk> for Chromaticities {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = chunk_data(chunk, b"cHRM")?;
+        if data.len() != 32 {
+            return Err(invalid("cHRM", "expected 32 bytes"));
+        }
+        let value = |i: usize| u32::from_be_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+        let point = |i: usize| Chromaticity { x: value(i), y: value(i + 1) };
+        Ok(Chromaticities { white: point(0), red: point(2), green: point(4), blue: point(6) })
+    }
+}
+
+impl From<Chromaticities> for Chunk {
+    fn from(value: Chromaticities) -> Self {
+        let data = [value.white, value.red, value.green, value.blue]
+            .iter()
+            .flat_map(|point| [point.x, point.y])
+            .flat_map(u32::to_be_bytes)
+            .collect();
+        Chunk::new(ChunkType::from_str("cHRM").unwrap(), data)
+    }
+}
+
+impl Display for Chromaticities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "white {} red {} green {} blue {}", self.white, self.red, self.green, self.blue)
+    }
+}
+
+impl TryFrom<&Chunk> for RenderingIntent {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        match chunk_data(chunk, b"sRGB")? {
+            [0] => Ok(RenderingIntent::Perceptual),
+            [1] => Ok(RenderingIntent::RelativeColorimetric),
+            [2] => Ok(RenderingIntent::Saturation),
+            [3] => Ok(RenderingIntent::AbsoluteColorimetric),
+            _ => Err(invalid("sRGB", "expected a rendering intent from 0 to 3"))
+        }
+    }
+}
+
+impl From<RenderingIntent> for Chunk {
+    fn from(value: RenderingIntent) -> Self {
+        Chunk::new(ChunkType::from_str("sRGB").unwrap(), vec![value as u8])
+    }
+}
+
+impl Display for RenderingIntent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            RenderingIntent::Perceptual => "perceptual",
+            RenderingIntent::RelativeColorimetric => "relative colorimetric",
+            RenderingIntent::Saturation => "saturation",
+            RenderingIntent::AbsoluteColorimetric => "absolute colorimetric"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl ColorConflict {
+    // The chunk that is dropped or rewritten to resolve the conflict.
+    pub fn chunk_type(&self) -> &'static str {
+        match self {
+            ColorConflict::IccProfileWithSrgb => "sRGB",
+            ColorConflict::SrgbGamma(_) => "gAMA",
+            ColorConflict::SrgbChromaticities(_) => "cHRM"
+        }
+    }
+
+    // What resolving the conflict does.
+    pub fn fix(&self) -> String {
+        match self {
+            ColorConflict::IccProfileWithSrgb => String::from("removed sRGB, which iCCP overrides"),
+            ColorConflict::SrgbGamma(_) => format!("set gAMA to the sRGB gamma {}", Gamma::SRGB),
+            ColorConflict::SrgbChromaticities(_) => String::from("set cHRM to the sRGB primaries")
+        }
+    }
+}
+
+impl Display for ColorConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorConflict::IccProfileWithSrgb => write!(f, "sRGB alongside iCCP; decoders without ICC support use sRGB instead"),
+            ColorConflict::SrgbGamma(gamma) => write!(f, "gAMA {} does not match sRGB, which implies {}", gamma, Gamma::SRGB),
+            ColorConflict::SrgbChromaticities(chromaticities) => {
+                write!(f, "cHRM {} does not match the sRGB primaries", chromaticities)
+            }
+        }
+    }
+}
+
+impl IccProfile {
+    pub fn new(name: &str, profile: Vec<u8>) -> Result<IccProfile, PngError> {
+        validate_keyword(name).map_err(|_| invalid("iCCP", "profile name must be a valid keyword"))?;
+        Ok(IccProfile { name: name.to_string(), profile })
+    }
+}
+
+impl TryFrom<&Chunk> for IccProfile {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = chunk_data(chunk, b"iCCP")?;
+        let separator = data.iter().position(|byte| *byte == 0).ok_or(invalid("iCCP", "missing profile name separator"))?;
+        let name = decode_latin1(&data[..separator]);
+        match &data[separator + 1..] {
+            [0, compressed @ ..] => IccProfile::new(&name, zlib::inflate(compressed)?),
+            [_, ..] => Err(invalid("iCCP", "unknown compression method")),
+            [] => Err(invalid("iCCP", "missing compression method"))
+        }
+    }
+}
+
+impl From<IccProfile> for Chunk {
+    fn from(value: IccProfile) -> Self {
+        let mut data = encode_latin1(&value.name).expect("profile name was validated");
+        data.extend([0, 0]);
+        data.extend(zlib::deflate(&value.profile, 9).expect("compressing into memory cannot fail"));
+        Chunk::new(ChunkType::from_str("iCCP").unwrap(), data)
+    }
+}
+
+impl Display for IccProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} bytes)", self.name, self.profile.len())
+    }
+}
+
+impl Png {
+    pub fn color_info(&self) -> Result<ColorInfo, PngError> {
+        Ok(ColorInfo {
+            gamma: self.chunk_as("gAMA")?,
+            chromaticities: self.chunk_as("cHRM")?,
+            rendering_intent: self.chunk_as("sRGB")?,
+            icc_profile: self.chunk_as("iCCP")?
+        })
+    }
+
+    pub fn set_gamma(&mut self, gamma: Gamma) {
+        self.place_chunk(gamma.into(), &COLOR_SPACE_BEFORE);
+    }
+
+    pub fn set_chromaticities(&mut self, chromaticities: Chromaticities) {
+        self.place_chunk(chromaticities.into(), &COLOR_SPACE_BEFORE);
+    }
+
+    pub fn set_rendering_intent(&mut self, intent: RenderingIntent) {
+        self.place_chunk(intent.into(), &COLOR_SPACE_BEFORE);
+    }
+
+    pub fn set_icc_profile(&mut self, profile: IccProfile) {
+        self.place_chunk(profile.into(), &COLOR_SPACE_BEFORE);
+    }
+
+    // Malformed color chunks are not reported here; decoders skip them. With
+    // iCCP present sRGB is dropped when resolving, so gAMA and cHRM are only
+    // checked against sRGB without one.
+    pub fn color_conflicts(&self) -> Vec<ColorConflict> {
+        let srgb: Option<RenderingIntent> = self.chunk_as("sRGB").ok().flatten();
+        let icc_profile: Option<IccProfile> = self.chunk_as("iCCP").ok().flatten();
+        let mut conflicts = Vec::new();
+        if srgb.is_none() {
+            return conflicts;
+        }
+        if icc_profile.is_some() {
+            conflicts.push(ColorConflict::IccProfileWithSrgb);
+            return conflicts;
+        }
+        if let Ok(Some(gamma)) = self.chunk_as::<Gamma>("gAMA") {
+            if gamma.0.abs_diff(Gamma::SRGB.0) > GAMMA_TOLERANCE {
+                conflicts.push(ColorConflict::SrgbGamma(gamma));
+            }
+        }
+        if let Ok(Some(chromaticities)) = self.chunk_as::<Chromaticities>("cHRM") {
+            if !chromaticities.is_near(&Chromaticities::SRGB) {
+                conflicts.push(ColorConflict::SrgbChromaticities(chromaticities));
+            }
+        }
+        conflicts
+    }
+
+    // Resolves every conflict by the spec's precedence and returns them.
+    pub fn resolve_color_conflicts(&mut self) -> Vec<ColorConflict> {
+        let conflicts = self.color_conflicts();
+        for conflict in conflicts.iter() {
+            match conflict {
+                ColorConflict::IccProfileWithSrgb => {
+                    // Only reported when there is an sRGB chunk.
+                    let _ = self.remove_chunks("sRGB");
+                },
+                ColorConflict::SrgbGamma(_) => self.set_gamma(Gamma::SRGB),
+                ColorConflict::SrgbChromaticities(_) => self.set_chromaticities(Chromaticities::SRGB)
+            }
+        }
+        conflicts
+    }
+}
+
+fn to_fixed_point(chunk_type: &str, value: f64) -> Result<u32, PngError> {
+    let scaled = (value * 100000.0).round();
+    if !(scaled > 0.0 && scaled <= u32::MAX as f64) {
+        return Err(invalid(chunk_type, "value must be positive and fit in 32 bits after scaling"));
+    }
+    Ok(scaled as u32)
+}
+
+fn chunk_data<'a>(chunk: &'a Chunk, chunk_type: &[u8; 4]) -> Result<&'a [u8], PngError> {
+    if chunk.chunk_type().bytes() != *chunk_type {
+        return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+    }
+    Ok(chunk.data())
+}
+
+fn invalid(chunk_type: &str, reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: chunk_type.to_string(), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_gamma_round_trip() {
+        let gamma = Gamma::from_f64(1.0 / 2.2).unwrap();
+        assert_eq!(gamma, Gamma(45455));
+        let chunk: Chunk = gamma.into();
+        assert_eq!(chunk.data(), [0, 0, 0xb1, 0x8f]);
+        assert_eq!(Gamma::try_from(&chunk).unwrap(), gamma);
+        assert_eq!(gamma.to_string(), "0.45455");
+        assert!(Gamma::from_f64(0.0).is_err());
+    }
+
+    #[test]
+    fn test_chromaticities_round_trip() {
+        let chunk: Chunk = Chromaticities::SRGB.into();
+        assert_eq!(chunk.length(), 32);
+        assert_eq!(&chunk.data()[..4], 31270u32.to_be_bytes());
+        assert_eq!(Chromaticities::try_from(&chunk).unwrap(), Chromaticities::SRGB);
+        assert_eq!(Chromaticity::from_f64(0.3127, 0.329).unwrap(), Chromaticities::SRGB.white);
+    }
+
+    #[test]
+    fn test_rendering_intent() {
+        let chunk: Chunk = RenderingIntent::Saturation.into();
+        assert_eq!(RenderingIntent::try_from(&chunk).unwrap(), RenderingIntent::Saturation);
+        let chunk = Chunk::new(ChunkType::from_str("sRGB").unwrap(), vec![4]);
+        assert!(RenderingIntent::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_icc_profile_round_trip() {
+        let profile = IccProfile::new("Display P3", vec![7; 500]).unwrap();
+        let chunk: Chunk = profile.clone().into();
+        assert!(chunk.data().starts_with(b"Display P3\0\0"));
+        assert!(chunk.data().len() < 100);
+        assert_eq!(IccProfile::try_from(&chunk).unwrap(), profile);
+        assert!(IccProfile::new(" bad", Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_color_info() {
+        let mut png = testing_png();
+        assert_eq!(png.color_info().unwrap(), ColorInfo::default());
+        png.set_rendering_intent(RenderingIntent::Perceptual);
+        png.set_gamma(Gamma(45455));
+        png.set_chromaticities(Chromaticities::SRGB);
+        png.set_gamma(Gamma(100000));
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "sRGB", "gAMA", "cHRM", "IDAT", "IEND"]);
+        let info = png.color_info().unwrap();
+        assert_eq!(info.gamma, Some(Gamma(100000)));
+        assert_eq!(info.rendering_intent, Some(RenderingIntent::Perceptual));
+        assert_eq!(info.icc_profile, None);
+    }
+
+    #[test]
+    fn test_color_conflicts() {
+        let mut png = testing_png();
+        png.set_gamma(Gamma(100000));
+        png.set_chromaticities(Chromaticities { white: Chromaticity { x: 31271, y: 32899 }, ..Chromaticities::SRGB });
+        assert!(png.color_conflicts().is_empty());
+        png.set_rendering_intent(RenderingIntent::Perceptual);
+        assert_eq!(png.color_conflicts(), [ColorConflict::SrgbGamma(Gamma(100000))]);
+        assert_eq!(png.color_conflicts()[0].to_string(), "gAMA 1 does not match sRGB, which implies 0.45455");
+        png.set_chromaticities(Chromaticities { white: Chromaticity { x: 34570, y: 35850 }, ..Chromaticities::SRGB });
+
+        let mut fixed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        let conflicts = fixed.resolve_color_conflicts();
+        assert_eq!(conflicts.iter().map(ColorConflict::chunk_type).collect::<Vec<_>>(), ["gAMA", "cHRM"]);
+        assert!(fixed.color_conflicts().is_empty());
+        assert_eq!(fixed.color_info().unwrap().gamma, Some(Gamma::SRGB));
+        assert_eq!(fixed.color_info().unwrap().chromaticities, Some(Chromaticities::SRGB));
+
+        png.set_icc_profile(IccProfile::new("Display P3", vec![7; 10]).unwrap());
+        assert_eq!(png.resolve_color_conflicts(), [ColorConflict::IccProfileWithSrgb]);
+        assert!(png.chunk_by_type("sRGB").is_none());
+        assert_eq!(png.color_info().unwrap().gamma, Some(Gamma(100000)));
+        assert!(png.color_conflicts().is_empty());
+    }
+}
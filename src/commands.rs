@@ -1,79 +1,1497 @@
-use std::{path::PathBuf, fs, str::FromStr};
+use std::{io::{BufReader, Write}, path::Path, str::FromStr};
 
 use clap::Parser;
 
-use crate::{args::{Cli, Commands}, png::Png, chunk::Chunk, chunk_type::ChunkType};
+use png_decode_encode::{
+    animation,
+    cancel::CancellationToken,
+    chunk::Chunk,
+    chunk_type::{ChunkType, ChunkTypeSet},
+    color::RenderingIntent,
+    conformance::{self, Edition},
+    contact_sheet::ContactSheet,
+    corrupt::{self, Corruption},
+    context::Context,
+    diff,
+    embed::{self, EmbedMode},
+    emulate::{self, Consumer},
+    encryption,
+    envelope::Envelope,
+    error::PngError,
+    extensions::PhysicalDimensions,
+    features,
+    filter::{FilterStats, FilterType},
+    fingerprint::Fingerprint,
+    idot,
+    integrity::{self, IntegrityStatus},
+    lint,
+    lsb,
+    metrics::{self, Metric},
+    optimize,
+    palette::{self, RemapMode, Rgb},
+    perceptual,
+    pixels,
+    png::{FilterAction, ParseMode, ParseOptions, Png},
+    policy::KeywordPolicy,
+    quantize,
+    reader::ChunkReader,
+    redact::{self, Rect, RedactMode},
+    repair,
+    render,
+    robust,
+    scale::{self, ScaleFilter},
+    session::{Limits, Session, WriteOptions},
+    spread::{self, Shard},
+    suggested_palette::SuggestedPalette,
+    text::{self, TextPlacement},
+    time::ModificationTime,
+    validate,
+    vendor,
+    zlib::{self, BlockType, CompressionLevel}
+};
 
-pub fn app() {
-    parse_cli();
+use crate::{args::{self, ApngCommand, Cli, Commands, Operation}, Result};
+
+pub fn app() -> Result<bool> {
+    parse_cli()
 }
 
-fn parse_cli() {
+// Runs the command line and returns whether every check passed.
+fn parse_cli() -> Result<bool> {
     let cli = Cli::parse();
+    let mut session = Session::stdio();
+    let parse_mode = if cli.normalize { ParseMode::Normalize } else { ParseMode::Preserve };
+    let compression = cli.compression.as_deref().map(CompressionLevel::from_str).transpose()?;
+    session.options = WriteOptions { stamp_software: !cli.no_software, refresh_time: cli.touch, parse_mode, compression };
+    if let Some(seed) = cli.seed {
+        session.context = Context::seeded(seed);
+    }
+    let defaults = Limits::default();
+    session.limits = Limits {
+        max_input_bytes: cli.max_input_bytes.unwrap_or(defaults.max_input_bytes),
+        max_pixels: cli.max_pixels.unwrap_or(defaults.max_pixels)
+    };
+    if let Some(path) = cli.keyword_policy {
+        session.keyword_policy = KeywordPolicy::from_str(&session.read_to_string(&path)?)?;
+    }
+    for pattern in cli.allow_keyword.iter() {
+        session.keyword_policy.allow(pattern)?;
+    }
+    for pattern in cli.deny_keyword.iter() {
+        session.keyword_policy.deny(pattern)?;
+    }
+    run(&mut session, cli.command, cli.verbose)?;
+    Ok(!session.failed)
+}
 
-    match cli.command {
-        Commands::Encode { file, chunk, message, output_file } => {
-            encode(file, chunk, message, output_file)
+fn run(session: &mut Session, command: Commands, verbose: bool) -> Result<()> {
+    match command {
+        Commands::Encode { file, chunk, message, output_file: _, integrity, spread: true, parity, envelope, .. } => {
+            let chunk_type = resolve_chunk_type(session, &chunk)?;
+            encode_spread(session, file, chunk_type, message, integrity, parity, envelope)
         },
-        Commands::Decode { file, chunk } => decode(file, chunk),
-        Commands::Remove { file, chunk } => remove(file, chunk),
-        Commands::Print { file } => print(file)
-
+        Commands::Encode { file, chunk, message, output_file, robust: true, .. } => {
+            let chunk_type = resolve_chunk_type(session, &chunk)?;
+            encode_robust(session, file, chunk_type, message, output_file)
+        },
+        Commands::Encode { file, chunk, message, output_file, integrity, spread: false, parity: _, envelope, mode, stealth, robust: false } => {
+            let chunk_type = resolve_chunk_type(session, &chunk)?;
+            encode(session, file, chunk_type, message, output_file, integrity, envelope, mode, stealth)
+        },
+        Commands::Decode { file, chunk, gather: true, .. } => decode_gather(session, file, chunk),
+        Commands::Decode { file, chunk, gather: false, inspect: true, .. } => decode_inspect(session, file, chunk),
+        Commands::Decode { file, chunk, gather: false, inspect: false, mode: Some(mode), .. } => decode_mode(session, file, chunk, mode),
+        Commands::Decode { file, chunk, gather: false, inspect: false, mode: None, robust: true } => decode_robust(session, file, chunk),
+        Commands::Decode { file, chunk, gather: false, inspect: false, mode: None, robust: false } => decode(session, file, chunk),
+        Commands::Remove { file, chunk, all, nth } => remove(session, file, chunk, all, nth),
+        Commands::Strip { file, types, vendor } => strip(session, file, types, vendor),
+        Commands::Print { file, describe, verify, hash } => print(session, file, describe, verify, hash),
+        Commands::Optimize { file, output_file, trials, json, synthesize_trns, emit_idot } => {
+            optimize(session, file, output_file, trials, json, synthesize_trns, emit_idot)
+        },
+        Commands::Verify { file, strong, profile } => verify(session, file, strong, profile),
+        Commands::Info { file } => info(session, file),
+        Commands::Metadata { file, output_file, dpi, time, histogram, suggested_palette, set, minimal_rewrite } => {
+            match minimal_rewrite {
+                true => set_text_in_place(session, file, output_file, set),
+                false => metadata(session, file, output_file, dpi, time, histogram, suggested_palette, set)
+            }
+        },
+        Commands::Fingerprint { file } => fingerprint(session, file),
+        Commands::Signature { file } => {
+            let signature = perceptual::Signature::from_image(&pixels::decode_rgba(&session.read_png(&file)?)?)?;
+            writeln!(session.logger, "{}", signature.to_json())?;
+            Ok(())
+        },
+        Commands::Convert { file, output_file, palette, exact, nearest, colorkey } => {
+            let mode = match (exact, nearest) {
+                (true, _) => Some(RemapMode::Exact),
+                (_, true) => Some(RemapMode::Nearest),
+                _ => None
+            };
+            convert(session, file, output_file, palette, mode, colorkey)
+        },
+        Commands::Quantize { file, output_file, colors } => quantize(session, file, output_file, colors),
+        Commands::Scale { file, output_file, factor, nearest, scale2x } => {
+            let scale_filter = match (nearest, scale2x) {
+                (true, _) => Some(ScaleFilter::Nearest),
+                (_, true) => Some(ScaleFilter::Scale2x),
+                _ => None
+            };
+            scale(session, file, output_file, factor, scale_filter)
+        },
+        Commands::Analyze { file, filters, zlib } => analyze(session, file, filters, zlib, verbose),
+        Commands::Compare { file, other_file, metric, min } => compare(session, file, other_file, metric, min),
+        Commands::Diff { file, other_file, html } => diff(session, file, other_file, html),
+        Commands::EncryptImage { file, output_file, password } => encrypt_image(session, file, output_file, password),
+        Commands::DecryptImage { file, output_file, password } => decrypt_image(session, file, output_file, password),
+        Commands::HidePixels { file, message, output_file, password } => hide_pixels(session, file, message, output_file, password),
+        Commands::RevealPixels { file, password } => {
+            let message = lsb::extract(&session.read_png(&file)?, password.as_deref())?;
+            writeln!(session.logger, "{:?}", std::str::from_utf8(&message).map_err(PngError::from)?)?;
+            Ok(())
+        },
+        Commands::Redact { file, output_file, rect, pixelate } => redact(session, file, output_file, rect, pixelate),
+        Commands::Lint { file, strip_legacy } => lint(session, file, strip_legacy),
+        Commands::Render { file, output_file, emulate } => render(session, file, output_file, emulate),
+        Commands::Doctor { file, output_file, fix } => doctor(session, file, output_file, fix),
+        Commands::Repair { file, output_file, drop_unreadable } => repair(session, file, output_file, drop_unreadable),
+        Commands::Generate { output_file, corrupt, from } => generate(session, output_file, corrupt, from),
+        Commands::Features => {
+            writeln!(session.logger, "{}", features::to_json())?;
+            Ok(())
+        },
+        Commands::Chain { input, output, steps } => {
+            // main adds its own "error: " prefix.
+            let operations = args::parse_chain(&steps).map_err(|err| err.to_string().trim_start_matches("error: ").trim_end().to_string())?;
+            chain(session, input, output, operations)
+        },
+        Commands::Apng { command: ApngCommand::ContactSheet { file, columns, out } } => {
+            apng_contact_sheet(session, file, columns, out)
+        },
+        Commands::Apng { command: ApngCommand::Retime { file, output_file, fps } } => apng_retime(session, file, output_file, fps),
+        Commands::Apng { command: ApngCommand::Loops { file, count, output_file } } => apng_loops(session, file, count, output_file)
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn encode(
+    session: &mut Session,
     file: String, 
-    chunk: String, 
+    chunk_type: ChunkType, 
     message: String, 
-    output_file: Option<String>
-    ) {
-    let file_raw = read_file(file).expect("could not read file");
-    let mut png_from_file = Png::try_from(file_raw.as_ref()).expect("cannot create PNG from file");
-    let message_as_bytes: Vec<u8> = message.as_bytes().try_into().expect("could not create bytes from message"); 
-    let chunk = Chunk::new(ChunkType::from_str(&chunk).expect(""), message_as_bytes);
-    png_from_file.append_chunk(chunk);
+    output_file: Option<String>,
+    integrity: bool,
+    envelope: bool,
+    mode: Option<String>,
+    stealth: bool
+    ) -> Result<()> {
+    let mut png_from_file = session.edit_png(&file)?;
+    let mode = match mode.as_deref() {
+        Some(mode) if mode.eq_ignore_ascii_case("auto") => {
+            // The envelope is the same size whatever mode it records.
+            let payload_len = Envelope::new().wrap(message.as_bytes()).len();
+            let chosen = embed::choose_mode(&png_from_file, payload_len, stealth);
+            writeln!(session.logger, "[MODE] {}", chosen)?;
+            Some(chosen)
+        },
+        Some(mode) => Some(EmbedMode::from_str(mode)?),
+        None => None
+    };
+    // Decode only takes a trailer or pixels for a message when the envelope
+    // says encode put one there.
+    let message_as_bytes: Vec<u8> = match (envelope, mode) {
+        (false, None) => message.as_bytes().to_vec(),
+        (_, mode) => Envelope { mode: mode.unwrap_or_default(), ..Envelope::new() }.wrap(message.as_bytes())
+    };
+    let mut trailer = Vec::new();
+    match mode.unwrap_or_default() {
+        EmbedMode::Chunk => png_from_file.append_chunk(Chunk::new(chunk_type, message_as_bytes)),
+        EmbedMode::Trailer => trailer = message_as_bytes,
+        EmbedMode::Pixels => png_from_file = lsb::embed(&png_from_file, &message_as_bytes, None, session.context.rng())?
+    }
+    if session.options.stamp_software {
+        png_from_file.set_software(text::SOFTWARE)?;
+    }
+    if integrity {
+        integrity::append_integrity_chunk(&mut png_from_file);
+    }
+    session.keyword_policy.check(&png_from_file)?;
     if let Some(file) = output_file {
-        let output_file_path = PathBuf::from_str(file.as_str())
-            .expect("cannot parse string to path");
-        fs::write(output_file_path, png_from_file.as_bytes())
-            .expect("cannot write data to file");
+        let mut bytes = png_from_file.as_bytes();
+        bytes.extend(trailer);
+        session.write(&file, &bytes)?;
+    }
+    writeln!(session.logger, "[PNG CREATED] {:?}", png_from_file)?;
+    Ok(())
+}
 
+fn encode_robust(session: &mut Session, file: String, chunk_type: ChunkType, message: String, output_file: Option<String>) -> Result<()> {
+    let png_from_file = session.edit_png(&file)?;
+    let mut robust = robust::embed(&png_from_file, message.as_bytes(), chunk_type, session.context.rng())?;
+    session.write_png(&mut robust, &output_file.unwrap_or(file))?;
+    writeln!(session.logger, "[ROBUST] {} bytes in a {} chunk and in the pixels", message.len(), chunk_type)?;
+    Ok(())
+}
+
+fn encode_spread(
+    session: &mut Session,
+    pattern: String,
+    chunk_type: ChunkType,
+    message: String,
+    integrity: bool,
+    parity: usize,
+    envelope: bool
+    ) -> Result<()> {
+    let files = session.expand_pattern(&pattern)?;
+    let mut carriers = files.iter().map(|file| session.edit_png(file)).collect::<std::result::Result<Vec<Png>, PngError>>()?;
+    let payload = match envelope {
+        true => Envelope { sharded: true, ..Envelope::new() }.wrap(message.as_bytes()),
+        false => message.as_bytes().to_vec()
+    };
+    let manifest = spread::spread(&mut carriers, chunk_type, &payload, parity)?;
+    for (carrier, file) in carriers.iter_mut().zip(&files) {
+        if integrity {
+            integrity::append_integrity_chunk(carrier);
+        }
+        session.write_png(carrier, file)?;
     }
-    println!("[PNG CREATED] {:?}", png_from_file);
+    writeln!(session.logger, "[SPREAD] {}", manifest)?;
+    Ok(())
 }
 
-fn decode(file: String, chunk: String) {
-    let file_raw = read_file(file).expect("could not read file");
-    let png_from_file = Png::try_from(file_raw.as_ref()).expect("cannot create PNG from file");
-    let chunk_raw = &png_from_file.chunk_by_type(chunk.as_str())
-        .expect("cannot parse chunk type")
-        .message_bytes;
+fn decode(session: &mut Session, file: String, chunk: String) -> Result<()> {
+    let png_from_file = session.read_png(&file)?;
+    let chunk_raw = png_from_file.chunk_by_type(chunk.as_str())
+        .ok_or(PngError::ChunkNotFound(chunk))?;
 
-    println!("{:?}", String::from_utf8(chunk_raw.to_owned()).expect("could not parse string from chunk"));
+    writeln!(session.logger, "{:?}", std::str::from_utf8(Envelope::open(chunk_raw.data())?).map_err(PngError::from)?)?;
+    Ok(())
 }
 
-fn remove(file: String, chunk: String) {
-    let file_raw = read_file(file.clone()).expect("could not read file");
-    let mut png_from_file = Png::try_from(file_raw.as_ref()).expect("cannot create PNG from file");
-    match png_from_file.remove_chunk(&chunk) {
-        Ok(result) => {
-            fs::write(file, png_from_file.as_bytes()).unwrap();
-            println!("[REMOVED] {:?}", result);
-        },
-        Err(err) => println!("{:?}", err)
+// Reads a message `encode --mode` left in the chunk, after IEND or in the
+// pixels. The file may have a trailer, so it is split before parsing.
+fn decode_mode(session: &mut Session, file: String, chunk: String, mode: String) -> Result<()> {
+    let modes = match mode.eq_ignore_ascii_case("auto") {
+        true => EmbedMode::ALL.to_vec(),
+        false => vec![EmbedMode::from_str(&mode)?]
+    };
+    let bytes = session.read(&file)?;
+    let (png_from_file, trailer) = embed::split_trailer(&bytes)?;
+    session.check_pixel_limit(&png_from_file)?;
+    let chunk_data = png_from_file.chunk_by_type(chunk.as_str()).map(|chunk| chunk.data());
+    let pixels = match modes.contains(&EmbedMode::Pixels) {
+        true => lsb::extract(&png_from_file, None).ok(),
+        false => None
+    };
+    let message = modes
+        .into_iter()
+        .find_map(|mode| embed::find_message(mode, chunk_data, trailer, pixels.as_deref()))
+        .ok_or(PngError::InvalidImageData("no message found"))?;
+    writeln!(session.logger, "{:?}", std::str::from_utf8(message).map_err(PngError::from)?)?;
+    Ok(())
+}
+
+fn decode_robust(session: &mut Session, file: String, chunk: String) -> Result<()> {
+    let png_from_file = session.read_png(&file)?;
+    let (message, source) = robust::extract(&png_from_file, parse_chunk_type(&chunk)?)?;
+    writeln!(session.logger, "[FOUND] in the {}", source)?;
+    writeln!(session.logger, "{:?}", std::str::from_utf8(&message).map_err(PngError::from)?)?;
+    Ok(())
+}
+
+fn decode_gather(session: &mut Session, pattern: String, chunk: String) -> Result<()> {
+    let carriers = session.expand_pattern(&pattern)?.iter().map(|file| session.read_png(file)).collect::<std::result::Result<Vec<Png>, PngError>>()?;
+    let payload = spread::gather(&carriers, parse_chunk_type(&chunk)?)?;
+    writeln!(session.logger, "{:?}", std::str::from_utf8(Envelope::open(&payload)?).map_err(PngError::from)?)?;
+    Ok(())
+}
+
+// Describes how a message was stored without decrypting or reassembling it.
+fn decode_inspect(session: &mut Session, file: String, chunk: String) -> Result<()> {
+    let png_from_file = session.read_png(&file)?;
+    let chunk_raw = png_from_file.chunk_by_type(chunk.as_str())
+        .ok_or(PngError::ChunkNotFound(chunk))?;
+    if *chunk_raw.chunk_type() == encryption::ENCRYPTED_CHUNK_TYPE {
+        writeln!(session.logger, "{:<14} yes, image data (format {})", "encrypted:", chunk_raw.data().first().copied().unwrap_or(0))?;
+        return Ok(());
+    }
+    let Ok(shard) = Shard::parse(chunk_raw) else {
+        match Envelope::inspect(chunk_raw.data())? {
+            Some((envelope, _)) => writeln!(session.logger, "{}", envelope)?,
+            None => writeln!(session.logger, "{:<14} none ({} bytes)", "envelope:", chunk_raw.length())?
+        }
+        return Ok(());
+    };
+    writeln!(session.logger, "{:<14} {} of {}", "shard:", shard.index + 1, shard.manifest.total_shards)?;
+    writeln!(session.logger, "{:<14} {}", "spread:", shard.manifest)?;
+    // The envelope header is at the start of the payload, so only the first
+    // shard has it, and only in full when the shard is long enough.
+    match (shard.index, Envelope::inspect(&shard.data)) {
+        (0, Ok(Some((envelope, _)))) => writeln!(session.logger, "{}", envelope)?,
+        (0, Ok(None)) => writeln!(session.logger, "{:<14} none", "envelope:")?,
+        (0, Err(_)) => writeln!(session.logger, "{:<14} continues in the next shard", "envelope:")?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn remove(session: &mut Session, file: String, chunk: String, all: bool, nth: Option<usize>) -> Result<()> {
+    let mut png_from_file = session.edit_png(&file)?;
+    if all {
+        let removed = png_from_file.remove_chunks(&chunk)?;
+        session.write_png(&mut png_from_file, &file)?;
+        writeln!(session.logger, "[REMOVED] {} chunk(s) of type {}", removed.len(), chunk)?;
+        return Ok(());
+    }
+    let result = match nth {
+        Some(n) => png_from_file.remove_nth_chunk(&chunk, n)?,
+        None => png_from_file.remove_chunk(&chunk)?
+    };
+    session.write_png(&mut png_from_file, &file)?;
+    writeln!(session.logger, "[REMOVED] {:?}", result)?;
+    Ok(())
+}
+
+fn strip(session: &mut Session, file: String, types: Option<String>, vendor: bool) -> Result<()> {
+    let mut types: ChunkTypeSet = types.as_deref().map(str::parse).transpose()?.unwrap_or_default();
+    if vendor {
+        types = types.union(&vendor::VENDOR_CHUNK_TYPES)?;
+    }
+    if types.iter().any(|chunk_type| chunk_type.is_critical()) {
+        return Err(PngError::InvalidArgument("critical chunks cannot be stripped").into());
+    }
+    let mut png_from_file = session.edit_png(&file)?;
+    let stripped = png_from_file.strip_chunks(&types);
+    session.write_png(&mut png_from_file, &file)?;
+    writeln!(session.logger, "[STRIPPED] {} chunk(s) of type {}", stripped, types)?;
+    Ok(())
+}
+
+fn print(session: &mut Session, file: String, describe: bool, verify: bool, hash: bool) -> Result<()> {
+    let mut chunks = ChunkReader::new(BufReader::new(session.open(&file)?))?;
+    if verify {
+        chunks = chunks.without_crc_check();
+    }
+    for chunk in chunks {
+        let chunk = chunk?;
+        write!(session.logger, "{} {:>10} bytes  crc {:08x}", chunk.chunk_type(), chunk.length(), chunk.crc())?;
+        if verify {
+            let computed = chunk.computed_crc();
+            let status = match (computed == chunk.crc(), session.color) {
+                (true, _) => "ok".to_string(),
+                (false, true) => "\x1b[31mMISMATCH\x1b[0m".to_string(),
+                (false, false) => "MISMATCH".to_string()
+            };
+            session.failed |= computed != chunk.crc();
+            write!(session.logger, "  computed {:08x}  {}", computed, status)?;
+        }
+        writeln!(session.logger)?;
+        if hash {
+            writeln!(session.logger, "      blake3 {}", blake3::hash(chunk.data()).to_hex())?;
+        }
+        if let Some(standard) = chunk.chunk_type().standard().filter(|_| describe) {
+            writeln!(session.logger, "      {}, {}", standard.description, standard.placement)?;
+        } else if let Some(vendor) = vendor::vendor_type(*chunk.chunk_type()).filter(|_| describe) {
+            writeln!(session.logger, "      {} {}", vendor.vendor, vendor.description)?;
+        }
+        // With --describe, known payloads are shown decoded instead of sniffed.
+        let rendered = describe
+            .then(|| render::decode_payload(&chunk))
+            .flatten()
+            .unwrap_or_else(|| render::render_payload(chunk.data()));
+        writeln!(session.logger, "      {}", rendered.replace('\n', "\n      "))?;
+    }
+    Ok(())
+}
+
+fn optimize(
+    session: &mut Session,
+    file: String,
+    output_file: Option<String>,
+    trials: bool,
+    json: bool,
+    synthesize_trns: bool,
+    emit_idot: bool
+    ) -> Result<()> {
+    let png_from_file = session.edit_png(&file)?;
+    let synthesized = match synthesize_trns {
+        true => optimize::synthesize_trns(&png_from_file)?,
+        false => None
+    };
+    // The synthesized image data is unfiltered, so filters are always tried for it.
+    let (filters, levels): (&[FilterType], &[u32]) = match (trials, synthesized.is_some()) {
+        (true, _) => (&FilterType::ALL, &optimize::DEFAULT_LEVELS),
+        (false, true) => (&FilterType::ALL, &[9]),
+        (false, false) => (&[], &[9])
+    };
+    // An explicit --compression replaces the levels that would be tried.
+    let compression = session.options.compression.map(|level| [level.level()]);
+    let levels = compression.as_ref().map_or(levels, |level| level.as_slice());
+    let (mut optimized, mut report) = optimize::run_trials(synthesized.as_ref().unwrap_or(&png_from_file), filters, levels)?;
+    report.original_size = png_from_file.as_bytes().len();
+    // Plain recompression that does not beat the file leaves it as it was.
+    let keep_original = synthesized.is_none() && !emit_idot && report.best().size >= report.original_size;
+    if !json {
+        match &synthesized {
+            Some(png) => writeln!(session.logger, "[TRNS] {} -> {}", png_from_file.header()?, png.header()?)?,
+            None if synthesize_trns => writeln!(session.logger, "[TRNS] alpha cannot be replaced by tRNS")?,
+            None => {}
+        }
+    }
+
+    if json {
+        writeln!(session.logger, "{}", report.to_json())?;
+    } else {
+        for (i, trial) in report.trials.iter().enumerate() {
+            let marker = if i == report.best { "*" } else { " " };
+            writeln!(session.logger, "{} {:<8} level {} {:>10} bytes {:>8.2} ms",
+                     marker,
+                     trial.filter_name(),
+                     trial.level,
+                     trial.size,
+                     trial.elapsed.as_secs_f64() * 1000.0)?;
+        }
+        match keep_original {
+            true => writeln!(session.logger, "[OPTIMIZED] {} bytes, kept the original", report.original_size)?,
+            false => writeln!(session.logger, "[OPTIMIZED] {} -> {} bytes", report.original_size, report.best().size)?
+        }
+    }
+    if keep_original {
+        optimized = png_from_file;
+    }
+    if emit_idot {
+        optimized = idot::with_idot(&optimized, report.best().level)?;
+        if !json {
+            writeln!(session.logger, "[IDOT] {}, {} bytes", optimized.idot()?.unwrap(), optimized.as_bytes().len())?;
+        }
+    }
+
+    session.write_png_with(&mut optimized, &output_file.unwrap_or(file), WriteOptions { compression: None, ..session.options })?;
+    Ok(())
+}
+
+// Reports every chunk with its byte offset, and fails on structural problems
+// before checking the integrity chunk.
+fn verify(session: &mut Session, file: String, strong: bool, profile: Option<String>) -> Result<()> {
+    let edition = profile.as_deref().map(Edition::from_str).transpose()?;
+    let bytes = session.read(&file)?;
+    let validation = validate::validate(&bytes);
+    writeln!(session.logger, "{:>10}  signature  {}", 0, if validation.signature_valid { "ok" } else { "FAILED" })?;
+    for chunk in validation.chunks.iter() {
+        writeln!(session.logger, "{}", chunk)?;
+    }
+    for (offset, problem) in validation.problems.iter() {
+        writeln!(session.logger, "{:>10}  FAILED: {}", offset, problem)?;
+    }
+    if !validation.passed() {
+        writeln!(session.logger, "[FAILED] {}: {} problem(s)", file, validation.problem_count())?;
+        session.failed = true;
+        return Ok(());
+    }
+    let png_from_file = Png::try_from(bytes.as_slice())?;
+    if strong {
+        match integrity::verify_integrity(&png_from_file) {
+            IntegrityStatus::Valid => {},
+            status => {
+                writeln!(session.logger, "[FAILED] {} integrity check: {:?}", file, status)?;
+                session.failed = true;
+                return Ok(());
+            }
+        }
+    }
+    if let Some(edition) = edition {
+        let problems = conformance::check(&png_from_file, edition);
+        for problem in problems.iter() {
+            writeln!(session.logger, "{:>10}  {}", edition, problem)?;
+        }
+        let highest = conformance::highest_edition(&png_from_file);
+        writeln!(session.logger, "{:<14} {}", "conforms to:", highest.map_or(String::from("no edition"), |edition| edition.to_string()))?;
+        if !problems.is_empty() {
+            writeln!(session.logger, "[FAILED] {} does not conform to {}: {} problem(s)", file, edition, problems.len())?;
+            session.failed = true;
+            return Ok(());
+        }
+    }
+    writeln!(session.logger, "[VERIFIED] {}", file)?;
+    Ok(())
+}
+
+// Nothing here needs the image data, so it is skipped rather than read.
+fn info(session: &mut Session, file: String) -> Result<()> {
+    let options = ParseOptions::new().chunk_filter(|header| match &header.chunk_type.bytes() {
+        b"IDAT" | b"fdAT" => FilterAction::Skip,
+        _ => FilterAction::Keep
+    });
+    let png_from_file = session.read_png_with(&file, &options)?;
+    writeln!(session.logger, "{:<14} {}", "image:", png_from_file.header()?)?;
+    if let Some(animation) = png_from_file.animation_control()? {
+        writeln!(session.logger, "{:<14} {}", "animation:", animation)?;
+    }
+    if let Some(palette) = png_from_file.palette()? {
+        writeln!(session.logger, "{:<14} {} colors", "palette:", palette.len())?;
+    }
+    if let Some(histogram) = png_from_file.histogram()? {
+        writeln!(session.logger, "{:<14} {}", "histogram:", histogram)?;
+    }
+    for palette in png_from_file.suggested_palettes()? {
+        writeln!(session.logger, "{:<14} {}", "suggested:", palette)?;
+    }
+    if let Some(transparency) = png_from_file.transparency()? {
+        writeln!(session.logger, "{:<14} {}", "transparency:", transparency)?;
+    }
+    if let Some(background) = png_from_file.background_color()? {
+        writeln!(session.logger, "{:<14} {}", "background:", background)?;
+    }
+    if let Some(significant_bits) = png_from_file.significant_bits()? {
+        writeln!(session.logger, "{:<14} {}", "significant:", significant_bits)?;
+    }
+    let color_info = png_from_file.color_info()?;
+    if let Some(gamma) = color_info.gamma {
+        writeln!(session.logger, "{:<14} {}", "gamma:", gamma)?;
+    }
+    if let Some(chromaticities) = color_info.chromaticities {
+        writeln!(session.logger, "{:<14} {}", "chromaticity:", chromaticities)?;
+    }
+    if let Some(intent) = color_info.rendering_intent {
+        writeln!(session.logger, "{:<14} {}", "srgb:", intent)?;
+    }
+    if let Some(profile) = color_info.icc_profile {
+        writeln!(session.logger, "{:<14} {}", "icc profile:", profile)?;
+    }
+    for (keyword, text) in png_from_file.text_entries() {
+        writeln!(session.logger, "{:<14} {}", format!("{}:", keyword), text)?;
+    }
+    if let Some(dimensions) = png_from_file.physical_dimensions()? {
+        writeln!(session.logger, "{:<14} {}", "resolution:", dimensions)?;
+    }
+    if let Some(offset) = png_from_file.image_offset()? {
+        writeln!(session.logger, "{:<14} {}", "offset:", offset)?;
+    }
+    if let Some(scale) = png_from_file.physical_scale()? {
+        writeln!(session.logger, "{:<14} {}", "scale:", scale)?;
+    }
+    if let Some(mode) = png_from_file.stereo_mode()? {
+        writeln!(session.logger, "{:<14} {}", "stereo:", mode)?;
+    }
+    if let Some(time) = png_from_file.modification_time()? {
+        writeln!(session.logger, "{:<14} {}", "modified:", time)?;
+    }
+    if let Some(exif) = png_from_file.exif()? {
+        let camera: Vec<String> = [exif.make, exif.model].into_iter().flatten().collect();
+        if !camera.is_empty() {
+            writeln!(session.logger, "{:<14} {}", "camera:", camera.join(" "))?;
+        }
+        if let Some(orientation) = exif.orientation {
+            writeln!(session.logger, "{:<14} {}", "orientation:", orientation)?;
+        }
+        if let Some(taken) = exif.date_time_original.or(exif.date_time) {
+            writeln!(session.logger, "{:<14} {}", "taken:", taken)?;
+        }
+        if let Some(gps) = exif.gps {
+            writeln!(session.logger, "{:<14} {}", "gps:", gps)?;
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn metadata(
+    session: &mut Session,
+    file: String,
+    output_file: Option<String>,
+    dpi: Option<f64>,
+    time: Option<String>,
+    histogram: bool,
+    suggested_palette: Option<String>,
+    set: Vec<String>
+    ) -> Result<()> {
+    let mut png_from_file = session.edit_png(&file)?;
+    for entry in set.iter() {
+        let (keyword, text) = parse_text_entry(entry)?;
+        png_from_file.set_text(keyword, text)?;
+        writeln!(session.logger, "[UPDATED] text {}", keyword)?;
+    }
+    if let Some(dpi) = dpi {
+        let dimensions = PhysicalDimensions::from_dpi(dpi)?;
+        png_from_file.set_physical_dimensions(dimensions);
+        writeln!(session.logger, "[UPDATED] resolution {}", dimensions)?;
+    }
+    if let Some(time) = time {
+        let time = ModificationTime::from_str(&time)?;
+        png_from_file.set_modification_time(time);
+        writeln!(session.logger, "[UPDATED] modified {}", time)?;
+    }
+    if histogram {
+        let histogram = png_from_file.regenerate_histogram()?;
+        writeln!(session.logger, "[UPDATED] histogram {}", histogram)?;
+    }
+    if let Some(path) = suggested_palette {
+        let name = Path::new(&path).file_stem().map_or(path.clone(), |stem| stem.to_string_lossy().into_owned());
+        let palette = SuggestedPalette::from_colors(&name, &palette::parse_gpl(&session.read_to_string(&path)?)?)?;
+        writeln!(session.logger, "[UPDATED] suggested palette {}", palette)?;
+        png_from_file.add_suggested_palette(palette)?;
+    }
+    session.write_png(&mut png_from_file, &output_file.unwrap_or(file))?;
+    Ok(())
+}
+
+// Leaves tIME, Software and the image data alone too, since any of them could
+// change the size of chunks before the text.
+fn set_text_in_place(session: &mut Session, file: String, output_file: Option<String>, set: Vec<String>) -> Result<()> {
+    let mut png_from_file = session.edit_png(&file)?;
+    png_from_file.set_refresh_time(false);
+    for entry in set.iter() {
+        let (keyword, text) = parse_text_entry(entry)?;
+        let placement = match png_from_file.set_text_in_place(keyword, text)? {
+            TextPlacement::InPlace => "in place",
+            TextPlacement::AfterImageData => "after the image data"
+        };
+        writeln!(session.logger, "[UPDATED] text {} ({})", keyword, placement)?;
+    }
+    let options = WriteOptions { stamp_software: false, compression: None, ..session.options };
+    session.write_png_with(&mut png_from_file, &output_file.unwrap_or(file), options)?;
+    Ok(())
+}
+
+fn parse_text_entry(entry: &str) -> Result<(&str, &str)> {
+    entry.split_once('=').ok_or_else(|| PngError::InvalidArgument("text entries are written KEYWORD=TEXT").into())
+}
+
+fn generate(session: &mut Session, output_file: String, corrupt: String, from: String) -> Result<()> {
+    let corruption = Corruption::from_str(&corrupt)?;
+    let png_from_file = session.read_png(&from)?;
+    // The corrupted bytes may not parse, so the policy is checked on the source.
+    session.keyword_policy.check(&png_from_file)?;
+    let (bytes, offset) = corrupt::corrupt(&png_from_file, corruption)?;
+    session.write(&output_file, &bytes)?;
+    writeln!(session.logger, "[GENERATED] {} at offset {}, written to {}", corruption, offset, output_file)?;
+    Ok(())
+}
+
+fn diff(session: &mut Session, file: String, other_file: String, html: Option<String>) -> Result<()> {
+    let (before, after) = (session.read_png(&file)?, session.read_png(&other_file)?);
+    for chunk_diff in diff::diff_chunks(&before, &after) {
+        writeln!(session.logger, "{}", chunk_diff)?;
+    }
+    let (before_image, after_image) = (pixels::decode_rgba(&before)?, pixels::decode_rgba(&after)?);
+    match metrics::differing_pixels(&before_image, &after_image) {
+        Ok(differing) => writeln!(session.logger, "differing pixels: {} of {}", differing, after_image.pixels.len())?,
+        Err(_) => writeln!(session.logger, "image sizes differ: {}x{} and {}x{}", before_image.width, before_image.height, after_image.width, after_image.height)?
+    }
+    if let Some(path) = html {
+        let report = diff::html_report(&before, &after, (&file, &other_file))?;
+        session.write(&path, report.as_bytes())?;
+        writeln!(session.logger, "[REPORT] written to {}", path)?;
+    }
+    Ok(())
+}
+
+fn fingerprint(session: &mut Session, file: String) -> Result<()> {
+    let png_from_file = session.read_png(&file)?;
+    let fingerprint = Fingerprint::from_png(&png_from_file);
+    writeln!(session.logger, "chunk order: {}", fingerprint.chunk_order.join(" "))?;
+    if let Some(header) = fingerprint.zlib_header {
+        writeln!(session.logger, "zlib level:  {}", ["fastest", "fast", "default", "maximum"][header.level as usize])?;
+    }
+    let filters: Vec<String> = FilterType::ALL
+        .iter()
+        .map(|filter_type| format!("{} {}", filter_type, fingerprint.filter_counts[*filter_type as usize]))
+        .collect();
+    writeln!(session.logger, "filters:     {}", filters.join(", "))?;
+    for evidence in fingerprint.evidence.iter() {
+        writeln!(session.logger, "  +{} {}: {}", evidence.weight, evidence.encoder, evidence.reason)?;
+    }
+    match fingerprint.scores().first() {
+        Some((encoder, score)) => writeln!(session.logger, "[GUESS] {} (score {})", encoder, score)?,
+        None => writeln!(session.logger, "[GUESS] unknown")?
+    }
+    Ok(())
+}
+
+fn convert(
+    session: &mut Session,
+    file: String,
+    output_file: Option<String>,
+    palette: Option<String>,
+    mode: Option<RemapMode>,
+    colorkey: Option<String>
+) -> Result<()> {
+    let png_from_file = session.edit_png(&file)?;
+    let output_file = output_file.unwrap_or(file);
+    if let Some(color) = colorkey {
+        let color = Rgb::from_str(&color)?;
+        let (mut keyed, count) = png_from_file.with_color_key(color)?;
+        session.write_png(&mut keyed, &output_file)?;
+        writeln!(session.logger, "[CONVERTED] {} pixel(s) of {} made transparent", count, color)?;
+        return Ok(());
+    }
+    let palette = palette.ok_or(PngError::InvalidArgument("convert needs --palette or --colorkey"))?;
+    let colors = palette::parse_gpl(&session.read_to_string(&palette)?)?;
+    let mode = mode.ok_or(PngError::InvalidArgument("convert --palette needs --exact or --nearest"))?;
+    let mut converted = palette::remap(&png_from_file, &colors, mode)?;
+    session.write_png(&mut converted, &output_file)?;
+    writeln!(session.logger, "[CONVERTED] remapped onto {} palette colors", colors.len())?;
+    Ok(())
+}
+
+fn quantize(session: &mut Session, file: String, output_file: Option<String>, colors: usize) -> Result<()> {
+    let png_from_file = session.edit_png(&file)?;
+    let level = session.options.compression.unwrap_or_default();
+    let mut quantized = quantize::quantize_png(&png_from_file, colors, level)?;
+    // Already compressed at the requested level.
+    session.write_png_with(&mut quantized, &output_file.unwrap_or(file), WriteOptions { compression: None, ..session.options })?;
+    let palette_len = quantized.palette()?.map_or(0, |palette| palette.len());
+    writeln!(session.logger, "[QUANTIZED] {} -> {} palette colors", png_from_file.header()?, palette_len)?;
+    Ok(())
+}
+
+fn repair(session: &mut Session, file: String, output_file: String, drop_unreadable: bool) -> Result<()> {
+    let (mut repaired, actions) = repair::repair(&session.read(&file)?, drop_unreadable)?;
+    for action in actions.iter() {
+        writeln!(session.logger, "  {}", action)?;
+    }
+    session.write_png(&mut repaired, &output_file)?;
+    match actions.len() {
+        0 => writeln!(session.logger, "[CLEAN] nothing to repair, copied to {}", output_file)?,
+        count => writeln!(session.logger, "[REPAIRED] {} action(s), written to {}", count, output_file)?
+    }
+    Ok(())
+}
+
+// Applies `operations` in order to one image, which is only read and written
+// once. Nothing is reported when the image goes to standard output.
+fn chain(session: &mut Session, input: String, output: String, operations: Vec<Operation>) -> Result<()> {
+    let mut png = session.edit_png(&input)?;
+    for operation in operations.iter() {
+        png = match operation {
+            Operation::Strip { types } => {
+                let types: ChunkTypeSet = types.parse()?;
+                if types.iter().any(|chunk_type| chunk_type.is_critical()) {
+                    return Err(PngError::InvalidArgument("critical chunks cannot be stripped").into());
+                }
+                png.strip_chunks(&types);
+                png
+            },
+            Operation::Remove { chunk } => {
+                png.remove_chunk(&parse_chunk_type(chunk)?.to_string())?;
+                png
+            },
+            Operation::Encode { chunk, message } => {
+                png.append_chunk(Chunk::new(resolve_chunk_type(session, chunk)?, message.as_bytes().to_vec()));
+                png
+            },
+            Operation::Normalize => {
+                png.normalize();
+                png
+            },
+            Operation::Optimize { level, trials } => {
+                if *level > 9 {
+                    return Err(PngError::InvalidArgument("compression level must be 0 to 9").into());
+                }
+                let filters: &[FilterType] = if *trials { &FilterType::ALL } else { &[] };
+                optimize::run_trials(&png, filters, &[*level])?.0
+            },
+            Operation::Quantize { colors } => {
+                quantize::quantize_png(&png, *colors, session.options.compression.unwrap_or_default())?
+            }
+        };
+    }
+    session.write_png(&mut png, &output)?;
+    if output != "-" {
+        writeln!(session.logger, "[CHAINED] {} operation(s) -> {}", operations.len(), output)?;
+    }
+    Ok(())
+}
+
+fn scale(session: &mut Session, file: String, output_file: Option<String>, factor: u32, scale_filter: Option<ScaleFilter>) -> Result<()> {
+    let scale_filter = scale_filter.ok_or(PngError::InvalidArgument("scale needs --nearest or --scale2x"))?;
+    let png_from_file = session.edit_png(&file)?;
+    let mut scaled = scale::scale(&png_from_file, factor, scale_filter)?;
+    session.write_png(&mut scaled, &output_file.unwrap_or(file))?;
+    writeln!(session.logger, "[SCALED] {} -> {}", png_from_file.header()?, scaled.header()?)?;
+    Ok(())
+}
+
+// Runs every analysis when none is selected.
+fn analyze(session: &mut Session, file: String, filters: bool, zlib: bool, verbose: bool) -> Result<()> {
+    let png_from_file = session.read_png(&file)?;
+    let all = !filters && !zlib;
+    if filters || all {
+        let stats = FilterStats::from_png(&png_from_file)?;
+        let counts = stats.counts();
+        writeln!(session.logger, "filters: {} scanlines, {} filter changes", stats.rows.len(), stats.switches())?;
+        for filter_type in FilterType::ALL {
+            let count = counts[filter_type as usize];
+            let percent = count as f64 * 100.0 / stats.rows.len().max(1) as f64;
+            writeln!(session.logger, "  {:<8} {:>8} {:>6.1}%", filter_type, count, percent)?;
+        }
+    }
+    if zlib || all {
+        analyze_zlib(session, &png_from_file)?;
+    }
+    if verbose {
+        let (_, stats) = pixels::decode_rgba_with_stats(&png_from_file, &CancellationToken::new())?;
+        writeln!(session.logger, "decode: {} compressed, {} inflated, {} peak buffers",
+                 render::format_size(stats.compressed_bytes),
+                 render::format_size(stats.inflate_output_bytes),
+                 render::format_size(stats.peak_buffer_bytes))?;
     }
+    Ok(())
 }
 
-fn print(file: String) {
-    let file_raw = read_file(file).expect("could not read file");
-    println!("{}", 
-             Png::try_from(file_raw.as_ref())
-             .expect("cannot create PNG from file")
-             );
+fn analyze_zlib(session: &mut Session, png: &Png) -> Result<()> {
+    let stream = zlib::analyze(&png.image_data())?;
+    let header = stream.header;
+    let level = ["fastest", "fast", "default", "maximum"][header.level as usize];
+    writeln!(session.logger, "zlib: method {}, {} byte window, {} compression", header.method, header.window_size, level)?;
+    match stream.dictionary_id {
+        Some(id) => writeln!(session.logger, "  preset dictionary {:08x}", id)?,
+        None => writeln!(session.logger, "  no preset dictionary")?
+    }
+    let count = |block_type| stream.blocks.iter().filter(|block| block.block_type == block_type).count();
+    writeln!(session.logger, "deflate: {} blocks, {} stored, {} fixed, {} dynamic",
+             stream.blocks.len(), count(BlockType::Stored), count(BlockType::Fixed), count(BlockType::Dynamic))?;
+    for (index, block) in stream.blocks.iter().enumerate() {
+        writeln!(session.logger, "  {:>6} {:<8} {:>10} bytes in {:>10} out{}",
+                 index, block.block_type, block.compressed_bits.div_ceil(8), block.output_bytes, if block.is_final { "  final" } else { "" })?;
+    }
+    match stream.adler32 {
+        Some(adler32) => writeln!(session.logger, "adler-32: {:08x}", adler32)?,
+        None => writeln!(session.logger, "adler-32: missing")?
+    }
+    if stream.trailing_bytes > 0 {
+        writeln!(session.logger, "  {} bytes after the stream", stream.trailing_bytes)?;
+    }
+    Ok(())
+}
+
+fn compare(session: &mut Session, file: String, other_file: String, metric: Option<String>, min: Option<f64>) -> Result<()> {
+    let image = pixels::decode_rgba(&session.read_png(&file)?)?;
+    let other = pixels::decode_rgba(&session.read_png(&other_file)?)?;
+    let differing = metrics::differing_pixels(&image, &other)?;
+    writeln!(session.logger, "differing pixels: {} of {}", differing, image.pixels.len())?;
+    if let Some(metric) = metric {
+        let metric = Metric::from_str(&metric)?;
+        let value = metric.measure(&image, &other)?;
+        match metric {
+            Metric::Psnr => writeln!(session.logger, "{}: {:.2} dB", metric, value)?,
+            Metric::Ssim => writeln!(session.logger, "{}: {:.4}", metric, value)?
+        }
+        if let Some(min) = min.filter(|min| value < *min) {
+            writeln!(session.logger, "[FAILED] {} is below {}", metric, min)?;
+            session.failed = true;
+        }
+    }
+    Ok(())
 }
 
-fn read_file(file: String) -> Result<Vec<u8>, std::io::Error> {
-    fs::read(PathBuf::from_str(file.as_str())
-             .expect("cannot parse path"))
+fn encrypt_image(session: &mut Session, file: String, output_file: Option<String>, password: String) -> Result<()> {
+    let png_from_file = session.edit_png(&file)?;
+    let mut encrypted = encryption::encrypt_image(&png_from_file, &password)?;
+    session.write_png(&mut encrypted, &output_file.unwrap_or(file))?;
+    writeln!(session.logger, "[ENCRYPTED] {}", png_from_file.header()?)?;
+    Ok(())
+}
+
+fn hide_pixels(session: &mut Session, file: String, message: String, output_file: Option<String>, password: Option<String>) -> Result<()> {
+    let png_from_file = session.edit_png(&file)?;
+    let capacity = lsb::capacity(&png_from_file, password.is_some())?;
+    let mut hidden = lsb::embed(&png_from_file, message.as_bytes(), password.as_deref(), session.context.rng())?;
+    session.write_png(&mut hidden, &output_file.unwrap_or(file))?;
+    writeln!(session.logger, "[HIDDEN] {} of {} bytes in the pixels", message.len(), capacity)?;
+    Ok(())
+}
+
+fn decrypt_image(session: &mut Session, file: String, output_file: Option<String>, password: String) -> Result<()> {
+    let png_from_file = session.edit_png(&file)?;
+    let mut decrypted = encryption::decrypt_image(&png_from_file, &password)?;
+    session.write_png(&mut decrypted, &output_file.unwrap_or(file))?;
+    writeln!(session.logger, "[DECRYPTED] {}", decrypted.header()?)?;
+    Ok(())
+}
+
+// The output keeps only the redacted pixels and color space metadata; see
+// `redact::redact` for what is dropped.
+fn redact(session: &mut Session, file: String, output_file: Option<String>, rects: Vec<String>, pixelate: Option<u32>) -> Result<()> {
+    let png_from_file = session.edit_png(&file)?;
+    let rects = rects.iter().map(|rect| Rect::from_str(rect)).collect::<std::result::Result<Vec<Rect>, PngError>>()?;
+    let mode = pixelate.map_or(RedactMode::Blackout, RedactMode::Pixelate);
+    let mut redacted = redact::redact(&png_from_file, &rects, mode)?;
+    let dropped = png_from_file.chunks().len() - redacted.chunks().len();
+    session.write_png(&mut redacted, &output_file.unwrap_or(file))?;
+    writeln!(session.logger, "[REDACTED] {} regions, dropped {} chunks", rects.len(), dropped)?;
+    Ok(())
+}
+
+fn apng_contact_sheet(session: &mut Session, file: String, columns: u32, out: String) -> Result<()> {
+    let png_from_file = session.read_png(&file)?;
+    if !png_from_file.is_animated() {
+        return Err(PngError::InvalidArgument("not an animated PNG").into());
+    }
+    let frame_count = png_from_file.frame_controls()?.len();
+    // The sheet is sized once the first frame shows the canvas is real.
+    let mut sheet: Option<ContactSheet> = None;
+    png_from_file.for_each_composited_frame(|index, control, canvas| {
+        let sheet = match &mut sheet {
+            Some(sheet) => sheet,
+            None => sheet.insert(ContactSheet::new(frame_count, columns, canvas.width, canvas.height)?)
+        };
+        sheet.add(index, &control, canvas)
+    })?;
+    let sheet = sheet.ok_or(PngError::InvalidArgument("animation has no frames"))?.finish();
+    let mut png = Png::from_rgba(&sheet, sheet.min_color_type())?;
+    session.write_png(&mut png, &out)?;
+    writeln!(session.logger, "[CONTACT SHEET] {} frames, {}x{}, written to {}", frame_count, sheet.width, sheet.height, out)?;
+    Ok(())
+}
+
+// Only the control chunks change, so the image data is written back as it was
+// read, whatever --compression says.
+fn apng_retime(session: &mut Session, file: String, output_file: Option<String>, fps: f64) -> Result<()> {
+    let mut png_from_file = session.edit_png(&file)?;
+    let changed = png_from_file.set_frame_rate(fps)?;
+    let (delay_num, delay_den) = animation::frame_delay(fps)?;
+    let options = WriteOptions { compression: None, ..session.options };
+    session.write_png_with(&mut png_from_file, &output_file.unwrap_or(file), options)?;
+    writeln!(session.logger, "[RETIMED] {} frame(s) to {}/{} s", changed, delay_num, delay_den)?;
+    Ok(())
+}
+
+fn apng_loops(session: &mut Session, file: String, count: u32, output_file: Option<String>) -> Result<()> {
+    let mut png_from_file = session.edit_png(&file)?;
+    let control = png_from_file.set_num_plays(count)?;
+    let options = WriteOptions { compression: None, ..session.options };
+    session.write_png_with(&mut png_from_file, &output_file.unwrap_or(file), options)?;
+    writeln!(session.logger, "[UPDATED] acTL: {}", control)?;
+    Ok(())
+}
+
+fn lint(session: &mut Session, file: String, strip_legacy: bool) -> Result<()> {
+    let mut png_from_file = session.edit_png(&file)?;
+    let findings = lint::lint(&png_from_file);
+    for finding in findings.iter() {
+        writeln!(session.logger, "{}", finding)?;
+    }
+    if strip_legacy {
+        let stripped = png_from_file.strip_gif_extensions();
+        if stripped > 0 {
+            session.write_png(&mut png_from_file, &file)?;
+            writeln!(session.logger, "[STRIPPED] {} legacy chunk(s)", stripped)?;
+        }
+    }
+    if findings.is_empty() {
+        writeln!(session.logger, "[CLEAN] {}", file)?;
+    }
+    Ok(())
+}
+
+// The output is tagged sRGB so viewers show it as the consumer would.
+fn render(session: &mut Session, file: String, output_file: String, emulate: String) -> Result<()> {
+    let consumer = Consumer::from_str(&emulate)?;
+    let (image, source) = emulate::emulate(&session.read_png(&file)?, consumer)?;
+    let mut png = Png::from_rgba(&image, image.min_color_type())?;
+    png.set_rendering_intent(RenderingIntent::Perceptual);
+    session.write_png(&mut png, &output_file)?;
+    writeln!(session.logger, "[RENDERED] as {} from {}, written to {}", consumer, source, output_file)?;
+    Ok(())
+}
+
+fn doctor(session: &mut Session, file: String, output_file: Option<String>, fix: bool) -> Result<()> {
+    let mut png_from_file = session.edit_png(&file)?;
+    let findings = lint::lint(&png_from_file);
+    for finding in findings.iter() {
+        writeln!(session.logger, "{}", finding)?;
+    }
+    if fix {
+        let conflicts = png_from_file.resolve_color_conflicts();
+        if !conflicts.is_empty() {
+            for conflict in conflicts.iter() {
+                writeln!(session.logger, "  {}", conflict.fix())?;
+            }
+            session.write_png(&mut png_from_file, &output_file.unwrap_or(file.clone()))?;
+            writeln!(session.logger, "[FIXED] {} color conflict(s)", conflicts.len())?;
+        }
+    }
+    if findings.is_empty() {
+        writeln!(session.logger, "[CLEAN] {}", file)?;
+    }
+    Ok(())
+}
+
+// "random" picks a private ancillary type, printed so the message can be found again.
+fn resolve_chunk_type(session: &mut Session, chunk: &str) -> Result<ChunkType> {
+    if chunk != "random" {
+        return parse_chunk_type(chunk);
+    }
+    let chunk_type = session.context.random_chunk_type();
+    writeln!(session.logger, "[CHUNK] {}", chunk_type)?;
+    Ok(chunk_type)
+}
+
+fn parse_chunk_type(chunk: &str) -> Result<ChunkType> {
+    let chunk_type = ChunkType::from_str(chunk).map_err(|reason| PngError::InvalidChunkTypeName { name: chunk.to_string(), reason })?;
+    match chunk_type.is_valid() {
+        true => Ok(chunk_type),
+        false => Err(PngError::InvalidChunkType(chunk.to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use png_decode_encode::{color::Gamma, ihdr::ColorType, session::{MemoryIo, MemoryLog}};
+
+    fn session() -> (Session, MemoryIo, MemoryLog) {
+        let (io, log) = (MemoryIo::new(), MemoryLog::new());
+        io.insert("a.png", Png::from_pixels(2, 1, ColorType::Rgb, 8, &[1, 2, 3, 4, 5, 6]).unwrap().as_bytes());
+        (Session::new(io.clone(), log.clone()), io, log)
+    }
+
+    fn encode_command(file: &str, chunk: &str, output_file: Option<&str>) -> Commands {
+        Commands::Encode {
+            file: file.to_string(),
+            chunk: chunk.to_string(),
+            message: String::from("hello"),
+            output_file: output_file.map(String::from),
+            integrity: false,
+            spread: false,
+            parity: 0,
+            envelope: false,
+            mode: None,
+            stealth: false,
+            robust: false
+        }
+    }
+
+    #[test]
+    fn test_encode_and_decode() {
+        let (mut session, io, log) = session();
+        run(&mut session, encode_command("a.png", "ruSt", Some("b.png")), false).unwrap();
+        assert!(io.get("b.png").is_some());
+        run(&mut session, Commands::Decode { file: String::from("b.png"), chunk: String::from("ruSt"), gather: false, inspect: false, mode: None, robust: false }, false).unwrap();
+        assert!(log.contents().ends_with("\"hello\"\n"));
+    }
+
+    #[test]
+    fn test_optimize_keeps_smaller_original() {
+        let (mut session, io, log) = session();
+        let optimize = Commands::Optimize { file: String::from("a.png"), output_file: Some(String::from("b.png")), trials: false, json: false, synthesize_trns: false, emit_idot: false };
+        run(&mut session, optimize, false).unwrap();
+        assert_eq!(io.get("b.png"), io.get("a.png"));
+        assert!(log.contents().contains("kept the original"));
+    }
+
+    #[test]
+    fn test_random_chunk_type_is_seeded() {
+        let mut chunk_types = Vec::new();
+        for _ in 0..2 {
+            let (mut session, _, log) = session();
+            session.context = Context::seeded(3);
+            run(&mut session, encode_command("a.png", "random", None), false).unwrap();
+            chunk_types.push(log.contents().lines().next().unwrap().to_string());
+        }
+        assert!(chunk_types[0].starts_with("[CHUNK] "));
+        assert_eq!(chunk_types[0], chunk_types[1]);
+    }
+
+    #[test]
+    fn test_remove_writes_back() {
+        let (mut session, io, _) = session();
+        run(&mut session, encode_command("a.png", "ruSt", Some("a.png")), false).unwrap();
+        let remove = |all, nth| Commands::Remove { file: String::from("a.png"), chunk: String::from("ruSt"), all, nth };
+        run(&mut session, remove(false, None), false).unwrap();
+        assert!(Png::try_from(io.get("a.png").unwrap().as_slice()).unwrap().chunk_by_type("ruSt").is_none());
+    }
+
+    #[test]
+    fn test_remove_all_and_nth() {
+        let (mut session, io, log) = session();
+        for _ in 0..4 {
+            run(&mut session, encode_command("a.png", "ruSt", Some("a.png")), false).unwrap();
+        }
+        let remove = |all, nth| Commands::Remove { file: String::from("a.png"), chunk: String::from("ruSt"), all, nth };
+        run(&mut session, remove(false, Some(3)), false).unwrap();
+        assert!(run(&mut session, remove(false, Some(3)), false).is_err());
+        run(&mut session, remove(true, None), false).unwrap();
+        assert!(log.contents().ends_with("[REMOVED] 3 chunk(s) of type ruSt\n"));
+        assert!(Png::try_from(io.get("a.png").unwrap().as_slice()).unwrap().chunk_by_type("ruSt").is_none());
+    }
+
+    #[test]
+    fn test_print_verify() {
+        let (mut session, io, log) = session();
+        let mut bytes = io.get("a.png").unwrap();
+        bytes[8 + 8] ^= 1;
+        io.insert("a.png", bytes);
+        let print = |verify| Commands::Print { file: String::from("a.png"), describe: false, verify, hash: verify };
+        assert!(run(&mut session, print(false), false).is_err());
+        run(&mut session, print(true), false).unwrap();
+        assert!(session.failed);
+        let output = log.contents();
+        let lines: Vec<&str> = output.lines().filter(|line| line.contains("computed")).collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("IHDR") && lines[0].ends_with("MISMATCH"));
+        assert!(lines[1].ends_with("ok"));
+        assert!(output.contains("      blake3 "));
+    }
+
+    #[test]
+    fn test_chain() {
+        let (mut session, io, log) = session();
+        let steps = "encode ruSt hello , encode tEXt Title\0x , strip tEXt , optimize --level 1";
+        let steps: Vec<String> = steps.split(' ').map(String::from).collect();
+        run(&mut session, Commands::Chain { input: String::from("a.png"), output: String::from("b.png"), steps }, false).unwrap();
+        let png = Png::try_from(io.get("b.png").unwrap().as_slice()).unwrap();
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"hello");
+        assert!(png.chunk_by_type("tEXt").is_none());
+        assert_eq!(log.contents(), "[CHAINED] 4 operation(s) -> b.png\n");
+    }
+
+    #[test]
+    fn test_parse_chain() {
+        let steps = |steps: &str| -> Vec<String> { steps.split(' ').map(String::from).collect() };
+        assert_eq!(args::parse_chain(&steps("normalize , quantize --colors 4")).unwrap(), [
+            Operation::Normalize,
+            Operation::Quantize { colors: 4 }
+        ]);
+        assert!(args::parse_chain(&steps("normalize ,")).is_err());
+        assert!(args::parse_chain(&steps("frobnicate")).is_err());
+        assert!(args::parse_chain(&steps("strip")).is_err());
+    }
+
+    #[test]
+    fn test_failed_check() {
+        let (mut session, _, log) = session();
+        run(&mut session, Commands::Verify { file: String::from("a.png"), strong: true, profile: None }, false).unwrap();
+        assert!(session.failed);
+        assert!(log.contents().lines().last().unwrap().starts_with("[FAILED] a.png integrity check"));
+    }
+
+    #[test]
+    fn test_verify_profile() {
+        let (mut session, io, log) = session();
+        let verify = |profile: &str| Commands::Verify { file: String::from("a.png"), strong: false, profile: Some(profile.to_string()) };
+        run(&mut session, verify("png-1.2"), false).unwrap();
+        assert!(log.contents().ends_with("conforms to:   png-3\n[VERIFIED] a.png\n"));
+        let mut png = Png::try_from(io.get("a.png").unwrap().as_slice()).unwrap();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("cICP").unwrap(), vec![1, 13, 0, 1])).unwrap();
+        io.insert("a.png", png.as_bytes());
+        run(&mut session, verify("png-3"), false).unwrap();
+        assert!(!session.failed);
+        run(&mut session, verify("png-1.2"), false).unwrap();
+        assert!(session.failed);
+        assert!(log.contents().ends_with(
+            "   png-1.2  cICP: chunk type was added in the third edition\nconforms to:   png-3\n[FAILED] a.png does not conform to png-1.2: 1 problem(s)\n"
+        ));
+        assert!(run(&mut session, verify("png-2"), false).is_err());
+    }
+
+    #[test]
+    fn test_verify_reports_structure() {
+        let (mut session, io, log) = session();
+        let mut bytes = io.get("a.png").unwrap();
+        bytes[8 + 8] ^= 1;
+        bytes.extend([0, 0]);
+        io.insert("a.png", bytes);
+        run(&mut session, Commands::Verify { file: String::from("a.png"), strong: false, profile: None }, false).unwrap();
+        assert!(session.failed);
+        let output = log.contents();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "         0  signature  ok");
+        assert!(lines[1].starts_with("         8  IHDR") && lines[1].contains("FAILED: crc mismatch"));
+        assert!(lines[lines.len() - 2].ends_with("FAILED: data after IEND"));
+        assert_eq!(lines[lines.len() - 1], "[FAILED] a.png: 2 problem(s)");
+    }
+
+    #[test]
+    fn test_metadata_minimal_rewrite() {
+        let (mut session, io, log) = session();
+        let metadata = |set: &[&str], minimal_rewrite| Commands::Metadata {
+            file: String::from("a.png"),
+            output_file: None,
+            dpi: None,
+            time: None,
+            histogram: false,
+            suggested_palette: None,
+            set: set.iter().map(|entry| entry.to_string()).collect(),
+            minimal_rewrite
+        };
+        run(&mut session, metadata(&["Title=first draft"], false), false).unwrap();
+        let before = io.get("a.png").unwrap();
+        run(&mut session, metadata(&["Title=final"], true), false).unwrap();
+        let after = io.get("a.png").unwrap();
+        assert_eq!(before.len(), after.len());
+        assert_eq!(Png::try_from(after.as_slice()).unwrap().title().as_deref(), Some("final"));
+        assert!(log.contents().ends_with("[UPDATED] text Title (in place)\n"));
+
+        run(&mut session, metadata(&["Author=me"], true), false).unwrap();
+        assert!(log.contents().ends_with("[UPDATED] text Author (after the image data)\n"));
+        assert_eq!(io.get("a.png").unwrap()[..before.len() - 12], after[..before.len() - 12]);
+        assert!(run(&mut session, metadata(&["Author"], true), false).is_err());
+    }
+
+    #[test]
+    fn test_generate_corrupt() {
+        let (mut session, io, log) = session();
+        let generate = |corrupt: &str| Commands::Generate { output_file: String::from("b.png"), corrupt: corrupt.to_string(), from: String::from("a.png") };
+        run(&mut session, generate("bad-crc"), false).unwrap();
+        assert!(log.contents().ends_with("[GENERATED] bad-crc at offset 33, written to b.png\n"));
+        assert!(Png::try_from(io.get("b.png").unwrap().as_slice()).is_err());
+        run(&mut session, Commands::Verify { file: String::from("b.png"), strong: false, profile: None }, false).unwrap();
+        assert!(session.failed);
+        assert!(run(&mut session, generate("bit-flip"), false).is_err());
+    }
+
+    #[test]
+    fn test_diff_html() {
+        let (mut session, io, log) = session();
+        io.insert("b.png", Png::from_pixels(2, 1, ColorType::Rgb, 8, &[1, 2, 3, 4, 5, 7]).unwrap().as_bytes());
+        let diff = Commands::Diff { file: String::from("a.png"), other_file: String::from("b.png"), html: Some(String::from("report.html")) };
+        run(&mut session, diff, false).unwrap();
+        let output = log.contents();
+        assert!(output.contains("IHDR         13 -> 13         same\n"));
+        assert!(output.contains("differing pixels: 1 of 2\n[REPORT] written to report.html\n"));
+        assert!(String::from_utf8(io.get("report.html").unwrap()).unwrap().starts_with("<!DOCTYPE html>"));
+    }
+
+    #[test]
+    fn test_keyword_policy() {
+        let (mut session, io, _) = session();
+        session.keyword_policy.deny("Host*").unwrap();
+        let metadata = |set: &str| Commands::Metadata {
+            file: String::from("a.png"),
+            output_file: Some(String::from("b.png")),
+            dpi: None,
+            time: None,
+            histogram: false,
+            suggested_palette: None,
+            set: vec![set.to_string()],
+            minimal_rewrite: false
+        };
+        let err = run(&mut session, metadata("Hostname=build-07"), false).unwrap_err();
+        assert_eq!(err.to_string(), "text keyword \"Hostname\" is not allowed by the keyword policy");
+        assert!(io.get("b.png").is_none());
+        let mut encode = encode_command("a.png", "tEXt", Some("b.png"));
+        if let Commands::Encode { message, .. } = &mut encode {
+            *message = String::from("Hostname\0build-07");
+        }
+        assert!(run(&mut session, encode, false).is_err());
+        run(&mut session, metadata("Title=ok"), false).unwrap();
+        assert!(io.get("b.png").is_some());
+        let mut png = Png::try_from(io.get("a.png").unwrap().as_slice()).unwrap();
+        png.set_text("Hostname", "build-07").unwrap();
+        io.insert("host.png", png.as_bytes());
+        let generate = Commands::Generate { output_file: String::from("c.png"), corrupt: String::from("bad-crc"), from: String::from("host.png") };
+        assert!(run(&mut session, generate, false).is_err());
+        assert!(io.get("c.png").is_none());
+    }
+
+    #[test]
+    fn test_vendor_chunks() {
+        let (mut session, io, log) = session();
+        let mut png = Png::try_from(io.get("a.png").unwrap().as_slice()).unwrap();
+        png.append_chunk(Chunk::new(ChunkType::from_str("vpAg").unwrap(), vec![0, 0, 0, 2, 0, 0, 0, 1, 0]));
+        png.append_chunk(Chunk::new(ChunkType::from_str("prVW").unwrap(), vec![1, 2, 3]));
+        io.insert("a.png", png.as_bytes());
+        run(&mut session, Commands::Print { file: String::from("a.png"), describe: true, verify: false, hash: false }, false).unwrap();
+        let output = log.contents();
+        assert!(output.contains("      ImageMagick virtual page size\n      2x1 pixels\n"));
+        assert!(output.contains("      Macromedia Fireworks preview thumbnail\n"));
+
+        let strip = |types: Option<&str>| Commands::Strip { file: String::from("a.png"), types: types.map(String::from), vendor: true };
+        run(&mut session, strip(None), false).unwrap();
+        let png = Png::try_from(io.get("a.png").unwrap().as_slice()).unwrap();
+        assert!(png.vendor_chunks().is_empty());
+        assert!(log.contents().ends_with("[STRIPPED] 2 chunk(s) of type caNv,iDOT,mkBF,mkBS,mkBT,mkTS,npLb,npOl,npTc,orNT,prVW,vpAg\n"));
+        assert!(run(&mut session, strip(Some("IDAT")), false).is_err());
+    }
+
+    // Writes "anim.png", two frames of "a.png" a tenth of a second each.
+    fn insert_animation(io: &MemoryIo) {
+        use png_decode_encode::animation::{AnimationControl, BlendOp, DisposeOp, FrameControl, FrameData};
+        let a = Png::try_from(io.get("a.png").unwrap().as_slice()).unwrap();
+        let control = |sequence_number| FrameControl {
+            sequence_number,
+            width: 2,
+            height: 1,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source
+        };
+        let chunks = vec![
+            a.chunks()[0].clone(),
+            AnimationControl { num_frames: 2, num_plays: 0 }.into(),
+            control(0).into(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), a.image_data()),
+            control(1).into(),
+            FrameData { sequence_number: 2, data: a.image_data() }.into(),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ];
+        io.insert("anim.png", Png::from_chunks(chunks).as_bytes());
+    }
+
+    #[test]
+    fn test_apng_contact_sheet() {
+        let (mut session, io, log) = session();
+        insert_animation(&io);
+        let command = |file: &str| Commands::Apng {
+            command: ApngCommand::ContactSheet { file: file.to_string(), columns: 5, out: String::from("sheet.png") }
+        };
+        run(&mut session, command("anim.png"), false).unwrap();
+        assert_eq!(log.contents(), "[CONTACT SHEET] 2 frames, 16x9, written to sheet.png\n");
+        let sheet = pixels::decode_rgba(&Png::try_from(io.get("sheet.png").unwrap().as_slice()).unwrap()).unwrap();
+        assert_eq!((sheet.width, sheet.height), (16, 9));
+        assert!(run(&mut session, command("a.png"), false).is_err());
+    }
+
+    #[test]
+    fn test_apng_retime_and_loops() {
+        let (mut session, io, log) = session();
+        session.options.compression = Some(CompressionLevel::Best);
+        insert_animation(&io);
+        let before = Png::try_from(io.get("anim.png").unwrap().as_slice()).unwrap();
+        let retime = |fps| Commands::Apng {
+            command: ApngCommand::Retime { file: String::from("anim.png"), output_file: None, fps }
+        };
+        run(&mut session, retime(24.0), false).unwrap();
+        run(&mut session, Commands::Apng {
+            command: ApngCommand::Loops { file: String::from("anim.png"), count: 3, output_file: None }
+        }, false).unwrap();
+        assert_eq!(log.contents(), "[RETIMED] 2 frame(s) to 1/24 s\n[UPDATED] acTL: 2 frames, plays 3 times\n");
+        let after = Png::try_from(io.get("anim.png").unwrap().as_slice()).unwrap();
+        assert_eq!(after.frames().unwrap().iter().map(|frame| frame.control.delay_den).collect::<Vec<_>>(), [24, 24]);
+        assert_eq!(after.animation_control().unwrap().unwrap().num_plays, 3);
+        assert_eq!(after.image_data(), before.image_data());
+        assert_eq!(after.chunks_by_type("fdAT")[0].data(), before.chunks_by_type("fdAT")[0].data());
+        assert!(run(&mut session, retime(0.0), false).is_err());
+        assert!(run(&mut session, Commands::Apng {
+            command: ApngCommand::Loops { file: String::from("a.png"), count: 0, output_file: None }
+        }, false).is_err());
+    }
+
+    #[test]
+    fn test_doctor_fixes_color_conflicts() {
+        let (mut session, io, log) = session();
+        let mut png = Png::try_from(io.get("a.png").unwrap().as_slice()).unwrap();
+        png.set_rendering_intent(RenderingIntent::Perceptual);
+        png.set_gamma(Gamma(22000));
+        io.insert("a.png", png.as_bytes());
+        let doctor = |fix| Commands::Doctor { file: String::from("a.png"), output_file: None, fix };
+        run(&mut session, doctor(false), false).unwrap();
+        assert_eq!(log.contents(), "warning: gAMA gAMA 0.22 does not match sRGB, which implies 0.45455\n");
+        run(&mut session, doctor(true), false).unwrap();
+        assert!(log.contents().ends_with("  set gAMA to the sRGB gamma 0.45455\n[FIXED] 1 color conflict(s)\n"));
+        let fixed = Png::try_from(io.get("a.png").unwrap().as_slice()).unwrap();
+        assert_eq!(fixed.color_info().unwrap().gamma, Some(Gamma::SRGB));
+    }
+
+    #[test]
+    fn test_render_emulates_consumers() {
+        let (mut session, io, log) = session();
+        let mut png = Png::try_from(io.get("a.png").unwrap().as_slice()).unwrap();
+        png.set_gamma(Gamma(100000));
+        io.insert("a.png", png.as_bytes());
+        let render = |emulate: &str| Commands::Render {
+            file: String::from("a.png"),
+            output_file: String::from("out.png"),
+            emulate: emulate.to_string()
+        };
+        run(&mut session, render("imagemagick"), false).unwrap();
+        let rendered = Png::try_from(io.get("out.png").unwrap().as_slice()).unwrap();
+        assert_eq!(pixels::decode_rgba(&rendered).unwrap().pixel(0, 0), [1, 2, 3, 255]);
+        run(&mut session, render("firefox"), false).unwrap();
+        assert!(log.contents().ends_with("[RENDERED] as firefox from gAMA 1, written to out.png\n"));
+        let rendered = Png::try_from(io.get("out.png").unwrap().as_slice()).unwrap();
+        assert_eq!(rendered.color_info().unwrap().rendering_intent, Some(RenderingIntent::Perceptual));
+        assert_ne!(pixels::decode_rgba(&rendered).unwrap().pixel(0, 0), [1, 2, 3, 255]);
+        assert!(run(&mut session, render("safari"), false).is_err());
+    }
+
+    #[test]
+    fn test_hide_and_reveal_pixels() {
+        let (mut session, io, log) = session();
+        let samples: Vec<u16> = (0..16 * 16 * 3).map(|i| (i % 256) as u16).collect();
+        io.insert("big.png", Png::from_samples(16, 16, ColorType::Rgb, 8, &samples).unwrap().as_bytes());
+        run(&mut session, Commands::HidePixels {
+            file: String::from("big.png"),
+            message: String::from("hello"),
+            output_file: Some(String::from("hidden.png")),
+            password: None
+        }, false).unwrap();
+        assert_eq!(log.contents(), "[HIDDEN] 5 of 92 bytes in the pixels\n");
+        run(&mut session, Commands::RevealPixels { file: String::from("hidden.png"), password: None }, false).unwrap();
+        assert!(log.contents().ends_with("\"hello\"\n"));
+        assert!(run(&mut session, Commands::HidePixels {
+            file: String::from("a.png"),
+            message: String::from("too long for two pixels"),
+            output_file: None,
+            password: None
+        }, false).is_err());
+    }
+
+    #[test]
+    fn test_encode_mode_auto() {
+        let (mut session, io, log) = session();
+        let samples: Vec<u16> = (0..16 * 16 * 3).map(|i| (i % 256) as u16).collect();
+        io.insert("big.png", Png::from_samples(16, 16, ColorType::Rgb, 8, &samples).unwrap().as_bytes());
+        let decode = |file: &str| Commands::Decode {
+            file: file.to_string(),
+            chunk: String::from("ruSt"),
+            gather: false,
+            inspect: false,
+            mode: Some(String::from("auto")),
+            robust: false
+        };
+        for (file, stealth, chosen) in [("big.png", true, "pixels"), ("a.png", true, "trailer"), ("a.png", false, "chunk")] {
+            let mut encode = encode_command(file, "ruSt", Some("b.png"));
+            if let Commands::Encode { mode, stealth: stealth_arg, .. } = &mut encode {
+                (*mode, *stealth_arg) = (Some(String::from("auto")), stealth);
+            }
+            run(&mut session, encode, false).unwrap();
+            assert!(log.contents().contains(&format!("[MODE] {}\n", chosen)));
+            run(&mut session, decode("b.png"), false).unwrap();
+            assert!(log.contents().ends_with("\"hello\"\n"));
+        }
+        // Noise after IEND and in the pixels is not taken for a message.
+        let mut bytes = io.get("a.png").unwrap();
+        bytes.extend(b"junk");
+        io.insert("junk.png", bytes);
+        assert!(run(&mut session, decode("junk.png"), false).is_err());
+    }
+
+    #[test]
+    fn test_encode_and_decode_robust() {
+        let (mut session, io, log) = session();
+        let samples: Vec<u16> = (0..64 * 64 * 3).map(|i| (i % 256) as u16).collect();
+        io.insert("big.png", Png::from_samples(64, 64, ColorType::Rgb, 8, &samples).unwrap().as_bytes());
+        let mut encode = encode_command("big.png", "ruSt", Some("b.png"));
+        if let Commands::Encode { robust, .. } = &mut encode {
+            *robust = true;
+        }
+        run(&mut session, encode, false).unwrap();
+        assert_eq!(log.contents(), "[ROBUST] 5 bytes in a ruSt chunk and in the pixels\n");
+        let mut stripped = Png::try_from(io.get("b.png").unwrap().as_slice()).unwrap();
+        stripped.remove_chunk("ruSt").unwrap();
+        io.insert("b.png", stripped.as_bytes());
+        let decode = Commands::Decode {
+            file: String::from("b.png"),
+            chunk: String::from("ruSt"),
+            gather: false,
+            inspect: false,
+            mode: None,
+            robust: true
+        };
+        run(&mut session, decode, false).unwrap();
+        assert!(log.contents().ends_with("[FOUND] in the pixels\n\"hello\"\n"));
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let (mut session, _, _) = session();
+        assert!(run(&mut session, Commands::Info { file: String::from("missing.png") }, false).is_err());
+    }
 }
@@ -0,0 +1,162 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::{ChunkType, ChunkTypeSet},
+    error::PngError,
+    png::Png
+};
+
+// Chunks the third edition added: HDR metadata, eXIf and APNG.
+const THIRD_EDITION_TYPES: ChunkTypeSet = ChunkTypeSet::of(&[b"cICP", b"mDCV", b"cLLI", b"eXIf", b"acTL", b"fcTL", b"fdAT"]);
+// Registered public extensions that `ChunkType::standard` does not describe.
+const REGISTERED_TYPES: ChunkTypeSet = ChunkTypeSet::of(&[b"fRAc", b"dSIG"]);
+
+// An edition of the PNG specification, oldest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edition {
+    // PNG 1.2, which the ISO second edition matches.
+    Png12,
+    Png3
+}
+
+// A rule of an edition that one chunk breaks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nonconformance {
+    pub chunk_type: ChunkType,
+    pub problem: String
+}
+
+impl Edition {
+    pub const ALL: [Edition; 2] = [Edition::Png12, Edition::Png3];
+}
+
+impl FromStr for Edition {
+    type Err = PngError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Edition::ALL
+            .into_iter()
+            .find(|edition| edition.to_string() == s.to_ascii_lowercase())
+            .ok_or(PngError::InvalidArgument("profile must be png-1.2 or png-3"))
+    }
+}
+
+impl Display for Edition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Edition::Png12 => "png-1.2",
+            Edition::Png3 => "png-3"
+        };
+        f.pad(name)
+    }
+}
+
+impl Display for Nonconformance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.chunk_type, self.problem)
+    }
+}
+
+// Checks the chunk order and the chunks against the rules of `edition`.
+// Public chunk types that neither edition defines or registers break both.
+pub fn check(png: &Png, edition: Edition) -> Vec<Nonconformance> {
+    let mut problems = Vec::new();
+    let mut report = |chunk: &Chunk, problem: String| problems.push(Nonconformance { chunk_type: *chunk.chunk_type(), problem });
+    for chunk in png.chunks() {
+        let chunk_type = *chunk.chunk_type();
+        let known = chunk_type.standard().is_some() || REGISTERED_TYPES.contains(chunk_type);
+        if chunk_type.is_public() && !known {
+            report(chunk, "public chunk type is not defined or registered".to_string());
+            continue;
+        }
+        match edition {
+            Edition::Png12 if THIRD_EDITION_TYPES.contains(chunk_type) => {
+                report(chunk, "chunk type was added in the third edition".to_string());
+            },
+            Edition::Png12 => {},
+            Edition::Png3 => {
+                if let Some(problem) = third_edition_problem(chunk) {
+                    report(chunk, problem.to_string());
+                }
+            }
+        }
+    }
+    if edition == Edition::Png3 {
+        if let Err(err) = png.validate_animation() {
+            problems.push(Nonconformance { chunk_type: ChunkType(u32::from_be_bytes(*b"acTL")), problem: err.to_string() });
+        }
+    }
+    for violation in png.order_violations() {
+        problems.push(Nonconformance { chunk_type: violation.chunk_type, problem: violation.problem.to_string() });
+    }
+    problems
+}
+
+// The newest edition `png` conforms to, if any.
+pub fn highest_edition(png: &Png) -> Option<Edition> {
+    Edition::ALL.into_iter().rev().find(|edition| check(png, *edition).is_empty())
+}
+
+fn third_edition_problem(chunk: &Chunk) -> Option<&'static str> {
+    let data = chunk.data();
+    match &chunk.chunk_type().bytes() {
+        b"cICP" if data.len() != 4 => Some("must be 4 bytes"),
+        // PNG only holds RGB, so the matrix coefficients must be identity.
+        b"cICP" if data[2] != 0 => Some("matrix coefficients must be 0"),
+        b"cICP" if data[3] > 1 => Some("video full range flag must be 0 or 1"),
+        b"mDCV" if data.len() != 24 => Some("must be 24 bytes"),
+        b"cLLI" if data.len() != 8 => Some("must be 8 bytes"),
+        b"eXIf" if !data.starts_with(b"II*\0") && !data.starts_with(b"MM\0*") => Some("must start with a TIFF byte order mark"),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+
+    fn testing_png() -> Png {
+        Png::from_pixels(1, 1, ColorType::Rgb, 8, &[1, 2, 3]).unwrap()
+    }
+
+    fn insert(png: &mut Png, chunk_type: &str, data: &[u8]) {
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())).unwrap();
+    }
+
+    #[test]
+    fn test_parse_edition() {
+        assert_eq!(Edition::from_str("PNG-1.2").unwrap(), Edition::Png12);
+        assert_eq!(Edition::Png3.to_string(), "png-3");
+        assert!(Edition::from_str("png-2").is_err());
+    }
+
+    #[test]
+    fn test_plain_file_conforms_to_both() {
+        let png = testing_png();
+        assert!(check(&png, Edition::Png12).is_empty());
+        assert_eq!(highest_edition(&png), Some(Edition::Png3));
+    }
+
+    #[test]
+    fn test_cicp() {
+        let mut png = testing_png();
+        insert(&mut png, "cICP", &[1, 13, 0, 1]);
+        assert_eq!(check(&png, Edition::Png12)[0].to_string(), "cICP: chunk type was added in the third edition");
+        assert_eq!(highest_edition(&png), Some(Edition::Png3));
+        png.chunks_by_type_mut("cICP")[0].message_bytes = vec![1, 13, 1, 1];
+        assert_eq!(check(&png, Edition::Png3)[0].problem, "matrix coefficients must be 0");
+        assert_eq!(highest_edition(&png), None);
+    }
+
+    #[test]
+    fn test_unregistered_public_chunk() {
+        let mut png = testing_png();
+        insert(&mut png, "iDOT", &[0; 28]);
+        assert!(Edition::ALL.iter().all(|edition| check(&png, *edition).len() == 1));
+        let mut png = testing_png();
+        insert(&mut png, "ruSt", b"private");
+        insert(&mut png, "fRAc", b"registered");
+        assert_eq!(highest_edition(&png), Some(Edition::Png3));
+    }
+}
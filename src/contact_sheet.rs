@@ -0,0 +1,201 @@
+use crate::{animation::FrameControl, error::PngError, pixels::ImageBuffer};
+
+const GAP: u32 = 4;
+const SHEET_COLOR: [u8; 4] = [48, 48, 48, 255];
+const LABEL_BACKGROUND: [u8; 4] = [0, 0, 0, 160];
+const LABEL_COLOR: [u8; 4] = [255, 255, 255, 255];
+const CHECKER: [[u8; 4]; 2] = [[255, 255, 255, 255], [204, 204, 204, 255]];
+const CHECKER_SIZE: u32 = 8;
+// 256 MB of RGBA; many frames of a large canvas are refused rather than laid out.
+const MAX_PIXELS: usize = 1 << 26;
+
+// 3x5 glyphs, one row per entry with the leftmost pixel in bit 2.
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const FONT: [(char, [u8; 5]); 14] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b011, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('#', [0b101, 0b111, 0b101, 0b111, 0b101]),
+    ('m', [0b000, 0b000, 0b111, 0b111, 0b101]),
+    ('s', [0b000, 0b011, 0b110, 0b011, 0b110]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+];
+
+// Lays composited frames out left to right in rows of `columns`, each over a
+// checkerboard so transparency shows, labelled with its number (from 1) and
+// delay in milliseconds in the top left corner. Frames are drawn as they are
+// added, so they do not have to be held at once.
+pub struct ContactSheet {
+    sheet: ImageBuffer,
+    columns: u32,
+    cell: (u32, u32),
+    // Labels grow with the frames so they stay readable on large animations.
+    scale: u32
+}
+
+impl ContactSheet {
+    pub fn new(frame_count: usize, columns: u32, cell_width: u32, cell_height: u32) -> Result<ContactSheet, PngError> {
+        if columns == 0 {
+            return Err(PngError::InvalidArgument("columns must be at least 1"));
+        }
+        if frame_count == 0 {
+            return Err(PngError::InvalidArgument("animation has no frames"));
+        }
+        let frame_count = u32::try_from(frame_count).map_err(|_| too_large())?;
+        let columns = columns.min(frame_count);
+        let rows = frame_count.div_ceil(columns);
+        let side = |count: u32, cell: u32| cell.checked_add(GAP)?.checked_mul(count)?.checked_add(GAP);
+        let (width, height) = side(columns, cell_width).zip(side(rows, cell_height)).ok_or_else(too_large)?;
+        let pixel_count = (width as usize).checked_mul(height as usize).filter(|count| *count <= MAX_PIXELS).ok_or_else(too_large)?;
+        Ok(ContactSheet {
+            sheet: ImageBuffer::new(width, height, vec![SHEET_COLOR; pixel_count])?,
+            columns,
+            cell: (cell_width, cell_height),
+            scale: (cell_width / 64).clamp(1, 4)
+        })
+    }
+
+    pub fn add(&mut self, index: usize, control: &FrameControl, frame: &ImageBuffer) -> Result<(), PngError> {
+        let (cell_width, cell_height) = self.cell;
+        if (frame.width, frame.height) != self.cell {
+            return Err(PngError::InvalidArgument("frames must all be the size of the canvas"));
+        }
+        let index = u32::try_from(index).map_err(|_| too_large())?;
+        let left = GAP + index % self.columns * (cell_width + GAP);
+        let top = GAP + index / self.columns * (cell_height + GAP);
+        if top >= self.sheet.height {
+            return Err(PngError::InvalidArgument("more frames than the contact sheet was made for"));
+        }
+        for (x, y, color) in frame.pixels() {
+            let checker = CHECKER[((x / CHECKER_SIZE + y / CHECKER_SIZE) % 2) as usize];
+            self.sheet.set_pixel(left + x, top + y, over(checker, color))?;
+        }
+        let label = format!("#{} {}ms", index + 1, control.delay().as_millis());
+        draw_label(&mut self.sheet, &label, (left, top), self.cell, self.scale);
+        Ok(())
+    }
+
+    pub fn finish(self) -> ImageBuffer {
+        self.sheet
+    }
+}
+
+// A contact sheet of frames that are already in memory.
+pub fn contact_sheet(frames: &[(FrameControl, ImageBuffer)], columns: u32) -> Result<ImageBuffer, PngError> {
+    let (cell_width, cell_height) = frames.first().map_or((0, 0), |(_, first)| (first.width, first.height));
+    let mut sheet = ContactSheet::new(frames.len(), columns, cell_width, cell_height)?;
+    for (index, (control, frame)) in frames.iter().enumerate() {
+        sheet.add(index, control, frame)?;
+    }
+    Ok(sheet.finish())
+}
+
+fn too_large() -> PngError {
+    PngError::InvalidArgument("contact sheet is too large")
+}
+
+// Draws `text` on a translucent box at `origin`, clipped to the cell.
+fn draw_label(sheet: &mut ImageBuffer, text: &str, origin: (u32, u32), cell: (u32, u32), scale: u32) {
+    let text_width = text.chars().count() as u32 * (GLYPH_WIDTH + 1) * scale - scale;
+    let (box_width, box_height) = ((text_width + 2 * scale).min(cell.0), ((GLYPH_HEIGHT + 2) * scale).min(cell.1));
+    for y in 0..box_height {
+        for x in 0..box_width {
+            let (x, y) = (origin.0 + x, origin.1 + y);
+            let color = over(sheet.pixel(x, y), LABEL_BACKGROUND);
+            sheet.set_pixel(x, y, color).unwrap();
+        }
+    }
+    for (i, c) in text.chars().enumerate() {
+        let Some((_, glyph)) = FONT.iter().find(|(glyph, _)| *glyph == c) else {
+            continue;
+        };
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits >> (GLYPH_WIDTH - 1 - column) & 1 == 0 {
+                    continue;
+                }
+                let x = scale + (i as u32 * (GLYPH_WIDTH + 1) + column) * scale;
+                let y = scale + row as u32 * scale;
+                for (dx, dy) in (0..scale).flat_map(|dx| (0..scale).map(move |dy| (dx, dy))) {
+                    if x + dx < box_width && y + dy < box_height {
+                        sheet.set_pixel(origin.0 + x + dx, origin.1 + y + dy, LABEL_COLOR).unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+// `top` over an opaque `bottom`.
+fn over(bottom: [u8; 4], top: [u8; 4]) -> [u8; 4] {
+    let alpha = top[3] as u32;
+    let mix = |i: usize| ((top[i] as u32 * alpha + bottom[i] as u32 * (255 - alpha) + 127) / 255) as u8;
+    [mix(0), mix(1), mix(2), 255]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{BlendOp, DisposeOp};
+
+    fn frame(delay_num: u16, color: [u8; 4]) -> (FrameControl, ImageBuffer) {
+        let control = FrameControl {
+            sequence_number: 0,
+            width: 20,
+            height: 10,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num,
+            delay_den: 1000,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source
+        };
+        (control, ImageBuffer::new(20, 10, vec![color; 200]).unwrap())
+    }
+
+    #[test]
+    fn test_grid_layout() {
+        let frames: Vec<_> = (0..5).map(|i| frame(40, [0, 0, 50 * i, 255])).collect();
+        let sheet = contact_sheet(&frames, 2).unwrap();
+        assert_eq!((sheet.width, sheet.height), (2 * 24 + 4, 3 * 14 + 4));
+        // Bottom right of each frame is clear of the label.
+        assert_eq!(sheet.pixel(4 + 19, 4 + 9), [0, 0, 0, 255]);
+        assert_eq!(sheet.pixel(28 + 19, 4 + 9), [0, 0, 50, 255]);
+        assert_eq!(sheet.pixel(4 + 19, 32 + 9), [0, 0, 200, 255]);
+        assert_eq!(sheet.pixel(28 + 19, 32 + 9), SHEET_COLOR);
+        assert_eq!(contact_sheet(&frames, 9).unwrap().width, 5 * 24 + 4);
+    }
+
+    #[test]
+    fn test_labels() {
+        let sheet = contact_sheet(&[frame(40, [255, 255, 255, 255])], 1).unwrap();
+        // "#1 40ms": the top left pixel of "#" is lit, the box around it is dark.
+        assert_eq!(sheet.pixel(5, 5), LABEL_COLOR);
+        assert_eq!(sheet.pixel(4, 4), over([255, 255, 255, 255], LABEL_BACKGROUND));
+        let lit = sheet.pixels().filter(|(_, _, color)| *color == LABEL_COLOR).count();
+        assert!(lit > 20);
+    }
+
+    #[test]
+    fn test_transparency_over_checkerboard() {
+        let sheet = contact_sheet(&[frame(1, [0, 0, 0, 0])], 1).unwrap();
+        assert_eq!(sheet.pixel(4 + 19, 4 + 9), CHECKER[((19 / 8 + 1) % 2) as usize]);
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert!(contact_sheet(&[], 5).is_err());
+        assert!(contact_sheet(&[frame(1, [0; 4])], 0).is_err());
+        assert!(matches!(ContactSheet::new(4, 4, u32::MAX - 2, 1), Err(PngError::InvalidArgument("contact sheet is too large"))));
+        assert!(matches!(ContactSheet::new(4, 4, 1 << 30, 1), Err(PngError::InvalidArgument("contact sheet is too large"))));
+        assert!(matches!(ContactSheet::new(100_000, 1, 4096, 4096), Err(PngError::InvalidArgument("contact sheet is too large"))));
+    }
+}
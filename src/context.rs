@@ -0,0 +1,62 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::chunk_type::ChunkType;
+
+// State for operations that make random choices. A seeded context makes
+// their output reproducible, so key material never comes from it.
+#[derive(Debug, Clone)]
+pub struct Context {
+    rng: StdRng,
+    seed: Option<u64>
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context { rng: StdRng::from_entropy(), seed: None }
+    }
+
+    pub fn seeded(seed: u64) -> Context {
+        Context { rng: StdRng::seed_from_u64(seed), seed: Some(seed) }
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    pub fn random_chunk_type(&mut self) -> ChunkType {
+        ChunkType::random_private_ancillary(&mut self.rng)
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_seeded_is_reproducible() {
+        let (mut first, mut second) = (Context::seeded(7), Context::seeded(7));
+        for _ in 0..10 {
+            assert_eq!(first.random_chunk_type(), second.random_chunk_type());
+        }
+        assert_eq!(first.rng().gen::<u64>(), second.rng().gen::<u64>());
+        assert_eq!(first.seed(), Some(7));
+    }
+
+    #[test]
+    fn test_random_chunk_type() {
+        let chunk_type = Context::new().random_chunk_type();
+        assert!(chunk_type.is_valid() && !chunk_type.is_critical() && !chunk_type.is_public());
+        assert_eq!(Context::new().seed(), None);
+    }
+}
@@ -0,0 +1,156 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    chunk::Chunk,
+    error::PngError,
+    optimize,
+    png::Png,
+    zlib::{deflate, inflate, CompressionLevel}
+};
+
+// A fault that `validate`, `repair` or the pixel decoder should catch, for
+// producing broken files to test other decoders against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corruption {
+    // The CRC of the first IDAT no longer matches its data.
+    BadCrc,
+    // The file ends halfway through the first IDAT's data.
+    Truncated,
+    // IHDR comes after the first IDAT instead of first.
+    Misordered,
+    // The first scanline has filter type 5, which does not exist.
+    BadFilter
+}
+
+impl Corruption {
+    pub const ALL: [Corruption; 4] = [Corruption::BadCrc, Corruption::Truncated, Corruption::Misordered, Corruption::BadFilter];
+}
+
+// The bytes of `png` with `corruption` applied, and the offset of the fault.
+pub fn corrupt(png: &Png, corruption: Corruption) -> Result<(Vec<u8>, usize), PngError> {
+    let ihdr = chunk_index(png, "IHDR")?;
+    let idat = chunk_index(png, "IDAT")?;
+    match corruption {
+        Corruption::BadCrc => {
+            let mut bytes = png.as_bytes();
+            let crc_end = chunk_offset(png.chunks(), idat + 1);
+            bytes[crc_end - 1] ^= 0xff;
+            Ok((bytes, chunk_offset(png.chunks(), idat)))
+        },
+        Corruption::Truncated => {
+            let mut bytes = png.as_bytes();
+            let cut = chunk_offset(png.chunks(), idat) + 8 + png.chunks()[idat].data().len() / 2;
+            bytes.truncate(cut);
+            Ok((bytes, cut))
+        },
+        Corruption::Misordered => {
+            let mut chunks = png.chunks().to_vec();
+            let header = chunks.remove(ihdr);
+            // IDAT moved down one when IHDR came out from in front of it.
+            let index = if ihdr < idat { idat } else { idat + 1 };
+            chunks.insert(index, header);
+            let offset = chunk_offset(&chunks, index);
+            Ok((png.with_chunks(chunks).as_bytes(), offset))
+        },
+        Corruption::BadFilter => {
+            let mut data = inflate(&png.image_data())?;
+            let Some(filter_type) = data.first_mut() else {
+                return Err(PngError::InvalidArgument("image data is empty"));
+            };
+            *filter_type = 5;
+            let compressed = deflate(&data, CompressionLevel::default().level())?;
+            Ok((optimize::replace_image_data(png, compressed).as_bytes(), chunk_offset(png.chunks(), idat)))
+        }
+    }
+}
+
+fn chunk_index(png: &Png, chunk_type: &str) -> Result<usize, PngError> {
+    png.chunks()
+        .iter()
+        .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+        .ok_or_else(|| PngError::ChunkNotFound(chunk_type.to_string()))
+}
+
+// Where chunk `index` starts in the file, or where the file ends for the index
+// past the last chunk.
+fn chunk_offset(chunks: &[Chunk], index: usize) -> usize {
+    Png::STANDARD_HEADER.len() + chunks[..index].iter().map(|chunk| 12 + chunk.data().len()).sum::<usize>()
+}
+
+impl FromStr for Corruption {
+    type Err = PngError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Corruption::ALL
+            .into_iter()
+            .find(|corruption| corruption.to_string() == s.to_ascii_lowercase())
+            .ok_or(PngError::InvalidArgument("corruption must be bad-crc, truncated, misordered or bad-filter"))
+    }
+}
+
+impl Display for Corruption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Corruption::BadCrc => "bad-crc",
+            Corruption::Truncated => "truncated",
+            Corruption::Misordered => "misordered",
+            Corruption::BadFilter => "bad-filter"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ihdr::ColorType, pixels, repair, validate::validate};
+
+    fn testing_png() -> Png {
+        Png::from_pixels(2, 2, ColorType::Rgb, 8, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]).unwrap()
+    }
+
+    #[test]
+    fn test_names() {
+        for corruption in Corruption::ALL {
+            assert_eq!(Corruption::from_str(&corruption.to_string()).unwrap(), corruption);
+        }
+        assert!(Corruption::from_str("bad-magic").is_err());
+    }
+
+    #[test]
+    fn test_bad_crc() {
+        let (bytes, offset) = corrupt(&testing_png(), Corruption::BadCrc).unwrap();
+        let validation = validate(&bytes);
+        let check = validation.chunks.iter().find(|check| !check.passed()).unwrap();
+        assert_eq!((check.offset, &check.chunk_type), (offset, b"IDAT"));
+        assert!(check.problems[0].starts_with("crc mismatch"));
+        let (repaired, _) = repair::repair(&bytes, false).unwrap();
+        assert_eq!(repaired.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let png = testing_png();
+        let (bytes, offset) = corrupt(&png, Corruption::Truncated).unwrap();
+        assert_eq!(bytes.len(), offset);
+        assert!(!validate(&bytes).passed());
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_misordered() {
+        let (bytes, offset) = corrupt(&testing_png(), Corruption::Misordered).unwrap();
+        let validation = validate(&bytes);
+        assert_eq!(validation.chunks[1].offset, offset);
+        assert_eq!(&validation.chunks[1].chunk_type, b"IHDR");
+        assert!(validation.chunks[1].problems.contains(&"must be the first chunk".to_string()));
+    }
+
+    #[test]
+    fn test_bad_filter() {
+        let (bytes, _) = corrupt(&testing_png(), Corruption::BadFilter).unwrap();
+        // The structure is fine, only the pixels cannot be read.
+        assert!(validate(&bytes).passed());
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+        assert!(matches!(pixels::decode_rgba(&png), Err(PngError::InvalidFilterType(5))));
+    }
+}
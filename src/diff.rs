@@ -0,0 +1,304 @@
+use std::{collections::HashSet, fmt::Display, fmt::Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::PngError, metrics, pixels::{self, ImageBuffer}, png::Png};
+
+// Identical pixels are dimmed to this fraction in the highlight image.
+const DIM: u32 = 3;
+const HIGHLIGHT: [u8; 4] = [255, 0, 64, 255];
+// Small images are scaled up in the report until they are about this wide.
+const PREVIEW_WIDTH: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkChange {
+    Same,
+    Changed,
+    Added,
+    Removed
+}
+
+// One row of the chunk table: a chunk matched between the two files by its
+// type and which occurrence of that type it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkDiff {
+    pub chunk_type: ChunkType,
+    pub occurrence: usize,
+    // Data lengths, `None` where the file does not have the chunk.
+    pub before: Option<u32>,
+    pub after: Option<u32>,
+    pub change: ChunkChange
+}
+
+// The pixel comparison of two images of the same size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PixelDiff {
+    pub differing: usize,
+    // Each channel averaged between the two images.
+    pub blend: ImageBuffer,
+    // The second image dimmed, with differing pixels in a bright color.
+    pub highlight: ImageBuffer
+}
+
+// Lines up the chunks of two files, keeping the order of `after` and putting
+// removed chunks where they were in `before`.
+pub fn diff_chunks(before: &Png, after: &Png) -> Vec<ChunkDiff> {
+    let (before, after) = (keyed(before.chunks()), keyed(after.chunks()));
+    let before_keys: HashSet<(ChunkType, usize)> = before.iter().map(|(key, _)| *key).collect();
+    let after_keys: HashSet<(ChunkType, usize)> = after.iter().map(|(key, _)| *key).collect();
+    let mut matched = HashSet::new();
+    let mut diffs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if let Some((key, chunk)) = before.get(i) {
+            if matched.contains(key) {
+                i += 1;
+                continue;
+            }
+            if !after_keys.contains(key) {
+                diffs.push(entry(*key, Some(chunk), None));
+                i += 1;
+                continue;
+            }
+        }
+        let Some((key, chunk)) = after.get(j) else {
+            break;
+        };
+        let old = before.iter().find(|(old_key, _)| old_key == key).map(|(_, chunk)| *chunk);
+        if before_keys.contains(key) {
+            matched.insert(*key);
+        }
+        diffs.push(entry(*key, old, Some(chunk)));
+        j += 1;
+    }
+    diffs
+}
+
+pub fn diff_pixels(before: &ImageBuffer, after: &ImageBuffer) -> Result<PixelDiff, PngError> {
+    let differing = metrics::differing_pixels(before, after)?;
+    let pairs = || before.pixels.iter().zip(after.pixels.iter());
+    let blend = pairs().map(|(a, b)| [0, 1, 2, 3].map(|i| ((a[i] as u16 + b[i] as u16).div_ceil(2)) as u8)).collect();
+    let highlight = pairs()
+        .map(|(a, b)| match a == b {
+            true => {
+                let gray = ((b[0] as u32 + b[1] as u32 + b[2] as u32) / 3 / DIM) as u8;
+                [gray, gray, gray, 255]
+            },
+            false => HIGHLIGHT
+        })
+        .collect();
+    Ok(PixelDiff {
+        differing,
+        blend: ImageBuffer::new(after.width, after.height, blend)?,
+        highlight: ImageBuffer::new(after.width, after.height, highlight)?
+    })
+}
+
+// A standalone HTML page with the chunk table, both images side by side, and
+// when they are the same size a blend, a flicker between them and the
+// differing pixels highlighted. Images are inlined as data URIs.
+pub fn html_report(before: &Png, after: &Png, names: (&str, &str)) -> Result<String, PngError> {
+    let (before_image, after_image) = (pixels::decode_rgba(before)?, pixels::decode_rgba(after)?);
+    let mut html = String::new();
+    let title = format!("{} vs {}", escape(names.0), escape(names.1));
+    // Writing to a String cannot fail.
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, STYLE, title
+    );
+
+    html.push_str("<h2>Chunks</h2>\n<table>\n<tr><th>Chunk</th><th>Before</th><th>After</th><th></th></tr>\n");
+    for diff in diff_chunks(before, after) {
+        let length = |length: Option<u32>| length.map_or(String::from("-"), |length| format!("{} bytes", length));
+        let _ = writeln!(
+            html,
+            "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            diff.change,
+            escape(&diff.chunk_type.to_string()),
+            length(diff.before),
+            length(diff.after),
+            diff.change
+        );
+    }
+    html.push_str("</table>\n<h2>Pixels</h2>\n");
+
+    let width = preview_width(after_image.width);
+    html.push_str("<div class=\"images\">\n");
+    figure(&mut html, names.0, &before.as_bytes(), preview_width(before_image.width));
+    figure(&mut html, names.1, &after.as_bytes(), width);
+    html.push_str("</div>\n");
+    match diff_pixels(&before_image, &after_image) {
+        Ok(diff) => {
+            let total = after_image.pixels.len();
+            let _ = writeln!(html, "<p>{} of {} pixels differ.</p>", diff.differing, total);
+            html.push_str("<div class=\"images\">\n");
+            figure(&mut html, "Blend", &encode(&diff.blend)?, width);
+            let _ = writeln!(
+                html,
+                "<figure><div class=\"flicker\"><img src=\"{}\" style=\"width: {}px\"><img src=\"{}\" style=\"width: {}px\"></div><figcaption>Flicker</figcaption></figure>",
+                data_uri(&before.as_bytes()), width, data_uri(&after.as_bytes()), width
+            );
+            figure(&mut html, "Differences", &encode(&diff.highlight)?, width);
+            html.push_str("</div>\n");
+        },
+        Err(_) => {
+            let _ = writeln!(
+                html,
+                "<p>The images are different sizes ({}x{} and {}x{}), so their pixels are not compared.</p>",
+                before_image.width, before_image.height, after_image.width, after_image.height
+            );
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    Ok(html)
+}
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+td, th { padding: 0.2em 1em; text-align: left; }
+tr.changed { background: #fff3c4; }
+tr.added { background: #d8f5d0; }
+tr.removed { background: #f8d4d4; }
+.images { display: flex; flex-wrap: wrap; gap: 1em; }
+img { image-rendering: pixelated; background: repeating-conic-gradient(#ccc 0 25%, #fff 0 50%) 0 0 / 16px 16px; }
+.flicker { position: relative; }
+.flicker img + img { position: absolute; left: 0; top: 0; animation: flicker 1s steps(1) infinite; }
+@keyframes flicker { 50% { opacity: 0; } }
+";
+
+fn keyed(chunks: &[Chunk]) -> Vec<((ChunkType, usize), &Chunk)> {
+    let mut seen: Vec<ChunkType> = Vec::new();
+    chunks
+        .iter()
+        .map(|chunk| {
+            let occurrence = seen.iter().filter(|chunk_type| *chunk_type == chunk.chunk_type()).count();
+            seen.push(*chunk.chunk_type());
+            ((*chunk.chunk_type(), occurrence), chunk)
+        })
+        .collect()
+}
+
+fn entry((chunk_type, occurrence): (ChunkType, usize), before: Option<&Chunk>, after: Option<&Chunk>) -> ChunkDiff {
+    let change = match (before, after) {
+        (Some(before), Some(after)) if before.data() == after.data() => ChunkChange::Same,
+        (Some(_), Some(_)) => ChunkChange::Changed,
+        (None, _) => ChunkChange::Added,
+        (_, None) => ChunkChange::Removed
+    };
+    ChunkDiff { chunk_type, occurrence, before: before.map(Chunk::length), after: after.map(Chunk::length), change }
+}
+
+fn figure(html: &mut String, caption: &str, png: &[u8], width: u32) {
+    let _ = writeln!(
+        html,
+        "<figure><img src=\"{}\" style=\"width: {}px\"><figcaption>{}</figcaption></figure>",
+        data_uri(png), width, escape(caption)
+    );
+}
+
+fn preview_width(width: u32) -> u32 {
+    width * (PREVIEW_WIDTH / width.max(1)).max(1)
+}
+
+fn encode(image: &ImageBuffer) -> Result<Vec<u8>, PngError> {
+    Ok(Png::from_rgba(image, image.min_color_type())?.as_bytes())
+}
+
+fn data_uri(png: &[u8]) -> String {
+    format!("data:image/png;base64,{}", STANDARD.encode(png))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+impl Display for ChunkChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ChunkChange::Same => "same",
+            ChunkChange::Changed => "changed",
+            ChunkChange::Added => "added",
+            ChunkChange::Removed => "removed"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for ChunkDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let length = |length: Option<u32>| length.map_or(String::from("-"), |length| length.to_string());
+        write!(f, "{} {:>10} -> {:<10} {}", self.chunk_type, length(self.before), length(self.after), self.change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn changes(diffs: &[ChunkDiff]) -> Vec<(String, ChunkChange)> {
+        diffs.iter().map(|diff| (diff.chunk_type.to_string(), diff.change)).collect()
+    }
+
+    #[test]
+    fn test_diff_chunks() {
+        let before = Png::from_chunks(vec![
+            chunk("IHDR", &[1]),
+            chunk("tEXt", b"a\0b"),
+            chunk("gAMA", &[0, 0, 0, 1]),
+            chunk("IDAT", &[1, 2]),
+            chunk("IEND", &[]),
+        ]);
+        let after = Png::from_chunks(vec![
+            chunk("IHDR", &[1]),
+            chunk("tEXt", b"a\0c"),
+            chunk("tEXt", b"new\0d"),
+            chunk("IDAT", &[1, 2]),
+            chunk("IEND", &[]),
+        ]);
+        let diffs = diff_chunks(&before, &after);
+        assert_eq!(changes(&diffs), [
+            ("IHDR".to_string(), ChunkChange::Same),
+            ("tEXt".to_string(), ChunkChange::Changed),
+            ("gAMA".to_string(), ChunkChange::Removed),
+            ("tEXt".to_string(), ChunkChange::Added),
+            ("IDAT".to_string(), ChunkChange::Same),
+            ("IEND".to_string(), ChunkChange::Same),
+        ]);
+        assert_eq!((diffs[2].before, diffs[2].after), (Some(4), None));
+        assert_eq!(diffs[3].occurrence, 1);
+        assert_eq!(diffs[1].to_string(), "tEXt          3 -> 3          changed");
+    }
+
+    #[test]
+    fn test_diff_pixels() {
+        let before = ImageBuffer::new(2, 1, vec![[30, 60, 90, 255], [0, 0, 0, 255]]).unwrap();
+        let after = ImageBuffer::new(2, 1, vec![[30, 60, 90, 255], [255, 255, 255, 255]]).unwrap();
+        let diff = diff_pixels(&before, &after).unwrap();
+        assert_eq!(diff.differing, 1);
+        assert_eq!(diff.blend.pixels, [[30, 60, 90, 255], [128, 128, 128, 255]]);
+        assert_eq!(diff.highlight.pixels, [[20, 20, 20, 255], HIGHLIGHT]);
+        assert!(diff_pixels(&before, &ImageBuffer::new(1, 1, vec![[0; 4]]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_html_report() {
+        let before = Png::from_pixels(2, 1, ColorType::Rgb, 8, &[1, 2, 3, 4, 5, 6]).unwrap();
+        let after = Png::from_pixels(2, 1, ColorType::Rgb, 8, &[1, 2, 3, 9, 9, 9]).unwrap();
+        let html = html_report(&before, &after, ("a.png", "<b>.png")).unwrap();
+        assert!(html.contains("<title>a.png vs &lt;b&gt;.png</title>"));
+        assert!(html.contains("<tr class=\"changed\"><td>IDAT</td>"));
+        assert!(html.contains("<p>1 of 2 pixels differ.</p>"));
+        assert!(html.contains("Flicker"));
+
+        let other = Png::from_pixels(1, 1, ColorType::Rgb, 8, &[1, 2, 3]).unwrap();
+        let html = html_report(&before, &other, ("a.png", "c.png")).unwrap();
+        assert!(html.contains("different sizes (2x1 and 1x1)"));
+    }
+}
@@ -0,0 +1,136 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{envelope::Envelope, error::PngError, lsb, png::Png};
+
+// The largest chunk the spec allows.
+const MAX_CHUNK_LEN: usize = i32::MAX as usize;
+
+// Where `encode` puts a message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EmbedMode {
+    // In an ancillary chunk of its own.
+    #[default]
+    Chunk,
+    // After IEND, where decoders stop reading. Strict parsers, including
+    // this one, reject such files until `repair` cuts the trailer off.
+    Trailer,
+    // In the low bits of the pixels, see `lsb`.
+    Pixels
+}
+
+impl EmbedMode {
+    pub const ALL: [EmbedMode; 3] = [EmbedMode::Chunk, EmbedMode::Trailer, EmbedMode::Pixels];
+}
+
+impl FromStr for EmbedMode {
+    type Err = PngError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EmbedMode::ALL
+            .into_iter()
+            .find(|mode| mode.to_string() == s.to_ascii_lowercase())
+            .ok_or(PngError::InvalidArgument("mode must be chunk, trailer, pixels or auto"))
+    }
+}
+
+impl Display for EmbedMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            EmbedMode::Chunk => "chunk",
+            EmbedMode::Trailer => "trailer",
+            EmbedMode::Pixels => "pixels"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Picks a mode for a payload of `len` bytes. With `stealth` the pixels come
+// first, as nothing in the file's structure gives them away, then the
+// trailer, which chunk listings do not show. Otherwise a chunk, which every
+// tool keeps intact, unless the payload is too big for one.
+pub fn choose_mode(png: &Png, len: usize, stealth: bool) -> EmbedMode {
+    let fits_pixels = lsb::capacity(png, false).is_ok_and(|capacity| len <= capacity);
+    match stealth {
+        true if fits_pixels => EmbedMode::Pixels,
+        true => EmbedMode::Trailer,
+        false if len <= MAX_CHUNK_LEN => EmbedMode::Chunk,
+        false => EmbedMode::Trailer
+    }
+}
+
+// Splits a file into the image up to IEND and whatever follows it, which is
+// empty for a file without a trailer.
+pub fn split_trailer(bytes: &[u8]) -> Result<(Png, &[u8]), PngError> {
+    let mut offset = Png::STANDARD_HEADER.len();
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+        offset = offset.saturating_add(length).saturating_add(12);
+        if header[4..8] == *b"IEND" {
+            break;
+        }
+    }
+    let end = offset.min(bytes.len());
+    Ok((Png::try_from(&bytes[..end])?, &bytes[end..]))
+}
+
+// The message hidden in `mode` by `encode`, which must have an envelope
+// recording that mode so that stray trailers and pixel noise are not taken
+// for messages. Chunks may hold plain messages.
+pub fn find_message<'a>(mode: EmbedMode, chunk: Option<&'a [u8]>, trailer: &'a [u8], pixels: Option<&'a [u8]>) -> Option<&'a [u8]> {
+    let data = match mode {
+        EmbedMode::Chunk => return chunk.and_then(|data| Envelope::open(data).ok()),
+        EmbedMode::Trailer => trailer,
+        EmbedMode::Pixels => pixels?
+    };
+    match Envelope::inspect(data) {
+        Ok(Some((envelope, _))) if envelope.mode == mode => Envelope::open(data).ok(),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+
+    fn testing_png() -> Png {
+        let samples: Vec<u16> = (0..8 * 8 * 3).map(|i| (i % 256) as u16).collect();
+        Png::from_samples(8, 8, ColorType::Rgb, 8, &samples).unwrap()
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(EmbedMode::from_str("Trailer").unwrap(), EmbedMode::Trailer);
+        assert!(EmbedMode::from_str("auto").is_err());
+    }
+
+    #[test]
+    fn test_choose_mode() {
+        let png = testing_png();
+        // 8x8 RGB holds 20 bytes after the length.
+        assert_eq!(choose_mode(&png, 20, true), EmbedMode::Pixels);
+        assert_eq!(choose_mode(&png, 21, true), EmbedMode::Trailer);
+        assert_eq!(choose_mode(&png, 20, false), EmbedMode::Chunk);
+        let indexed = Png::from_samples(2, 1, ColorType::Grayscale, 1, &[0, 1]).unwrap();
+        assert_eq!(choose_mode(&indexed, 1, true), EmbedMode::Trailer);
+    }
+
+    #[test]
+    fn test_split_trailer() {
+        let png = testing_png();
+        let mut bytes = png.as_bytes();
+        let (parsed, trailer) = split_trailer(&bytes).unwrap();
+        assert_eq!((parsed.as_bytes(), trailer), (bytes.clone(), &[][..]));
+        bytes.extend(b"hidden");
+        assert_eq!(split_trailer(&bytes).unwrap().1, b"hidden");
+        assert!(split_trailer(&bytes[..20]).is_err());
+    }
+
+    #[test]
+    fn test_find_message() {
+        let trailer = Envelope { mode: EmbedMode::Trailer, ..Envelope::new() }.wrap(b"hello");
+        assert_eq!(find_message(EmbedMode::Trailer, None, &trailer, None), Some(&b"hello"[..]));
+        assert_eq!(find_message(EmbedMode::Pixels, None, &trailer, Some(&trailer)), None);
+        assert_eq!(find_message(EmbedMode::Trailer, None, b"junk", None), None);
+        assert_eq!(find_message(EmbedMode::Chunk, Some(b"plain"), b"", None), Some(&b"plain"[..]));
+    }
+}
@@ -0,0 +1,234 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    color::{Chromaticities, Chromaticity, Gamma, IccProfile, RenderingIntent},
+    error::PngError,
+    pixels::{self, ImageBuffer},
+    png::Png
+};
+
+// The exponent of the display the spec assumes, close to the sRGB curve.
+const DISPLAY_EXPONENT: f64 = 2.2;
+
+type Matrix = [[f64; 3]; 3];
+
+// A program that shows PNG files, with its default color handling. ICC
+// profiles are not applied; a consumer that follows iCCP shows the samples as
+// they are, as it would for a profile close to sRGB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consumer {
+    // iCCP, then sRGB, then gAMA with cHRM.
+    Chrome,
+    // iCCP, then sRGB, then gAMA. cHRM is ignored.
+    Firefox,
+    // Keeps gAMA and cHRM as metadata and shows the samples untouched.
+    ImageMagick
+}
+
+// The chunk a consumer took the color space from. Malformed chunks are
+// skipped, as decoders do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColorSource {
+    IccProfile(String),
+    Srgb(RenderingIntent),
+    Gamma { gamma: Gamma, chromaticities: Option<Chromaticities> },
+    Untagged
+}
+
+impl Consumer {
+    pub const ALL: [Consumer; 3] = [Consumer::Chrome, Consumer::Firefox, Consumer::ImageMagick];
+
+    pub fn color_source(&self, png: &Png) -> ColorSource {
+        if *self == Consumer::ImageMagick {
+            return ColorSource::Untagged;
+        }
+        if let Ok(Some(profile)) = png.chunk_as::<IccProfile>("iCCP") {
+            return ColorSource::IccProfile(profile.name);
+        }
+        if let Ok(Some(intent)) = png.chunk_as::<RenderingIntent>("sRGB") {
+            return ColorSource::Srgb(intent);
+        }
+        match png.chunk_as::<Gamma>("gAMA") {
+            // A zero gamma cannot be applied and is ignored.
+            Ok(Some(gamma)) if gamma.0 > 0 => {
+                let chromaticities = match self {
+                    Consumer::Chrome => png.chunk_as("cHRM").ok().flatten(),
+                    _ => None
+                };
+                ColorSource::Gamma { gamma, chromaticities }
+            },
+            _ => ColorSource::Untagged
+        }
+    }
+}
+
+impl FromStr for Consumer {
+    type Err = PngError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Consumer::ALL
+            .into_iter()
+            .find(|consumer| consumer.to_string() == s.to_ascii_lowercase())
+            .ok_or(PngError::InvalidArgument("consumer must be chrome, firefox or imagemagick"))
+    }
+}
+
+impl Display for Consumer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Consumer::Chrome => "chrome",
+            Consumer::Firefox => "firefox",
+            Consumer::ImageMagick => "imagemagick"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Display for ColorSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorSource::IccProfile(name) => write!(f, "iCCP {:?}, shown as sRGB", name),
+            ColorSource::Srgb(intent) => write!(f, "sRGB, {} rendering intent", intent),
+            ColorSource::Gamma { gamma, chromaticities: None } => write!(f, "gAMA {}", gamma),
+            ColorSource::Gamma { gamma, chromaticities: Some(chromaticities) } => {
+                write!(f, "gAMA {} and cHRM {}", gamma, chromaticities)
+            },
+            ColorSource::Untagged => write!(f, "no color chunks, shown as sRGB")
+        }
+    }
+}
+
+// Decodes `png` to what `consumer` shows on an sRGB display.
+pub fn emulate(png: &Png, consumer: Consumer) -> Result<(ImageBuffer, ColorSource), PngError> {
+    let mut image = pixels::decode_rgba(png)?;
+    let source = consumer.color_source(png);
+    if let ColorSource::Gamma { gamma, chromaticities } = &source {
+        // Primaries that do not form a color space are ignored.
+        let to_srgb = chromaticities.and_then(|chromaticities| to_srgb_matrix(&chromaticities));
+        let decode = 1.0 / gamma.value();
+        let encode = 1.0 / DISPLAY_EXPONENT;
+        for pixel in image.pixels.iter_mut() {
+            let light = [0, 1, 2].map(|i| (pixel[i] as f64 / 255.0).powf(decode));
+            let light = match &to_srgb {
+                Some(matrix) => multiply(matrix, light).map(|value| value.clamp(0.0, 1.0)),
+                None => light
+            };
+            for i in 0..3 {
+                pixel[i] = (light[i].powf(encode) * 255.0).round() as u8;
+            }
+        }
+    }
+    Ok((image, source))
+}
+
+// Linear RGB in the file's primaries to linear sRGB, scaling XYZ so the
+// file's white point maps to D65.
+fn to_srgb_matrix(chromaticities: &Chromaticities) -> Option<Matrix> {
+    let from_rgb = rgb_to_xyz(chromaticities)?;
+    let to_rgb = invert(&rgb_to_xyz(&Chromaticities::SRGB)?)?;
+    let (white, d65) = (xyz(&chromaticities.white)?, xyz(&Chromaticities::SRGB.white)?);
+    let adapt = [[d65[0] / white[0], 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, d65[2] / white[2]]];
+    Some(compose(&to_rgb, &compose(&adapt, &from_rgb)))
+}
+
+fn rgb_to_xyz(chromaticities: &Chromaticities) -> Option<Matrix> {
+    let [red, green, blue] = [chromaticities.red, chromaticities.green, chromaticities.blue].map(|primary| xyz(&primary));
+    let (red, green, blue) = (red?, green?, blue?);
+    let primaries = [[red[0], green[0], blue[0]], [red[1], green[1], blue[1]], [red[2], green[2], blue[2]]];
+    let scale = multiply(&invert(&primaries)?, xyz(&chromaticities.white)?);
+    Some(primaries.map(|row| [row[0] * scale[0], row[1] * scale[1], row[2] * scale[2]]))
+}
+
+// XYZ with Y = 1.
+fn xyz(chromaticity: &Chromaticity) -> Option<[f64; 3]> {
+    let (x, y) = (chromaticity.x as f64 / 100000.0, chromaticity.y as f64 / 100000.0);
+    (y > 0.0).then(|| [x / y, 1.0, (1.0 - x - y) / y])
+}
+
+fn multiply(matrix: &Matrix, vector: [f64; 3]) -> [f64; 3] {
+    matrix.map(|row| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2])
+}
+
+fn compose(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [[0.0; 3]; 3];
+    for (i, row) in product.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    product
+}
+
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let determinant: f64 = (0..3).map(|j| m[0][j] * cofactor(0, j)).sum();
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = cofactor(j, i) / determinant;
+        }
+    }
+    Some(inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+
+    fn testing_png() -> Png {
+        Png::from_pixels(2, 1, ColorType::Rgba, 8, &[64, 128, 192, 255, 255, 0, 0, 128]).unwrap()
+    }
+
+    #[test]
+    fn test_parse_consumer() {
+        assert_eq!(Consumer::from_str("Chrome").unwrap(), Consumer::Chrome);
+        assert_eq!(Consumer::from_str("imagemagick").unwrap(), Consumer::ImageMagick);
+        assert!(Consumer::from_str("safari").is_err());
+    }
+
+    #[test]
+    fn test_chunk_precedence() {
+        let mut png = testing_png();
+        png.set_gamma(Gamma(100000));
+        png.set_chromaticities(Chromaticities::SRGB);
+        assert_eq!(Consumer::Chrome.color_source(&png), ColorSource::Gamma {
+            gamma: Gamma(100000),
+            chromaticities: Some(Chromaticities::SRGB)
+        });
+        assert_eq!(Consumer::Firefox.color_source(&png), ColorSource::Gamma { gamma: Gamma(100000), chromaticities: None });
+        assert_eq!(Consumer::ImageMagick.color_source(&png), ColorSource::Untagged);
+        png.set_rendering_intent(RenderingIntent::Perceptual);
+        assert_eq!(Consumer::Firefox.color_source(&png), ColorSource::Srgb(RenderingIntent::Perceptual));
+        png.set_icc_profile(IccProfile::new("Display P3", vec![7; 10]).unwrap());
+        assert_eq!(Consumer::Chrome.color_source(&png).to_string(), "iCCP \"Display P3\", shown as sRGB");
+    }
+
+    #[test]
+    fn test_emulate_gamma() {
+        let mut png = testing_png();
+        let (untouched, _) = emulate(&png, Consumer::Chrome).unwrap();
+        png.set_gamma(Gamma(100000));
+        let (chrome, source) = emulate(&png, Consumer::Chrome).unwrap();
+        assert_eq!(source.to_string(), "gAMA 1");
+        // Linear samples come out lighter, alpha is left alone.
+        assert_eq!(chrome.pixel(0, 0), [136, 186, 224, 255]);
+        assert_eq!(chrome.pixel(1, 0), [255, 0, 0, 128]);
+        assert_eq!(emulate(&png, Consumer::ImageMagick).unwrap().0, untouched);
+    }
+
+    #[test]
+    fn test_emulate_srgb_chromaticities_change_nothing() {
+        let mut png = testing_png();
+        png.set_gamma(Gamma::from_f64(1.0 / DISPLAY_EXPONENT).unwrap());
+        png.set_chromaticities(Chromaticities::SRGB);
+        let (chrome, _) = emulate(&png, Consumer::Chrome).unwrap();
+        assert_eq!(chrome, emulate(&png, Consumer::ImageMagick).unwrap().0);
+    }
+}
@@ -0,0 +1,232 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{aead::{Aead, KeyInit, Payload}, ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    error::PngError,
+    filter::{self, FilterType},
+    ihdr::{ColorType, Ihdr},
+    palette::COLOR_DEPENDENT_TYPES,
+    png::Png,
+    zlib
+};
+
+// Private, unsafe-to-copy chunk holding the encrypted pixel data. Its payload is
+// a format version, the Argon2id parameters, salt and nonce, then the
+// ChaCha20-Poly1305 ciphertext of the original IHDR, IDAT and color chunks.
+pub const ENCRYPTED_CHUNK_TYPE: ChunkType = ChunkType(u32::from_be_bytes(*b"enCR"));
+
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = 1 + 12 + SALT_LEN + NONCE_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST
+        }
+    }
+}
+
+impl Png {
+    pub fn is_encrypted(&self) -> bool {
+        self.chunks().iter().any(|chunk| *chunk.chunk_type() == ENCRYPTED_CHUNK_TYPE)
+    }
+}
+
+// Moves the image data into an encrypted chunk and puts a black placeholder of
+// the same size in its place. Text and other metadata stay readable.
+pub fn encrypt_image(png: &Png, password: &str) -> Result<Png, PngError> {
+    encrypt_image_with(png, password, KdfParams::default())
+}
+
+pub fn encrypt_image_with(png: &Png, password: &str, params: KdfParams) -> Result<Png, PngError> {
+    if png.is_encrypted() {
+        return Err(PngError::InvalidArgument("image is already encrypted"));
+    }
+    let ihdr = png.header()?;
+    let is_protected = |chunk: &Chunk| {
+        matches!(&chunk.chunk_type().bytes(), b"IHDR" | b"IDAT") || COLOR_DEPENDENT_TYPES.contains(*chunk.chunk_type())
+    };
+    let plaintext: Vec<u8> = png.chunks().iter().filter(|chunk| is_protected(chunk)).flat_map(Chunk::as_bytes).collect();
+
+    let mut header = vec![VERSION];
+    header.extend(params.memory_kib.to_be_bytes());
+    header.extend(params.iterations.to_be_bytes());
+    header.extend(params.parallelism.to_be_bytes());
+    let mut salt_and_nonce = [0u8; SALT_LEN + NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt_and_nonce);
+    header.extend(salt_and_nonce);
+
+    let cipher = cipher(password, &header)?;
+    let nonce = Nonce::from_slice(&header[HEADER_LEN - NONCE_LEN..]);
+    let ciphertext = cipher
+        .encrypt(nonce, Payload { msg: &plaintext, aad: &header })
+        .map_err(|_| PngError::InvalidArgument("image data could not be encrypted"))?;
+    let mut payload = header;
+    payload.extend(ciphertext);
+
+    let placeholder = Ihdr::new(ihdr.width, ihdr.height, 1, ColorType::Grayscale)?;
+    let blank = vec![0u8; placeholder.row_len(ihdr.width) * ihdr.height as usize];
+    let filtered = filter::filter(&blank, 1, placeholder.row_len(ihdr.width), FilterType::None);
+    let mut placeholder_data = Some(zlib::deflate(&filtered, 9)?);
+
+    let mut chunks = Vec::new();
+    for chunk in png.chunks() {
+        match &chunk.chunk_type().bytes() {
+            b"IHDR" => chunks.push(placeholder.into()),
+            b"IDAT" => if let Some(data) = placeholder_data.take() {
+                chunks.push(Chunk::new(ENCRYPTED_CHUNK_TYPE, std::mem::take(&mut payload)));
+                chunks.push(Chunk::new(ChunkType(u32::from_be_bytes(*b"IDAT")), data));
+            },
+            _ if is_protected(chunk) => {},
+            _ => chunks.push(chunk.clone())
+        }
+    }
+    if placeholder_data.is_some() {
+        return Err(PngError::ChunkNotFound(String::from("IDAT")));
+    }
+    Ok(png.with_chunks(chunks))
+}
+
+// Restores the image data encrypted by `encrypt_image`. Fails with
+// `PngError::DecryptionFailed` for a wrong password or modified payload.
+pub fn decrypt_image(png: &Png, password: &str) -> Result<Png, PngError> {
+    let payload = png
+        .chunks()
+        .iter()
+        .find(|chunk| *chunk.chunk_type() == ENCRYPTED_CHUNK_TYPE)
+        .ok_or(PngError::ChunkNotFound(ENCRYPTED_CHUNK_TYPE.to_string()))?
+        .data();
+    if payload.len() < HEADER_LEN || payload[0] != VERSION {
+        return Err(invalid("unsupported format version"));
+    }
+    let (header, ciphertext) = payload.split_at(HEADER_LEN);
+    let nonce = Nonce::from_slice(&header[HEADER_LEN - NONCE_LEN..]);
+    let plaintext = cipher(password, header)?
+        .decrypt(nonce, Payload { msg: ciphertext, aad: header })
+        .map_err(|_| PngError::DecryptionFailed)?;
+
+    let mut restored = Vec::new();
+    let mut offset = 0;
+    while offset < plaintext.len() {
+        let length = plaintext
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()) as usize)
+            .ok_or_else(|| invalid("truncated chunk in encrypted data"))?;
+        let bytes = plaintext.get(offset..offset + length + 12).ok_or_else(|| invalid("truncated chunk in encrypted data"))?;
+        restored.push(Chunk::try_from(bytes)?);
+        offset += bytes.len();
+    }
+    let Some((ihdr, rest)) = restored.split_first().filter(|(ihdr, _)| ihdr.chunk_type().bytes() == *b"IHDR") else {
+        return Err(invalid("encrypted data does not start with IHDR"));
+    };
+
+    let mut chunks = Vec::new();
+    for chunk in png.chunks() {
+        match &chunk.chunk_type().bytes() {
+            b"IHDR" => chunks.push(ihdr.clone()),
+            b"IDAT" => {},
+            _ if *chunk.chunk_type() == ENCRYPTED_CHUNK_TYPE => chunks.extend(rest.iter().cloned()),
+            _ => chunks.push(chunk.clone())
+        }
+    }
+    Ok(png.with_chunks(chunks))
+}
+
+fn cipher(password: &str, header: &[u8]) -> Result<ChaCha20Poly1305, PngError> {
+    let number = |i: usize| u32::from_be_bytes(header[i..i + 4].try_into().unwrap());
+    let params = KdfParams { memory_kib: number(1), iterations: number(5), parallelism: number(9) };
+    let key = derive_key(password, &header[13..13 + SALT_LEN], params)?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+// Argon2id, for every key made from a password.
+pub(crate) fn derive_key(password: &str, salt: &[u8], params: KdfParams) -> Result<[u8; 32], PngError> {
+    let params = Params::new(params.memory_kib, params.iterations, params.parallelism, Some(32))
+        .map_err(|_| invalid("unsupported key derivation parameters"))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|_| invalid("key derivation failed"))?;
+    Ok(key)
+}
+
+fn invalid(reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: ENCRYPTED_CHUNK_TYPE.to_string(), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{palette::{Palette, Rgb}, pixels, text::TextChunk};
+
+    // Cheap parameters so the tests don't spend their time in Argon2.
+    const TEST_PARAMS: KdfParams = KdfParams { memory_kib: 64, iterations: 1, parallelism: 1 };
+
+    fn testing_png() -> Png {
+        let raw = [0u8, 1, 1, 0, 1, 0];
+        let filtered = filter::filter(&raw, 1, 3, FilterType::None);
+        let mut png = Png::from_chunks(vec![
+            Ihdr::new(3, 2, 8, ColorType::Indexed).unwrap().into(),
+            TextChunk::new("Title", "dice").unwrap().into(),
+            Chunk::new(ChunkType(u32::from_be_bytes(*b"IDAT")), zlib::deflate(&filtered, 6).unwrap()),
+            Chunk::new(ChunkType(u32::from_be_bytes(*b"IEND")), Vec::new()),
+        ]);
+        png.set_palette(Palette::new(vec![Rgb::new(255, 0, 0), Rgb::new(0, 0, 255)]).unwrap());
+        png
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let png = testing_png();
+        let encrypted = encrypt_image_with(&png, "hunter2", TEST_PARAMS).unwrap();
+        assert!(encrypted.is_encrypted());
+        assert_eq!(encrypted.palette().unwrap(), None);
+        assert_eq!(encrypted.text("Title"), Some(String::from("dice")));
+        let placeholder = pixels::decode_rgba(&encrypted).unwrap();
+        assert!(placeholder.pixels.iter().all(|pixel| *pixel == [0, 0, 0, 255]));
+
+        let decrypted = decrypt_image(&encrypted, "hunter2").unwrap();
+        assert!(!decrypted.is_encrypted());
+        assert_eq!(pixels::decode_rgba(&decrypted).unwrap(), pixels::decode_rgba(&png).unwrap());
+        let types: Vec<String> = decrypted.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "tEXt", "PLTE", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let encrypted = encrypt_image_with(&testing_png(), "hunter2", TEST_PARAMS).unwrap();
+        assert!(matches!(decrypt_image(&encrypted, "hunter3"), Err(PngError::DecryptionFailed)));
+    }
+
+    #[test]
+    fn test_tampered_payload() {
+        let mut encrypted = encrypt_image_with(&testing_png(), "hunter2", TEST_PARAMS).unwrap();
+        let index = encrypted.chunks().iter().position(|chunk| *chunk.chunk_type() == ENCRYPTED_CHUNK_TYPE).unwrap();
+        let mut data = encrypted.chunks()[index].data().to_vec();
+        // Weakening the stored parameters is caught as well as editing the ciphertext.
+        data[4] ^= 1;
+        encrypted.chunks[index] = Chunk::new(ENCRYPTED_CHUNK_TYPE, data);
+        assert!(matches!(decrypt_image(&encrypted, "hunter2"), Err(PngError::DecryptionFailed)));
+    }
+
+    #[test]
+    fn test_encrypt_twice() {
+        let encrypted = encrypt_image_with(&testing_png(), "hunter2", TEST_PARAMS).unwrap();
+        assert!(encrypt_image_with(&encrypted, "hunter2", TEST_PARAMS).is_err());
+        assert!(matches!(decrypt_image(&testing_png(), "hunter2"), Err(PngError::ChunkNotFound(_))));
+    }
+}
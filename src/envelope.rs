@@ -0,0 +1,184 @@
+use std::fmt::Display;
+
+use crate::{embed::EmbedMode, error::PngError, text::SOFTWARE};
+
+// Optional header in front of a message written by `encode`, so other
+// versions of the tool can tell how the payload was produced. The magic,
+// version, flags and created-by fields keep this layout in every version;
+// only what follows them may change.
+const MAGIC: [u8; 4] = *b"PDEv";
+pub const ENVELOPE_VERSION: u8 = 1;
+
+const COMPRESSED: u8 = 1;
+const ENCRYPTED: u8 = 1 << 1;
+const SHARDED: u8 = 1 << 2;
+// Neither bit is a message in a chunk.
+const TRAILER: u8 = 1 << 3;
+const PIXELS: u8 = 1 << 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub version: u8,
+    pub compressed: bool,
+    pub encrypted: bool,
+    // The payload was split across several carriers before being wrapped.
+    pub sharded: bool,
+    // Where `encode` put the message, so `decode --mode auto` can check it
+    // found a message and not stray bytes.
+    pub mode: EmbedMode,
+    pub created_by: String
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            version: ENVELOPE_VERSION,
+            compressed: false,
+            encrypted: false,
+            sharded: false,
+            mode: EmbedMode::Chunk,
+            created_by: SOFTWARE.to_string()
+        }
+    }
+
+    pub fn wrap(&self, payload: &[u8]) -> Vec<u8> {
+        let created_by = &self.created_by.as_bytes()[..self.created_by.len().min(u8::MAX as usize)];
+        let flags = [
+            (self.compressed, COMPRESSED),
+            (self.encrypted, ENCRYPTED),
+            (self.sharded, SHARDED),
+            (self.mode == EmbedMode::Trailer, TRAILER),
+            (self.mode == EmbedMode::Pixels, PIXELS),
+        ]
+            .iter()
+            .filter(|(set, _)| *set)
+            .fold(0, |flags, (_, bit)| flags | bit);
+        let mut data = MAGIC.to_vec();
+        data.extend([self.version, flags, created_by.len() as u8]);
+        data.extend(created_by);
+        data.extend(payload);
+        data
+    }
+
+    // Reads the header without interpreting the payload, so envelopes from
+    // newer versions can still be described. `None` for a plain message.
+    pub fn inspect(data: &[u8]) -> Result<Option<(Envelope, &[u8])>, PngError> {
+        if !data.starts_with(&MAGIC) {
+            return Ok(None);
+        }
+        let truncated = || PngError::InvalidArgument("envelope header is truncated");
+        let [version, flags, name_len]: [u8; 3] = data.get(4..7).ok_or_else(truncated)?.try_into().unwrap();
+        let payload_start = 7 + name_len as usize;
+        let created_by = data.get(7..payload_start).ok_or_else(truncated)?;
+        let envelope = Envelope {
+            version,
+            compressed: flags & COMPRESSED != 0,
+            encrypted: flags & ENCRYPTED != 0,
+            sharded: flags & SHARDED != 0,
+            mode: match (flags & TRAILER != 0, flags & PIXELS != 0) {
+                (true, false) => EmbedMode::Trailer,
+                (false, true) => EmbedMode::Pixels,
+                (false, false) => EmbedMode::Chunk,
+                (true, true) => return Err(PngError::InvalidArgument("envelope records more than one mode"))
+            },
+            created_by: String::from_utf8_lossy(created_by).into_owned()
+        };
+        Ok(Some((envelope, &data[payload_start..])))
+    }
+
+    // The message inside `data`, which is returned as is when it has no
+    // envelope. Envelopes this version cannot read are rejected.
+    pub fn open(data: &[u8]) -> Result<&[u8], PngError> {
+        match Envelope::inspect(data)? {
+            None => Ok(data),
+            Some((envelope, _)) if envelope.version > ENVELOPE_VERSION => {
+                Err(PngError::InvalidArgument("envelope was written by a newer version"))
+            },
+            Some((envelope, _)) if envelope.compressed || envelope.encrypted => {
+                Err(PngError::InvalidArgument("compressed or encrypted envelopes are not supported"))
+            },
+            Some((_, payload)) => Ok(payload)
+        }
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope::new()
+    }
+}
+
+impl Display for Envelope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+        writeln!(f, "{:<14} {}", "envelope:", self.version)?;
+        writeln!(f, "{:<14} {}", "created by:", self.created_by)?;
+        writeln!(f, "{:<14} {}", "compressed:", yes_no(self.compressed))?;
+        writeln!(f, "{:<14} {}", "encrypted:", yes_no(self.encrypted))?;
+        writeln!(f, "{:<14} {}", "mode:", self.mode)?;
+        write!(f, "{:<14} {}", "sharded:", yes_no(self.sharded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_and_open() {
+        let data = Envelope::new().wrap(b"hello");
+        assert!(data.starts_with(&MAGIC));
+        assert_eq!(Envelope::open(&data).unwrap(), b"hello");
+        let (envelope, payload) = Envelope::inspect(&data).unwrap().unwrap();
+        assert_eq!(envelope, Envelope::new());
+        assert_eq!(payload, b"hello");
+    }
+
+    #[test]
+    fn test_plain_message() {
+        assert!(Envelope::inspect(b"hello").unwrap().is_none());
+        assert_eq!(Envelope::open(b"hello").unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_flags() {
+        let envelope = Envelope { sharded: true, encrypted: true, ..Envelope::new() };
+        let data = envelope.wrap(b"x");
+        assert_eq!(data[5], ENCRYPTED | SHARDED);
+        assert_eq!(Envelope::inspect(&data).unwrap().unwrap().0, envelope);
+        assert!(Envelope::open(&data).is_err());
+    }
+
+    #[test]
+    fn test_mode() {
+        for mode in EmbedMode::ALL {
+            let data = Envelope { mode, ..Envelope::new() }.wrap(b"x");
+            assert_eq!(Envelope::inspect(&data).unwrap().unwrap().0.mode, mode);
+        }
+        let mut data = Envelope::new().wrap(b"x");
+        assert_eq!(data[5], 0);
+        data[5] = TRAILER | PIXELS;
+        assert!(Envelope::inspect(&data).is_err());
+    }
+
+    #[test]
+    fn test_newer_version() {
+        let data = Envelope { version: ENVELOPE_VERSION + 1, created_by: String::from("future"), ..Envelope::new() }.wrap(b"x");
+        let (envelope, _) = Envelope::inspect(&data).unwrap().unwrap();
+        assert_eq!(envelope.created_by, "future");
+        assert!(Envelope::open(&data).is_err());
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert!(Envelope::inspect(b"PDEv\x01").is_err());
+        assert!(Envelope::inspect(b"PDEv\x01\x00\x05ab").is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let text = Envelope { created_by: String::from("tool 1.0"), ..Envelope::new() }.to_string();
+        assert!(text.contains("created by:    tool 1.0"));
+        assert!(text.ends_with("sharded:       no"));
+    }
+}
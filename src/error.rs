@@ -0,0 +1,140 @@
+use alloc::string::{FromUtf8Error, String};
+use core::{fmt::Display, str::Utf8Error};
+
+use crate::chunk_type::ChunkTypeError;
+#[cfg(feature = "std")]
+use std::io;
+
+#[derive(Debug)]
+pub enum PngError {
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    Utf8(Utf8Error),
+    #[cfg(feature = "std")]
+    Compression(io::Error),
+    InvalidSignature,
+    InvalidChunkType(String),
+    // A chunk type given by name, such as on the command line, that is not one.
+    InvalidChunkTypeName { name: String, reason: ChunkTypeError },
+    CrcMismatch { chunk_type: String, stored: u32, computed: u32 },
+    LengthMismatch { declared: u32, actual: usize },
+    TruncatedChunk { offset: usize },
+    InvalidStructure { offset: usize, chunk_index: usize, reason: &'static str },
+    InvalidChunkOrder { chunk_type: String, index: usize, reason: &'static str },
+    ChunkNotFound(String),
+    InvalidChunkData { chunk_type: String, reason: &'static str },
+    InvalidFilterType(u8),
+    InvalidImageData(&'static str),
+    InvalidArgument(&'static str),
+    InvalidPalette { line: usize, reason: &'static str },
+    InvalidPolicy { line: usize, reason: &'static str },
+    KeywordNotAllowed(String),
+    UnmappedColor { x: u32, y: u32, color: [u8; 3] },
+    Cancelled,
+    DecryptionFailed
+}
+
+pub type Result<T> = core::result::Result<T, PngError>;
+
+impl Display for PngError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            PngError::Io(err) => write!(f, "io error: {}", err),
+            PngError::Utf8(err) => write!(f, "chunk data is not valid utf8: {}", err),
+            #[cfg(feature = "std")]
+            PngError::Compression(err) => write!(f, "zlib stream error: {}", err),
+            PngError::InvalidSignature => write!(f, "file does not start with the PNG signature"),
+            PngError::InvalidChunkType(chunk_type) => write!(f, "invalid chunk type {:?}", chunk_type),
+            PngError::InvalidChunkTypeName { name, reason } => write!(f, "invalid chunk type {:?}: {}", name, reason),
+            PngError::CrcMismatch { chunk_type, stored, computed } => write!(
+                f,
+                "crc mismatch in {} chunk: stored {:08x}, computed {:08x}",
+                chunk_type, stored, computed
+            ),
+            PngError::LengthMismatch { declared, actual } => write!(
+                f,
+                "chunk declares {} data bytes but has {}",
+                declared, actual
+            ),
+            PngError::TruncatedChunk { offset } => write!(f, "truncated chunk at byte offset {}", offset),
+            PngError::InvalidStructure { offset, chunk_index, reason } => write!(
+                f,
+                "{} (chunk {} at byte offset {})",
+                reason, chunk_index, offset
+            ),
+            PngError::InvalidChunkOrder { chunk_type, index, reason } => write!(f, "{} {} (chunk {})", chunk_type, reason, index),
+            PngError::ChunkNotFound(chunk_type) => write!(f, "no {} chunk found", chunk_type),
+            PngError::InvalidChunkData { chunk_type, reason } => write!(f, "invalid {} chunk: {}", chunk_type, reason),
+            PngError::InvalidFilterType(filter_type) => write!(f, "invalid scanline filter type {}", filter_type),
+            PngError::InvalidImageData(reason) => write!(f, "invalid image data: {}", reason),
+            PngError::InvalidArgument(reason) => write!(f, "invalid argument: {}", reason),
+            PngError::InvalidPalette { line, reason } => write!(f, "invalid palette on line {}: {}", line, reason),
+            PngError::InvalidPolicy { line, reason } => write!(f, "invalid keyword policy on line {}: {}", line, reason),
+            PngError::KeywordNotAllowed(keyword) => write!(f, "text keyword {:?} is not allowed by the keyword policy", keyword),
+            PngError::UnmappedColor { x, y, color: [r, g, b] } => write!(
+                f,
+                "color #{:02x}{:02x}{:02x} at ({}, {}) is not in the palette",
+                r, g, b, x, y
+            ),
+            PngError::Cancelled => write!(f, "decoding was cancelled"),
+            PngError::DecryptionFailed => write!(f, "wrong password or the encrypted data was modified")
+        }
+    }
+}
+
+impl core::error::Error for PngError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            PngError::Io(err) | PngError::Compression(err) => Some(err),
+            PngError::Utf8(err) => Some(err),
+            PngError::InvalidChunkTypeName { reason, .. } => Some(reason),
+            _ => None
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for PngError {
+    fn from(value: io::Error) -> Self {
+        PngError::Io(value)
+    }
+}
+
+impl From<Utf8Error> for PngError {
+    fn from(value: Utf8Error) -> Self {
+        PngError::Utf8(value)
+    }
+}
+
+impl From<FromUtf8Error> for PngError {
+    fn from(value: FromUtf8Error) -> Self {
+        PngError::Utf8(value.utf8_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_error_display() {
+        let err = PngError::CrcMismatch { chunk_type: String::from("RuSt"), stored: 1, computed: 2 };
+        assert_eq!(err.to_string(), "crc mismatch in RuSt chunk: stored 00000001, computed 00000002");
+    }
+
+    #[test]
+    fn test_error_from_io() {
+        let err = PngError::from(io::Error::new(io::ErrorKind::NotFound, "missing"));
+        assert!(matches!(err, PngError::Io(_)));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn test_error_from_utf8() {
+        let err = PngError::from(String::from_utf8(vec![0xff]).unwrap_err());
+        assert!(matches!(err, PngError::Utf8(_)));
+    }
+}
@@ -0,0 +1,396 @@
+use std::fmt::Display;
+
+use crate::{chunk::Chunk, chunk_type::{ChunkType, ChunkTypeSet}, error::PngError, png::Png};
+
+const EXIF_CHUNK_TYPE: ChunkType = ChunkType(u32::from_be_bytes(*b"eXIf"));
+
+const TAG_MAKE: u16 = 0x010f;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_DATE_TIME: u16 = 0x0132;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_GPS_LATITUDE_REF: u16 = 1;
+const TAG_GPS_LATITUDE: u16 = 2;
+const TAG_GPS_LONGITUDE_REF: u16 = 3;
+const TAG_GPS_LONGITUDE: u16 = 4;
+
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+
+// Decimal degrees, negative for south and west.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsCoordinates {
+    pub latitude: f64,
+    pub longitude: f64
+}
+
+// The commonly used fields of an eXIf chunk. Everything else in the TIFF
+// payload is ignored when parsing and not written back by `set_exif`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Exif {
+    // 1-8, how the image must be rotated or flipped for display.
+    pub orientation: Option<u16>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    // "YYYY:MM:DD HH:MM:SS", as EXIF stores it.
+    pub date_time: Option<String>,
+    pub date_time_original: Option<String>,
+    pub gps: Option<GpsCoordinates>
+}
+
+#[derive(Clone, Copy)]
+enum ByteOrder {
+    Little,
+    Big
+}
+
+struct Entry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    // Where the 4 byte value field is, which holds the value or its offset.
+    value_offset: usize
+}
+
+struct TiffReader<'a> {
+    data: &'a [u8],
+    order: ByteOrder
+}
+
+impl<'a> TiffReader<'a> {
+    fn new(data: &'a [u8]) -> Result<TiffReader<'a>, PngError> {
+        let order = match data.get(0..4) {
+            Some(b"II*\0") => ByteOrder::Little,
+            Some(b"MM\0*") => ByteOrder::Big,
+            _ => return Err(invalid("missing TIFF header"))
+        };
+        Ok(TiffReader { data, order })
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], PngError> {
+        self.data.get(offset..offset.saturating_add(len)).ok_or_else(|| invalid("offset points outside the payload"))
+    }
+
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        match self.order {
+            ByteOrder::Little => u16::from_le_bytes(bytes),
+            ByteOrder::Big => u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self.order {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes)
+        }
+    }
+
+    fn ifd(&self, offset: usize) -> Result<Vec<Entry>, PngError> {
+        let count = self.u16(self.bytes(offset, 2)?) as usize;
+        let entries = self.bytes(offset + 2, count * 12)?;
+        Ok(entries
+            .chunks(12)
+            .enumerate()
+            .map(|(i, entry)| Entry {
+                tag: self.u16(&entry[0..2]),
+                field_type: self.u16(&entry[2..4]),
+                count: self.u32(&entry[4..8]),
+                value_offset: offset + 2 + i * 12 + 8
+            })
+            .collect())
+    }
+
+    fn value(&self, entry: &Entry) -> Result<&'a [u8], PngError> {
+        let size = match entry.field_type {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 => 4,
+            5 | 10 | 12 => 8,
+            _ => return Err(invalid("unknown field type"))
+        };
+        let len = size * entry.count as usize;
+        if len <= 4 {
+            return self.bytes(entry.value_offset, len);
+        }
+        self.bytes(self.u32(self.bytes(entry.value_offset, 4)?) as usize, len)
+    }
+
+    fn ascii(&self, entry: &Entry) -> Result<String, PngError> {
+        let bytes = self.value(entry)?;
+        let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+        Ok(String::from_utf8_lossy(&bytes[..end]).trim_end().to_string())
+    }
+
+    fn number(&self, entry: &Entry) -> Result<u32, PngError> {
+        match entry.field_type {
+            TYPE_SHORT => Ok(self.u16(self.value(entry)?) as u32),
+            TYPE_LONG => Ok(self.u32(self.value(entry)?)),
+            _ => Err(invalid("expected a SHORT or LONG field"))
+        }
+    }
+
+    // Degrees, minutes and seconds as three RATIONALs.
+    fn degrees(&self, entry: &Entry) -> Result<f64, PngError> {
+        if entry.field_type != TYPE_RATIONAL || entry.count != 3 {
+            return Err(invalid("GPS coordinates must be three RATIONALs"));
+        }
+        let bytes = self.value(entry)?;
+        let parts: Vec<f64> = bytes
+            .chunks(8)
+            .map(|rational| match self.u32(&rational[4..8]) {
+                0 => 0.0,
+                denominator => self.u32(&rational[0..4]) as f64 / denominator as f64
+            })
+            .collect();
+        Ok(parts[0] + parts[1] / 60.0 + parts[2] / 3600.0)
+    }
+}
+
+impl Exif {
+    // Parses the TIFF payload of an eXIf chunk.
+    pub fn parse(data: &[u8]) -> Result<Exif, PngError> {
+        let reader = TiffReader::new(data)?;
+        let mut exif = Exif::default();
+        let ifd0 = reader.ifd(reader.u32(reader.bytes(4, 4)?) as usize)?;
+        for entry in ifd0.iter() {
+            match entry.tag {
+                TAG_MAKE => exif.make = Some(reader.ascii(entry)?),
+                TAG_MODEL => exif.model = Some(reader.ascii(entry)?),
+                TAG_ORIENTATION => exif.orientation = Some(reader.number(entry)? as u16),
+                TAG_DATE_TIME => exif.date_time = Some(reader.ascii(entry)?),
+                TAG_EXIF_IFD => {
+                    let sub_ifd = reader.ifd(reader.number(entry)? as usize)?;
+                    if let Some(entry) = sub_ifd.iter().find(|entry| entry.tag == TAG_DATE_TIME_ORIGINAL) {
+                        exif.date_time_original = Some(reader.ascii(entry)?);
+                    }
+                },
+                TAG_GPS_IFD => {
+                    let gps_ifd = reader.ifd(reader.number(entry)? as usize)?;
+                    let find = |tag: u16| gps_ifd.iter().find(|entry| entry.tag == tag);
+                    if let (Some(latitude), Some(longitude)) = (find(TAG_GPS_LATITUDE), find(TAG_GPS_LONGITUDE)) {
+                        let sign = |entry: Option<&Entry>, negative: &str| -> Result<f64, PngError> {
+                            Ok(match entry.map(|entry| reader.ascii(entry)).transpose()? {
+                                Some(reference) if reference == negative => -1.0,
+                                _ => 1.0
+                            })
+                        };
+                        exif.gps = Some(GpsCoordinates {
+                            latitude: sign(find(TAG_GPS_LATITUDE_REF), "S")? * reader.degrees(latitude)?,
+                            longitude: sign(find(TAG_GPS_LONGITUDE_REF), "W")? * reader.degrees(longitude)?
+                        });
+                    }
+                },
+                _ => {}
+            }
+        }
+        Ok(exif)
+    }
+
+    // Writes a big-endian TIFF payload holding the fields that are set.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ifd0 = Vec::new();
+        let mut exif_ifd = Vec::new();
+        let mut gps_ifd = Vec::new();
+        if let Some(make) = &self.make {
+            ifd0.push(ascii_field(TAG_MAKE, make));
+        }
+        if let Some(model) = &self.model {
+            ifd0.push(ascii_field(TAG_MODEL, model));
+        }
+        if let Some(orientation) = self.orientation {
+            ifd0.push((TAG_ORIENTATION, TYPE_SHORT, 1, orientation.to_be_bytes().to_vec()));
+        }
+        if let Some(date_time) = &self.date_time {
+            ifd0.push(ascii_field(TAG_DATE_TIME, date_time));
+        }
+        if let Some(date_time_original) = &self.date_time_original {
+            exif_ifd.push(ascii_field(TAG_DATE_TIME_ORIGINAL, date_time_original));
+        }
+        if let Some(gps) = self.gps {
+            gps_ifd.push(ascii_field(TAG_GPS_LATITUDE_REF, if gps.latitude < 0.0 { "S" } else { "N" }));
+            gps_ifd.push((TAG_GPS_LATITUDE, TYPE_RATIONAL, 3, degrees_field(gps.latitude)));
+            gps_ifd.push(ascii_field(TAG_GPS_LONGITUDE_REF, if gps.longitude < 0.0 { "W" } else { "E" }));
+            gps_ifd.push((TAG_GPS_LONGITUDE, TYPE_RATIONAL, 3, degrees_field(gps.longitude)));
+        }
+
+        // IFD0 comes first with the pointers to the sub-IFDs, which follow it
+        // in order, each with its out-of-line values right after it.
+        let pointer_count = [&exif_ifd, &gps_ifd].iter().filter(|ifd| !ifd.is_empty()).count();
+        let ifd0_size = ifd_size(&ifd0, pointer_count);
+        let mut next = 8 + ifd0_size;
+        for (tag, ifd) in [(TAG_EXIF_IFD, &exif_ifd), (TAG_GPS_IFD, &gps_ifd)] {
+            if !ifd.is_empty() {
+                ifd0.push((tag, TYPE_LONG, 1, (next as u32).to_be_bytes().to_vec()));
+                next += ifd_size(ifd, 0);
+            }
+        }
+
+        let mut bytes = b"MM\0*".to_vec();
+        bytes.extend(8u32.to_be_bytes());
+        write_ifd(&mut bytes, ifd0);
+        for ifd in [exif_ifd, gps_ifd] {
+            if !ifd.is_empty() {
+                write_ifd(&mut bytes, ifd);
+            }
+        }
+        bytes
+    }
+}
+
+type Field = (u16, u16, u32, Vec<u8>);
+
+fn ascii_field(tag: u16, value: &str) -> Field {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.push(0);
+    (tag, TYPE_ASCII, bytes.len() as u32, bytes)
+}
+
+// Whole degrees and minutes, and seconds in 1/1000ths.
+fn degrees_field(value: f64) -> Vec<u8> {
+    let value = value.abs();
+    let degrees = value.trunc();
+    let minutes = ((value - degrees) * 60.0).trunc();
+    let seconds = ((value - degrees) * 60.0 - minutes) * 60.0;
+    [(degrees as u32, 1), (minutes as u32, 1), ((seconds * 1000.0).round() as u32, 1000)]
+        .iter()
+        .flat_map(|(numerator, denominator): &(u32, u32)| numerator.to_be_bytes().into_iter().chain(denominator.to_be_bytes()))
+        .collect()
+}
+
+// Size of an IFD with `extra` more entries, including out-of-line values.
+fn ifd_size(fields: &[Field], extra: usize) -> usize {
+    let values: usize = fields.iter().filter(|(_, _, _, value)| value.len() > 4).map(|(_, _, _, value)| value.len().next_multiple_of(2)).sum();
+    2 + 12 * (fields.len() + extra) + 4 + values
+}
+
+fn write_ifd(bytes: &mut Vec<u8>, mut fields: Vec<Field>) {
+    fields.sort_by_key(|(tag, ..)| *tag);
+    let mut value_offset = bytes.len() + 2 + 12 * fields.len() + 4;
+    let mut values = Vec::new();
+    bytes.extend((fields.len() as u16).to_be_bytes());
+    for (tag, field_type, count, value) in fields.iter() {
+        bytes.extend(tag.to_be_bytes());
+        bytes.extend(field_type.to_be_bytes());
+        bytes.extend(count.to_be_bytes());
+        if value.len() <= 4 {
+            let mut inline = value.clone();
+            inline.resize(4, 0);
+            bytes.extend(inline);
+        } else {
+            bytes.extend((value_offset as u32).to_be_bytes());
+            values.extend(value);
+            if value.len() % 2 == 1 {
+                values.push(0);
+            }
+            value_offset += value.len().next_multiple_of(2);
+        }
+    }
+    bytes.extend(0u32.to_be_bytes());
+    bytes.extend(values);
+}
+
+impl Display for GpsCoordinates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.6}, {:.6}", self.latitude, self.longitude)
+    }
+}
+
+impl Png {
+    // The raw TIFF payload of the eXIf chunk.
+    pub fn exif_data(&self) -> Option<&[u8]> {
+        self.chunk_by_type("eXIf").map(|chunk| chunk.data())
+    }
+
+    pub fn exif(&self) -> Result<Option<Exif>, PngError> {
+        self.exif_data().map(Exif::parse).transpose()
+    }
+
+    // Replaces the eXIf chunk, or inserts one before the image data.
+    pub fn set_exif(&mut self, exif: &Exif) {
+        self.place_chunk(Chunk::new(EXIF_CHUNK_TYPE, exif.to_bytes()), &ChunkTypeSet::of(&[b"IDAT"]));
+    }
+
+    pub fn remove_exif(&mut self) -> Option<Chunk> {
+        self.remove_chunk("eXIf").ok()
+    }
+}
+
+fn invalid(reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: String::from("eXIf"), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn full_exif() -> Exif {
+        Exif {
+            orientation: Some(6),
+            make: Some(String::from("Canon")),
+            model: Some(String::from("EOS 5D")),
+            date_time: Some(String::from("2023:01:02 03:04:05")),
+            date_time_original: Some(String::from("2023:01:01 12:00:00")),
+            gps: Some(GpsCoordinates { latitude: 51.5, longitude: -0.125 })
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let exif = full_exif();
+        assert_eq!(Exif::parse(&exif.to_bytes()).unwrap(), exif);
+        assert_eq!(Exif::parse(&Exif::default().to_bytes()).unwrap(), Exif::default());
+    }
+
+    #[test]
+    fn test_parse_little_endian() {
+        // IFD0 with an inline Orientation and a Model stored after the IFD.
+        let mut data = b"II*\0".to_vec();
+        data.extend(8u32.to_le_bytes());
+        data.extend(2u16.to_le_bytes());
+        data.extend([0x12, 0x01, 3, 0, 1, 0, 0, 0, 3, 0, 0, 0]);
+        data.extend([0x10, 0x01, 2, 0, 6, 0, 0, 0, 38, 0, 0, 0]);
+        data.extend(0u32.to_le_bytes());
+        data.extend(b"Pixel\0");
+        let exif = Exif::parse(&data).unwrap();
+        assert_eq!(exif.orientation, Some(3));
+        assert_eq!(exif.model.as_deref(), Some("Pixel"));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Exif::parse(b"GIF89a").is_err());
+        let mut data = b"MM\0*".to_vec();
+        data.extend(100u32.to_be_bytes());
+        assert!(Exif::parse(&data).is_err());
+    }
+
+    #[test]
+    fn test_gps_seconds_precision() {
+        let exif = Exif { gps: Some(GpsCoordinates { latitude: -33.856784, longitude: 151.215297 }), ..Exif::default() };
+        let gps = Exif::parse(&exif.to_bytes()).unwrap().gps.unwrap();
+        assert!((gps.latitude + 33.856784).abs() < 1e-6);
+        assert!((gps.longitude - 151.215297).abs() < 1e-6);
+        assert_eq!(gps.to_string(), "-33.856784, 151.215297");
+    }
+
+    #[test]
+    fn test_set_exif() {
+        let mut png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ]);
+        assert_eq!(png.exif().unwrap(), None);
+        png.set_exif(&full_exif());
+        assert_eq!(png.chunks()[0].chunk_type().to_string(), "eXIf");
+        assert_eq!(png.exif().unwrap(), Some(full_exif()));
+        assert!(png.exif_data().unwrap().starts_with(b"MM\0*"));
+        assert!(png.remove_exif().is_some());
+    }
+}
@@ -0,0 +1,515 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{chunk::Chunk, chunk_type::{ChunkType, ChunkTypeSet}, error::PngError, png::Png};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetUnit {
+    Pixel,
+    Micrometer
+}
+
+// oFFs: position of the image on a larger page or display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageOffset {
+    pub x: i32,
+    pub y: i32,
+    pub unit: OffsetUnit
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleUnit {
+    Meter,
+    Radian
+}
+
+// sCAL: physical size of one pixel. The values are kept as the ASCII
+// floating point strings from the file so they round-trip exactly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhysicalScale {
+    pub unit: ScaleUnit,
+    pub pixel_width: String,
+    pub pixel_height: String
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DimensionUnit {
+    // Only the pixel aspect ratio is known.
+    Unknown,
+    Meter
+}
+
+// pHYs: pixels per unit along each axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+    pub x: u32,
+    pub y: u32,
+    pub unit: DimensionUnit
+}
+
+// sTER: the image is a side-by-side stereo pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoMode {
+    CrossFuse,
+    DivergingFuse
+}
+
+// gIFg: GIF Graphic Control Extension carried over by old GIF converters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GifGraphicControl {
+    pub disposal_method: u8,
+    pub user_input: bool,
+    // Delay in hundredths of a second.
+    pub delay: u16
+}
+
+// gIFx: GIF Application Extension carried over by old GIF converters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GifApplication {
+    pub identifier: [u8; 8],
+    pub authentication_code: [u8; 3],
+    pub data: Vec<u8>
+}
+
+pub const GIF_EXTENSION_TYPES: ChunkTypeSet = ChunkTypeSet::of(&[b"gIFg", b"gIFx"]);
+
+impl TryFrom<&Chunk> for ImageOffset {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = chunk_data(chunk, b"oFFs")?;
+        if data.len() != 9 {
+            return Err(invalid("oFFs", "expected 9 bytes"));
+        }
+        let unit = match data[8] {
+            0 => OffsetUnit::Pixel,
+            1 => OffsetUnit::Micrometer,
+            _ => return Err(invalid("oFFs", "unknown unit"))
+        };
+        Ok(ImageOffset {
+            x: i32::from_be_bytes(data[0..4].try_into().unwrap()),
+            y: i32::from_be_bytes(data[4..8].try_into().unwrap()),
+            unit
+        })
+    }
+}
+
+impl From<ImageOffset> for Chunk {
+    fn from(value: ImageOffset) -> Self {
+        let mut data = Vec::with_capacity(9);
+        data.extend(value.x.to_be_bytes());
+        data.extend(value.y.to_be_bytes());
+        data.push(match value.unit {
+            OffsetUnit::Pixel => 0,
+            OffsetUnit::Micrometer => 1
+        });
+        Chunk::new(ChunkType::from_str("oFFs").unwrap(), data)
+    }
+}
+
+impl Display for ImageOffset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = match self.unit {
+            OffsetUnit::Pixel => "px",
+            OffsetUnit::Micrometer => "µm"
+        };
+        write!(f, "x {} {}, y {} {}", self.x, unit, self.y, unit)
+    }
+}
+
+impl PhysicalScale {
+    pub fn new(unit: ScaleUnit, pixel_width: f64, pixel_height: f64) -> Result<PhysicalScale, PngError> {
+        if !(pixel_width > 0.0 && pixel_height > 0.0 && pixel_width.is_finite() && pixel_height.is_finite()) {
+            return Err(invalid("sCAL", "pixel size must be positive"));
+        }
+        Ok(PhysicalScale { unit, pixel_width: pixel_width.to_string(), pixel_height: pixel_height.to_string() })
+    }
+
+    pub fn width(&self) -> f64 {
+        self.pixel_width.parse().unwrap_or(f64::NAN)
+    }
+
+    pub fn height(&self) -> f64 {
+        self.pixel_height.parse().unwrap_or(f64::NAN)
+    }
+}
+
+impl TryFrom<&Chunk> for PhysicalScale {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = chunk_data(chunk, b"sCAL")?;
+        let unit = match data.first() {
+            Some(1) => ScaleUnit::Meter,
+            Some(2) => ScaleUnit::Radian,
+            _ => return Err(invalid("sCAL", "unknown unit"))
+        };
+        let values = std::str::from_utf8(&data[1..]).map_err(|_| invalid("sCAL", "pixel size is not ASCII"))?;
+        let (pixel_width, pixel_height) = values.split_once('\0').ok_or(invalid("sCAL", "missing separator"))?;
+        let is_positive = |value: &str| value.parse::<f64>().is_ok_and(|value| value > 0.0);
+        if !is_positive(pixel_width) || !is_positive(pixel_height) {
+            return Err(invalid("sCAL", "pixel size must be a positive number"));
+        }
+        Ok(PhysicalScale { unit, pixel_width: pixel_width.to_string(), pixel_height: pixel_height.to_string() })
+    }
+}
+
+impl From<PhysicalScale> for Chunk {
+    fn from(value: PhysicalScale) -> Self {
+        let mut data = vec![match value.unit {
+            ScaleUnit::Meter => 1,
+            ScaleUnit::Radian => 2
+        }];
+        data.extend(value.pixel_width.as_bytes());
+        data.push(0);
+        data.extend(value.pixel_height.as_bytes());
+        Chunk::new(ChunkType::from_str("sCAL").unwrap(), data)
+    }
+}
+
+impl Display for PhysicalScale {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = match self.unit {
+            ScaleUnit::Meter => "m",
+            ScaleUnit::Radian => "rad"
+        };
+        write!(f, "{} {} x {} {} per pixel", self.pixel_width, unit, self.pixel_height, unit)
+    }
+}
+
+impl PhysicalDimensions {
+    const METERS_PER_INCH: f64 = 0.0254;
+
+    pub fn from_dpi(dpi: f64) -> Result<PhysicalDimensions, PngError> {
+        let pixels_per_meter = (dpi / PhysicalDimensions::METERS_PER_INCH).round();
+        if !(pixels_per_meter >= 1.0 && pixels_per_meter <= u32::MAX as f64) {
+            return Err(invalid("pHYs", "dpi must be positive"));
+        }
+        let pixels_per_meter = pixels_per_meter as u32;
+        Ok(PhysicalDimensions { x: pixels_per_meter, y: pixels_per_meter, unit: DimensionUnit::Meter })
+    }
+
+    // Horizontal and vertical dots per inch, when the unit is known.
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        match self.unit {
+            DimensionUnit::Meter => Some((
+                self.x as f64 * PhysicalDimensions::METERS_PER_INCH,
+                self.y as f64 * PhysicalDimensions::METERS_PER_INCH
+            )),
+            DimensionUnit::Unknown => None
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for PhysicalDimensions {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = chunk_data(chunk, b"pHYs")?;
+        if data.len() != 9 {
+            return Err(invalid("pHYs", "expected 9 bytes"));
+        }
+        let unit = match data[8] {
+            0 => DimensionUnit::Unknown,
+            1 => DimensionUnit::Meter,
+            _ => return Err(invalid("pHYs", "unknown unit"))
+        };
+        Ok(PhysicalDimensions {
+            x: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            y: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            unit
+        })
+    }
+}
+
+impl From<PhysicalDimensions> for Chunk {
+    fn from(value: PhysicalDimensions) -> Self {
+        let mut data = Vec::with_capacity(9);
+        data.extend(value.x.to_be_bytes());
+        data.extend(value.y.to_be_bytes());
+        data.push(match value.unit {
+            DimensionUnit::Unknown => 0,
+            DimensionUnit::Meter => 1
+        });
+        Chunk::new(ChunkType::from_str("pHYs").unwrap(), data)
+    }
+}
+
+impl Display for PhysicalDimensions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.dpi() {
+            Some((x, y)) if self.x == self.y => write!(f, "{} px/m ({:.0} dpi)", self.x, x.max(y)),
+            Some((x, y)) => write!(f, "{} x {} px/m ({:.0} x {:.0} dpi)", self.x, self.y, x, y),
+            None => write!(f, "aspect ratio {}:{}", self.x, self.y)
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for StereoMode {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        match chunk_data(chunk, b"sTER")? {
+            [0] => Ok(StereoMode::CrossFuse),
+            [1] => Ok(StereoMode::DivergingFuse),
+            _ => Err(invalid("sTER", "expected a mode byte of 0 or 1"))
+        }
+    }
+}
+
+impl From<StereoMode> for Chunk {
+    fn from(value: StereoMode) -> Self {
+        let mode = match value {
+            StereoMode::CrossFuse => 0,
+            StereoMode::DivergingFuse => 1
+        };
+        Chunk::new(ChunkType::from_str("sTER").unwrap(), vec![mode])
+    }
+}
+
+impl Display for StereoMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StereoMode::CrossFuse => write!(f, "cross-fuse"),
+            StereoMode::DivergingFuse => write!(f, "diverging-fuse")
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for GifGraphicControl {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        match chunk_data(chunk, b"gIFg")? {
+            [disposal_method, user_input, delay @ ..] if delay.len() == 2 => Ok(GifGraphicControl {
+                disposal_method: *disposal_method,
+                user_input: *user_input != 0,
+                delay: u16::from_be_bytes([delay[0], delay[1]])
+            }),
+            _ => Err(invalid("gIFg", "expected 4 bytes"))
+        }
+    }
+}
+
+impl From<GifGraphicControl> for Chunk {
+    fn from(value: GifGraphicControl) -> Self {
+        let mut data = vec![value.disposal_method, u8::from(value.user_input)];
+        data.extend(value.delay.to_be_bytes());
+        Chunk::new(ChunkType::from_str("gIFg").unwrap(), data)
+    }
+}
+
+impl Display for GifGraphicControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "disposal {}, delay {} cs", self.disposal_method, self.delay)?;
+        if self.user_input {
+            write!(f, ", waits for user input")?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&Chunk> for GifApplication {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = chunk_data(chunk, b"gIFx")?;
+        if data.len() < 11 {
+            return Err(invalid("gIFx", "expected at least 11 bytes"));
+        }
+        Ok(GifApplication {
+            identifier: data[0..8].try_into().unwrap(),
+            authentication_code: data[8..11].try_into().unwrap(),
+            data: data[11..].to_vec()
+        })
+    }
+}
+
+impl From<GifApplication> for Chunk {
+    fn from(value: GifApplication) -> Self {
+        let mut data = value.identifier.to_vec();
+        data.extend(value.authentication_code);
+        data.extend(value.data);
+        Chunk::new(ChunkType::from_str("gIFx").unwrap(), data)
+    }
+}
+
+impl Display for GifApplication {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{} ({} bytes)",
+               String::from_utf8_lossy(&self.identifier),
+               String::from_utf8_lossy(&self.authentication_code),
+               self.data.len())
+    }
+}
+
+impl Png {
+    pub fn image_offset(&self) -> Result<Option<ImageOffset>, PngError> {
+        self.chunk_as("oFFs")
+    }
+
+    pub fn set_image_offset(&mut self, offset: ImageOffset) {
+        self.place_chunk(offset.into(), &ChunkTypeSet::of(&[b"IDAT"]));
+    }
+
+    pub fn physical_scale(&self) -> Result<Option<PhysicalScale>, PngError> {
+        self.chunk_as("sCAL")
+    }
+
+    pub fn set_physical_scale(&mut self, scale: PhysicalScale) {
+        self.place_chunk(scale.into(), &ChunkTypeSet::of(&[b"IDAT"]));
+    }
+
+    pub fn physical_dimensions(&self) -> Result<Option<PhysicalDimensions>, PngError> {
+        self.chunk_as("pHYs")
+    }
+
+    pub fn set_physical_dimensions(&mut self, dimensions: PhysicalDimensions) {
+        self.place_chunk(dimensions.into(), &ChunkTypeSet::of(&[b"IDAT"]));
+    }
+
+    pub fn stereo_mode(&self) -> Result<Option<StereoMode>, PngError> {
+        self.chunk_as("sTER")
+    }
+
+    pub fn set_stereo_mode(&mut self, mode: StereoMode) {
+        self.place_chunk(mode.into(), &ChunkTypeSet::of(&[b"IDAT"]));
+    }
+
+    pub fn gif_extensions(&self) -> Vec<&Chunk> {
+        self.chunks()
+            .iter()
+            .filter(|chunk| GIF_EXTENSION_TYPES.contains(*chunk.chunk_type()))
+            .collect()
+    }
+
+    // Removes every gIFg and gIFx chunk, returning how many were dropped.
+    pub fn strip_gif_extensions(&mut self) -> usize {
+        self.strip_chunks(&GIF_EXTENSION_TYPES)
+    }
+}
+
+fn chunk_data<'a>(chunk: &'a Chunk, chunk_type: &[u8; 4]) -> Result<&'a [u8], PngError> {
+    if chunk.chunk_type().bytes() != *chunk_type {
+        return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+    }
+    Ok(chunk.data())
+}
+
+fn invalid(chunk_type: &str, reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: chunk_type.to_string(), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        Png::from_pixels(1, 1, crate::ihdr::ColorType::Rgb, 8, &[1, 2, 3]).unwrap()
+    }
+
+    #[test]
+    fn test_image_offset_round_trip() {
+        let offset = ImageOffset { x: -20, y: 300, unit: OffsetUnit::Micrometer };
+        let chunk: Chunk = offset.into();
+        assert_eq!(chunk.data(), [255, 255, 255, 236, 0, 0, 1, 44, 1]);
+        assert_eq!(ImageOffset::try_from(&chunk).unwrap(), offset);
+        assert_eq!(offset.to_string(), "x -20 µm, y 300 µm");
+    }
+
+    #[test]
+    fn test_invalid_image_offset() {
+        let chunk = Chunk::new(ChunkType::from_str("oFFs").unwrap(), vec![0; 9].into_iter().chain([2]).collect());
+        assert!(ImageOffset::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_physical_scale_round_trip() {
+        let chunk = Chunk::new(ChunkType::from_str("sCAL").unwrap(), b"\x011.5e-4\x000.00015".to_vec());
+        let scale = PhysicalScale::try_from(&chunk).unwrap();
+        assert_eq!(scale.unit, ScaleUnit::Meter);
+        assert_eq!(scale.pixel_width, "1.5e-4");
+        assert_eq!(scale.width(), scale.height());
+        let actual: Chunk = scale.into();
+        assert_eq!(actual.data(), chunk.data());
+    }
+
+    #[test]
+    fn test_invalid_physical_scale() {
+        let chunk = Chunk::new(ChunkType::from_str("sCAL").unwrap(), b"\x01-1\x002".to_vec());
+        assert!(PhysicalScale::try_from(&chunk).is_err());
+        assert!(PhysicalScale::new(ScaleUnit::Radian, 0.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_physical_dimensions_round_trip() {
+        let dimensions = PhysicalDimensions::from_dpi(300.0).unwrap();
+        assert_eq!(dimensions.x, 11811);
+        let chunk: Chunk = dimensions.into();
+        assert_eq!(chunk.data(), [0, 0, 0x2e, 0x23, 0, 0, 0x2e, 0x23, 1]);
+        assert_eq!(PhysicalDimensions::try_from(&chunk).unwrap(), dimensions);
+        assert_eq!(dimensions.to_string(), "11811 px/m (300 dpi)");
+        assert!(PhysicalDimensions::from_dpi(0.0).is_err());
+    }
+
+    #[test]
+    fn test_physical_dimensions_aspect_ratio() {
+        let dimensions = PhysicalDimensions { x: 2, y: 1, unit: DimensionUnit::Unknown };
+        assert_eq!(dimensions.dpi(), None);
+        assert_eq!(dimensions.to_string(), "aspect ratio 2:1");
+    }
+
+    #[test]
+    fn test_stereo_mode_round_trip() {
+        let chunk: Chunk = StereoMode::DivergingFuse.into();
+        assert_eq!(chunk.data(), [1]);
+        assert_eq!(StereoMode::try_from(&chunk).unwrap(), StereoMode::DivergingFuse);
+    }
+
+    #[test]
+    fn test_png_accessors() {
+        let mut png = testing_png();
+        assert_eq!(png.stereo_mode().unwrap(), None);
+        png.set_stereo_mode(StereoMode::CrossFuse);
+        png.set_image_offset(ImageOffset { x: 1, y: 2, unit: OffsetUnit::Pixel });
+        png.set_physical_scale(PhysicalScale::new(ScaleUnit::Meter, 0.001, 0.002).unwrap());
+        png.set_stereo_mode(StereoMode::DivergingFuse);
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "sTER", "oFFs", "sCAL", "IDAT", "IEND"]);
+        assert!(png.order_violations().is_empty());
+        assert_eq!(png.stereo_mode().unwrap(), Some(StereoMode::DivergingFuse));
+        assert_eq!(png.image_offset().unwrap().unwrap().y, 2);
+        assert_eq!(png.physical_scale().unwrap().unwrap().height(), 0.002);
+    }
+
+    #[test]
+    fn test_gif_graphic_control_round_trip() {
+        let control = GifGraphicControl { disposal_method: 2, user_input: false, delay: 300 };
+        let chunk: Chunk = control.into();
+        assert_eq!(chunk.data(), [2, 0, 1, 44]);
+        assert_eq!(GifGraphicControl::try_from(&chunk).unwrap(), control);
+        assert_eq!(control.to_string(), "disposal 2, delay 300 cs");
+        assert!(GifGraphicControl::try_from(&Chunk::new(ChunkType::from_str("gIFg").unwrap(), vec![0; 3])).is_err());
+    }
+
+    #[test]
+    fn test_gif_application_round_trip() {
+        let chunk = Chunk::new(ChunkType::from_str("gIFx").unwrap(), b"NETSCAPE2.0\x01\x00\x00".to_vec());
+        let application = GifApplication::try_from(&chunk).unwrap();
+        assert_eq!(&application.identifier, b"NETSCAPE");
+        assert_eq!(application.data, [1, 0, 0]);
+        assert_eq!(application.to_string(), "NETSCAPE2.0 (3 bytes)");
+        let actual: Chunk = application.into();
+        assert_eq!(actual.data(), chunk.data());
+    }
+
+    #[test]
+    fn test_strip_gif_extensions() {
+        let mut png = testing_png();
+        png.append_chunk(GifGraphicControl { disposal_method: 0, user_input: false, delay: 10 }.into());
+        png.append_chunk(Chunk::new(ChunkType::from_str("gIFx").unwrap(), vec![0; 11]));
+        assert_eq!(png.gif_extensions().len(), 2);
+        assert_eq!(png.strip_gif_extensions(), 2);
+        assert!(png.gif_extensions().is_empty());
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_wrong_chunk_type() {
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), vec![0]);
+        assert!(matches!(StereoMode::try_from(&chunk), Err(PngError::InvalidChunkType(_))));
+    }
+}
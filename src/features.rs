@@ -0,0 +1,66 @@
+use serde_json::{json, Map, Value};
+
+use crate::text::SOFTWARE;
+
+// An optional capability that scripts can check for before relying on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capability {
+    pub name: &'static str,
+    pub enabled: bool,
+    pub description: &'static str
+}
+
+// Every capability this tool may offer, and whether this build has it, read
+// from the features it was compiled with. The names stay stable across
+// versions so a missing entry means an older binary.
+pub const CAPABILITIES: [Capability; 9] = [
+    Capability { name: "crypto", enabled: cfg!(feature = "std"), description: "encrypt-image, decrypt-image and enCR messages" },
+    Capability { name: "apng", enabled: true, description: "reading and validating animated PNGs" },
+    Capability { name: "parallel-inflate", enabled: true, description: "inflating iDOT-indexed image data one thread per segment" },
+    Capability { name: "mmap", enabled: cfg!(feature = "mmap"), description: "parsing files through a memory map" },
+    Capability { name: "serde", enabled: cfg!(feature = "serde"), description: "serializing files, chunks and chunk types" },
+    Capability { name: "wasm", enabled: cfg!(feature = "wasm"), description: "wasm-bindgen wrappers for the browser" },
+    Capability { name: "image-interop", enabled: false, description: "reading and writing other image formats" },
+    Capability { name: "parallel", enabled: cfg!(feature = "rayon"), description: "verifying chunk CRCs in parallel" },
+    Capability { name: "net", enabled: false, description: "reading and writing files over the network" },
+];
+
+pub fn enabled(name: &str) -> bool {
+    CAPABILITIES.iter().any(|capability| capability.name == name && capability.enabled)
+}
+
+pub fn to_json() -> Value {
+    let features: Map<String, Value> = CAPABILITIES
+        .iter()
+        .map(|capability| (capability.name.to_string(), Value::Bool(capability.enabled)))
+        .collect();
+    json!({
+        "version": SOFTWARE,
+        "features": features
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enabled() {
+        assert!(enabled("crypto"));
+        assert!(!enabled("net"));
+        assert!(!enabled("unknown"));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let report = to_json();
+        assert_eq!(report["version"], SOFTWARE);
+        assert_eq!(report["features"]["apng"], true);
+        assert_eq!(report["features"]["parallel-inflate"], true);
+        assert_eq!(report["features"]["parallel"], cfg!(feature = "rayon"));
+        assert_eq!(report["features"]["mmap"], cfg!(feature = "mmap"));
+        assert_eq!(report["features"]["serde"], cfg!(feature = "serde"));
+        assert_eq!(report["features"]["wasm"], cfg!(feature = "wasm"));
+        assert_eq!(report["features"].as_object().unwrap().len(), CAPABILITIES.len());
+    }
+}
@@ -0,0 +1,350 @@
+use std::fmt::Display;
+
+use crate::{cancel::CancellationToken, error::PngError, ihdr::{ColorType, Ihdr}, png::Png, zlib};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4
+}
+
+impl FilterType {
+    pub const ALL: [FilterType; 5] = [
+        FilterType::None,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Average,
+        FilterType::Paeth
+    ];
+}
+
+impl TryFrom<u8> for FilterType {
+    type Error = PngError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(FilterType::None),
+            1 => Ok(FilterType::Sub),
+            2 => Ok(FilterType::Up),
+            3 => Ok(FilterType::Average),
+            4 => Ok(FilterType::Paeth),
+            _ => Err(PngError::InvalidFilterType(value))
+        }
+    }
+}
+
+impl Display for FilterType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FilterType::None => "none",
+            FilterType::Sub => "sub",
+            FilterType::Up => "up",
+            FilterType::Average => "average",
+            FilterType::Paeth => "paeth"
+        };
+        f.pad(name)
+    }
+}
+
+// How an encoder picks the filter of each scanline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterStrategy {
+    None,
+    Fixed(FilterType),
+    // Tries every filter on each scanline and keeps the one whose output has
+    // the smallest sum of absolute values, reading the bytes as signed.
+    Adaptive
+}
+
+impl FilterStrategy {
+    // Palette and packed samples compress better unfiltered; everything
+    // else is filtered adaptively.
+    pub fn for_image(ihdr: &Ihdr) -> FilterStrategy {
+        match ihdr.color_type == ColorType::Indexed || ihdr.bit_depth < 8 {
+            true => FilterStrategy::None,
+            false => FilterStrategy::Adaptive
+        }
+    }
+}
+
+// Filter type of every scanline in the image data, in stream order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterStats {
+    pub rows: Vec<FilterType>
+}
+
+pub(crate) struct ScanlineLayout {
+    pub bpp: usize,
+    pub row_len: usize,
+    pub interlaced: bool
+}
+
+impl ScanlineLayout {
+    pub fn from_png(png: &Png) -> Result<ScanlineLayout, PngError> {
+        let ihdr = png.header()?;
+        Ok(ScanlineLayout {
+            bpp: ihdr.filter_bpp(),
+            row_len: ihdr.row_len(ihdr.width),
+            interlaced: ihdr.interlaced
+        })
+    }
+}
+
+impl FilterStats {
+    // Reads only the filter byte of each scanline, so no unfiltering is done.
+    pub fn from_png(png: &Png) -> Result<FilterStats, PngError> {
+        let ihdr = png.header()?;
+        let filtered = zlib::inflate(&png.image_data())?;
+        let mut rows = Vec::new();
+        let mut offset = 0;
+        for (height, row_len) in scanline_blocks(&ihdr) {
+            for _ in 0..height {
+                let filter_byte = *filtered.get(offset).ok_or(PngError::InvalidImageData("image data ends early"))?;
+                rows.push(FilterType::try_from(filter_byte)?);
+                offset += row_len + 1;
+            }
+        }
+        Ok(FilterStats { rows })
+    }
+
+    // Scanline count per filter type, indexed by the filter type byte.
+    pub fn counts(&self) -> [usize; 5] {
+        let mut counts = [0; 5];
+        for filter_type in self.rows.iter() {
+            counts[*filter_type as usize] += 1;
+        }
+        counts
+    }
+
+    // How often consecutive scanlines use different filters.
+    pub fn switches(&self) -> usize {
+        self.rows.windows(2).filter(|pair| pair[0] != pair[1]).count()
+    }
+}
+
+// (x, y) of the first pixel and (dx, dy) between pixels for each Adam7 pass.
+pub(crate) const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)
+];
+
+// Width and height of each block of scanlines: the whole image, or each
+// non-empty Adam7 pass when interlaced.
+pub(crate) fn pass_sizes(ihdr: &Ihdr) -> Vec<(u32, u32)> {
+    if !ihdr.interlaced {
+        return vec![(ihdr.width, ihdr.height)];
+    }
+    ADAM7_PASSES
+        .iter()
+        .map(|(x0, y0, dx, dy)| (ihdr.width.saturating_sub(*x0).div_ceil(*dx), ihdr.height.saturating_sub(*y0).div_ceil(*dy)))
+        .filter(|(width, height)| *width > 0 && *height > 0)
+        .collect()
+}
+
+// Height and unfiltered row length of each block of scanlines.
+pub(crate) fn scanline_blocks(ihdr: &Ihdr) -> Vec<(usize, usize)> {
+    pass_sizes(ihdr)
+        .into_iter()
+        .map(|(width, height)| (height as usize, ihdr.row_len(width)))
+        .collect()
+}
+
+// Reverses scanline filtering. `data` holds `rows` scanlines of `row_len`
+// bytes, each prefixed by its filter type byte; the result has no filter bytes.
+pub fn unfilter(data: &[u8], bpp: usize, row_len: usize) -> Result<Vec<u8>, PngError> {
+    unfilter_with(data, bpp, row_len, &CancellationToken::new())
+}
+
+// `unfilter` that checks `token` before every scanline.
+pub fn unfilter_with(data: &[u8], bpp: usize, row_len: usize, token: &CancellationToken) -> Result<Vec<u8>, PngError> {
+    if !data.len().is_multiple_of(row_len + 1) {
+        return Err(PngError::InvalidImageData("filtered data is not a whole number of scanlines"));
+    }
+    let rows = data.len() / (row_len + 1);
+    let mut raw = vec![0u8; rows * row_len];
+    for row in 0..rows {
+        token.check()?;
+        let filter_type = FilterType::try_from(data[row * (row_len + 1)])?;
+        let line = &data[row * (row_len + 1) + 1..(row + 1) * (row_len + 1)];
+        let (previous, current) = raw.split_at_mut(row * row_len);
+        let prior = if row == 0 { None } else { Some(&previous[(row - 1) * row_len..]) };
+        let current = &mut current[..row_len];
+        for i in 0..row_len {
+            let a = if i >= bpp { current[i - bpp] } else { 0 };
+            let b = prior.map_or(0, |p| p[i]);
+            let c = if i >= bpp { prior.map_or(0, |p| p[i - bpp]) } else { 0 };
+            current[i] = line[i].wrapping_add(predict(filter_type, a, b, c));
+        }
+    }
+    Ok(raw)
+}
+
+// Applies `filter_type` to every scanline of `raw`, prefixing each with its filter byte.
+pub fn filter(raw: &[u8], bpp: usize, row_len: usize, filter_type: FilterType) -> Vec<u8> {
+    filter_with(raw, bpp, row_len, FilterStrategy::Fixed(filter_type))
+}
+
+pub fn filter_with(raw: &[u8], bpp: usize, row_len: usize, strategy: FilterStrategy) -> Vec<u8> {
+    let rows = raw.len().checked_div(row_len).unwrap_or(0);
+    let mut filtered = Vec::with_capacity(rows * (row_len + 1));
+    for row in 0..rows {
+        let prior = if row == 0 { None } else { Some(&raw[(row - 1) * row_len..row * row_len]) };
+        let current = &raw[row * row_len..(row + 1) * row_len];
+        let (filter_type, line) = match strategy {
+            FilterStrategy::None => (FilterType::None, current.to_vec()),
+            FilterStrategy::Fixed(filter_type) => (filter_type, filter_row(current, prior, bpp, filter_type)),
+            FilterStrategy::Adaptive => FilterType::ALL
+                .iter()
+                .map(|filter_type| (*filter_type, filter_row(current, prior, bpp, *filter_type)))
+                .min_by_key(|(_, line)| line.iter().map(|byte| (*byte as i8).unsigned_abs() as u64).sum::<u64>())
+                .unwrap()
+        };
+        filtered.push(filter_type as u8);
+        filtered.extend(line);
+    }
+    filtered
+}
+
+fn filter_row(current: &[u8], prior: Option<&[u8]>, bpp: usize, filter_type: FilterType) -> Vec<u8> {
+    (0..current.len())
+        .map(|i| {
+            let a = if i >= bpp { current[i - bpp] } else { 0 };
+            let b = prior.map_or(0, |p| p[i]);
+            let c = if i >= bpp { prior.map_or(0, |p| p[i - bpp]) } else { 0 };
+            current[i].wrapping_sub(predict(filter_type, a, b, c))
+        })
+        .collect()
+}
+
+fn predict(filter_type: FilterType, a: u8, b: u8, c: u8) -> u8 {
+    match filter_type {
+        FilterType::None => 0,
+        FilterType::Sub => a,
+        FilterType::Up => b,
+        FilterType::Average => ((a as u16 + b as u16) / 2) as u8,
+        FilterType::Paeth => paeth_predictor(a, b, c)
+    }
+}
+
+fn paeth_predictor(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+    fn testing_rows() -> Vec<u8> {
+        (0..48u8).map(|i| i.wrapping_mul(37).wrapping_add(i / 6)).collect()
+    }
+
+    #[test]
+    fn test_filter_round_trip() {
+        let raw = testing_rows();
+        for filter_type in FilterType::ALL {
+            let filtered = filter(&raw, 3, 12, filter_type);
+            assert_eq!(filtered.len(), 4 * 13);
+            assert_eq!(unfilter(&filtered, 3, 12).unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn test_filter_strategies_round_trip() {
+        let raw = testing_rows();
+        for strategy in [FilterStrategy::None, FilterStrategy::Fixed(FilterType::Up), FilterStrategy::Adaptive] {
+            assert_eq!(unfilter(&filter_with(&raw, 3, 12, strategy), 3, 12).unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_picks_smallest_row() {
+        // A horizontal ramp suits Sub; identical rows below it suit Up.
+        let ramp: Vec<u8> = (0..12).map(|i| i * 10).collect();
+        let raw = ramp.repeat(3);
+        let filtered = filter_with(&raw, 1, 12, FilterStrategy::Adaptive);
+        let types: Vec<u8> = filtered.chunks(13).map(|row| row[0]).collect();
+        assert_eq!(types, [FilterType::Sub as u8, FilterType::Up as u8, FilterType::Up as u8]);
+        assert!(filtered.chunks(13).skip(1).all(|row| row[1..].iter().all(|byte| *byte == 0)));
+    }
+
+    #[test]
+    fn test_strategy_for_image() {
+        assert_eq!(FilterStrategy::for_image(&Ihdr::new(1, 1, 8, ColorType::Rgb).unwrap()), FilterStrategy::Adaptive);
+        assert_eq!(FilterStrategy::for_image(&Ihdr::new(1, 1, 8, ColorType::Indexed).unwrap()), FilterStrategy::None);
+        assert_eq!(FilterStrategy::for_image(&Ihdr::new(1, 1, 4, ColorType::Grayscale).unwrap()), FilterStrategy::None);
+    }
+
+    #[test]
+    fn test_filter_none_is_identity() {
+        let raw = testing_rows();
+        let filtered = filter(&raw, 3, 12, FilterType::None);
+        assert_eq!(filtered[0], 0);
+        assert_eq!(&filtered[1..13], &raw[..12]);
+    }
+
+    fn testing_png(ihdr: Ihdr, filtered: &[u8]) -> Png {
+        Png::from_chunks(vec![
+            ihdr.into(),
+            Chunk::new(ChunkType(u32::from_be_bytes(*b"IDAT")), zlib::deflate(filtered, 6).unwrap()),
+            Chunk::new(ChunkType(u32::from_be_bytes(*b"IEND")), Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_filter_stats() {
+        let mut filtered = filter(&testing_rows(), 3, 12, FilterType::Paeth);
+        filtered[13] = FilterType::Sub as u8;
+        let ihdr = Ihdr::new(4, 4, 8, ColorType::Rgb).unwrap();
+        let stats = FilterStats::from_png(&testing_png(ihdr, &filtered)).unwrap();
+        assert_eq!(stats.rows.len(), 4);
+        assert_eq!(stats.counts(), [0, 1, 0, 0, 3]);
+        assert_eq!(stats.switches(), 2);
+    }
+
+    #[test]
+    fn test_filter_stats_truncated() {
+        let ihdr = Ihdr::new(4, 8, 8, ColorType::Rgb).unwrap();
+        let filtered = filter(&testing_rows(), 3, 12, FilterType::None);
+        assert!(FilterStats::from_png(&testing_png(ihdr, &filtered)).is_err());
+    }
+
+    #[test]
+    fn test_interlaced_scanline_blocks() {
+        let mut ihdr = Ihdr::new(3, 3, 8, ColorType::Grayscale).unwrap();
+        ihdr.interlaced = true;
+        assert_eq!(scanline_blocks(&ihdr), [(1, 1), (1, 1), (1, 2), (2, 1), (1, 3)]);
+        ihdr.interlaced = false;
+        assert_eq!(scanline_blocks(&ihdr), [(3, 3)]);
+    }
+
+    #[test]
+    fn test_paeth_predictor() {
+        assert_eq!(paeth_predictor(10, 20, 10), 20);
+        assert_eq!(paeth_predictor(20, 10, 10), 20);
+        assert_eq!(paeth_predictor(5, 5, 5), 5);
+    }
+
+    #[test]
+    fn test_unfilter_invalid_filter_type() {
+        let data = [7, 1, 2, 3];
+        assert!(matches!(unfilter(&data, 1, 3), Err(PngError::InvalidFilterType(7))));
+    }
+
+    #[test]
+    fn test_unfilter_partial_scanline() {
+        let data = [0, 1, 2];
+        assert!(unfilter(&data, 1, 3).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use std::fmt::Display;
+
+use crate::{filter::FilterStats, png::Png, text::TEXT_CHUNK_TYPES, zlib::ZlibHeader};
+
+const LIBPNG_IDAT_SIZE: u32 = 8192;
+
+// Chunk orders encoders are known for. A signature matches when its chunk
+// types appear in the file in this order, not necessarily next to each other.
+const ORDER_SIGNATURES: [(Encoder, u32, &[&str], &str); 4] = [
+    (Encoder::ImageMagick, 2, &["bKGD", "tIME", "IDAT", "tEXt"], "bKGD and tIME before the image data and tEXt after it"),
+    (Encoder::Photoshop, 1, &["iCCP", "iTXt", "IDAT"], "iCCP then iTXt ahead of the image data"),
+    (Encoder::Libpng, 1, &["tIME", "tEXt", "IDAT"], "tIME then tEXt ahead of the image data, as png_write_info writes them"),
+    (Encoder::ThisTool, 1, &["IDAT", "tIME", "IEND"], "tIME after the image data, where edits by this tool put it")
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoder {
+    Libpng,
+    Photoshop,
+    ImageMagick,
+    ThisTool
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evidence {
+    pub encoder: Encoder,
+    pub weight: u32,
+    pub reason: String
+}
+
+#[derive(Debug)]
+pub struct Fingerprint {
+    pub chunk_order: Vec<String>,
+    pub zlib_header: Option<ZlibHeader>,
+    // Scanline count per filter type, indexed by the filter type byte.
+    pub filter_counts: [usize; 5],
+    pub evidence: Vec<Evidence>
+}
+
+impl Encoder {
+    pub const ALL: [Encoder; 4] = [Encoder::Libpng, Encoder::Photoshop, Encoder::ImageMagick, Encoder::ThisTool];
+}
+
+impl Display for Encoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Encoder::Libpng => "libpng",
+            Encoder::Photoshop => "Photoshop",
+            Encoder::ImageMagick => "ImageMagick",
+            Encoder::ThisTool => "png-decode-encode"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Fingerprint {
+    pub fn from_png(png: &Png) -> Fingerprint {
+        let mut fingerprint = Fingerprint {
+            chunk_order: png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect(),
+            zlib_header: ZlibHeader::parse(&png.image_data()),
+            filter_counts: FilterStats::from_png(png).map(|stats| stats.counts()).unwrap_or_default(),
+            evidence: Vec::new()
+        };
+        fingerprint.collect_text_evidence(png);
+        fingerprint.collect_chunk_evidence(png);
+        fingerprint.collect_stream_evidence(png);
+        fingerprint.collect_order_evidence();
+        fingerprint
+    }
+
+    // Total evidence weight per encoder, highest first, omitting encoders without evidence.
+    pub fn scores(&self) -> Vec<(Encoder, u32)> {
+        let mut scores: Vec<(Encoder, u32)> = Encoder::ALL
+            .iter()
+            .map(|encoder| {
+                let score = self.evidence
+                    .iter()
+                    .filter(|evidence| evidence.encoder == *encoder)
+                    .map(|evidence| evidence.weight)
+                    .sum();
+                (*encoder, score)
+            })
+            .filter(|(_, score)| *score > 0)
+            .collect();
+        scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        scores
+    }
+
+    pub fn best_guess(&self) -> Option<Encoder> {
+        self.scores().first().map(|(encoder, _)| *encoder)
+    }
+
+    fn add(&mut self, encoder: Encoder, weight: u32, reason: impl Into<String>) {
+        self.evidence.push(Evidence { encoder, weight, reason: reason.into() });
+    }
+
+    fn collect_text_evidence(&mut self, png: &Png) {
+        for chunk in png.chunks() {
+            if !TEXT_CHUNK_TYPES.contains(*chunk.chunk_type()) {
+                continue;
+            }
+            let data = chunk.data();
+            let keyword_end = data.iter().position(|byte| *byte == 0).unwrap_or(data.len());
+            let keyword = String::from_utf8_lossy(&data[..keyword_end]).into_owned();
+            let value = String::from_utf8_lossy(&data[(keyword_end + 1).min(data.len())..]).into_owned();
+            match keyword.as_str() {
+                "Software" if value.contains("ImageMagick") => self.add(Encoder::ImageMagick, 5, "Software keyword names ImageMagick"),
+                "Software" if value.contains("Adobe") || value.contains("Photoshop") => {
+                    self.add(Encoder::Photoshop, 5, format!("Software keyword is {:?}", value))
+                },
+                "Software" if value.contains("png-decode-encode") => self.add(Encoder::ThisTool, 5, "Software keyword names this tool"),
+                "date:create" | "date:modify" | "date:timestamp" => {
+                    self.add(Encoder::ImageMagick, 3, format!("{} text keyword", keyword))
+                },
+                "XML:com.adobe.xmp" => self.add(Encoder::Photoshop, 3, "embedded Adobe XMP packet"),
+                _ => {}
+            }
+        }
+    }
+
+    fn collect_chunk_evidence(&mut self, png: &Png) {
+        if png.chunk_by_type("inTG").is_some() {
+            self.add(Encoder::ThisTool, 4, "inTG integrity chunk");
+        }
+        if png.chunk_by_type("vpAg").is_some() {
+            self.add(Encoder::ImageMagick, 3, "vpAg virtual page chunk");
+        }
+        if let Some(iccp) = png.chunk_by_type("iCCP") {
+            if iccp.data().starts_with(b"Photoshop ICC profile") {
+                self.add(Encoder::Photoshop, 3, "iCCP profile named by Photoshop");
+            }
+        }
+
+        let idat_lengths: Vec<u32> = png.chunks_by_type("IDAT").iter().map(|chunk| chunk.length()).collect();
+        if let Some((_, full)) = idat_lengths.split_last() {
+            if !full.is_empty() && full.iter().all(|length| *length == LIBPNG_IDAT_SIZE) {
+                self.add(Encoder::Libpng, 2, "IDAT split into 8192-byte chunks");
+            }
+        }
+    }
+
+    fn collect_stream_evidence(&mut self, png: &Png) {
+        if let Some(header) = self.zlib_header {
+            match header.level {
+                2 => self.add(Encoder::Libpng, 1, "zlib stream uses the default compression level"),
+                3 if png.chunks_by_type("IDAT").len() == 1 => {
+                    self.add(Encoder::ThisTool, 1, "single IDAT at maximum compression")
+                },
+                _ => {}
+            }
+        }
+        match self.filter_counts.iter().filter(|count| **count > 0).count() {
+            0 => {},
+            1 => self.add(Encoder::ThisTool, 1, "every scanline uses the same filter"),
+            _ => self.add(Encoder::Libpng, 1, "adaptive per-scanline filtering")
+        }
+    }
+
+    fn collect_order_evidence(&mut self) {
+        for (encoder, weight, signature, reason) in ORDER_SIGNATURES {
+            let mut order = self.chunk_order.iter();
+            if signature.iter().all(|chunk_type| order.any(|x| x == chunk_type)) {
+                self.add(encoder, weight, format!("chunk order: {}", reason));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::{chunk::Chunk, chunk_type::ChunkType, filter::{self, FilterType}, zlib::deflate};
+
+    fn chunk_from_bytes(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    fn testing_png(filtered: &[u8], level: u32, extra: Vec<Chunk>) -> Png {
+        let mut ihdr = Vec::new();
+        ihdr.extend(4u32.to_be_bytes());
+        ihdr.extend(4u32.to_be_bytes());
+        ihdr.extend([8, 0, 0, 0, 0]);
+        let mut chunks = vec![chunk_from_bytes("IHDR", &ihdr)];
+        chunks.extend(extra);
+        chunks.push(chunk_from_bytes("IDAT", &deflate(filtered, level).unwrap()));
+        chunks.push(chunk_from_bytes("IEND", &[]));
+        Png::from_chunks(chunks)
+    }
+
+    fn raw_rows() -> Vec<u8> {
+        (0..16).map(|i| (i * 9) as u8).collect()
+    }
+
+    #[test]
+    fn test_filter_counts() {
+        let mut filtered = filter::filter(&raw_rows(), 1, 4, FilterType::Sub);
+        filtered[5] = FilterType::None as u8;
+        let fingerprint = Fingerprint::from_png(&testing_png(&filtered, 6, Vec::new()));
+        assert_eq!(fingerprint.filter_counts, [1, 3, 0, 0, 0]);
+        assert_eq!(fingerprint.chunk_order, ["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_guess_libpng() {
+        let mut filtered = filter::filter(&raw_rows(), 1, 4, FilterType::Up);
+        filtered[0] = FilterType::None as u8;
+        let fingerprint = Fingerprint::from_png(&testing_png(&filtered, 6, Vec::new()));
+        assert_eq!(fingerprint.best_guess(), Some(Encoder::Libpng));
+    }
+
+    #[test]
+    fn test_guess_imagemagick() {
+        let filtered = filter::filter(&raw_rows(), 1, 4, FilterType::None);
+        let extra = vec![
+            chunk_from_bytes("tEXt", b"date:create\x002023-01-01T00:00:00+00:00"),
+            chunk_from_bytes("tEXt", b"Software\x00ImageMagick 7.1.0"),
+        ];
+        let fingerprint = Fingerprint::from_png(&testing_png(&filtered, 9, extra));
+        assert_eq!(fingerprint.best_guess(), Some(Encoder::ImageMagick));
+        assert_eq!(fingerprint.scores()[0], (Encoder::ImageMagick, 8));
+    }
+
+    #[test]
+    fn test_guess_photoshop() {
+        let filtered = filter::filter(&raw_rows(), 1, 4, FilterType::None);
+        let extra = vec![chunk_from_bytes("iTXt", b"XML:com.adobe.xmp\x00\x00\x00\x00\x00<x:xmpmeta/>")];
+        let fingerprint = Fingerprint::from_png(&testing_png(&filtered, 6, extra));
+        assert_eq!(fingerprint.best_guess(), Some(Encoder::Photoshop));
+    }
+
+    #[test]
+    fn test_guess_this_tool() {
+        let filtered = filter::filter(&raw_rows(), 1, 4, FilterType::Paeth);
+        let extra = vec![chunk_from_bytes("inTG", &[1; 33])];
+        let fingerprint = Fingerprint::from_png(&testing_png(&filtered, 9, extra));
+        assert_eq!(fingerprint.best_guess(), Some(Encoder::ThisTool));
+    }
+
+    #[test]
+    fn test_order_evidence() {
+        let filtered = filter::filter(&raw_rows(), 1, 4, FilterType::None);
+        let mut png = testing_png(&filtered, 9, vec![chunk_from_bytes("bKGD", &[0, 0]), chunk_from_bytes("tIME", &[7, 231, 1, 1, 0, 0, 0])]);
+        png.append_chunk(chunk_from_bytes("tEXt", b"Comment\x00x"));
+        let fingerprint = Fingerprint::from_png(&png);
+        assert!(fingerprint.evidence.iter().any(|evidence| evidence.encoder == Encoder::ImageMagick && evidence.weight == 2));
+        assert!(!fingerprint.evidence.iter().any(|evidence| evidence.encoder == Encoder::ThisTool && evidence.reason.starts_with("chunk order")));
+
+        let mut png = testing_png(&filtered, 9, Vec::new());
+        png.append_chunk(chunk_from_bytes("tIME", &[7, 231, 1, 1, 0, 0, 0]));
+        let fingerprint = Fingerprint::from_png(&png);
+        assert!(fingerprint.evidence.iter().any(|evidence| evidence.encoder == Encoder::ThisTool && evidence.reason.starts_with("chunk order")));
+    }
+
+    #[test]
+    fn test_no_evidence() {
+        let png = Png::from_chunks(vec![chunk_from_bytes("IEND", &[])]);
+        let fingerprint = Fingerprint::from_png(&png);
+        assert!(fingerprint.evidence.is_empty());
+        assert_eq!(fingerprint.best_guess(), None);
+    }
+}
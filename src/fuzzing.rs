@@ -0,0 +1,44 @@
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+// Four ASCII letters in any case, so the property bits all get exercised,
+// including the reserved one.
+impl<'a> Arbitrary<'a> for ChunkType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut bytes = [0u8; 4];
+        for byte in bytes.iter_mut() {
+            let letter = u.int_in_range(0..=25u8)?;
+            *byte = if bool::arbitrary(u)? { b'A' + letter } else { b'a' + letter };
+        }
+        Ok(ChunkType(u32::from_be_bytes(bytes)))
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (8, Some(8))
+    }
+}
+
+// A chunk with a correct length and CRC; use raw bytes to reach the error paths.
+impl<'a> Arbitrary<'a> for Chunk {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Chunk::new(ChunkType::arbitrary(u)?, Vec::arbitrary(u)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arbitrary_chunks_round_trip() {
+        let seed: Vec<u8> = (0..=255u8).cycle().take(4096).map(|byte| byte.wrapping_mul(151)).collect();
+        let mut u = Unstructured::new(&seed);
+        for _ in 0..32 {
+            let chunk = Chunk::arbitrary(&mut u).unwrap();
+            assert!(chunk.chunk_type().bytes().iter().all(u8::is_ascii_alphabetic));
+            let parsed = Chunk::try_from(chunk.as_bytes().as_slice()).unwrap();
+            assert_eq!(parsed.as_bytes(), chunk.as_bytes());
+        }
+    }
+}
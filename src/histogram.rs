@@ -0,0 +1,186 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{chunk::Chunk, chunk_type::{ChunkType, ChunkTypeSet}, error::PngError, filter, ihdr::ColorType, pixels, png::Png, zlib};
+
+// hIST: approximate usage frequency of each palette entry, one per entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    frequencies: Vec<u16>
+}
+
+impl Histogram {
+    pub fn new(frequencies: Vec<u16>) -> Histogram {
+        Histogram { frequencies }
+    }
+
+    pub fn frequencies(&self) -> &[u16] {
+        &self.frequencies
+    }
+
+    pub fn len(&self) -> usize {
+        self.frequencies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frequencies.is_empty()
+    }
+
+    // Counts how often each palette index is used by an indexed image. The
+    // counts are scaled down to fit in 16 bits when needed, keeping every
+    // used entry at 1 or more.
+    pub fn from_png(png: &Png) -> Result<Histogram, PngError> {
+        let ihdr = png.header()?;
+        if ihdr.color_type != ColorType::Indexed {
+            return Err(PngError::InvalidArgument("histograms can only be counted for indexed images"));
+        }
+        let palette = png.palette()?.ok_or(PngError::ChunkNotFound(String::from("PLTE")))?;
+        let inflated = zlib::inflate(&png.image_data())?;
+
+        let mut counts = vec![0u64; palette.len()];
+        let mut offset = 0;
+        for (width, height) in filter::pass_sizes(&ihdr) {
+            let row_len = ihdr.row_len(width);
+            let filtered = inflated
+                .get(offset..offset + (row_len + 1) * height as usize)
+                .ok_or(PngError::InvalidImageData("image data ends early"))?;
+            offset += filtered.len();
+            for row in filter::unfilter(filtered, 1, row_len)?.chunks(row_len) {
+                for x in 0..width as usize {
+                    let index = pixels::sample(row, x, ihdr.bit_depth) as usize;
+                    *counts.get_mut(index).ok_or(PngError::InvalidImageData("palette index out of range"))? += 1;
+                }
+            }
+        }
+
+        let max = counts.iter().copied().max().unwrap_or(0).max(u16::MAX as u64);
+        let frequencies = counts
+            .iter()
+            .map(|count| (count * u16::MAX as u64).div_ceil(max) as u16)
+            .collect();
+        Ok(Histogram { frequencies })
+    }
+}
+
+impl TryFrom<&Chunk> for Histogram {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if chunk.chunk_type().bytes() != *b"hIST" {
+            return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+        }
+        let data = chunk.data();
+        if !data.len().is_multiple_of(2) {
+            return Err(invalid("expected two bytes per palette entry"));
+        }
+        Ok(Histogram { frequencies: data.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect() })
+    }
+}
+
+impl From<Histogram> for Chunk {
+    fn from(value: Histogram) -> Self {
+        let data = value.frequencies.iter().flat_map(|frequency| frequency.to_be_bytes()).collect();
+        Chunk::new(ChunkType::from_str("hIST").unwrap(), data)
+    }
+}
+
+impl Display for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let used = self.frequencies.iter().filter(|frequency| **frequency > 0).count();
+        write!(f, "{} of {} palette entries used", used, self.frequencies.len())
+    }
+}
+
+impl Png {
+    // hIST is only valid alongside a PLTE with the same number of entries.
+    pub fn histogram(&self) -> Result<Option<Histogram>, PngError> {
+        let Some(histogram) = self.chunk_as::<Histogram>("hIST")? else {
+            return Ok(None);
+        };
+        check_palette_length(self, &histogram)?;
+        Ok(Some(histogram))
+    }
+
+    // Replaces the hIST chunk, or inserts one after PLTE and before the image data.
+    pub fn set_histogram(&mut self, histogram: Histogram) -> Result<(), PngError> {
+        check_palette_length(self, &histogram)?;
+        self.place_chunk(histogram.into(), &ChunkTypeSet::of(&[b"IDAT"]));
+        Ok(())
+    }
+
+    // Counts the palette usage of the image data and stores it as hIST.
+    pub fn regenerate_histogram(&mut self) -> Result<Histogram, PngError> {
+        let histogram = Histogram::from_png(self)?;
+        self.set_histogram(histogram.clone())?;
+        Ok(histogram)
+    }
+
+    pub fn remove_histogram(&mut self) -> Option<Chunk> {
+        self.remove_chunk("hIST").ok()
+    }
+}
+
+fn check_palette_length(png: &Png, histogram: &Histogram) -> Result<(), PngError> {
+    match png.palette()? {
+        None => Err(invalid("only allowed in images with a PLTE chunk")),
+        Some(palette) if palette.len() != histogram.len() => Err(invalid("must have one entry per palette entry")),
+        Some(_) => Ok(())
+    }
+}
+
+fn invalid(reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: String::from("hIST"), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{filter::FilterType, ihdr::Ihdr, palette::{Palette, Rgb}};
+
+    fn indexed_png(width: u32, bit_depth: u8, raw: &[u8], colors: usize) -> Png {
+        let row_len = raw.len();
+        let mut png = Png::from_chunks(vec![
+            Ihdr::new(width, 1, bit_depth, ColorType::Indexed).unwrap().into(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), zlib::deflate(&filter::filter(raw, 1, row_len, FilterType::Sub), 6).unwrap()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ]);
+        png.set_palette(Palette::new((0..colors).map(|i| Rgb::new(i as u8, 0, 0)).collect()).unwrap());
+        png
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let histogram = Histogram::new(vec![1, 0, 65535]);
+        let chunk: Chunk = histogram.clone().into();
+        assert_eq!(chunk.data(), [0, 1, 0, 0, 255, 255]);
+        assert_eq!(Histogram::try_from(&chunk).unwrap(), histogram);
+        assert_eq!(histogram.to_string(), "2 of 3 palette entries used");
+    }
+
+    #[test]
+    fn test_requires_matching_palette() {
+        let mut png = indexed_png(2, 8, &[0, 1], 2);
+        assert!(png.set_histogram(Histogram::new(vec![1])).is_err());
+        png.set_histogram(Histogram::new(vec![1, 1])).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "PLTE", "hIST", "IDAT", "IEND"]);
+
+        png.remove_chunk("PLTE").unwrap();
+        assert!(png.histogram().is_err());
+        assert!(png.set_histogram(Histogram::new(vec![1, 1])).is_err());
+    }
+
+    #[test]
+    fn test_regenerate_packed_indices() {
+        // Six 2-bit indices: 0, 2, 2, 1, 2, 0, then padding.
+        let mut png = indexed_png(6, 2, &[0b00_10_10_01, 0b10_00_11_11], 4);
+        let histogram = png.regenerate_histogram().unwrap();
+        assert_eq!(histogram.frequencies(), [2, 1, 3, 0]);
+        assert_eq!(png.histogram().unwrap(), Some(histogram));
+    }
+
+    #[test]
+    fn test_regenerate_scales_large_counts() {
+        let raw: Vec<u8> = (0..70000).map(|i| if i == 0 { 1 } else { 0 }).collect();
+        let png = indexed_png(70000, 8, &raw, 3);
+        assert_eq!(Histogram::from_png(&png).unwrap().frequencies(), [65535, 1, 0]);
+    }
+}
@@ -0,0 +1,356 @@
+use std::{fmt::Display, io, thread};
+
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    error::PngError,
+    filter::{self, FilterType},
+    png::Png,
+    zlib
+};
+
+const IDOT_CHUNK_TYPE: ChunkType = ChunkType(u32::from_be_bytes(*b"iDOT"));
+const IDAT_CHUNK_TYPE: ChunkType = ChunkType(u32::from_be_bytes(*b"IDAT"));
+// One thread per segment, so files with more segments are inflated the
+// usual way instead.
+const MAX_SEGMENTS: usize = 64;
+// Deflate turns one byte of input into at most 1032 bytes of output.
+const MAX_INFLATE_RATIO: usize = 1032;
+
+// A run of scanlines whose compressed data starts at an IDAT chunk and does
+// not refer back to earlier segments, so it can be inflated on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdotSegment {
+    pub rows: u32,
+    // From the start of the iDOT chunk to the start of the segment's first IDAT.
+    pub offset: u32
+}
+
+// iDOT: Apple's index of the image data. The layout is not published; as
+// written by Apple it is the segment count, a zero, the rows of the first
+// segment, the offset of the first segment, the rows of every segment and
+// the offsets of the segments after the first, all 32-bit big-endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Idot {
+    pub segments: Vec<IdotSegment>
+}
+
+impl TryFrom<&Chunk> for Idot {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if *chunk.chunk_type() != IDOT_CHUNK_TYPE {
+            return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+        }
+        let data = chunk.data();
+        if data.len() < 20 {
+            return Err(invalid("expected at least 20 bytes"));
+        }
+        let count = u32_at(data, 0) as usize;
+        if count == 0 || data.len() != 12 + 8 * count {
+            return Err(invalid("length does not match the segment count"));
+        }
+        let segments = (0..count)
+            .map(|i| IdotSegment {
+                rows: u32_at(data, 16 + 4 * i),
+                offset: if i == 0 { u32_at(data, 12) } else { u32_at(data, 12 + 4 * count + 4 * i) }
+            })
+            .collect();
+        Ok(Idot { segments })
+    }
+}
+
+impl From<Idot> for Chunk {
+    fn from(value: Idot) -> Self {
+        let first = value.segments.first().copied().unwrap_or(IdotSegment { rows: 0, offset: 0 });
+        let mut data = Vec::with_capacity(12 + 8 * value.segments.len());
+        for field in [value.segments.len() as u32, 0, first.rows, first.offset] {
+            data.extend(field.to_be_bytes());
+        }
+        data.extend(value.segments.iter().flat_map(|segment| segment.rows.to_be_bytes()));
+        data.extend(value.segments.iter().skip(1).flat_map(|segment| segment.offset.to_be_bytes()));
+        Chunk::new(IDOT_CHUNK_TYPE, data)
+    }
+}
+
+impl Display for Idot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows: Vec<String> = self.segments.iter().map(|segment| segment.rows.to_string()).collect();
+        write!(f, "{} segments of {} rows", self.segments.len(), rows.join(", "))
+    }
+}
+
+impl Png {
+    pub fn idot(&self) -> Result<Option<Idot>, PngError> {
+        self.chunk_as("iDOT")
+    }
+}
+
+// Inflates the image data one thread per iDOT segment. `None` when there is
+// no iDOT, the image is interlaced, or the segments do not line up with the
+// IDAT chunks and scanlines; callers then inflate the usual way, which also
+// reports any actual corruption.
+pub fn inflate_segments(png: &Png) -> Option<Vec<u8>> {
+    let ihdr = png.header().ok()?;
+    let idot = png.idot().ok()??;
+    if ihdr.interlaced || idot.segments.len() < 2 || idot.segments.len() > MAX_SEGMENTS {
+        return None;
+    }
+    let stride = ihdr.row_len(ihdr.width).checked_add(1)?;
+    if idot.segments.iter().map(|segment| segment.rows as u64).sum::<u64>() != ihdr.height as u64 {
+        return None;
+    }
+    // The IHDR and iDOT are not trusted to size buffers: image data that
+    // cannot inflate to the image's size is left to the usual path to reject.
+    let total = (ihdr.height as usize).checked_mul(stride)?;
+    let data = segment_data(png, &idot)?;
+    if total > data.iter().map(Vec::len).sum::<usize>().saturating_mul(MAX_INFLATE_RATIO) {
+        return None;
+    }
+    let last = data.len() - 1;
+    let results: Vec<Option<(Vec<u8>, usize)>> = thread::scope(|scope| {
+        let handles: Vec<_> = data
+            .iter()
+            .zip(idot.segments.iter())
+            .enumerate()
+            .map(|(i, (data, segment))| scope.spawn(move || inflate_segment(data, i == 0, segment.rows as usize * stride, i == last)))
+            .collect();
+        handles.into_iter().map(|handle| handle.join().ok().flatten()).collect()
+    });
+    let mut inflated = Vec::with_capacity(total);
+    let mut trailer = None;
+    for (segment, consumed) in results.into_iter().collect::<Option<Vec<_>>>()? {
+        inflated.extend(segment);
+        trailer = Some(consumed);
+    }
+    // Raw inflation skips the zlib checksum, so it is checked here.
+    let checksum = data[last].get(trailer?..trailer? + 4)?;
+    (u32::from_be_bytes(checksum.try_into().unwrap()) == adler32(&inflated)).then_some(inflated)
+}
+
+// Re-deflates the image data in two segments that can be inflated in
+// parallel and indexes them with an iDOT chunk right before the first IDAT.
+// The first scanline of the second segment is refiltered with Sub when its
+// filter reads the row above.
+pub fn with_idot(png: &Png, level: u32) -> Result<Png, PngError> {
+    let ihdr = png.header()?;
+    if ihdr.interlaced {
+        return Err(PngError::InvalidArgument("iDOT needs a non-interlaced image"));
+    }
+    if ihdr.height < 2 {
+        return Err(PngError::InvalidArgument("iDOT needs at least two rows"));
+    }
+    let row_len = ihdr.row_len(ihdr.width);
+    let stride = row_len + 1;
+    let mut filtered = zlib::inflate(&png.image_data())?;
+    if filtered.len() != stride * ihdr.height as usize {
+        return Err(PngError::InvalidImageData("image data does not match the IHDR dimensions"));
+    }
+    let first_rows = ihdr.height.div_ceil(2);
+    let split = first_rows as usize * stride;
+    if matches!(FilterType::try_from(filtered[split])?, FilterType::Up | FilterType::Average | FilterType::Paeth) {
+        let raw = filter::unfilter(&filtered[..split + stride], ihdr.filter_bpp(), row_len)?;
+        let row = filter::filter(&raw[first_rows as usize * row_len..], ihdr.filter_bpp(), row_len, FilterType::Sub);
+        filtered[split..split + stride].copy_from_slice(&row);
+    }
+
+    let mut compress = Compress::new(Compression::new(level.min(9)), true);
+    let first = deflate_part(&mut compress, &filtered[..split], FlushCompress::Full)?;
+    let second = deflate_part(&mut compress, &filtered[split..], FlushCompress::Finish)?;
+    let idot = Idot {
+        segments: vec![
+            IdotSegment { rows: first_rows, offset: 40 },
+            IdotSegment { rows: ihdr.height - first_rows, offset: 40 + 12 + first.len() as u32 },
+        ]
+    };
+    let mut image = vec![Chunk::from(idot), Chunk::new(IDAT_CHUNK_TYPE, first), Chunk::new(IDAT_CHUNK_TYPE, second)];
+    let mut chunks = Vec::new();
+    for chunk in png.chunks() {
+        match *chunk.chunk_type() {
+            IDOT_CHUNK_TYPE => {},
+            IDAT_CHUNK_TYPE => chunks.append(&mut image),
+            _ => chunks.push(chunk.clone())
+        }
+    }
+    Ok(png.with_chunks(chunks))
+}
+
+// The compressed data of each segment: the IDAT chunks from the one the
+// segment's offset points at up to the next segment's.
+fn segment_data(png: &Png, idot: &Idot) -> Option<Vec<Vec<u8>>> {
+    let chunks = png.chunks();
+    let idot_index = chunks.iter().position(|chunk| *chunk.chunk_type() == IDOT_CHUNK_TYPE)?;
+    let mut offset = 0u64;
+    let mut starts = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate().skip(idot_index) {
+        if let Some(segment) = idot.segments.get(starts.len()).filter(|segment| segment.offset as u64 == offset) {
+            if *chunk.chunk_type() != IDAT_CHUNK_TYPE || (starts.is_empty() && segment.rows == 0) {
+                return None;
+            }
+            starts.push(index);
+        }
+        offset += 12 + chunk.length() as u64;
+    }
+    let first_idat = chunks.iter().position(|chunk| *chunk.chunk_type() == IDAT_CHUNK_TYPE)?;
+    if starts.len() != idot.segments.len() || starts[0] != first_idat {
+        return None;
+    }
+    let idat_end = first_idat + chunks[first_idat..].iter().take_while(|chunk| *chunk.chunk_type() == IDAT_CHUNK_TYPE).count();
+    let mut ends = starts[1..].to_vec();
+    ends.push(idat_end);
+    starts
+        .iter()
+        .zip(ends)
+        .map(|(start, end)| (*start < end && end <= idat_end).then(|| chunks[*start..end].iter().flat_map(|chunk| chunk.data()).copied().collect()))
+        .collect()
+}
+
+// Inflates exactly `expected` bytes. Only the first segment has a zlib
+// header; the rest are raw deflate data continuing the same stream. Returns
+// the output with how much input was used.
+fn inflate_segment(data: &[u8], zlib_header: bool, expected: usize, last: bool) -> Option<(Vec<u8>, usize)> {
+    if expected > data.len().saturating_mul(MAX_INFLATE_RATIO) {
+        return None;
+    }
+    let mut decompress = Decompress::new(zlib_header);
+    let mut output = Vec::with_capacity(expected + 1);
+    loop {
+        let (consumed, produced) = (decompress.total_in() as usize, output.len());
+        let status = decompress.decompress_vec(data.get(consumed..)?, &mut output, FlushDecompress::None).ok()?;
+        let stalled = decompress.total_in() as usize == consumed && output.len() == produced;
+        if status == Status::StreamEnd || output.len() > expected || stalled {
+            // Only the last segment may finish the stream.
+            let finished = status == Status::StreamEnd;
+            return (output.len() == expected && finished == last).then_some((output, decompress.total_in() as usize));
+        }
+    }
+}
+
+fn deflate_part(compress: &mut Compress, data: &[u8], flush: FlushCompress) -> Result<Vec<u8>, PngError> {
+    let start = compress.total_in();
+    let mut output: Vec<u8> = Vec::with_capacity(data.len() / 2 + 64);
+    loop {
+        if output.len() == output.capacity() {
+            output.reserve(output.capacity());
+        }
+        let consumed = (compress.total_in() - start) as usize;
+        let status = compress
+            .compress_vec(&data[consumed..], &mut output, flush)
+            .map_err(|err| PngError::Compression(io::Error::other(err)))?;
+        let all_in = (compress.total_in() - start) as usize == data.len();
+        let done = match flush {
+            FlushCompress::Finish => status == Status::StreamEnd,
+            _ => all_in && output.len() < output.capacity()
+        };
+        if done {
+            return Ok(output);
+        }
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before b overflows.
+    for block in data.chunks(5552) {
+        for byte in block {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn invalid(reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: String::from("iDOT"), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ihdr::ColorType, pixels::{self, ImageBuffer}};
+
+    fn testing_png(width: u32, height: u32) -> Png {
+        let pixels = (0..width * height).map(|i| [(i % 251) as u8, (i / 7 % 256) as u8, (i * 3 % 256) as u8, 255]).collect();
+        Png::from_rgba(&ImageBuffer::new(width, height, pixels).unwrap(), ColorType::Rgb).unwrap()
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let idot = Idot { segments: vec![IdotSegment { rows: 3, offset: 40 }, IdotSegment { rows: 2, offset: 900 }] };
+        let chunk = Chunk::from(idot.clone());
+        assert_eq!(chunk.data().len(), 28);
+        assert_eq!(&chunk.data()[..16], [0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 40]);
+        assert_eq!(Idot::try_from(&chunk).unwrap(), idot);
+        assert_eq!(idot.to_string(), "2 segments of 3, 2 rows");
+        assert!(Idot::try_from(&Chunk::new(IDOT_CHUNK_TYPE, vec![0, 0, 0, 3, 0, 0, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn test_parallel_inflate_matches() {
+        let png = testing_png(40, 31);
+        let with_idot = with_idot(&png, 9).unwrap();
+        assert_eq!(with_idot.idot().unwrap().unwrap().segments.iter().map(|segment| segment.rows).collect::<Vec<_>>(), [16, 15]);
+        let inflated = inflate_segments(&with_idot).unwrap();
+        assert_eq!(inflated, zlib::inflate(&with_idot.image_data()).unwrap());
+        assert_eq!(pixels::decode_rgba(&with_idot).unwrap(), pixels::decode_rgba(&png).unwrap());
+        let reparsed = Png::try_from(with_idot.as_bytes().as_slice()).unwrap();
+        assert!(inflate_segments(&reparsed).is_some());
+    }
+
+    #[test]
+    fn test_refilters_segment_start() {
+        let png = testing_png(8, 4);
+        let filtered = filter::filter(&filter::unfilter(&zlib::inflate(&png.image_data()).unwrap(), 3, 24).unwrap(), 3, 24, FilterType::Paeth);
+        let paeth = crate::optimize::replace_image_data(&png, zlib::deflate(&filtered, 6).unwrap());
+        let with_idot = with_idot(&paeth, 6).unwrap();
+        let inflated = inflate_segments(&with_idot).unwrap();
+        assert_eq!(inflated[2 * 25], FilterType::Sub as u8);
+        assert_eq!(pixels::decode_rgba(&with_idot).unwrap(), pixels::decode_rgba(&png).unwrap());
+    }
+
+    #[test]
+    fn test_mismatched_index_falls_back() {
+        let png = testing_png(16, 16);
+        assert!(inflate_segments(&png).is_none());
+        let mut with_idot = with_idot(&png, 9).unwrap();
+        let mut idot = with_idot.idot().unwrap().unwrap();
+        idot.segments[1].offset += 1;
+        with_idot.replace_chunk(Chunk::from(idot));
+        assert!(inflate_segments(&with_idot).is_none());
+        assert_eq!(pixels::decode_rgba(&with_idot).unwrap(), pixels::decode_rgba(&png).unwrap());
+    }
+
+    #[test]
+    fn test_oversized_header_is_not_trusted() {
+        let ihdr = crate::ihdr::Ihdr::new(i32::MAX as u32, i32::MAX as u32, 16, ColorType::Rgba).unwrap();
+        let idot = Idot {
+            segments: vec![IdotSegment { rows: 1 << 30, offset: 40 }, IdotSegment { rows: i32::MAX as u32 - (1 << 30), offset: 56 }]
+        };
+        let png = Png::from_chunks(vec![
+            ihdr.into(),
+            Chunk::from(idot),
+            Chunk::new(IDAT_CHUNK_TYPE, vec![0x78, 0x9c, 0x03, 0x00]),
+            Chunk::new(IDAT_CHUNK_TYPE, vec![0; 4]),
+            Chunk::new(ChunkType(u32::from_be_bytes(*b"IEND")), Vec::new()),
+        ]);
+        assert!(inflate_segments(&png).is_none());
+        let many = Idot { segments: vec![IdotSegment { rows: 1, offset: 40 }; MAX_SEGMENTS + 1] };
+        let mut with_idot = with_idot(&testing_png(4, MAX_SEGMENTS as u32 + 1), 6).unwrap();
+        with_idot.replace_chunk(Chunk::from(many));
+        assert!(inflate_segments(&with_idot).is_none());
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(&[]), 1);
+    }
+}
@@ -0,0 +1,217 @@
+use alloc::{string::{String, ToString}, vec::Vec};
+use core::{fmt::Display, str::FromStr};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::PngError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayscaleAlpha = 4,
+    Rgba = 6
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    pub interlaced: bool
+}
+
+impl ColorType {
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        matches!(self, ColorType::GrayscaleAlpha | ColorType::Rgba)
+    }
+
+    // Bit depths the spec allows for this color type.
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColorType::Grayscale => &[1, 2, 4, 8, 16],
+            ColorType::Indexed => &[1, 2, 4, 8],
+            ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16]
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = PngError;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(invalid("unknown color type"))
+        }
+    }
+}
+
+impl Display for ColorType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let name = match self {
+            ColorType::Grayscale => "grayscale",
+            ColorType::Rgb => "rgb",
+            ColorType::Indexed => "indexed",
+            ColorType::GrayscaleAlpha => "grayscale+alpha",
+            ColorType::Rgba => "rgba"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Ihdr {
+    pub fn new(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> Result<Ihdr, PngError> {
+        let ihdr = Ihdr {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlaced: false
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    // Bytes per complete pixel, rounded up to 1, as used by the scanline filters.
+    pub fn filter_bpp(&self) -> usize {
+        (self.bits_per_pixel() / 8).max(1)
+    }
+
+    // Bytes in one unfiltered scanline of `width` pixels.
+    pub fn row_len(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    fn validate(&self) -> Result<(), PngError> {
+        if self.width == 0 || self.height == 0 || self.width > i32::MAX as u32 || self.height > i32::MAX as u32 {
+            return Err(invalid("width and height must be between 1 and 2^31 - 1"));
+        }
+        if !self.color_type.allowed_bit_depths().contains(&self.bit_depth) {
+            return Err(invalid("bit depth is not allowed for the color type"));
+        }
+        if self.compression_method != 0 || self.filter_method != 0 {
+            return Err(invalid("unknown compression or filter method"));
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if chunk.chunk_type().bytes() != *b"IHDR" {
+            return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+        }
+        let data = chunk.data();
+        if data.len() != 13 {
+            return Err(invalid("expected 13 bytes"));
+        }
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            height: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            bit_depth: data[8],
+            color_type: ColorType::try_from(data[9])?,
+            compression_method: data[10],
+            filter_method: data[11],
+            interlaced: match data[12] {
+                0 => false,
+                1 => true,
+                _ => return Err(invalid("unknown interlace method"))
+            }
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+}
+
+impl From<Ihdr> for Chunk {
+    fn from(value: Ihdr) -> Self {
+        let mut data = Vec::with_capacity(13);
+        data.extend(value.width.to_be_bytes());
+        data.extend(value.height.to_be_bytes());
+        data.extend([
+            value.bit_depth,
+            value.color_type as u8,
+            value.compression_method,
+            value.filter_method,
+            u8::from(value.interlaced)
+        ]);
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), data)
+    }
+}
+
+impl Display for Ihdr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}x{} {}-bit {}", self.width, self.height, self.bit_depth, self.color_type)?;
+        if self.interlaced {
+            write!(f, ", interlaced")?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: String::from("IHDR"), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ihdr_round_trip() {
+        let ihdr = Ihdr::new(640, 480, 8, ColorType::Rgba).unwrap();
+        let chunk: Chunk = ihdr.into();
+        assert_eq!(chunk.data(), [0, 0, 2, 128, 0, 0, 1, 224, 8, 6, 0, 0, 0]);
+        assert_eq!(Ihdr::try_from(&chunk).unwrap(), ihdr);
+        assert_eq!(ihdr.to_string(), "640x480 8-bit rgba");
+    }
+
+    #[test]
+    fn test_ihdr_layout() {
+        let ihdr = Ihdr::new(5, 1, 2, ColorType::Indexed).unwrap();
+        assert_eq!(ihdr.filter_bpp(), 1);
+        assert_eq!(ihdr.row_len(ihdr.width), 2);
+        let ihdr = Ihdr::new(5, 1, 16, ColorType::Rgb).unwrap();
+        assert_eq!(ihdr.filter_bpp(), 6);
+        assert_eq!(ihdr.row_len(ihdr.width), 30);
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        assert!(Ihdr::new(0, 1, 8, ColorType::Rgb).is_err());
+        assert!(Ihdr::new(1, 1, 4, ColorType::Rgb).is_err());
+        assert!(Ihdr::new(1, 1, 16, ColorType::Indexed).is_err());
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 5, 0, 0, 0]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 12]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_wrong_chunk_type() {
+        let chunk = Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new());
+        assert!(matches!(Ihdr::try_from(&chunk), Err(PngError::InvalidChunkType(_))));
+    }
+}
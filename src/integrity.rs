@@ -0,0 +1,161 @@
+use std::str::FromStr;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png};
+
+// Private, ancillary and unsafe to copy: editors that rewrite critical chunks
+// should drop it rather than carry a stale hash along.
+pub const INTEGRITY_CHUNK_TYPE: &str = "inTG";
+
+const BLAKE3_ALGORITHM: u8 = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IntegrityStatus {
+    Valid,
+    Missing,
+    Mismatch,
+    Malformed
+}
+
+// Hashes every critical chunk (length, type, data and CRC) in order.
+pub fn critical_digest<'a>(chunks: impl IntoIterator<Item = &'a Chunk>) -> blake3::Hash {
+    let mut hasher = blake3::Hasher::new();
+    for chunk in chunks.into_iter().filter(|chunk| chunk.chunk_type().is_critical()) {
+        hasher.update(&chunk.as_bytes());
+    }
+    hasher.finalize()
+}
+
+// Adds an integrity chunk right before IEND, replacing any existing one.
+pub fn append_integrity_chunk(png: &mut Png) {
+    let chunk_type = integrity_chunk_type();
+    png.chunks.retain(|chunk| chunk.chunk_type() != &chunk_type);
+    let position = png.chunks
+        .iter()
+        .position(|chunk| chunk.chunk_type().bytes() == *b"IEND")
+        .unwrap_or(png.chunks.len());
+
+    let mut data = vec![BLAKE3_ALGORITHM];
+    data.extend(critical_digest(&png.chunks[..position]).as_bytes());
+    png.chunks.insert(position, Chunk::new(chunk_type, data));
+}
+
+// The hash covers the critical chunks before the integrity chunk, so any
+// after it other than IEND were added later and count as a mismatch.
+pub fn verify_integrity(png: &Png) -> IntegrityStatus {
+    let chunk_type = integrity_chunk_type();
+    let Some(position) = png.chunks().iter().position(|chunk| chunk.chunk_type() == &chunk_type) else {
+        return IntegrityStatus::Missing;
+    };
+    let (data, after) = (png.chunks()[position].data(), &png.chunks()[position + 1..]);
+    if data.len() != 33 || data[0] != BLAKE3_ALGORITHM || after.iter().any(|chunk| chunk.chunk_type() == &chunk_type) {
+        return IntegrityStatus::Malformed;
+    }
+    if after.iter().any(|chunk| chunk.chunk_type().is_critical() && chunk.chunk_type().bytes() != *b"IEND") {
+        return IntegrityStatus::Mismatch;
+    }
+
+    let expected: [u8; 32] = data[1..].try_into().unwrap();
+    if critical_digest(&png.chunks()[..position]) == blake3::Hash::from(expected) {
+        IntegrityStatus::Valid
+    } else {
+        IntegrityStatus::Mismatch
+    }
+}
+
+fn integrity_chunk_type() -> ChunkType {
+    ChunkType::from_str(INTEGRITY_CHUNK_TYPE).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.as_bytes().to_vec())
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header"),
+            chunk_from_strings("teXt", "metadata"),
+            chunk_from_strings("IDAT", "pixels"),
+            chunk_from_strings("IEND", ""),
+        ])
+    }
+
+    #[test]
+    fn test_integrity_chunk_type_is_private_ancillary() {
+        let chunk_type = integrity_chunk_type();
+        assert!(chunk_type.is_valid());
+        assert!(!chunk_type.is_critical());
+        assert!(!chunk_type.is_public());
+        assert!(!chunk_type.is_safe_to_copy());
+    }
+
+    #[test]
+    fn test_append_integrity_chunk_before_iend() {
+        let mut png = testing_png();
+        append_integrity_chunk(&mut png);
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "teXt", "IDAT", "inTG", "IEND"]);
+        assert_eq!(verify_integrity(&png), IntegrityStatus::Valid);
+    }
+
+    #[test]
+    fn test_append_integrity_chunk_replaces_existing() {
+        let mut png = testing_png();
+        append_integrity_chunk(&mut png);
+        append_integrity_chunk(&mut png);
+        assert_eq!(png.chunks().len(), 5);
+        assert_eq!(verify_integrity(&png), IntegrityStatus::Valid);
+    }
+
+    #[test]
+    fn test_verify_integrity_missing() {
+        assert_eq!(verify_integrity(&testing_png()), IntegrityStatus::Missing);
+    }
+
+    #[test]
+    fn test_verify_integrity_detects_critical_change() {
+        let mut png = testing_png();
+        append_integrity_chunk(&mut png);
+        png.chunks[2] = chunk_from_strings("IDAT", "tampered");
+        assert_eq!(verify_integrity(&png), IntegrityStatus::Mismatch);
+    }
+
+    #[test]
+    fn test_verify_integrity_detects_critical_chunk_after_it() {
+        let mut png = testing_png();
+        append_integrity_chunk(&mut png);
+        png.chunks.insert(4, chunk_from_strings("IDAT", "appended"));
+        assert_eq!(verify_integrity(&png), IntegrityStatus::Mismatch);
+        png.chunks[4] = chunk_from_strings("PLTE", "second");
+        assert_eq!(verify_integrity(&png), IntegrityStatus::Mismatch);
+        png.chunks[4] = chunk_from_strings("teXt", "ancillary");
+        assert_eq!(verify_integrity(&png), IntegrityStatus::Valid);
+    }
+
+    #[test]
+    fn test_verify_integrity_rejects_second_integrity_chunk() {
+        let mut png = testing_png();
+        append_integrity_chunk(&mut png);
+        let copy = png.chunks[3].clone();
+        png.chunks.insert(4, copy);
+        assert_eq!(verify_integrity(&png), IntegrityStatus::Malformed);
+    }
+
+    #[test]
+    fn test_verify_integrity_ignores_ancillary_change() {
+        let mut png = testing_png();
+        append_integrity_chunk(&mut png);
+        png.chunks[1] = chunk_from_strings("teXt", "edited");
+        assert_eq!(verify_integrity(&png), IntegrityStatus::Valid);
+    }
+
+    #[test]
+    fn test_verify_integrity_malformed() {
+        let mut png = testing_png();
+        png.chunks.insert(3, chunk_from_strings(INTEGRITY_CHUNK_TYPE, "short"));
+        assert_eq!(verify_integrity(&png), IntegrityStatus::Malformed);
+    }
+}
@@ -0,0 +1,111 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod animation;
+#[cfg(feature = "std")]
+pub mod background;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod cancel;
+pub mod checksum;
+pub mod chunk;
+pub mod chunk_type;
+#[cfg(feature = "std")]
+pub mod color;
+#[cfg(feature = "std")]
+pub mod conformance;
+#[cfg(feature = "std")]
+pub mod contact_sheet;
+#[cfg(feature = "std")]
+pub mod corrupt;
+#[cfg(feature = "std")]
+pub mod context;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod embed;
+#[cfg(feature = "std")]
+pub mod emulate;
+#[cfg(feature = "std")]
+pub mod encryption;
+#[cfg(feature = "std")]
+pub mod envelope;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod exif;
+#[cfg(feature = "std")]
+pub mod extensions;
+#[cfg(feature = "std")]
+pub mod features;
+#[cfg(feature = "std")]
+pub mod filter;
+#[cfg(feature = "std")]
+pub mod fingerprint;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+#[cfg(feature = "std")]
+pub mod histogram;
+#[cfg(feature = "std")]
+pub mod idot;
+pub mod ihdr;
+#[cfg(feature = "std")]
+pub mod integrity;
+#[cfg(feature = "std")]
+pub mod lint;
+#[cfg(feature = "std")]
+pub mod lsb;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod optimize;
+#[cfg(feature = "std")]
+pub mod palette;
+#[cfg(feature = "std")]
+pub mod perceptual;
+#[cfg(feature = "std")]
+pub mod pixels;
+pub mod png;
+#[cfg(feature = "cli")]
+pub mod policy;
+#[cfg(feature = "std")]
+pub mod quantize;
+#[cfg(feature = "std")]
+pub mod reader;
+#[cfg(feature = "std")]
+pub mod scale;
+#[cfg(feature = "cli")]
+pub mod session;
+#[cfg(feature = "serde")]
+pub mod serialize;
+#[cfg(feature = "std")]
+pub mod significant_bits;
+#[cfg(feature = "std")]
+pub mod spread;
+#[cfg(feature = "std")]
+pub mod suggested_palette;
+#[cfg(feature = "std")]
+pub mod redact;
+#[cfg(feature = "std")]
+pub mod render;
+#[cfg(feature = "std")]
+pub mod repair;
+#[cfg(feature = "std")]
+pub mod robust;
+#[cfg(feature = "std")]
+pub mod text;
+pub mod time;
+#[cfg(feature = "std")]
+pub mod transparency;
+#[cfg(feature = "std")]
+pub mod validate;
+#[cfg(feature = "std")]
+pub mod vendor;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
+pub mod writer;
+#[cfg(feature = "std")]
+pub mod zlib;
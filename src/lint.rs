@@ -0,0 +1,182 @@
+use std::fmt::Display;
+
+use crate::{extensions::{GifApplication, GifGraphicControl}, png::Png, vendor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub chunk_type: String,
+    pub message: String
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning")
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} {}", self.severity, self.chunk_type, self.message)
+    }
+}
+
+pub fn lint(png: &Png) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    lint_chunk_order(png, &mut findings);
+    lint_gif_extensions(png, &mut findings);
+    lint_vendor_chunks(png, &mut findings);
+    lint_color_conflicts(png, &mut findings);
+    findings
+}
+
+fn lint_chunk_order(png: &Png, findings: &mut Vec<Finding>) {
+    for violation in png.order_violations() {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            chunk_type: violation.chunk_type.to_string(),
+            message: violation.problem.to_string()
+        });
+    }
+}
+
+// gIFg and gIFx were written by GIF converters and are ignored by every
+// modern decoder, so they are reported as legacy metadata.
+fn lint_gif_extensions(png: &Png, findings: &mut Vec<Finding>) {
+    for chunk in png.gif_extensions() {
+        let chunk_type = chunk.chunk_type().to_string();
+        let details = match chunk_type.as_str() {
+            "gIFg" => GifGraphicControl::try_from(chunk).map(|control| control.to_string()),
+            _ => GifApplication::try_from(chunk).map(|application| application.to_string())
+        };
+        let (severity, message) = match details {
+            Ok(details) => (Severity::Info, format!("legacy GIF conversion metadata ({})", details)),
+            Err(err) => (Severity::Warning, format!("malformed legacy GIF conversion metadata: {}", err))
+        };
+        findings.push(Finding { severity, chunk_type, message });
+    }
+}
+
+// Vendor chunks only mean something to the program that wrote them, so
+// they are explained to help decide whether to strip them.
+fn lint_vendor_chunks(png: &Png, findings: &mut Vec<Finding>) {
+    for chunk in png.vendor_chunks() {
+        let vendor = vendor::vendor_type(*chunk.chunk_type()).unwrap();
+        let (severity, message) = match vendor::decode_payload(chunk) {
+            Some(Ok(details)) => (Severity::Info, format!("{} {} ({})", vendor.vendor, vendor.description, details)),
+            Some(Err(err)) => (Severity::Warning, format!("malformed {} {}: {}", vendor.vendor, vendor.description, err)),
+            None => (Severity::Info, format!("{} {}", vendor.vendor, vendor.description))
+        };
+        findings.push(Finding { severity, chunk_type: chunk.chunk_type().to_string(), message });
+    }
+}
+
+// Decoders disagree on which color chunk wins, so conflicting ones show
+// different colors in different browsers.
+fn lint_color_conflicts(png: &Png, findings: &mut Vec<Finding>) {
+    for conflict in png.color_conflicts() {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            chunk_type: conflict.chunk_type().to_string(),
+            message: conflict.to_string()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::{chunk::Chunk, chunk_type::ChunkType, color::{Gamma, IccProfile, RenderingIntent}};
+
+    fn testing_png(extra: Vec<Chunk>) -> Png {
+        let mut chunks = vec![Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13])];
+        chunks.extend(extra);
+        chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_clean_png() {
+        assert!(lint(&testing_png(Vec::new())).is_empty());
+    }
+
+    #[test]
+    fn test_reports_chunk_order() {
+        let chunk = |chunk_type: &str| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; 4]);
+        let png = testing_png(vec![
+            chunk("PLTE"),
+            chunk("gAMA"),
+            chunk("IDAT"),
+            chunk("tEXt"),
+            chunk("IDAT"),
+            chunk("pHYs"),
+            chunk("gAMA"),
+        ]);
+        let findings: Vec<String> = lint(&png).iter().map(Finding::to_string).collect();
+        assert_eq!(findings, [
+            "warning: gAMA must come before PLTE and IDAT",
+            "warning: IDAT chunks must be consecutive",
+            "warning: pHYs must come before IDAT",
+            "warning: gAMA must come before PLTE and IDAT",
+            "warning: gAMA must not appear more than once",
+        ]);
+    }
+
+    #[test]
+    fn test_transparency_after_palette() {
+        let chunk = |chunk_type: &str| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; 3]);
+        let findings = lint(&testing_png(vec![chunk("tRNS"), chunk("PLTE"), chunk("IDAT")]));
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].to_string(), "warning: tRNS must come after PLTE");
+    }
+
+    #[test]
+    fn test_reports_gif_extensions() {
+        let png = testing_png(vec![
+            Chunk::new(ChunkType::from_str("gIFg").unwrap(), vec![1, 0, 0, 5]),
+            Chunk::new(ChunkType::from_str("gIFx").unwrap(), vec![0; 4]),
+        ]);
+        let findings = lint(&png);
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].severity, Severity::Info);
+        assert_eq!(findings[0].to_string(), "info: gIFg legacy GIF conversion metadata (disposal 1, delay 5 cs)");
+        assert_eq!(findings[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_reports_vendor_chunks() {
+        let png = testing_png(vec![
+            Chunk::new(ChunkType::from_str("vpAg").unwrap(), vec![0, 0, 0, 4, 0, 0, 0, 3, 0]),
+            Chunk::new(ChunkType::from_str("mkBT").unwrap(), vec![0; 4]),
+            Chunk::new(ChunkType::from_str("orNT").unwrap(), vec![0]),
+        ]);
+        let findings: Vec<String> = lint(&png).iter().map(Finding::to_string).collect();
+        assert_eq!(findings, [
+            "info: vpAg ImageMagick virtual page size (4x3 pixels)",
+            "info: mkBT Macromedia Fireworks editable document data",
+            "warning: orNT malformed ImageMagick EXIF orientation: invalid orNT chunk: orientation must be 1 to 8",
+        ]);
+    }
+
+    #[test]
+    fn test_reports_color_conflicts() {
+        let mut png = testing_png(vec![Chunk::new(ChunkType::from_str("IDAT").unwrap(), Vec::new())]);
+        png.set_rendering_intent(RenderingIntent::Perceptual);
+        png.set_gamma(Gamma(100000));
+        let findings: Vec<String> = lint(&png).iter().map(Finding::to_string).collect();
+        assert_eq!(findings, ["warning: gAMA gAMA 1 does not match sRGB, which implies 0.45455"]);
+        png.set_icc_profile(IccProfile::new("Display P3", vec![7; 10]).unwrap());
+        let findings: Vec<String> = lint(&png).iter().map(Finding::to_string).collect();
+        assert_eq!(findings, ["warning: sRGB sRGB alongside iCCP; decoders without ICC support use sRGB instead"]);
+    }
+}
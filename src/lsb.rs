@@ -0,0 +1,230 @@
+use rand::{Rng, RngCore};
+
+use crate::{
+    encryption::{self, KdfParams},
+    error::PngError,
+    filter::{self, FilterStrategy},
+    ihdr::{ColorType, Ihdr},
+    optimize,
+    pixels::{self, RawImage},
+    png::Png,
+    zlib
+};
+
+// The message length, big-endian, goes in front of the message.
+const LENGTH_BYTES: usize = 4;
+// A keyed embedding starts with the salt for the key, in image order.
+const SALT_LEN: usize = 16;
+
+// Where message bits go: the least significant bit of every color sample of
+// an 8 or 16-bit image, in row order. Alpha is left alone, since changes to
+// it show, and palette indices are not samples.
+pub(crate) struct Carrier {
+    pub(crate) raw: RawImage,
+    // Byte offset of the low byte of each color sample.
+    pub(crate) positions: Vec<usize>
+}
+
+impl Carrier {
+    pub(crate) fn new(png: &Png) -> Result<Carrier, PngError> {
+        let raw = pixels::decode_raw(png)?;
+        if raw.color_type == ColorType::Indexed || raw.bit_depth < 8 {
+            return Err(PngError::InvalidArgument("hiding in pixels needs an 8 or 16-bit grayscale or truecolor image"));
+        }
+        let channels = raw.color_type.channels();
+        let color_channels = channels - raw.color_type.has_alpha() as usize;
+        let sample_bytes = raw.bit_depth as usize / 8;
+        let positions = (0..raw.data.len() / sample_bytes)
+            .filter(|sample| sample % channels < color_channels)
+            .map(|sample| sample * sample_bytes + sample_bytes - 1)
+            .collect();
+        Ok(Carrier { raw, positions })
+    }
+
+    pub(crate) fn bit(&self, position: usize) -> u8 {
+        self.raw.data[position] & 1
+    }
+
+    // LSB matching: a sample with the wrong low bit moves one up or down at
+    // random instead of having the bit overwritten, so the image does not get
+    // the paired histogram values that give LSB replacement away.
+    pub(crate) fn set_bit<R: Rng + ?Sized>(&mut self, position: usize, bit: u8, rng: &mut R) {
+        if self.bit(position) == bit {
+            return;
+        }
+        let value = match self.raw.bit_depth {
+            16 => u16::from_be_bytes([self.raw.data[position - 1], self.raw.data[position]]),
+            _ => self.raw.data[position] as u16
+        };
+        let max = if self.raw.bit_depth == 16 { u16::MAX } else { u8::MAX as u16 };
+        let value = match value {
+            0 => 1,
+            _ if value == max => value - 1,
+            _ if rng.gen() => value + 1,
+            _ => value - 1
+        };
+        match self.raw.bit_depth {
+            16 => self.raw.data[position - 1..=position].copy_from_slice(&value.to_be_bytes()),
+            _ => self.raw.data[position] = value as u8
+        }
+    }
+
+    // The positions after the salt in the order message bits use them:
+    // shuffled by the key, or in image order without one.
+    fn message_positions(&self, key: Option<[u8; 32]>) -> Vec<usize> {
+        let Some(key) = key else {
+            return self.positions.clone();
+        };
+        let mut positions = self.positions[SALT_LEN * 8..].to_vec();
+        let mut stream = blake3::Hasher::new_keyed(&key).finalize_xof();
+        for i in (1..positions.len()).rev() {
+            let mut bytes = [0u8; 8];
+            stream.fill(&mut bytes);
+            positions.swap(i, (u64::from_be_bytes(bytes) % (i as u64 + 1)) as usize);
+        }
+        positions
+    }
+
+    fn reserved_bits(keyed: bool) -> usize {
+        (LENGTH_BYTES + if keyed { SALT_LEN } else { 0 }) * 8
+    }
+
+    pub(crate) fn into_png(self, png: &Png) -> Result<Png, PngError> {
+        let raw = self.raw;
+        // Interlaced images come back non-interlaced.
+        let ihdr = Ihdr::new(raw.width, raw.height, raw.bit_depth, raw.color_type)?;
+        let row_len = ihdr.row_len(raw.width);
+        let filtered = filter::filter_with(&raw.data, ihdr.filter_bpp(), row_len, FilterStrategy::for_image(&ihdr));
+        let mut hidden = optimize::replace_image_data(png, zlib::deflate(&filtered, 9)?);
+        hidden.replace_chunk(ihdr.into());
+        Ok(hidden)
+    }
+}
+
+// How many message bytes `png` can hold, with or without a password.
+pub fn capacity(png: &Png, keyed: bool) -> Result<usize, PngError> {
+    let bits = Carrier::new(png)?.positions.len();
+    Ok(bits.saturating_sub(Carrier::reserved_bits(keyed)) / 8)
+}
+
+// Hides `message` in the pixels. With a password the bits are spread over
+// the image in an order only the password gives, so they are not bunched at
+// the top where an attacker would look first. `rng` picks the direction of
+// each ±1 change; the salt always comes from the system.
+pub fn embed<R: Rng + ?Sized>(png: &Png, message: &[u8], password: Option<&str>, rng: &mut R) -> Result<Png, PngError> {
+    let mut carrier = Carrier::new(png)?;
+    let reserved = Carrier::reserved_bits(password.is_some());
+    let available = carrier.positions.len().saturating_sub(reserved) / 8;
+    if carrier.positions.len() < reserved || message.len() > available || message.len() > u32::MAX as usize {
+        return Err(PngError::InvalidArgument("message does not fit in the image's pixels"));
+    }
+    let key = match password {
+        Some(password) => {
+            let mut salt = [0u8; SALT_LEN];
+            rand::thread_rng().fill_bytes(&mut salt);
+            for (i, bit) in bits(&salt).enumerate() {
+                carrier.set_bit(carrier.positions[i], bit, rng);
+            }
+            Some(encryption::derive_key(password, &salt, KdfParams::default())?)
+        },
+        None => None
+    };
+    let positions = carrier.message_positions(key);
+    let length = (message.len() as u32).to_be_bytes();
+    let payload = bits(&length).chain(bits(message));
+    for (position, bit) in positions.into_iter().zip(payload) {
+        carrier.set_bit(position, bit, rng);
+    }
+    carrier.into_png(png)
+}
+
+// Reads a message hidden by `embed` with the same password. A wrong password
+// gives an error or garbage, as nothing marks a message as hidden.
+pub fn extract(png: &Png, password: Option<&str>) -> Result<Vec<u8>, PngError> {
+    let carrier = Carrier::new(png)?;
+    let key = match password {
+        Some(password) => {
+            let salt_bits: Vec<u8> = carrier.positions.iter().take(SALT_LEN * 8).map(|position| carrier.bit(*position)).collect();
+            if salt_bits.len() < SALT_LEN * 8 {
+                return Err(PngError::InvalidImageData("no message hidden in the pixels"));
+            }
+            Some(encryption::derive_key(password, &from_bits(&salt_bits), KdfParams::default())?)
+        },
+        None => None
+    };
+    let message_bits: Vec<u8> = carrier.message_positions(key).iter().map(|position| carrier.bit(*position)).collect();
+    let length = message_bits
+        .get(..LENGTH_BYTES * 8)
+        .map(|length| u32::from_be_bytes(from_bits(length).try_into().unwrap()) as usize)
+        .filter(|length| length.checked_add(LENGTH_BYTES).is_some_and(|bytes| bytes * 8 <= message_bits.len()))
+        .ok_or(PngError::InvalidImageData("no message hidden in the pixels"))?;
+    Ok(from_bits(&message_bits[LENGTH_BYTES * 8..(LENGTH_BYTES + length) * 8]))
+}
+
+// Most significant bit first.
+pub(crate) fn bits(bytes: &[u8]) -> impl Iterator<Item = u8> + '_ {
+    bytes.iter().flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+}
+
+pub(crate) fn from_bits(bits: &[u8]) -> Vec<u8> {
+    bits.chunks(8).map(|byte| byte.iter().fold(0, |value, bit| value << 1 | bit)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn testing_png(color_type: ColorType, bit_depth: u8) -> Png {
+        let samples: Vec<u16> = (0..32 * 32 * color_type.channels()).map(|i| (i * 37 % 256) as u16).collect();
+        Png::from_samples(32, 32, color_type, bit_depth, &samples).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for (color_type, bit_depth) in [(ColorType::Rgb, 8), (ColorType::GrayscaleAlpha, 8), (ColorType::Rgba, 16)] {
+            let png = testing_png(color_type, bit_depth);
+            let hidden = embed(&png, b"hello", None, &mut rng).unwrap();
+            assert_eq!(extract(&hidden, None).unwrap(), b"hello");
+            assert_eq!(hidden.header().unwrap(), png.header().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_changes_are_plus_or_minus_one() {
+        let png = testing_png(ColorType::Rgba, 8);
+        let hidden = embed(&png, &[0xa5; 100], None, &mut StdRng::seed_from_u64(2)).unwrap();
+        let (before, after) = (pixels::decode_raw(&png).unwrap().data, pixels::decode_raw(&hidden).unwrap().data);
+        let mut raised = 0;
+        for (i, (old, new)) in before.iter().zip(after.iter()).enumerate() {
+            assert!(old.abs_diff(*new) <= 1);
+            if i % 4 == 3 {
+                assert_eq!(old, new);
+            }
+            raised += (new > old) as usize;
+        }
+        // Not just the replacement direction a set or cleared bit implies.
+        assert!(raised > 0 && before.iter().zip(after.iter()).any(|(old, new)| new < old));
+    }
+
+    #[test]
+    fn test_password_spreads_bits() {
+        let png = testing_png(ColorType::Rgb, 8);
+        let hidden = embed(&png, b"secret", Some("hunter2"), &mut StdRng::seed_from_u64(3)).unwrap();
+        assert_eq!(extract(&hidden, Some("hunter2")).unwrap(), b"secret");
+        assert_ne!(extract(&hidden, Some("wrong")).ok().as_deref(), Some(&b"secret"[..]));
+        assert_ne!(extract(&hidden, None).ok().as_deref(), Some(&b"secret"[..]));
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = testing_png(ColorType::Rgb, 8);
+        assert_eq!(capacity(&png, false).unwrap(), 32 * 32 * 3 / 8 - LENGTH_BYTES);
+        assert_eq!(capacity(&png, true).unwrap(), 32 * 32 * 3 / 8 - LENGTH_BYTES - SALT_LEN);
+        let message = vec![1; capacity(&png, false).unwrap() + 1];
+        assert!(embed(&png, &message, None, &mut StdRng::seed_from_u64(4)).is_err());
+        let low_depth = Png::from_samples(2, 2, ColorType::Grayscale, 4, &[1, 2, 3, 4]).unwrap();
+        assert!(capacity(&low_depth, false).is_err());
+    }
+}
@@ -1,19 +1,19 @@
-use std::{path::PathBuf, str::FromStr, fs};
-
 use commands::app;
 
 
 mod args;
-mod chunk;
-mod chunk_type;
 mod commands;
-mod png;
 
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
-fn main() -> Result<()> {
-    app();
-    Ok(())
+fn main() {
+    match app() {
+        Ok(true) => {},
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
 }
-
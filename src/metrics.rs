@@ -0,0 +1,197 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{error::PngError, pixels::ImageBuffer};
+
+// Alpha is ignored by both metrics; they compare the decoded color values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    // Peak signal-to-noise ratio over the RGB channels, in dB.
+    Psnr,
+    // Mean structural similarity over luma, between -1 and 1.
+    Ssim
+}
+
+impl Metric {
+    pub fn measure(&self, a: &ImageBuffer, b: &ImageBuffer) -> Result<f64, PngError> {
+        match self {
+            Metric::Psnr => psnr(a, b),
+            Metric::Ssim => ssim(a, b)
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = PngError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "psnr" => Ok(Metric::Psnr),
+            "ssim" => Ok(Metric::Ssim),
+            _ => Err(PngError::InvalidArgument("metric must be psnr or ssim"))
+        }
+    }
+}
+
+impl Display for Metric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Metric::Psnr => f.pad("psnr"),
+            Metric::Ssim => f.pad("ssim")
+        }
+    }
+}
+
+// Number of pixels whose RGBA values differ.
+pub fn differing_pixels(a: &ImageBuffer, b: &ImageBuffer) -> Result<usize, PngError> {
+    check_dimensions(a, b)?;
+    Ok(a.pixels.iter().zip(&b.pixels).filter(|(a, b)| a != b).count())
+}
+
+// Infinite for identical images.
+pub fn psnr(a: &ImageBuffer, b: &ImageBuffer) -> Result<f64, PngError> {
+    check_dimensions(a, b)?;
+    let squared_error: f64 = a.pixels
+        .iter()
+        .zip(&b.pixels)
+        .flat_map(|(a, b)| (0..3).map(move |channel| a[channel] as f64 - b[channel] as f64))
+        .map(|difference| difference * difference)
+        .sum();
+    let mse = squared_error / (a.pixels.len() * 3) as f64;
+    if mse == 0.0 {
+        return Ok(f64::INFINITY);
+    }
+    Ok(10.0 * (255.0 * 255.0 / mse).log10())
+}
+
+const SSIM_WINDOW: usize = 8;
+const SSIM_STEP: usize = 4;
+
+// Averaged over 8x8 windows placed every 4 pixels, with the last row and
+// column of windows pushed back to the image edge. Images smaller than a
+// window use a single window covering the whole image.
+pub fn ssim(a: &ImageBuffer, b: &ImageBuffer) -> Result<f64, PngError> {
+    check_dimensions(a, b)?;
+    let (width, height) = (a.width as usize, a.height as usize);
+    let (luma_a, luma_b) = (luma(a), luma(b));
+    let (window_width, window_height) = (SSIM_WINDOW.min(width), SSIM_WINDOW.min(height));
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for top in window_starts(height, window_height) {
+        for left in window_starts(width, window_width) {
+            let index = |x: usize, y: usize| (top + y) * width + left + x;
+            let samples = (0..window_height).flat_map(|y| (0..window_width).map(move |x| index(x, y)));
+            total += window_ssim(samples.map(|i| (luma_a[i], luma_b[i])));
+            windows += 1;
+        }
+    }
+    Ok(total / windows as f64)
+}
+
+fn window_starts(length: usize, window: usize) -> Vec<usize> {
+    let last = length - window;
+    let mut starts: Vec<usize> = (0..=last).step_by(SSIM_STEP).collect();
+    if starts.last() != Some(&last) {
+        starts.push(last);
+    }
+    starts
+}
+
+fn window_ssim(samples: impl Iterator<Item = (f64, f64)>) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let samples: Vec<(f64, f64)> = samples.collect();
+    let n = samples.len() as f64;
+    let mean_a = samples.iter().map(|(a, _)| a).sum::<f64>() / n;
+    let mean_b = samples.iter().map(|(_, b)| b).sum::<f64>() / n;
+    let (mut variance_a, mut variance_b, mut covariance) = (0.0, 0.0, 0.0);
+    for (a, b) in &samples {
+        variance_a += (a - mean_a) * (a - mean_a);
+        variance_b += (b - mean_b) * (b - mean_b);
+        covariance += (a - mean_a) * (b - mean_b);
+    }
+    let (variance_a, variance_b, covariance) = (variance_a / n, variance_b / n, covariance / n);
+    ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+        / ((mean_a * mean_a + mean_b * mean_b + C1) * (variance_a + variance_b + C2))
+}
+
+// Rec. 601 luma.
+fn luma(image: &ImageBuffer) -> Vec<f64> {
+    image.pixels
+        .iter()
+        .map(|[r, g, b, _]| 0.299 * *r as f64 + 0.587 * *g as f64 + 0.114 * *b as f64)
+        .collect()
+}
+
+fn check_dimensions(a: &ImageBuffer, b: &ImageBuffer) -> Result<(), PngError> {
+    if a.width != b.width || a.height != b.height {
+        return Err(PngError::InvalidArgument("images have different dimensions"));
+    }
+    if a.pixels.is_empty() {
+        return Err(PngError::InvalidArgument("images are empty"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> ImageBuffer {
+        let pixels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| pixel(x, y)).collect();
+        ImageBuffer { width, height, pixels }
+    }
+
+    fn gradient(x: u32, y: u32) -> [u8; 4] {
+        let value = (x * 16 + y * 8) as u8;
+        [value, value / 2, 255 - value, 255]
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let a = image(10, 9, gradient);
+        assert_eq!(psnr(&a, &a).unwrap(), f64::INFINITY);
+        assert!((ssim(&a, &a).unwrap() - 1.0).abs() < 1e-12);
+        assert_eq!(differing_pixels(&a, &a).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_psnr() {
+        let a = image(4, 4, |_, _| [100, 100, 100, 255]);
+        let b = image(4, 4, |_, _| [110, 90, 100, 0]);
+        // MSE is (100 + 100 + 0) / 3.
+        let expected = 10.0 * (255.0f64 * 255.0 / (200.0 / 3.0)).log10();
+        assert!((psnr(&a, &b).unwrap() - expected).abs() < 1e-9);
+        assert_eq!(differing_pixels(&a, &b).unwrap(), 16);
+    }
+
+    #[test]
+    fn test_ssim_drops_with_noise() {
+        let a = image(16, 16, gradient);
+        let slight = image(16, 16, |x, y| gradient(x, y).map(|value| value.saturating_add(((x + y) % 2) as u8)));
+        let heavy = image(16, 16, |x, y| gradient(x, y).map(|value| value ^ (((x * 7 + y * 13) % 64) as u8)));
+        let (slight, heavy) = (ssim(&a, &slight).unwrap(), ssim(&a, &heavy).unwrap());
+        assert!(slight > 0.95 && slight < 1.0);
+        assert!(heavy < slight);
+    }
+
+    #[test]
+    fn test_window_starts() {
+        assert_eq!(window_starts(8, 8), [0]);
+        assert_eq!(window_starts(17, 8), [0, 4, 8, 9]);
+        assert_eq!(window_starts(3, 3), [0]);
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        let (a, b) = (image(2, 2, gradient), image(2, 3, gradient));
+        assert!(matches!(psnr(&a, &b), Err(PngError::InvalidArgument(_))));
+        assert!(ssim(&a, &b).is_err());
+    }
+
+    #[test]
+    fn test_parse_metric() {
+        assert_eq!("PSNR".parse::<Metric>().unwrap(), Metric::Psnr);
+        assert_eq!("ssim".parse::<Metric>().unwrap(), Metric::Ssim);
+        assert!("mse".parse::<Metric>().is_err());
+    }
+}
@@ -0,0 +1,364 @@
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::{ChunkType, ChunkTypeSet},
+    error::PngError,
+    filter::{self, FilterType, ScanlineLayout},
+    ihdr::{ColorType, Ihdr},
+    pixels,
+    png::Png,
+    transparency::Transparency,
+    zlib::{deflate, inflate, CompressionLevel}
+};
+
+pub const DEFAULT_LEVELS: [u32; 3] = [1, 6, 9];
+
+#[derive(Debug, Clone)]
+pub struct Trial {
+    // `None` keeps the scanline filters chosen by the original encoder.
+    pub filter: Option<FilterType>,
+    pub level: u32,
+    pub size: usize,
+    pub elapsed: Duration
+}
+
+#[derive(Debug)]
+pub struct TrialReport {
+    pub original_size: usize,
+    pub trials: Vec<Trial>,
+    pub best: usize
+}
+
+impl Trial {
+    pub fn filter_name(&self) -> String {
+        match self.filter {
+            Some(filter_type) => filter_type.to_string(),
+            None => String::from("original")
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "filter": self.filter_name(),
+            "level": self.level,
+            "size": self.size,
+            "millis": self.elapsed.as_secs_f64() * 1000.0
+        })
+    }
+}
+
+impl TrialReport {
+    pub fn best(&self) -> &Trial {
+        &self.trials[self.best]
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "original_size": self.original_size,
+            "best": self.best().to_json(),
+            "trials": self.trials.iter().map(Trial::to_json).collect::<Vec<Value>>()
+        })
+    }
+}
+
+// Re-encodes the image data once for every combination of `filters` and `levels`
+// and returns the smallest result. The original filtering is always tried, and
+// is the only filter option for interlaced images.
+pub fn run_trials(png: &Png, filters: &[FilterType], levels: &[u32]) -> Result<(Png, TrialReport), PngError> {
+    if levels.is_empty() {
+        return Err(PngError::InvalidArgument("at least one compression level is required"));
+    }
+    let layout = ScanlineLayout::from_png(png)?;
+    let original_size = png.as_bytes().len();
+    let idat_size: usize = idat_chunks(png).map(|chunk| chunk.length() as usize + 12).sum();
+    let base_size = original_size - idat_size + 12;
+    let filtered = inflate(&png.image_data())?;
+
+    let mut candidates: Vec<(Option<FilterType>, Vec<u8>)> = vec![(None, filtered.clone())];
+    if !layout.interlaced && !filters.is_empty() {
+        let raw = filter::unfilter(&filtered, layout.bpp, layout.row_len)?;
+        for filter_type in filters {
+            candidates.push((Some(*filter_type), filter::filter(&raw, layout.bpp, layout.row_len, *filter_type)));
+        }
+    }
+
+    let mut trials: Vec<Trial> = Vec::new();
+    let mut best: Option<(usize, Vec<u8>)> = None;
+    for (filter_type, data) in candidates.iter() {
+        for level in levels {
+            let start = Instant::now();
+            let compressed = deflate(data, *level)?;
+            let elapsed = start.elapsed();
+            let size = base_size + compressed.len();
+            if best.as_ref().is_none_or(|(index, _)| size < trials[*index].size) {
+                best = Some((trials.len(), compressed));
+            }
+            trials.push(Trial { filter: *filter_type, level: *level, size, elapsed });
+        }
+    }
+
+    let (best, compressed) = best.expect("at least one trial was run");
+    let report = TrialReport { original_size, trials, best };
+    Ok((replace_image_data(png, compressed), report))
+}
+
+// Losslessly drops the alpha channel of an RGBA or gray+alpha image whose alpha
+// is only ever 0 or fully opaque, where every transparent pixel has the same
+// color and no opaque pixel uses it. That color becomes the tRNS key, or there
+// is no tRNS when nothing is transparent. Returns `None` when the image does
+// not qualify. The new image data is unfiltered; run it through `run_trials`.
+pub fn synthesize_trns(png: &Png) -> Result<Option<Png>, PngError> {
+    let ihdr = png.header()?;
+    let color_type = match ihdr.color_type {
+        ColorType::Rgba => ColorType::Rgb,
+        ColorType::GrayscaleAlpha => ColorType::Grayscale,
+        _ => return Ok(None)
+    };
+    let raw = pixels::decode_raw(png)?.data;
+
+    let sample_len = ihdr.bit_depth as usize / 8;
+    let pixel_len = ihdr.filter_bpp();
+    let color_len = pixel_len - sample_len;
+    let opaque = vec![0xff; sample_len];
+    let mut key: Option<&[u8]> = None;
+    for pixel in raw.chunks(pixel_len) {
+        let (color, alpha) = pixel.split_at(color_len);
+        if alpha == opaque.as_slice() {
+            continue;
+        }
+        if alpha.iter().any(|byte| *byte != 0) || key.is_some_and(|key| key != color) {
+            return Ok(None);
+        }
+        key = Some(color);
+    }
+    if key.is_some_and(|key| raw.chunks(pixel_len).any(|pixel| pixel.starts_with(key) && pixel.ends_with(&opaque))) {
+        return Ok(None);
+    }
+
+    let samples: Vec<u16> = key.map_or(Vec::new(), |key| key
+        .chunks(sample_len)
+        .map(|sample| sample.iter().fold(0, |value, byte| value << 8 | *byte as u16))
+        .collect());
+    let transparency = match samples.as_slice() {
+        [] => None,
+        [gray] => Some(Transparency::Gray(*gray)),
+        [r, g, b] => Some(Transparency::Rgb(*r, *g, *b)),
+        _ => unreachable!("gray or rgb samples")
+    };
+
+    let new_ihdr = Ihdr { color_type, interlaced: false, ..ihdr };
+    let new_row_len = new_ihdr.row_len(ihdr.width);
+    let stripped: Vec<u8> = raw.chunks(pixel_len).flat_map(|pixel| &pixel[..color_len]).copied().collect();
+    let filtered = filter::filter(&stripped, new_ihdr.filter_bpp(), new_row_len, FilterType::None);
+    let significant_bits = png.significant_bits().ok().flatten();
+    let mut synthesized = replace_image_data(png, deflate(&filtered, 9)?);
+    synthesized.replace_chunk(new_ihdr.into());
+    // sBIT has one entry per channel, so the alpha entry goes with the channel.
+    synthesized.strip_chunks(&ChunkTypeSet::of(&[b"sBIT"]));
+    if let Some(significant_bits) = significant_bits {
+        synthesized.set_significant_bits(significant_bits.without_alpha())?;
+    }
+    if let Some(transparency) = transparency {
+        synthesized.set_transparency(transparency)?;
+    }
+    Ok(Some(synthesized))
+}
+
+fn idat_chunks(png: &Png) -> impl Iterator<Item = &Chunk> {
+    png.chunks().iter().filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+}
+
+// Re-deflates the image data at `level`, keeping the scanline filters.
+pub fn recompress(png: &Png, level: CompressionLevel) -> Result<Png, PngError> {
+    Ok(replace_image_data(png, deflate(&inflate(&png.image_data())?, level.level())?))
+}
+
+// Swaps in one IDAT holding `compressed`. An iDOT index of the old image
+// data would point at segments that are gone, so it is dropped.
+pub(crate) fn replace_image_data(png: &Png, compressed: Vec<u8>) -> Png {
+    let mut idat = Some(Chunk::new(ChunkType(u32::from_be_bytes(*b"IDAT")), compressed));
+    let mut chunks = Vec::new();
+    for chunk in png.chunks() {
+        match &chunk.chunk_type().bytes() {
+            b"iDOT" => {},
+            b"IDAT" => chunks.extend(idat.take()),
+            _ => chunks.push(chunk.clone())
+        }
+    }
+    png.with_chunks(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{significant_bits::SignificantBits, zlib::ZlibHeader};
+
+    fn chunk_from_bytes(chunk_type: &[u8; 4], data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType(u32::from_be_bytes(*chunk_type)), data)
+    }
+
+    fn testing_png() -> Png {
+        let width: u32 = 8;
+        let height: u32 = 8;
+        let mut ihdr = Vec::new();
+        ihdr.extend(width.to_be_bytes());
+        ihdr.extend(height.to_be_bytes());
+        ihdr.extend([8, 2, 0, 0, 0]);
+
+        let raw: Vec<u8> = (0..(width * height * 3)).map(|i| (i % 251) as u8).collect();
+        let filtered = filter::filter(&raw, 3, 24, FilterType::None);
+        let compressed = deflate(&filtered, 0).unwrap();
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+
+        Png::from_chunks(vec![
+            chunk_from_bytes(b"IHDR", ihdr),
+            chunk_from_bytes(b"IDAT", first.to_vec()),
+            chunk_from_bytes(b"IDAT", second.to_vec()),
+            chunk_from_bytes(b"IEND", Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_run_trials_grid() {
+        let png = testing_png();
+        let (_, report) = run_trials(&png, &FilterType::ALL, &DEFAULT_LEVELS).unwrap();
+        assert_eq!(report.trials.len(), 6 * 3);
+        assert!(report.trials.iter().all(|trial| trial.size >= report.best().size));
+    }
+
+    #[test]
+    fn test_run_trials_writes_best() {
+        let png = testing_png();
+        let (optimized, report) = run_trials(&png, &FilterType::ALL, &DEFAULT_LEVELS).unwrap();
+        assert_eq!(optimized.as_bytes().len(), report.best().size);
+        assert!(report.best().size < report.original_size);
+        assert_eq!(idat_chunks(&optimized).count(), 1);
+        assert!(png.is_pixel_data_untouched());
+        assert!(!optimized.is_pixel_data_untouched());
+        assert_eq!(inflate(&optimized.image_data()).map(|data| data.len()).unwrap(), 8 * 25);
+    }
+
+    #[test]
+    fn test_run_trials_preserves_pixels() {
+        let png = testing_png();
+        let (optimized, _) = run_trials(&png, &FilterType::ALL, &[9]).unwrap();
+        let original = filter::unfilter(&inflate(&png.image_data()).unwrap(), 3, 24).unwrap();
+        let actual = filter::unfilter(&inflate(&optimized.image_data()).unwrap(), 3, 24).unwrap();
+        assert_eq!(original, actual);
+    }
+
+    #[test]
+    fn test_trial_report_json() {
+        let png = testing_png();
+        let (_, report) = run_trials(&png, &[FilterType::Paeth], &[6]).unwrap();
+        let json = report.to_json();
+        assert_eq!(json["trials"].as_array().unwrap().len(), 2);
+        assert_eq!(json["original_size"], report.original_size);
+        assert!(json["best"]["filter"].is_string());
+    }
+
+    fn rgba_png(pixels: &[[u8; 4]]) -> Png {
+        let raw: Vec<u8> = pixels.iter().flatten().copied().collect();
+        let filtered = filter::filter(&raw, 4, raw.len(), FilterType::Sub);
+        Png::from_chunks(vec![
+            Ihdr::new(pixels.len() as u32, 1, 8, ColorType::Rgba).unwrap().into(),
+            chunk_from_bytes(b"IDAT", deflate(&filtered, 6).unwrap()),
+            chunk_from_bytes(b"IEND", Vec::new()),
+        ])
+    }
+
+    fn raw_pixels(png: &Png) -> Vec<u8> {
+        let layout = ScanlineLayout::from_png(png).unwrap();
+        filter::unfilter(&inflate(&png.image_data()).unwrap(), layout.bpp, layout.row_len).unwrap()
+    }
+
+    #[test]
+    fn test_synthesize_trns() {
+        let png = rgba_png(&[[1, 2, 3, 255], [9, 9, 9, 0], [4, 5, 6, 255], [9, 9, 9, 0]]);
+        let synthesized = synthesize_trns(&png).unwrap().unwrap();
+        assert_eq!(synthesized.header().unwrap().color_type, ColorType::Rgb);
+        assert_eq!(synthesized.transparency().unwrap(), Some(Transparency::Rgb(9, 9, 9)));
+        assert_eq!(raw_pixels(&synthesized), [1, 2, 3, 9, 9, 9, 4, 5, 6, 9, 9, 9]);
+        let types: Vec<String> = synthesized.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "tRNS", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_synthesize_trns_opaque() {
+        let png = rgba_png(&[[1, 2, 3, 255], [4, 5, 6, 255]]);
+        let synthesized = synthesize_trns(&png).unwrap().unwrap();
+        assert_eq!(synthesized.transparency().unwrap(), None);
+        assert_eq!(raw_pixels(&synthesized), [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_synthesize_trns_keeps_significant_bits() {
+        let mut png = rgba_png(&[[1, 2, 3, 255]]);
+        png.set_significant_bits(SignificantBits::Rgba(5, 6, 5, 1)).unwrap();
+        let synthesized = synthesize_trns(&png).unwrap().unwrap();
+        assert_eq!(synthesized.significant_bits().unwrap(), Some(SignificantBits::Rgb(5, 6, 5)));
+    }
+
+    #[test]
+    fn test_synthesize_trns_not_lossless() {
+        // Partial alpha.
+        assert!(synthesize_trns(&rgba_png(&[[1, 2, 3, 128]])).unwrap().is_none());
+        // Two different transparent colors.
+        assert!(synthesize_trns(&rgba_png(&[[1, 2, 3, 0], [4, 5, 6, 0]])).unwrap().is_none());
+        // The transparent color is also used by an opaque pixel.
+        assert!(synthesize_trns(&rgba_png(&[[1, 2, 3, 255], [1, 2, 3, 0]])).unwrap().is_none());
+        // No alpha channel to drop.
+        assert!(synthesize_trns(&testing_png()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_synthesize_trns_interlaced() {
+        // 2x2 gray+alpha: Adam7 passes 1, 6 and 7 hold (0, 0), (1, 0) and row 1.
+        let mut ihdr = Ihdr::new(2, 2, 8, ColorType::GrayscaleAlpha).unwrap();
+        ihdr.interlaced = true;
+        let filtered = [0, 10, 255, 0, 0, 0, 0, 20, 255, 30, 255];
+        let png = Png::from_chunks(vec![
+            ihdr.into(),
+            chunk_from_bytes(b"IDAT", deflate(&filtered, 6).unwrap()),
+            chunk_from_bytes(b"IEND", Vec::new()),
+        ]);
+        let synthesized = synthesize_trns(&png).unwrap().unwrap();
+        assert!(!synthesized.header().unwrap().interlaced);
+        assert_eq!(synthesized.transparency().unwrap(), Some(Transparency::Gray(0)));
+        assert_eq!(raw_pixels(&synthesized), [10, 0, 20, 30]);
+    }
+
+    #[test]
+    fn test_recompress() {
+        let png = testing_png();
+        let compressed = recompress(&png, CompressionLevel::Best).unwrap();
+        assert_eq!(raw_pixels(&compressed), raw_pixels(&png));
+        assert_eq!(idat_chunks(&compressed).count(), 1);
+        assert_eq!(ZlibHeader::parse(&compressed.image_data()).unwrap().level, 3);
+        let fast = recompress(&png, CompressionLevel::Fast).unwrap();
+        assert_eq!(ZlibHeader::parse(&fast.image_data()).unwrap().level, 0);
+    }
+
+    #[test]
+    fn test_reencoding_drops_idot() {
+        let indexed = crate::idot::with_idot(&testing_png(), 6).unwrap();
+        assert!(indexed.idot().unwrap().is_some());
+        let (optimized, _) = run_trials(&indexed, &FilterType::ALL, &[9]).unwrap();
+        let types: Vec<String> = optimized.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+        assert!(recompress(&indexed, CompressionLevel::Fast).unwrap().chunk_by_type("iDOT").is_none());
+        let scaled = crate::scale::scale(&indexed, 2, crate::scale::ScaleFilter::Nearest).unwrap();
+        assert!(scaled.chunk_by_type("iDOT").is_none());
+        let image = crate::pixels::decode_rgba(&indexed).unwrap();
+        assert!(indexed.with_rgba(&image).unwrap().chunk_by_type("iDOT").is_none());
+    }
+
+    #[test]
+    fn test_run_trials_without_levels() {
+        let png = testing_png();
+        assert!(run_trials(&png, &FilterType::ALL, &[]).is_err());
+    }
+}
@@ -0,0 +1,355 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::{ChunkType, ChunkTypeSet},
+    error::PngError,
+    filter::{self, FilterType},
+    ihdr::{ColorType, Ihdr},
+    pixels,
+    png::Png,
+    zlib
+};
+
+// Chunks describing the old colors, dropped when an image is remapped.
+pub(crate) const COLOR_DEPENDENT_TYPES: ChunkTypeSet = ChunkTypeSet::of(&[b"PLTE", b"tRNS", b"bKGD", b"hIST", b"sBIT"]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8
+}
+
+// PLTE: up to 256 RGB entries, indexed by the pixel values of a color type 3 image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    entries: Vec<Rgb>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemapMode {
+    // Every color must already be in the palette.
+    Exact,
+    // Colors missing from the palette take the closest entry.
+    Nearest
+}
+
+impl Rgb {
+    pub fn new(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb { r, g, b }
+    }
+
+    fn distance(&self, other: &Rgb) -> u32 {
+        let channel = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+        channel(self.r, other.r) + channel(self.g, other.g) + channel(self.b, other.b)
+    }
+}
+
+impl Display for Rgb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+// "#rrggbb", as Display writes it, with or without the "#".
+impl FromStr for Rgb {
+    type Err = PngError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(PngError::InvalidArgument("color must be hex like #ff00ff"));
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        Ok(Rgb::new(channel(0), channel(2), channel(4)))
+    }
+}
+
+impl Palette {
+    pub const MAX_ENTRIES: usize = 256;
+
+    pub fn new(entries: Vec<Rgb>) -> Result<Palette, PngError> {
+        if entries.is_empty() || entries.len() > Palette::MAX_ENTRIES {
+            return Err(invalid("palette must have 1 to 256 entries"));
+        }
+        Ok(Palette { entries })
+    }
+
+    pub fn entries(&self) -> &[Rgb] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: u8) -> Option<Rgb> {
+        self.entries.get(index as usize).copied()
+    }
+
+    pub fn set(&mut self, index: u8, color: Rgb) -> Result<(), PngError> {
+        let entry = self.entries.get_mut(index as usize).ok_or(PngError::InvalidArgument("palette index out of range"))?;
+        *entry = color;
+        Ok(())
+    }
+
+    // Appends `color` and returns its index.
+    pub fn push(&mut self, color: Rgb) -> Result<u8, PngError> {
+        if self.entries.len() == Palette::MAX_ENTRIES {
+            return Err(invalid("palette must have 1 to 256 entries"));
+        }
+        self.entries.push(color);
+        Ok((self.entries.len() - 1) as u8)
+    }
+
+    pub fn position(&self, color: Rgb) -> Option<u8> {
+        self.entries.iter().position(|entry| *entry == color).map(|index| index as u8)
+    }
+}
+
+impl TryFrom<&Chunk> for Palette {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if chunk.chunk_type().bytes() != *b"PLTE" {
+            return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+        }
+        let data = chunk.data();
+        if !data.len().is_multiple_of(3) {
+            return Err(invalid("length is not a multiple of 3"));
+        }
+        Palette::new(data.chunks_exact(3).map(|rgb| Rgb::new(rgb[0], rgb[1], rgb[2])).collect())
+    }
+}
+
+impl From<Palette> for Chunk {
+    fn from(value: Palette) -> Self {
+        let data = value.entries.iter().flat_map(|color| [color.r, color.g, color.b]).collect();
+        new_chunk(b"PLTE", data)
+    }
+}
+
+impl Png {
+    pub fn palette(&self) -> Result<Option<Palette>, PngError> {
+        self.chunk_as("PLTE")
+    }
+
+    // Replaces the PLTE chunk, or inserts one before the image data.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.place_chunk(palette.into(), &ChunkTypeSet::of(&[b"IDAT"]));
+    }
+}
+
+// Parses a GIMP .gpl palette: a "GIMP Palette" line, optional Name/Columns
+// headers and comments, then one "R G B [name]" entry per line.
+pub fn parse_gpl(source: &str) -> Result<Vec<Rgb>, PngError> {
+    let mut lines = source.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+    match lines.find(|(_, line)| !line.is_empty()) {
+        Some((_, "GIMP Palette")) => {},
+        Some((line, _)) => return Err(PngError::InvalidPalette { line, reason: "missing GIMP Palette header" }),
+        None => return Err(PngError::InvalidPalette { line: 1, reason: "palette is empty" })
+    }
+
+    let mut colors = Vec::new();
+    for (line, text) in lines {
+        if text.is_empty() || text.starts_with('#') || text.starts_with("Name:") || text.starts_with("Columns:") {
+            continue;
+        }
+        let mut values = text.split_whitespace().map(|value| value.parse::<u8>());
+        match (values.next(), values.next(), values.next()) {
+            (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => colors.push(Rgb::new(r, g, b)),
+            _ => return Err(PngError::InvalidPalette { line, reason: "expected three values from 0 to 255" })
+        }
+        if colors.len() > 256 {
+            return Err(PngError::InvalidPalette { line, reason: "more than 256 colors" });
+        }
+    }
+    if colors.is_empty() {
+        return Err(PngError::InvalidPalette { line: 1, reason: "palette has no colors" });
+    }
+    Ok(colors)
+}
+
+// Re-encodes `png` as an 8-bit indexed image using `palette` as its PLTE, with
+// no dithering. Chunks that describe the old colors are dropped.
+pub fn remap(png: &Png, palette: &[Rgb], mode: RemapMode) -> Result<Png, PngError> {
+    let plte: Chunk = Palette::new(palette.to_vec())?.into();
+    let image = pixels::decode_rgba(png)?;
+    let mut lookup: HashMap<Rgb, u8> = HashMap::new();
+    for (i, color) in palette.iter().enumerate().rev() {
+        lookup.insert(*color, i as u8);
+    }
+
+    let mut indices = Vec::with_capacity(image.pixels.len());
+    for (i, [r, g, b, a]) in image.pixels.iter().copied().enumerate() {
+        if a != 255 {
+            return Err(PngError::InvalidImageData("transparent pixels cannot be remapped"));
+        }
+        let color = Rgb::new(r, g, b);
+        let index = match (lookup.get(&color), mode) {
+            (Some(index), _) => *index,
+            (None, RemapMode::Exact) => {
+                let (x, y) = (i as u32 % image.width, i as u32 / image.width);
+                return Err(PngError::UnmappedColor { x, y, color: [r, g, b] });
+            },
+            (None, RemapMode::Nearest) => {
+                let index = nearest(palette, &color);
+                lookup.insert(color, index);
+                index
+            }
+        };
+        indices.push(index);
+    }
+
+    let ihdr: Chunk = Ihdr::new(image.width, image.height, 8, ColorType::Indexed)?.into();
+    let filtered = filter::filter(&indices, 1, image.width as usize, FilterType::None);
+    let mut idat = Some(zlib::deflate(&filtered, 9)?);
+
+    let mut chunks = Vec::new();
+    for chunk in png.chunks() {
+        match &chunk.chunk_type().bytes() {
+            _ if COLOR_DEPENDENT_TYPES.contains(*chunk.chunk_type()) => {},
+            b"IHDR" => chunks.push(ihdr.clone()),
+            b"IDAT" => {
+                if let Some(idat) = idat.take() {
+                    chunks.push(plte.clone());
+                    chunks.push(new_chunk(b"IDAT", idat));
+                }
+            },
+            _ => chunks.push(chunk.clone())
+        }
+    }
+    Ok(png.with_chunks(chunks))
+}
+
+fn nearest(palette: &[Rgb], color: &Rgb) -> u8 {
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| entry.distance(color))
+        .map(|(i, _)| i as u8)
+        .expect("palette is not empty")
+}
+
+fn new_chunk(chunk_type: &[u8; 4], data: Vec<u8>) -> Chunk {
+    Chunk::new(ChunkType(u32::from_be_bytes(*chunk_type)), data)
+}
+
+fn invalid(reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: String::from("PLTE"), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GPL: &str = "GIMP Palette\nName: Test\nColumns: 2\n#\n  0   0   0\tBlack\n255 255 255\tWhite\n255   0   0\tRed\n";
+
+    fn testing_png(raw: &[u8]) -> Png {
+        let mut ihdr = Vec::new();
+        ihdr.extend(2u32.to_be_bytes());
+        ihdr.extend(2u32.to_be_bytes());
+        ihdr.extend([8, 2, 0, 0, 0]);
+        let filtered = filter::filter(raw, 3, 6, FilterType::Up);
+        Png::from_chunks(vec![
+            new_chunk(b"IHDR", ihdr),
+            new_chunk(b"tEXt", b"Title\0art".to_vec()),
+            new_chunk(b"IDAT", zlib::deflate(&filtered, 6).unwrap()),
+            new_chunk(b"IEND", Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_parse_gpl() {
+        let colors = parse_gpl(GPL).unwrap();
+        assert_eq!(colors, [Rgb::new(0, 0, 0), Rgb::new(255, 255, 255), Rgb::new(255, 0, 0)]);
+        assert_eq!(colors[2].to_string(), "#ff0000");
+    }
+
+    #[test]
+    fn test_parse_invalid_gpl() {
+        assert!(matches!(parse_gpl("JASC-PAL\n"), Err(PngError::InvalidPalette { line: 1, .. })));
+        assert!(matches!(parse_gpl("GIMP Palette\n0 0\n"), Err(PngError::InvalidPalette { line: 2, .. })));
+        assert!(matches!(parse_gpl("GIMP Palette\n0 0 256\n"), Err(PngError::InvalidPalette { line: 2, .. })));
+        assert!(parse_gpl("GIMP Palette\n# nothing\n").is_err());
+        assert!(parse_gpl(&format!("GIMP Palette\n{}", "1 2 3\n".repeat(257))).is_err());
+    }
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(Rgb::from_str("#FF00fe").unwrap(), Rgb::new(255, 0, 254));
+        assert_eq!(Rgb::from_str("102030").unwrap().to_string(), "#102030");
+        for invalid in ["#ff00f", "#+f00ff", "ff00ff00", "#gg0000"] {
+            assert!(Rgb::from_str(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_palette_round_trip() {
+        let chunk = new_chunk(b"PLTE", vec![0, 0, 0, 255, 128, 0]);
+        let palette = Palette::try_from(&chunk).unwrap();
+        assert_eq!(palette.entries(), [Rgb::new(0, 0, 0), Rgb::new(255, 128, 0)]);
+        assert_eq!(palette.position(Rgb::new(255, 128, 0)), Some(1));
+        let actual: Chunk = palette.into();
+        assert_eq!(actual.data(), chunk.data());
+    }
+
+    #[test]
+    fn test_invalid_palette() {
+        assert!(Palette::try_from(&new_chunk(b"PLTE", vec![0, 0, 0, 1])).is_err());
+        assert!(Palette::try_from(&new_chunk(b"PLTE", Vec::new())).is_err());
+        assert!(Palette::try_from(&new_chunk(b"PLTE", vec![0; 3 * 257])).is_err());
+        assert!(matches!(Palette::try_from(&new_chunk(b"tRNS", vec![0; 3])), Err(PngError::InvalidChunkType(_))));
+    }
+
+    #[test]
+    fn test_edit_palette() {
+        let mut palette = Palette::new(vec![Rgb::new(0, 0, 0)]).unwrap();
+        palette.set(0, Rgb::new(1, 2, 3)).unwrap();
+        assert!(palette.set(1, Rgb::new(1, 2, 3)).is_err());
+        assert_eq!(palette.push(Rgb::new(4, 5, 6)).unwrap(), 1);
+        assert_eq!(palette.get(1), Some(Rgb::new(4, 5, 6)));
+        let mut full = Palette::new(vec![Rgb::new(0, 0, 0); 256]).unwrap();
+        assert!(full.push(Rgb::new(0, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_set_palette_before_image_data() {
+        let mut png = testing_png(&[0; 12]);
+        png.set_palette(Palette::new(vec![Rgb::new(9, 9, 9)]).unwrap());
+        png.set_palette(Palette::new(vec![Rgb::new(1, 1, 1), Rgb::new(2, 2, 2)]).unwrap());
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "tEXt", "PLTE", "IDAT", "IEND"]);
+        assert_eq!(png.palette().unwrap().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_remap_exact() {
+        let png = testing_png(&[255, 0, 0, 0, 0, 0, 255, 255, 255, 255, 0, 0]);
+        let remapped = remap(&png, &parse_gpl(GPL).unwrap(), RemapMode::Exact).unwrap();
+        let types: Vec<String> = remapped.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "tEXt", "PLTE", "IDAT", "IEND"]);
+        assert_eq!(remapped.header().unwrap().color_type, ColorType::Indexed);
+        let indices = filter::unfilter(&zlib::inflate(&remapped.image_data()).unwrap(), 1, 2).unwrap();
+        assert_eq!(indices, [2, 0, 1, 2]);
+        assert_eq!(pixels::decode_rgba(&remapped).unwrap(), pixels::decode_rgba(&png).unwrap());
+    }
+
+    #[test]
+    fn test_remap_exact_miss() {
+        let png = testing_png(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 250, 10, 10]);
+        let err = remap(&png, &parse_gpl(GPL).unwrap(), RemapMode::Exact).unwrap_err();
+        assert!(matches!(err, PngError::UnmappedColor { x: 1, y: 1, color: [250, 10, 10] }));
+    }
+
+    #[test]
+    fn test_remap_nearest() {
+        let png = testing_png(&[0, 0, 0, 10, 10, 10, 200, 200, 200, 250, 10, 10]);
+        let remapped = remap(&png, &parse_gpl(GPL).unwrap(), RemapMode::Nearest).unwrap();
+        let indices = filter::unfilter(&zlib::inflate(&remapped.image_data()).unwrap(), 1, 2).unwrap();
+        assert_eq!(indices, [0, 0, 1, 2]);
+    }
+}
@@ -0,0 +1,214 @@
+use std::f64::consts::PI;
+
+use serde_json::{json, Value};
+
+use crate::{error::PngError, pixels::ImageBuffer, quantize};
+
+const DOMINANT_COLORS: usize = 5;
+
+// Compact descriptors of how an image looks, which stay close when it is
+// resized, recompressed or slightly edited. Hashes are compared by the number
+// of differing bits, see `hamming_distance`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub width: u32,
+    pub height: u32,
+    pub average_hash: u64,
+    pub difference_hash: u64,
+    pub perceptual_hash: u64,
+    // The most used colors after reducing the image to a few, with the share
+    // of visible pixels each covers, most used first.
+    pub dominant_colors: Vec<([u8; 3], f64)>
+}
+
+impl Signature {
+    pub fn from_image(image: &ImageBuffer) -> Result<Signature, PngError> {
+        if image.pixels.is_empty() {
+            return Err(PngError::InvalidArgument("image has no pixels"));
+        }
+        Ok(Signature {
+            width: image.width,
+            height: image.height,
+            average_hash: average_hash(image),
+            difference_hash: difference_hash(image),
+            perceptual_hash: perceptual_hash(image),
+            dominant_colors: dominant_colors(image)?
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        let colors: Vec<Value> = self
+            .dominant_colors
+            .iter()
+            .map(|([r, g, b], share)| json!({ "color": format!("#{:02x}{:02x}{:02x}", r, g, b), "share": (share * 1000.0).round() / 1000.0 }))
+            .collect();
+        json!({
+            "width": self.width,
+            "height": self.height,
+            "ahash": format!("{:016x}", self.average_hash),
+            "dhash": format!("{:016x}", self.difference_hash),
+            "phash": format!("{:016x}", self.perceptual_hash),
+            "dominant_colors": colors
+        })
+    }
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+// Bits set where the 8x8 thumbnail is brighter than its mean.
+pub fn average_hash(image: &ImageBuffer) -> u64 {
+    let thumbnail = resize_luma(image, 8, 8);
+    let mean = thumbnail.iter().sum::<f64>() / 64.0;
+    to_bits(thumbnail.iter().map(|value| *value > mean))
+}
+
+// Bits set where a pixel of the 9x8 thumbnail is darker than its right neighbor.
+pub fn difference_hash(image: &ImageBuffer) -> u64 {
+    let thumbnail = resize_luma(image, 9, 8);
+    to_bits(thumbnail.chunks(9).flat_map(|row| row.windows(2).map(|pair| pair[0] < pair[1])))
+}
+
+// Bits set where the lowest 8x8 frequencies of a 32x32 thumbnail's DCT are
+// above their median, leaving out the average brightness term.
+pub fn perceptual_hash(image: &ImageBuffer) -> u64 {
+    const SIZE: usize = 32;
+    let thumbnail = resize_luma(image, SIZE as u32, SIZE as u32);
+    let cosines: Vec<f64> = (0..8)
+        .flat_map(|u| (0..SIZE).map(move |x| ((2 * x + 1) as f64 * u as f64 * PI / (2 * SIZE) as f64).cos()))
+        .collect();
+    let mut coefficients = Vec::with_capacity(64);
+    for v in 0..8 {
+        for u in 0..8 {
+            let mut sum = 0.0;
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    sum += thumbnail[y * SIZE + x] * cosines[u * SIZE + x] * cosines[v * SIZE + y];
+                }
+            }
+            coefficients.push(sum);
+        }
+    }
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = (sorted[31] + sorted[32]) / 2.0;
+    to_bits(coefficients.iter().map(|value| *value > median))
+}
+
+fn dominant_colors(image: &ImageBuffer) -> Result<Vec<([u8; 3], f64)>, PngError> {
+    let quantized = quantize::quantize(image, DOMINANT_COLORS)?;
+    let mut counts = vec![0usize; quantized.palette.len()];
+    for index in quantized.indices.iter() {
+        counts[*index as usize] += 1;
+    }
+    // Fully transparent pixels share one entry with zero alpha.
+    let visible = |index: usize| quantized.alpha.get(index).is_none_or(|alpha| *alpha != 0);
+    let total: usize = (0..counts.len()).filter(|index| visible(*index)).map(|index| counts[index]).sum();
+    let mut colors: Vec<([u8; 3], usize)> = (0..counts.len())
+        .filter(|index| visible(*index) && counts[*index] > 0)
+        .map(|index| {
+            let color = quantized.palette.get(index as u8).unwrap();
+            ([color.r, color.g, color.b], counts[index])
+        })
+        .collect();
+    colors.sort_by_key(|(color, count)| (std::cmp::Reverse(*count), *color));
+    Ok(colors.into_iter().map(|(color, count)| (color, count as f64 / total as f64)).collect())
+}
+
+// Luma of the image over white, averaged down (or repeated up) to
+// `width` x `height`.
+fn resize_luma(image: &ImageBuffer, width: u32, height: u32) -> Vec<f64> {
+    let luma: Vec<f64> = image
+        .pixels
+        .iter()
+        .map(|[r, g, b, a]| {
+            let value = 0.299 * *r as f64 + 0.587 * *g as f64 + 0.114 * *b as f64;
+            let alpha = *a as f64 / 255.0;
+            value * alpha + 255.0 * (1.0 - alpha)
+        })
+        .collect();
+    let span = |i: u32, from: u32, to: u32| {
+        let start = (i as u64 * from as u64 / to as u64) as usize;
+        let end = ((i as u64 + 1) * from as u64).div_ceil(to as u64) as usize;
+        start..end.max(start + 1)
+    };
+    let mut resized = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let (rows, columns) = (span(y, image.height, height), span(x, image.width, width));
+            let count = (rows.len() * columns.len()) as f64;
+            let sum: f64 = rows.flat_map(|row| columns.clone().map(move |column| (row, column)))
+                .map(|(row, column)| luma[row * image.width as usize + column])
+                .sum();
+            resized.push(sum / count);
+        }
+    }
+    resized
+}
+
+fn to_bits(bits: impl Iterator<Item = bool>) -> u64 {
+    bits.fold(0, |hash, bit| (hash << 1) | bit as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, color: impl Fn(u32, u32) -> [u8; 4]) -> ImageBuffer {
+        let pixels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| color(x, y)).collect();
+        ImageBuffer::new(width, height, pixels).unwrap()
+    }
+
+    fn pattern(width: u32, height: u32) -> ImageBuffer {
+        image(width, height, |x, y| {
+            let value = ((x * 255 / width) ^ (y * 255 / height)) as u8;
+            [value, value / 2, 255 - value, 255]
+        })
+    }
+
+    #[test]
+    fn test_hashes_survive_resizing() {
+        let (small, large) = (Signature::from_image(&pattern(64, 48)).unwrap(), Signature::from_image(&pattern(128, 96)).unwrap());
+        assert!(hamming_distance(small.average_hash, large.average_hash) <= 4);
+        assert!(hamming_distance(small.difference_hash, large.difference_hash) <= 4);
+        assert!(hamming_distance(small.perceptual_hash, large.perceptual_hash) <= 4);
+    }
+
+    #[test]
+    fn test_hashes_tell_images_apart() {
+        let flipped = image(64, 48, |x, y| pattern(64, 48).pixel(63 - x, y));
+        let (a, b) = (Signature::from_image(&pattern(64, 48)).unwrap(), Signature::from_image(&flipped).unwrap());
+        assert!(hamming_distance(a.perceptual_hash, b.perceptual_hash) > 10);
+        assert!(hamming_distance(a.difference_hash, b.difference_hash) > 10);
+    }
+
+    #[test]
+    fn test_difference_hash_of_gradient() {
+        let gradient = image(90, 8, |x, _| [(x * 2) as u8, (x * 2) as u8, (x * 2) as u8, 255]);
+        assert_eq!(difference_hash(&gradient), u64::MAX);
+        assert_eq!(difference_hash(&image(3, 3, |_, _| [9, 9, 9, 255])), 0);
+    }
+
+    #[test]
+    fn test_dominant_colors() {
+        let image = image(4, 1, |x, _| match x {
+            0 => [0, 0, 0, 0],
+            3 => [0, 0, 255, 255],
+            _ => [255, 0, 0, 255]
+        });
+        let signature = Signature::from_image(&image).unwrap();
+        assert_eq!(signature.dominant_colors.len(), 2);
+        assert_eq!(signature.dominant_colors[0].0, [255, 0, 0]);
+        assert!((signature.dominant_colors[0].1 - 2.0 / 3.0).abs() < 1e-9);
+        let json = signature.to_json();
+        assert_eq!(json["dominant_colors"][1]["color"], "#0000ff");
+        assert_eq!(json["dominant_colors"][1]["share"], 0.333);
+        assert_eq!(json["ahash"].as_str().unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_empty_image() {
+        assert!(Signature::from_image(&ImageBuffer::new(0, 0, Vec::new()).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,992 @@
+use std::collections::HashSet;
+
+use crate::{
+    cancel::CancellationToken,
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    error::PngError,
+    filter::{self, FilterStrategy},
+    idot,
+    ihdr::{ColorType, Ihdr},
+    palette::{Palette, COLOR_DEPENDENT_TYPES},
+    png::Png,
+    quantize,
+    transparency::Transparency,
+    zlib::{self, CompressionLevel}
+};
+
+// Unfiltered image data in the image's own format: `height` rows of packed
+// samples, big-endian at 16 bits, with Adam7 passes put back in place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawImage {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub data: Vec<u8>
+}
+
+// 8-bit RGBA pixels in row order. Decoding produces one and the RGBA
+// encoders take one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>
+}
+
+// Approximate memory used by a decode. Buffers are counted by capacity, and
+// the peak is the most that was held at the same time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+    pub compressed_bytes: usize,
+    pub inflate_output_bytes: usize,
+    pub peak_buffer_bytes: usize
+}
+
+// Pixel layouts `Png::decode_into` writes, as GUI toolkits and display
+// controllers take them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Rgba8,
+    Bgra8,
+    // 5 bits of red, 6 of green and 5 of blue in a little-endian u16. Alpha
+    // is dropped.
+    Rgb565
+}
+
+impl OutputFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            OutputFormat::Rgba8 | OutputFormat::Bgra8 => 4,
+            OutputFormat::Rgb565 => 2
+        }
+    }
+
+    fn write(&self, pixel: [u8; 4], out: &mut [u8]) {
+        let [r, g, b, a] = pixel;
+        match self {
+            OutputFormat::Rgba8 => out.copy_from_slice(&pixel),
+            OutputFormat::Bgra8 => out.copy_from_slice(&[b, g, r, a]),
+            OutputFormat::Rgb565 => {
+                let value = (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3;
+                out.copy_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+}
+
+impl DecodeStats {
+    fn hold(&mut self, buffers: &[usize]) {
+        self.peak_buffer_bytes = self.peak_buffer_bytes.max(buffers.iter().sum());
+    }
+}
+
+impl RawImage {
+    pub fn row_len(&self) -> usize {
+        (self.width as usize * self.color_type.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+    pub fn row(&self, y: u32) -> &[u8] {
+        let row_len = self.row_len();
+        &self.data[y as usize * row_len..(y as usize + 1) * row_len]
+    }
+
+    // Every sample in row order, unpacked, with 16-bit samples read big-endian.
+    pub fn samples(&self) -> Vec<u16> {
+        let row_samples = self.width as usize * self.color_type.channels();
+        (0..self.height)
+            .flat_map(|y| (0..row_samples).map(move |i| sample16(self.row(y), i, self.bit_depth)))
+            .collect()
+    }
+}
+
+impl Png {
+    pub fn decode_pixels(&self) -> Result<RawImage, PngError> {
+        decode_raw(self)
+    }
+
+    // Decodes straight into a caller's framebuffer, with rows packed one
+    // after another. Each scanline is converted into the buffer as it is
+    // unfiltered, without an RGBA copy of the whole image.
+    pub fn decode_into(&self, buffer: &mut [u8], format: OutputFormat) -> Result<(), PngError> {
+        let width = self.header()?.width as usize;
+        self.decode_into_with_stride(buffer, format, width * format.bytes_per_pixel())
+    }
+
+    // `decode_into` with rows starting `stride` bytes apart. Bytes past the
+    // end of each row are left alone, so padding in the buffer keeps its
+    // contents.
+    pub fn decode_into_with_stride(&self, buffer: &mut [u8], format: OutputFormat, stride: usize) -> Result<(), PngError> {
+        let ihdr = self.header()?;
+        let bpp = format.bytes_per_pixel();
+        let row_bytes = ihdr.width as usize * bpp;
+        if stride < row_bytes {
+            return Err(PngError::InvalidArgument("stride is shorter than a row of the image"));
+        }
+        let needed = (ihdr.height as usize).saturating_sub(1).saturating_mul(stride).saturating_add(row_bytes);
+        if buffer.len() < needed {
+            return Err(PngError::InvalidArgument("buffer is too small for the image"));
+        }
+        let (token, mut stats) = (CancellationToken::new(), DecodeStats::default());
+        let (ihdr, inflated) = inflate_checked(self, &token, &mut stats)?;
+        convert_scanlines(self, &ihdr, &inflated, &token, &mut stats, 0, &mut |y, x0, dx, row| {
+            let out = &mut buffer[y * stride..y * stride + row_bytes];
+            for (x, pixel) in row.iter().enumerate() {
+                let start = (x0 + x * dx) * bpp;
+                format.write(*pixel, &mut out[start..start + bpp]);
+            }
+        })
+    }
+
+    // Encodes rows of packed samples, laid out like `RawImage::data`, into a
+    // non-interlaced IHDR/IDAT/IEND image. Indexed images still need a
+    // palette from `set_palette`.
+    pub fn from_pixels(width: u32, height: u32, color_type: ColorType, bit_depth: u8, data: &[u8]) -> Result<Png, PngError> {
+        let ihdr = Ihdr::new(width, height, bit_depth, color_type)?;
+        Png::from_pixels_with(width, height, color_type, bit_depth, data, FilterStrategy::for_image(&ihdr), CompressionLevel::Default)
+    }
+
+    // `from_pixels` taking one value per sample, in row order, instead of
+    // packed rows. Values must fit in `bit_depth` bits.
+    pub fn from_samples(width: u32, height: u32, color_type: ColorType, bit_depth: u8, samples: &[u16]) -> Result<Png, PngError> {
+        let ihdr = Ihdr::new(width, height, bit_depth, color_type)?;
+        let row_samples = width as usize * color_type.channels();
+        if samples.len() != row_samples * height as usize {
+            return Err(PngError::InvalidArgument("sample count does not match the image dimensions"));
+        }
+        if samples.iter().any(|value| (*value as u32) >> bit_depth != 0) {
+            return Err(PngError::InvalidArgument("sample value does not fit in the bit depth"));
+        }
+        let mut data = Vec::with_capacity(ihdr.row_len(width) * height as usize);
+        for row in samples.chunks(row_samples) {
+            match bit_depth {
+                16 => data.extend(row.iter().flat_map(|value| value.to_be_bytes())),
+                _ => {
+                    let mut packed = vec![0u8; ihdr.row_len(width)];
+                    for (i, value) in row.iter().enumerate() {
+                        let bits = i * bit_depth as usize;
+                        packed[bits / 8] |= (*value as u8) << (8 - bit_depth as usize - bits % 8);
+                    }
+                    data.extend(packed);
+                }
+            }
+        }
+        Png::from_pixels(width, height, color_type, bit_depth, &data)
+    }
+
+    // Encodes `image` as an 8-bit image of `color_type`. Indexed images get a
+    // palette, and tRNS when needed, of the image's colors, which must be 256
+    // or fewer.
+    pub fn from_rgba(image: &ImageBuffer, color_type: ColorType) -> Result<Png, PngError> {
+        if color_type != ColorType::Indexed {
+            return Png::from_pixels(image.width, image.height, color_type, 8, &image.to_samples(color_type)?);
+        }
+        let colors: HashSet<[u8; 4]> = image.pixels.iter().map(|pixel| if pixel[3] == 0 { [0; 4] } else { *pixel }).collect();
+        if colors.len() > Palette::MAX_ENTRIES {
+            return Err(PngError::InvalidArgument("image has more than 256 colors"));
+        }
+        quantize::quantize(image, Palette::MAX_ENTRIES)?.to_png(CompressionLevel::Default)
+    }
+
+    // Re-encodes this image with the pixels of `image`, keeping every chunk
+    // that does not describe the old colors. The color type stays the same
+    // when the new pixels fit it at 8 bits, otherwise it becomes the smallest
+    // one that holds them.
+    pub fn with_rgba(&self, image: &ImageBuffer) -> Result<Png, PngError> {
+        let color_type = self.header()?.color_type;
+        let encoded = Png::from_rgba(image, color_type).or_else(|_| Png::from_rgba(image, image.min_color_type()))?;
+        Ok(self.replace_image(&encoded))
+    }
+
+    // Swaps in the IHDR, palette and image data of `encoded`, a freshly
+    // encoded IHDR/.../IEND image, dropping chunks that describe the old
+    // colors and any iDOT index of the old image data.
+    pub fn replace_image(&self, encoded: &Png) -> Png {
+        let (ihdr, rest) = encoded.chunks().split_first().unwrap();
+        let mut image_chunks = Some(&rest[..rest.len() - 1]);
+        let mut chunks = Vec::new();
+        for chunk in self.chunks() {
+            match &chunk.chunk_type().bytes() {
+                _ if COLOR_DEPENDENT_TYPES.contains(*chunk.chunk_type()) => {},
+                b"iDOT" => {},
+                b"IHDR" => chunks.push(ihdr.clone()),
+                b"IDAT" => chunks.extend(image_chunks.take().into_iter().flatten().cloned()),
+                _ => chunks.push(chunk.clone())
+            }
+        }
+        self.with_chunks(chunks)
+    }
+
+    pub fn from_pixels_with(
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        bit_depth: u8,
+        data: &[u8],
+        strategy: FilterStrategy,
+        compression: CompressionLevel
+    ) -> Result<Png, PngError> {
+        let ihdr = Ihdr::new(width, height, bit_depth, color_type)?;
+        let row_len = ihdr.row_len(width);
+        if data.len() != row_len * height as usize {
+            return Err(PngError::InvalidArgument("pixel data does not match the image dimensions"));
+        }
+        let filtered = filter::filter_with(data, ihdr.filter_bpp(), row_len, strategy);
+        Ok(Png::from_chunks(vec![
+            ihdr.into(),
+            Chunk::new(ChunkType(u32::from_be_bytes(*b"IDAT")), zlib::deflate(&filtered, compression.level())?),
+            Chunk::new(ChunkType(u32::from_be_bytes(*b"IEND")), Vec::new()),
+        ]))
+    }
+}
+
+impl ImageBuffer {
+    pub fn new(width: u32, height: u32, pixels: Vec<[u8; 4]>) -> Result<ImageBuffer, PngError> {
+        if pixels.len() != width as usize * height as usize {
+            return Err(PngError::InvalidArgument("pixel count does not match the image dimensions"));
+        }
+        Ok(ImageBuffer { width, height, pixels })
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[[u8; 4]]> {
+        self.pixels.chunks_exact(self.width.max(1) as usize)
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [[u8; 4]]> {
+        self.pixels.chunks_exact_mut(self.width.max(1) as usize)
+    }
+
+    // Every pixel with its x and y, in row order.
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32, [u8; 4])> + '_ {
+        self.rows()
+            .zip(0..)
+            .flat_map(|(row, y)| row.iter().zip(0..).map(move |(pixel, x)| (x, y, *pixel)))
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        (x < self.width && y < self.height).then(|| self.pixel(x, y))
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 4]) -> Result<(), PngError> {
+        if x >= self.width || y >= self.height {
+            return Err(PngError::InvalidArgument("pixel is outside the image"));
+        }
+        self.pixels[(y * self.width + x) as usize] = color;
+        Ok(())
+    }
+
+    // The smallest grayscale or truecolor type that holds every pixel exactly.
+    pub fn min_color_type(&self) -> ColorType {
+        let gray = self.pixels.iter().all(|[r, g, b, _]| r == g && g == b);
+        let opaque = self.pixels.iter().all(|pixel| pixel[3] == 255);
+        match (gray, opaque) {
+            (true, true) => ColorType::Grayscale,
+            (true, false) => ColorType::GrayscaleAlpha,
+            (false, true) => ColorType::Rgb,
+            (false, false) => ColorType::Rgba
+        }
+    }
+
+    // The pixels as rows of 8-bit `color_type` samples. Dropping a channel
+    // must not lose anything: gray needs equal r, g and b, and color types
+    // without alpha need opaque pixels. Indexed images need a palette, see
+    // `Png::from_rgba`.
+    pub fn to_samples(&self, color_type: ColorType) -> Result<Vec<u8>, PngError> {
+        if color_type == ColorType::Indexed {
+            return Err(PngError::InvalidArgument("indexed samples need a palette"));
+        }
+        let gray = matches!(color_type, ColorType::Grayscale | ColorType::GrayscaleAlpha);
+        let mut data = Vec::with_capacity(self.pixels.len() * color_type.channels());
+        for [r, g, b, a] in self.pixels.iter().copied() {
+            if (gray && (r != g || g != b)) || (!color_type.has_alpha() && a != 255) {
+                return Err(PngError::InvalidArgument("pixels do not fit the color type"));
+            }
+            match color_type {
+                ColorType::Grayscale => data.push(r),
+                ColorType::GrayscaleAlpha => data.extend([r, a]),
+                ColorType::Rgb => data.extend([r, g, b]),
+                _ => data.extend([r, g, b, a])
+            }
+        }
+        Ok(data)
+    }
+}
+
+// Decodes the image data of a PNG into 8-bit RGBA, rounding 16-bit samples.
+pub fn decode_rgba(png: &Png) -> Result<ImageBuffer, PngError> {
+    decode_rgba_with(png, &CancellationToken::new())
+}
+
+// `decode_rgba` that gives up with `PngError::Cancelled` once `token` is cancelled.
+pub fn decode_rgba_with(png: &Png, token: &CancellationToken) -> Result<ImageBuffer, PngError> {
+    decode_rgba_with_stats(png, token).map(|(image, _)| image)
+}
+
+pub fn decode_rgba_with_stats(png: &Png, token: &CancellationToken) -> Result<(ImageBuffer, DecodeStats), PngError> {
+    let mut stats = DecodeStats::default();
+    let image = decode(png, token, &mut stats, &mut |_, _| {})?;
+    Ok((image, stats))
+}
+
+// `decode_rgba` that calls `on_row` with the index and pixels of an image row
+// each time a scanline is decoded. Interlaced images report a row once per
+// Adam7 pass that touches it, with pixels from later passes still zeroed, so
+// a viewer can show the image as it sharpens.
+pub fn decode_with<F>(png: &Png, mut on_row: F) -> Result<ImageBuffer, PngError>
+where
+    F: FnMut(u32, &[[u8; 4]])
+{
+    decode(png, &CancellationToken::new(), &mut DecodeStats::default(), &mut on_row)
+}
+
+fn decode(
+    png: &Png,
+    token: &CancellationToken,
+    stats: &mut DecodeStats,
+    on_row: &mut dyn FnMut(u32, &[[u8; 4]])
+) -> Result<ImageBuffer, PngError> {
+    let (ihdr, inflated) = inflate_checked(png, token, stats)?;
+    let (width, height) = (ihdr.width as usize, ihdr.height as usize);
+    let mut pixels = vec![[0u8; 4]; width * height];
+    convert_scanlines(png, &ihdr, &inflated, token, stats, pixels.capacity() * 4, &mut |y, x0, dx, row| {
+        let image_row = &mut pixels[y * width..(y + 1) * width];
+        for (x, pixel) in row.iter().enumerate() {
+            image_row[x0 + x * dx] = *pixel;
+        }
+        on_row(y as u32, image_row);
+    })?;
+    Ok(ImageBuffer { width: ihdr.width, height: ihdr.height, pixels })
+}
+
+// The inflated image data, checked to be the size the IHDR calls for before
+// anything is allocated from the IHDR. Inflating stops as soon as the data
+// passes that size, so a small stream cannot inflate to gigabytes first.
+fn inflate_checked(png: &Png, token: &CancellationToken, stats: &mut DecodeStats) -> Result<(Ihdr, Vec<u8>), PngError> {
+    let ihdr = png.header()?;
+    let expected = filtered_len(&ihdr).ok_or(PngError::InvalidImageData("image data does not match the IHDR dimensions"))?;
+    let compressed = png.image_data();
+    token.check()?;
+    // iDOT segments inflate exactly the rows the IHDR calls for.
+    let inflated = match idot::inflate_segments(png) {
+        Some(inflated) => inflated,
+        None => zlib::inflate_limited(&compressed, expected, token)?
+    };
+    stats.compressed_bytes = compressed.len();
+    stats.inflate_output_bytes = inflated.len();
+    stats.hold(&[compressed.capacity(), inflated.capacity()]);
+    if inflated.len() != expected {
+        return Err(PngError::InvalidImageData("image data does not match the IHDR dimensions"));
+    }
+    Ok((ihdr, inflated))
+}
+
+// Takes the image row, the x of the first pixel, the distance between pixels
+// and the converted pixels of one scanline.
+type ScanlineSink<'a> = dyn FnMut(usize, usize, usize, &[[u8; 4]]) + 'a;
+
+// Unfilters each pass and converts its scanlines to 8-bit RGBA one at a
+// time for `emit`. `held` is what the caller keeps for the output, for the
+// stats.
+fn convert_scanlines(
+    png: &Png,
+    ihdr: &Ihdr,
+    inflated: &[u8],
+    token: &CancellationToken,
+    stats: &mut DecodeStats,
+    held: usize,
+    emit: &mut ScanlineSink
+) -> Result<(), PngError> {
+    let (width, height, bit_depth, color_type) = (ihdr.width, ihdr.height, ihdr.bit_depth, ihdr.color_type);
+    let palette = match color_type {
+        ColorType::Indexed => indexed_palette(png)?,
+        _ => Vec::new()
+    };
+    let transparency = png.transparency()?;
+    let alpha = |key: bool| if key { 0 } else { 255 };
+    // Color keys are compared at full precision before samples are scaled to 8 bits.
+    let to_u8 = |value: u16| {
+        let max = (1u32 << bit_depth) - 1;
+        ((value as u32 * 255 + max / 2) / max) as u8
+    };
+    let convert = |row: &[u8], x: usize| -> Result<[u8; 4], PngError> {
+        let channel = |i: usize| sample16(row, x * color_type.channels() + i, bit_depth);
+        Ok(match color_type {
+            ColorType::Grayscale => {
+                let value = channel(0);
+                let key = transparency == Some(Transparency::Gray(value));
+                let value = to_u8(value);
+                [value, value, value, alpha(key)]
+            },
+            ColorType::Rgb => {
+                let [r, g, b] = [channel(0), channel(1), channel(2)];
+                let key = transparency == Some(Transparency::Rgb(r, g, b));
+                [to_u8(r), to_u8(g), to_u8(b), alpha(key)]
+            },
+            ColorType::Indexed => *palette
+                .get(sample(row, x, bit_depth) as usize)
+                .ok_or(PngError::InvalidImageData("palette index out of range"))?,
+            ColorType::GrayscaleAlpha => {
+                let value = to_u8(channel(0));
+                [value, value, value, to_u8(channel(1))]
+            },
+            ColorType::Rgba => [to_u8(channel(0)), to_u8(channel(1)), to_u8(channel(2)), to_u8(channel(3))]
+        })
+    };
+
+    let passes = if ihdr.interlaced { filter::ADAM7_PASSES.as_slice() } else { &[(0, 0, 1, 1)] };
+    let mut converted = Vec::with_capacity(width as usize);
+    let mut offset = 0;
+    for (x0, y0, dx, dy) in passes {
+        let pass_width = width.saturating_sub(*x0).div_ceil(*dx) as usize;
+        let pass_height = height.saturating_sub(*y0).div_ceil(*dy) as usize;
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let row_len = ihdr.row_len(pass_width as u32);
+        let filtered = &inflated[offset..offset + (row_len + 1) * pass_height];
+        offset += filtered.len();
+        let raw = filter::unfilter_with(filtered, ihdr.filter_bpp(), row_len, token)?;
+        stats.hold(&[inflated.len(), raw.capacity(), converted.capacity() * 4, held]);
+
+        for (i, row) in raw.chunks(row_len).enumerate() {
+            token.check()?;
+            converted.clear();
+            for x in 0..pass_width {
+                converted.push(convert(row, x)?);
+            }
+            emit((y0 + i as u32 * dy) as usize, *x0 as usize, *dx as usize, &converted);
+        }
+    }
+    Ok(())
+}
+
+// Concatenates the IDAT chunks, inflates them and reverses the scanline
+// filters of every pass, keeping the pixels at their original bit depth.
+pub fn decode_raw(png: &Png) -> Result<RawImage, PngError> {
+    let (ihdr, inflated) = inflate_checked(png, &CancellationToken::new(), &mut DecodeStats::default())?;
+    let bits_per_pixel = ihdr.bits_per_pixel();
+    let image_row_len = ihdr.row_len(ihdr.width);
+    let mut data = vec![0u8; image_row_len * ihdr.height as usize];
+
+    let passes = if ihdr.interlaced { filter::ADAM7_PASSES.as_slice() } else { &[(0, 0, 1, 1)] };
+    let mut offset = 0;
+    for (x0, y0, dx, dy) in passes {
+        let pass_width = ihdr.width.saturating_sub(*x0).div_ceil(*dx) as usize;
+        let pass_height = ihdr.height.saturating_sub(*y0).div_ceil(*dy) as usize;
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let row_len = ihdr.row_len(pass_width as u32);
+        let filtered = inflated
+            .get(offset..offset + (row_len + 1) * pass_height)
+            .ok_or(PngError::InvalidImageData("image data does not match the IHDR dimensions"))?;
+        offset += filtered.len();
+        let raw = filter::unfilter(filtered, ihdr.filter_bpp(), row_len)?;
+
+        for (i, row) in raw.chunks(row_len).enumerate() {
+            let y = *y0 as usize + i * *dy as usize;
+            let image_row = &mut data[y * image_row_len..(y + 1) * image_row_len];
+            if !ihdr.interlaced {
+                image_row.copy_from_slice(row);
+                continue;
+            }
+            for x in 0..pass_width {
+                let image_x = *x0 as usize + x * *dx as usize;
+                if bits_per_pixel >= 8 {
+                    let len = bits_per_pixel / 8;
+                    image_row[image_x * len..(image_x + 1) * len].copy_from_slice(&row[x * len..(x + 1) * len]);
+                } else {
+                    let bits = image_x * bits_per_pixel;
+                    image_row[bits / 8] |= sample(row, x, ihdr.bit_depth) << (8 - bits_per_pixel - bits % 8);
+                }
+            }
+        }
+    }
+    if offset != inflated.len() {
+        return Err(PngError::InvalidImageData("image data does not match the IHDR dimensions"));
+    }
+    Ok(RawImage { width: ihdr.width, height: ihdr.height, bit_depth: ihdr.bit_depth, color_type: ihdr.color_type, data })
+}
+
+// Bytes of filtered image data `ihdr` calls for, filter type bytes included,
+// or None if that is more than fits in memory.
+fn filtered_len(ihdr: &Ihdr) -> Option<usize> {
+    filter::pass_sizes(ihdr).into_iter().try_fold(0usize, |total, (width, height)| {
+        let row = (width as usize).checked_mul(ihdr.bits_per_pixel())?.div_ceil(8).checked_add(1)?;
+        total.checked_add(row.checked_mul(height as usize)?)
+    })
+}
+
+// Reads the `index`th sample of a packed scanline with samples of `bit_depth` <= 8 bits.
+pub(crate) fn sample(row: &[u8], index: usize, bit_depth: u8) -> u8 {
+    let bits = index * bit_depth as usize;
+    let shift = 8 - bit_depth as usize - bits % 8;
+    (row[bits / 8] >> shift) & (((1u16 << bit_depth) - 1) as u8)
+}
+
+// Reads the `index`th sample of a scanline at any bit depth.
+fn sample16(row: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+        _ => sample(row, index, bit_depth) as u16
+    }
+}
+
+fn indexed_palette(png: &Png) -> Result<Vec<[u8; 4]>, PngError> {
+    let palette = png.palette()?.ok_or(PngError::ChunkNotFound(String::from("PLTE")))?;
+    let alpha = match png.transparency()? {
+        Some(Transparency::Palette(alpha)) => alpha,
+        _ => Vec::new()
+    };
+    Ok(palette.entries()
+        .iter()
+        .enumerate()
+        .map(|(i, color)| [color.r, color.g, color.b, alpha.get(i).copied().unwrap_or(255)])
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterType;
+
+    fn chunk_from_bytes(chunk_type: &[u8; 4], data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType(u32::from_be_bytes(*chunk_type)), data)
+    }
+
+    fn testing_png(width: u32, height: u32, bit_depth: u8, color_type: u8, raw: &[u8], extra: Vec<Chunk>) -> Png {
+        let mut ihdr = Vec::new();
+        ihdr.extend(width.to_be_bytes());
+        ihdr.extend(height.to_be_bytes());
+        ihdr.extend([bit_depth, color_type, 0, 0, 0]);
+        let row_len = raw.len() / height as usize;
+        let filtered = filter::filter(raw, 1, row_len, FilterType::Up);
+        let mut chunks = vec![chunk_from_bytes(b"IHDR", ihdr)];
+        chunks.extend(extra);
+        chunks.push(chunk_from_bytes(b"IDAT", zlib::deflate(&filtered, 6).unwrap()));
+        chunks.push(chunk_from_bytes(b"IEND", Vec::new()));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_decode_rgb() {
+        let png = testing_png(2, 1, 8, 2, &[1, 2, 3, 4, 5, 6], Vec::new());
+        let image = decode_rgba(&png).unwrap();
+        assert_eq!(image.pixels, [[1, 2, 3, 255], [4, 5, 6, 255]]);
+    }
+
+    #[test]
+    fn test_decode_packed_indexed() {
+        let plte = chunk_from_bytes(b"PLTE", vec![0, 0, 0, 255, 0, 0, 0, 255, 0]);
+        let trns = chunk_from_bytes(b"tRNS", vec![0]);
+        let png = testing_png(3, 2, 2, 3, &[0b00_01_10_00, 0b10_10_01_00], vec![plte, trns]);
+        let image = decode_rgba(&png).unwrap();
+        assert_eq!(image.pixel(0, 0), [0, 0, 0, 0]);
+        assert_eq!(image.pixel(1, 0), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(2, 1), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(0, 1), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_decode_rgb_color_key() {
+        let trns = chunk_from_bytes(b"tRNS", vec![0, 4, 0, 5, 0, 6]);
+        let png = testing_png(2, 1, 8, 2, &[1, 2, 3, 4, 5, 6], vec![trns]);
+        assert_eq!(decode_rgba(&png).unwrap().pixels, [[1, 2, 3, 255], [4, 5, 6, 0]]);
+    }
+
+    #[test]
+    fn test_decode_low_bit_depth_gray() {
+        let png = testing_png(2, 1, 1, 0, &[0b0100_0000], Vec::new());
+        assert_eq!(decode_rgba(&png).unwrap().pixels, [[0, 0, 0, 255], [255, 255, 255, 255]]);
+    }
+
+    #[test]
+    fn test_palette_index_out_of_range() {
+        let plte = chunk_from_bytes(b"PLTE", vec![0, 0, 0]);
+        let png = testing_png(1, 1, 8, 3, &[1], vec![plte]);
+        assert!(matches!(decode_rgba(&png), Err(PngError::InvalidImageData(_))));
+    }
+
+    #[test]
+    fn test_decode_stats() {
+        let png = testing_png(2, 2, 8, 2, &[7; 12], Vec::new());
+        let (_, stats) = decode_rgba_with_stats(&png, &CancellationToken::new()).unwrap();
+        assert_eq!(stats.compressed_bytes, png.image_data().len());
+        assert_eq!(stats.inflate_output_bytes, 14);
+        assert!(stats.peak_buffer_bytes >= 12 + 16);
+    }
+
+    #[test]
+    fn test_decode_cancelled() {
+        let png = testing_png(2, 1, 8, 2, &[1, 2, 3, 4, 5, 6], Vec::new());
+        let token = CancellationToken::with_timeout(std::time::Duration::ZERO);
+        assert!(matches!(decode_rgba_with(&png, &token), Err(PngError::Cancelled)));
+    }
+
+    // 8-bit RGB pixels split into Adam7 passes, each filtered with None.
+    fn interlaced_png(width: u32, height: u32, rgb: &[u8]) -> Png {
+        let mut filtered = Vec::new();
+        for (x0, y0, dx, dy) in filter::ADAM7_PASSES {
+            let xs: Vec<u32> = (x0..width).step_by(dx as usize).collect();
+            if xs.is_empty() {
+                continue;
+            }
+            for y in (y0..height).step_by(dy as usize) {
+                filtered.push(0);
+                for x in xs.iter() {
+                    let i = (y * width + x) as usize * 3;
+                    filtered.extend(&rgb[i..i + 3]);
+                }
+            }
+        }
+        let mut ihdr = crate::ihdr::Ihdr::new(width, height, 8, ColorType::Rgb).unwrap();
+        ihdr.interlaced = true;
+        Png::from_chunks(vec![
+            ihdr.into(),
+            chunk_from_bytes(b"IDAT", zlib::deflate(&filtered, 6).unwrap()),
+            chunk_from_bytes(b"IEND", Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_decode_interlaced() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| i as u8).collect();
+        let interlaced = decode_rgba(&interlaced_png(5, 3, &rgb)).unwrap();
+        let plain = decode_rgba(&testing_png(5, 3, 8, 2, &rgb, Vec::new())).unwrap();
+        assert_eq!(interlaced, plain);
+    }
+
+    // A spec-legal header for an image far bigger than its data.
+    fn oversized_png() -> Png {
+        let ihdr = Ihdr::new(i32::MAX as u32, i32::MAX as u32, 16, ColorType::Rgba).unwrap();
+        Png::from_chunks(vec![
+            ihdr.into(),
+            chunk_from_bytes(b"IDAT", zlib::deflate(&[0; 16], 6).unwrap()),
+            chunk_from_bytes(b"IEND", Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_decode_oversized_header() {
+        assert!(matches!(decode_rgba(&oversized_png()), Err(PngError::InvalidImageData(_))));
+        assert!(matches!(oversized_png().decode_pixels(), Err(PngError::InvalidImageData(_))));
+        let small = testing_png(2, 2, 8, 2, &[0; 12], Vec::new());
+        let mut ihdr = small.header().unwrap();
+        ihdr.height = 3;
+        let mut taller = Png::from_chunks(small.chunks().to_vec());
+        taller.replace_chunk(ihdr.into());
+        assert!(decode_rgba(&taller).is_err());
+        assert!(decode_raw(&taller).is_err());
+    }
+
+    #[test]
+    fn test_decode_stops_inflating_at_the_header_size() {
+        // 1x1 grayscale needs 2 bytes; the IDAT inflates to 16 MB.
+        let bomb = testing_png(1, 1, 8, 0, &[0], Vec::new());
+        let mut chunks = bomb.chunks().to_vec();
+        chunks[1] = chunk_from_bytes(b"IDAT", zlib::deflate(&vec![0; 16 << 20], 9).unwrap());
+        let bomb = Png::from_chunks(chunks);
+        assert!(bomb.image_data().len() < 32 * 1024);
+        let result = decode_rgba(&bomb);
+        assert!(matches!(result, Err(PngError::InvalidImageData("zlib stream inflates to more than expected"))));
+        let result = decode_raw(&bomb);
+        assert!(matches!(result, Err(PngError::InvalidImageData("zlib stream inflates to more than expected"))));
+    }
+
+    #[test]
+    fn test_decode_with_rows() {
+        let png = testing_png(2, 3, 8, 2, &[9; 18], Vec::new());
+        let mut rows = Vec::new();
+        let image = decode_with(&png, |y, row| rows.push((y, row.to_vec()))).unwrap();
+        assert_eq!(rows.iter().map(|(y, _)| *y).collect::<Vec<u32>>(), [0, 1, 2]);
+        assert!(rows.iter().all(|(_, row)| row == &[[9, 9, 9, 255]; 2]));
+        assert_eq!(image.pixels.len(), 6);
+    }
+
+    #[test]
+    fn test_decode_into() {
+        let png = testing_png(2, 2, 8, 2, &[255, 0, 0, 0, 255, 0, 0, 0, 255, 8, 16, 24], Vec::new());
+        let mut buffer = [0xaa; 2 * 10];
+        png.decode_into_with_stride(&mut buffer, OutputFormat::Bgra8, 10).unwrap();
+        assert_eq!(buffer[..10], [0, 0, 255, 255, 0, 255, 0, 255, 0xaa, 0xaa]);
+        assert_eq!(buffer[10..18], [255, 0, 0, 255, 24, 16, 8, 255]);
+        let mut buffer = [0; 2 * 2 * 2];
+        png.decode_into(&mut buffer, OutputFormat::Rgb565).unwrap();
+        assert_eq!(buffer[..6], [0x00, 0xf8, 0xe0, 0x07, 0x1f, 0x00]);
+        assert_eq!(u16::from_le_bytes([buffer[6], buffer[7]]), 1 << 11 | 4 << 5 | 3);
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| i as u8).collect();
+        let mut interlaced = vec![0; 5 * 3 * 4];
+        interlaced_png(5, 3, &rgb).decode_into(&mut interlaced, OutputFormat::Rgba8).unwrap();
+        let plain = decode_rgba(&testing_png(5, 3, 8, 2, &rgb, Vec::new())).unwrap();
+        assert_eq!(interlaced, plain.pixels.concat());
+    }
+
+    #[test]
+    fn test_decode_into_invalid() {
+        let png = testing_png(2, 2, 8, 2, &[0; 12], Vec::new());
+        assert!(png.decode_into_with_stride(&mut [0; 32], OutputFormat::Rgba8, 7).is_err());
+        assert!(png.decode_into(&mut [0; 15], OutputFormat::Rgba8).is_err());
+        // The last row needs no padding after it.
+        assert!(png.decode_into_with_stride(&mut [0; 20], OutputFormat::Rgba8, 12).is_ok());
+    }
+
+    #[test]
+    fn test_decode_with_adam7_passes() {
+        let rgb = [200; 3 * 3 * 3];
+        let mut rows = Vec::new();
+        decode_with(&interlaced_png(3, 3, &rgb), |y, row| rows.push((y, row.to_vec()))).unwrap();
+        // Passes 1, 4, 5, 6 and 7 cover a 3x3 image; 2 and 3 are empty.
+        assert_eq!(rows.iter().map(|(y, _)| *y).collect::<Vec<u32>>(), [0, 0, 2, 0, 2, 1]);
+        let (blank, filled) = ([0; 4], [200, 200, 200, 255]);
+        assert_eq!(rows[0].1, [filled, blank, blank]);
+        assert_eq!(rows[1].1, [filled, blank, filled]);
+        assert_eq!(rows[3].1, [filled; 3]);
+        assert_eq!(rows[5].1, [filled; 3]);
+    }
+
+    #[test]
+    fn test_decode_raw_filters() {
+        let raw: Vec<u8> = (0..4 * 3 * 6).map(|i| (i * 37 % 256) as u8).collect();
+        for filter_type in FilterType::ALL {
+            let mut ihdr = Vec::new();
+            ihdr.extend([0, 0, 0, 4, 0, 0, 0, 3, 16, 2, 0, 0, 0]);
+            let png = Png::from_chunks(vec![
+                chunk_from_bytes(b"IHDR", ihdr),
+                chunk_from_bytes(b"IDAT", zlib::deflate(&filter::filter(&raw, 6, 24, filter_type), 6).unwrap()),
+                chunk_from_bytes(b"IEND", Vec::new()),
+            ]);
+            let image = png.decode_pixels().unwrap();
+            assert_eq!(image.data, raw);
+            assert_eq!(image.row(2), &raw[48..]);
+        }
+    }
+
+    #[test]
+    fn test_decode_raw_interlaced() {
+        let rgb: Vec<u8> = (0..9 * 5 * 3).map(|i| i as u8).collect();
+        let image = decode_raw(&interlaced_png(9, 5, &rgb)).unwrap();
+        assert_eq!(image.data, rgb);
+        assert_eq!((image.width, image.height, image.bit_depth), (9, 5, 8));
+    }
+
+    #[test]
+    fn test_decode_raw_interlaced_packed() {
+        // A 1-bit 10x3 checkerboard; each pass row is padded to a whole byte.
+        let bit = |x: u32, y: u32| ((x + y) % 2) as u8;
+        let mut filtered = Vec::new();
+        for (x0, y0, dx, dy) in filter::ADAM7_PASSES {
+            let xs: Vec<u32> = (x0..10).step_by(dx as usize).collect();
+            if xs.is_empty() {
+                continue;
+            }
+            for y in (y0..3).step_by(dy as usize) {
+                filtered.push(0);
+                let mut row = vec![0u8; xs.len().div_ceil(8)];
+                for (i, x) in xs.iter().enumerate() {
+                    row[i / 8] |= bit(*x, y) << (7 - i % 8);
+                }
+                filtered.extend(row);
+            }
+        }
+        let mut ihdr = crate::ihdr::Ihdr::new(10, 3, 1, ColorType::Grayscale).unwrap();
+        ihdr.interlaced = true;
+        let png = Png::from_chunks(vec![
+            ihdr.into(),
+            chunk_from_bytes(b"IDAT", zlib::deflate(&filtered, 6).unwrap()),
+            chunk_from_bytes(b"IEND", Vec::new()),
+        ]);
+        let image = png.decode_pixels().unwrap();
+        assert_eq!(image.data, [0b0101_0101, 0b0100_0000, 0b1010_1010, 0b1000_0000, 0b0101_0101, 0b0100_0000]);
+    }
+
+    #[test]
+    fn test_decode_raw_truncated() {
+        let mut png = testing_png(2, 2, 8, 2, &[7; 12], Vec::new());
+        png.chunks[1] = chunk_from_bytes(b"IDAT", zlib::deflate(&[0; 7], 6).unwrap());
+        assert!(matches!(decode_raw(&png), Err(PngError::InvalidImageData(_))));
+    }
+
+    #[test]
+    fn test_from_pixels_round_trip() {
+        let cases: [(ColorType, u8, u32); 4] = [
+            (ColorType::Rgb, 8, 5),
+            (ColorType::Rgba, 16, 3),
+            (ColorType::Grayscale, 2, 7),
+            (ColorType::GrayscaleAlpha, 8, 4),
+        ];
+        for (color_type, bit_depth, width) in cases {
+            let row_len = (width as usize * color_type.channels() * bit_depth as usize).div_ceil(8);
+            let data: Vec<u8> = (0..row_len * 3).map(|i| (i * 29 % 251) as u8).collect();
+            // Padding bits at the end of a packed row are always zero.
+            let data: Vec<u8> = data.chunks(row_len).flat_map(|row| {
+                let mut row = row.to_vec();
+                let padding = row_len * 8 - width as usize * color_type.channels() * bit_depth as usize;
+                *row.last_mut().unwrap() &= 0xff << padding;
+                row
+            }).collect();
+            let png = Png::from_pixels(width, 3, color_type, bit_depth, &data).unwrap();
+            let reparsed = Png::try_from(png.as_bytes().as_ref()).unwrap();
+            let image = reparsed.decode_pixels().unwrap();
+            assert_eq!((image.width, image.color_type, image.bit_depth), (width, color_type, bit_depth));
+            assert_eq!(image.data, data);
+        }
+    }
+
+    #[test]
+    fn test_from_pixels_with_strategy() {
+        let data: Vec<u8> = (0..8 * 8).map(|i| (i % 8 * 30) as u8).collect();
+        let strategy = FilterStrategy::Fixed(FilterType::Up);
+        let png = Png::from_pixels_with(8, 8, ColorType::Grayscale, 8, &data, strategy, CompressionLevel::Fast).unwrap();
+        let stats = filter::FilterStats::from_png(&png).unwrap();
+        assert!(stats.rows.iter().all(|filter_type| *filter_type == FilterType::Up));
+        assert_eq!(png.decode_pixels().unwrap().data, data);
+        assert_eq!(zlib::ZlibHeader::parse(&png.image_data()).unwrap().level, 0);
+
+        let stored = Png::from_pixels_with(8, 8, ColorType::Grayscale, 8, &data, strategy, CompressionLevel::Level(0)).unwrap();
+        assert!(stored.image_data().len() > png.image_data().len());
+        assert_eq!(stored.decode_pixels().unwrap().data, data);
+    }
+
+    #[test]
+    fn test_from_pixels_indexed() {
+        let mut png = Png::from_pixels(2, 1, ColorType::Indexed, 8, &[1, 0]).unwrap();
+        png.set_palette(crate::palette::Palette::new(vec![
+            crate::palette::Rgb::new(0, 0, 0),
+            crate::palette::Rgb::new(255, 0, 0),
+        ]).unwrap());
+        assert_eq!(decode_rgba(&png).unwrap().pixels, [[255, 0, 0, 255], [0, 0, 0, 255]]);
+    }
+
+    #[test]
+    fn test_from_pixels_invalid() {
+        assert!(Png::from_pixels(2, 2, ColorType::Rgb, 8, &[0; 11]).is_err());
+        assert!(Png::from_pixels(2, 2, ColorType::Rgb, 4, &[0; 6]).is_err());
+    }
+
+    #[test]
+    fn test_decode_rgba_16_bit() {
+        let trns = chunk_from_bytes(b"tRNS", vec![0x12, 0x34, 0, 0, 0xff, 0xff]);
+        let raw = [0x12, 0x34, 0, 0, 0xff, 0xff, 0x80, 0x00, 0x00, 0x7f, 0xff, 0xff];
+        let png = testing_png(2, 1, 16, 2, &raw, vec![trns]);
+        assert_eq!(decode_rgba(&png).unwrap().pixels, [[18, 0, 255, 0], [128, 0, 255, 255]]);
+    }
+
+    #[test]
+    fn test_samples_round_trip() {
+        let cases: [(ColorType, u8); 4] = [
+            (ColorType::Rgb, 16),
+            (ColorType::GrayscaleAlpha, 16),
+            (ColorType::Grayscale, 4),
+            (ColorType::Rgba, 8),
+        ];
+        for (color_type, bit_depth) in cases {
+            let count = 5 * 3 * color_type.channels();
+            let samples: Vec<u16> = (0..count).map(|i| (i as u32 * 40503 % (1 << bit_depth)) as u16).collect();
+            let png = Png::from_samples(5, 3, color_type, bit_depth, &samples).unwrap();
+            let reparsed = Png::try_from(png.as_bytes().as_ref()).unwrap();
+            assert_eq!(reparsed.decode_pixels().unwrap().samples(), samples);
+        }
+        let image = Png::from_samples(1, 1, ColorType::Grayscale, 16, &[0x1234]).unwrap().decode_pixels().unwrap();
+        assert_eq!(image.data, [0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_from_samples_invalid() {
+        assert!(Png::from_samples(2, 1, ColorType::Grayscale, 8, &[1]).is_err());
+        assert!(Png::from_samples(1, 1, ColorType::Grayscale, 4, &[16]).is_err());
+    }
+
+    #[test]
+    fn test_from_rgba_color_types() {
+        let gray = vec![[0, 0, 0, 255], [90, 90, 90, 255], [255, 255, 255, 255]];
+        let translucent_gray = vec![[0, 0, 0, 0], [90, 90, 90, 128], [255, 255, 255, 255]];
+        let rgb = vec![[1, 2, 3, 255], [4, 5, 6, 255], [7, 8, 9, 255]];
+        let rgba = vec![[0, 0, 0, 0], [4, 5, 6, 128], [7, 8, 9, 255]];
+        let cases = [
+            (gray, ColorType::Grayscale),
+            (translucent_gray, ColorType::GrayscaleAlpha),
+            (rgb, ColorType::Rgb),
+            (rgba, ColorType::Rgba),
+        ];
+        for (pixels, color_type) in cases {
+            let image = ImageBuffer { width: 3, height: 1, pixels };
+            assert_eq!(image.min_color_type(), color_type);
+            for target in [color_type, ColorType::Rgba, ColorType::Indexed] {
+                let png = Png::from_rgba(&image, target).unwrap();
+                assert_eq!(png.header().unwrap().color_type, target);
+                assert_eq!(decode_rgba(&png).unwrap(), image);
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_samples() {
+        let image = ImageBuffer { width: 2, height: 1, pixels: vec![[1, 1, 1, 255], [2, 3, 4, 200]] };
+        assert_eq!(image.to_samples(ColorType::Rgba).unwrap(), [1, 1, 1, 255, 2, 3, 4, 200]);
+        assert!(image.to_samples(ColorType::Rgb).is_err());
+        assert!(image.to_samples(ColorType::GrayscaleAlpha).is_err());
+        assert!(image.to_samples(ColorType::Indexed).is_err());
+        let image = ImageBuffer { width: 2, height: 1, pixels: vec![[1, 1, 1, 255], [2, 2, 2, 200]] };
+        assert_eq!(image.to_samples(ColorType::GrayscaleAlpha).unwrap(), [1, 255, 2, 200]);
+    }
+
+    #[test]
+    fn test_from_rgba_too_many_colors() {
+        let pixels = (0..300).map(|i| [(i % 256) as u8, (i / 256) as u8, 0, 255]).collect();
+        let image = ImageBuffer { width: 300, height: 1, pixels };
+        assert!(Png::from_rgba(&image, ColorType::Indexed).is_err());
+        assert!(Png::from_rgba(&image, ColorType::Rgb).is_ok());
+    }
+
+    #[test]
+    fn test_get_and_set_pixel() {
+        let mut image = ImageBuffer { width: 2, height: 1, pixels: vec![[1, 2, 3, 255], [4, 5, 6, 255]] };
+        assert_eq!(image.get_pixel(1, 0), Some([4, 5, 6, 255]));
+        assert_eq!(image.get_pixel(2, 0), None);
+        assert_eq!(image.get_pixel(0, 1), None);
+        image.set_pixel(0, 0, [9, 9, 9, 128]).unwrap();
+        assert_eq!(image.pixel(0, 0), [9, 9, 9, 128]);
+        assert!(image.set_pixel(0, 1, [0; 4]).is_err());
+    }
+
+    #[test]
+    fn test_with_rgba() {
+        let text = chunk_from_bytes(b"tEXt", b"Title\0gray".to_vec());
+        let bkgd = chunk_from_bytes(b"bKGD", vec![0, 7]);
+        let png = testing_png(2, 1, 8, 0, &[10, 20], vec![bkgd, text]);
+        let mut image = decode_rgba(&png).unwrap();
+
+        image.set_pixel(1, 0, [30, 30, 30, 255]).unwrap();
+        let edited = png.with_rgba(&image).unwrap();
+        assert_eq!(edited.header().unwrap().color_type, ColorType::Grayscale);
+        assert_eq!(decode_rgba(&edited).unwrap(), image);
+
+        // A colored pixel no longer fits grayscale.
+        image.set_pixel(0, 0, [255, 0, 0, 255]).unwrap();
+        let edited = png.with_rgba(&image).unwrap();
+        assert_eq!(edited.header().unwrap().color_type, ColorType::Rgb);
+        assert_eq!(decode_rgba(&edited).unwrap(), image);
+        let types: Vec<String> = edited.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "tEXt", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_image_buffer_iterators() {
+        let mut image = ImageBuffer::new(2, 2, vec![[1, 0, 0, 255], [2, 0, 0, 255], [3, 0, 0, 255], [4, 0, 0, 255]]).unwrap();
+        let rows: Vec<&[[u8; 4]]> = image.rows().collect();
+        assert_eq!(rows, [[[1, 0, 0, 255], [2, 0, 0, 255]], [[3, 0, 0, 255], [4, 0, 0, 255]]]);
+        for row in image.rows_mut().skip(1) {
+            row[0] = [9, 9, 9, 255];
+        }
+        let pixels: Vec<(u32, u32, [u8; 4])> = image.pixels().collect();
+        assert_eq!(pixels[1], (1, 0, [2, 0, 0, 255]));
+        assert_eq!(pixels[2], (0, 1, [9, 9, 9, 255]));
+        assert_eq!(pixels.len(), 4);
+        assert!(ImageBuffer::new(2, 2, vec![[0; 4]; 3]).is_err());
+        assert_eq!(ImageBuffer::new(0, 0, Vec::new()).unwrap().rows().count(), 0);
+    }
+}
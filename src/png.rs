@@ -1,51 +1,552 @@
-use std::{str::FromStr, fmt::Display, io::ErrorKind};
+use alloc::{boxed::Box, string::{String, ToString}, vec::Vec};
+use core::{str::FromStr, fmt::Display};
+#[cfg(feature = "std")]
+use std::{fs::{self, File}, io::BufWriter, path::Path};
 
-use crate::{chunk::Chunk, chunk_type::ChunkType};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::{chunk::{Chunk, ChunkRef}, chunk_type::{ChunkType, ChunkTypeSet, Placement}, error::PngError, ihdr::Ihdr, time::TIME_CHUNK_TYPE};
+
+// How `Png` arranges chunks when parsing a file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    // Keeps the chunks exactly as they are in the file, including how the
+    // image data is split across IDAT chunks.
+    #[default]
+    Preserve,
+    // Moves chunks into the order the spec requires (see `Png::normalize`)
+    // and merges the IDAT chunks.
+    Normalize
+}
+
+// The length and type of a chunk, read before its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkHeader {
+    // Where the chunk starts in the file.
+    pub offset: usize,
+    pub length: u32,
+    pub chunk_type: ChunkType
+}
+
+// What a `ParseOptions::chunk_filter` wants done with a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterAction {
+    Keep,
+    // Leaves the chunk out without copying its data or checking its CRC.
+    Skip,
+    // Ends parsing before this chunk. The file need not end with IEND then.
+    Stop
+}
+
+pub type ChunkFilter<'a> = Box<dyn Fn(&ChunkHeader) -> FilterAction + 'a>;
+
+// How to parse a file: the chunk arrangement, and which chunks to load.
+#[derive(Default)]
+pub struct ParseOptions<'a> {
+    mode: ParseMode,
+    chunk_filter: Option<ChunkFilter<'a>>,
+    defer_crc_check: bool
+}
+
+// A chunk that breaks one of the spec's ordering rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderViolation {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub problem: &'static str
+}
+
+// The CRC stored with a chunk and the one computed from its type and data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrcCheck {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub stored: u32,
+    pub computed: u32
+}
+
+// A chunk `Png::try_from_lenient` left out, by the offset it starts at in
+// the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    CrcMismatch { offset: usize, chunk_type: ChunkType, stored: u32, computed: u32 },
+    // The length runs past the end of the file.
+    BadLength { offset: usize, length: u32 },
+    InvalidChunkType { offset: usize },
+    // Too few bytes left for a chunk header and CRC.
+    Truncated { offset: usize },
+    DataAfterIend { offset: usize, bytes: usize },
+    MissingIend
+}
+
+// A Png split by role, each group in file order. `image_data` keeps the
+// animation frames with it: IDAT, fcTL and fdAT. `metadata` holds the other
+// chunks the spec defines, and `unknown` every other type along with repeats
+// of IHDR and PLTE. IEND is left out.
+#[derive(Debug, Clone, Default)]
+pub struct PngParts {
+    pub header: Option<Chunk>,
+    pub palette: Option<Chunk>,
+    pub image_data: Vec<Chunk>,
+    pub metadata: Vec<Chunk>,
+    pub unknown: Vec<Chunk>
+}
+
+// A parsed file whose chunks borrow their data from the parsed buffer, for
+// reading large files without a second copy of them in memory.
+#[derive(Debug, Clone)]
+pub struct PngRef<'a> {
+    signature: [u8; 8],
+    chunks: Vec<ChunkRef<'a>>
+}
 
-#[derive(Debug)]
 pub struct Png {
-    pub header: [u8; 8], 
-    pub chunks: Vec<Chunk>
+    pub signature: [u8; 8], 
+    pub chunks: Vec<Chunk>,
+    // Digest of the IDAT chunks as parsed or constructed, so edits that
+    // re-encode the image data can be told apart from metadata-only ones.
+    pub(crate) original_image_digest: [u8; 32],
+    // When set, adding or removing a chunk also sets tIME to the current time.
+    pub(crate) refresh_time: bool
 }
 
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
-    pub fn header(&self) -> &[u8; 8] {
-       &self.header
+    pub fn signature(&self) -> &[u8; 8] {
+       &self.signature
+    }
+    pub fn header(&self) -> Result<Ihdr, PngError> {
+        self.chunk_as("IHDR")?.ok_or(PngError::ChunkNotFound(String::from("IHDR")))
     }
     pub fn chunks(&self) -> &[Chunk] {
-        &self.chunks.as_ref()
+        self.chunks.as_ref()
+    }
+    // For editing chunks in place. tIME is not refreshed, and a chunk whose
+    // data changes keeps its old CRC until it is rebuilt with `Chunk::new`.
+    pub fn chunks_mut(&mut self) -> &mut [Chunk] {
+        self.chunks.as_mut()
+    }
+    // Keeps the chunks `keep` returns true for, e.g. only the critical ones.
+    // Returns how many were removed.
+    pub fn retain_chunks(&mut self, keep: impl FnMut(&Chunk) -> bool) -> usize {
+        let before = self.chunks.len();
+        self.chunks.retain(keep);
+        let removed = before - self.chunks.len();
+        if removed > 0 {
+            self.refresh_modification_time();
+        }
+        removed
     }
     
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png { 
-            header: Png::STANDARD_HEADER, 
-            chunks 
+            signature: Png::STANDARD_HEADER, 
+            original_image_digest: image_digest(&chunks),
+            chunks,
+            refresh_time: false
+        }
+    }
+    // A new Png with `chunks` that still compares against this one's original image data.
+    #[cfg(feature = "std")]
+    pub(crate) fn with_chunks(&self, chunks: Vec<Chunk>) -> Png {
+        Png {
+            signature: self.signature,
+            chunks,
+            original_image_digest: self.original_image_digest,
+            refresh_time: self.refresh_time
+        }
+    }
+    fn parsed(signature: [u8; 8], chunks: Vec<Chunk>) -> Png {
+        Png { signature, original_image_digest: image_digest(&chunks), chunks, refresh_time: false }
+    }
+    // One check per chunk, in order. Parsing rejects bad CRCs unless told to
+    // defer the check, so other mismatches come from chunks built with
+    // `Chunk::from_bytes_unchecked` or edited in place.
+    pub fn verify_all_crcs(&self) -> Vec<CrcCheck> {
+        #[cfg(feature = "rayon")]
+        let chunks = self.chunks.par_iter();
+        #[cfg(not(feature = "rayon"))]
+        let chunks = self.chunks.iter();
+        chunks.enumerate().map(|(index, chunk)| CrcCheck::of(index, chunk)).collect()
+    }
+    pub fn partition(&self) -> PngParts {
+        let mut parts = PngParts::default();
+        for chunk in self.chunks.iter().cloned() {
+            match &chunk.chunk_type().bytes() {
+                b"IHDR" if parts.header.is_none() => parts.header = Some(chunk),
+                b"PLTE" if parts.palette.is_none() => parts.palette = Some(chunk),
+                b"IDAT" | b"fcTL" | b"fdAT" => parts.image_data.push(chunk),
+                b"IEND" => {},
+                b"IHDR" | b"PLTE" => parts.unknown.push(chunk),
+                _ if chunk.chunk_type().standard().is_some() => parts.metadata.push(chunk),
+                _ => parts.unknown.push(chunk)
+            }
+        }
+        parts
+    }
+    // Rebuilds a Png in spec order: IHDR, the color space chunks, PLTE, the
+    // rest of the metadata, the unknown chunks, the image data and a new
+    // IEND. Text and unknown chunks that followed the image data move ahead
+    // of it.
+    pub fn from_parts(parts: PngParts) -> Png {
+        let (before_palette, after_palette): (Vec<Chunk>, Vec<Chunk>) = parts
+            .metadata
+            .into_iter()
+            .partition(|chunk| chunk.chunk_type().standard().is_some_and(|standard| standard.placement == Placement::BeforePlte));
+        let iend = Chunk::new(ChunkType(u32::from_be_bytes(*b"IEND")), Vec::new());
+        let chunks = parts.header
+            .into_iter()
+            .chain(before_palette)
+            .chain(parts.palette)
+            .chain(after_palette)
+            .chain(parts.unknown)
+            .chain(parts.image_data)
+            .chain(core::iter::once(iend))
+            .collect();
+        Png::from_chunks(chunks)
+    }
+    pub fn set_refresh_time(&mut self, enabled: bool) {
+        self.refresh_time = enabled;
+    }
+    // Parses with `ParseMode::Preserve`.
+    #[cfg(feature = "std")]
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Png, PngError> {
+        Png::from_file_with(path, ParseMode::Preserve)
+    }
+    #[cfg(feature = "std")]
+    pub fn from_file_with(path: impl AsRef<Path>, mode: ParseMode) -> Result<Png, PngError> {
+        let bytes = fs::read(path)?;
+        Png::from_bytes_with(&bytes, mode)
+    }
+    pub fn from_bytes_with(bytes: &[u8], mode: ParseMode) -> Result<Png, PngError> {
+        Png::from_bytes_with_options(bytes, &ParseOptions::new().mode(mode))
+    }
+    pub fn from_bytes_with_options(bytes: &[u8], options: &ParseOptions) -> Result<Png, PngError> {
+        let mut png = parse(bytes, options)?;
+        if options.mode == ParseMode::Normalize {
+            png.normalize();
+            png.original_image_digest = image_digest(&png.chunks);
+        }
+        Ok(png)
+    }
+    // Parses what it can of a damaged file. A chunk that cannot be read or
+    // fails its CRC is left out with a warning, and parsing goes on from the
+    // next intact chunk after it, so the chunks around the damage survive.
+    // Only a bad signature is an error; the order of the chunks is not
+    // checked and IHDR may be missing.
+    pub fn try_from_lenient(bytes: &[u8]) -> Result<(Png, Vec<ParseWarning>), PngError> {
+        if !bytes.starts_with(&Png::STANDARD_HEADER) {
+            return Err(PngError::InvalidSignature);
+        }
+        let mut chunks = Vec::new();
+        let mut warnings = Vec::new();
+        let mut offset = Png::STANDARD_HEADER.len();
+        // Resyncing checks CRCs of at most a few times the file's size, so a
+        // file full of chunk-like bytes is not hashed over and over.
+        let mut crc_budget = bytes.len().saturating_mul(4);
+        while offset < bytes.len() {
+            match intact_chunk_at(bytes, offset) {
+                Ok(chunk) => {
+                    offset += chunk.length() as usize + 12;
+                    let is_iend = chunk.chunk_type().bytes() == *b"IEND";
+                    chunks.push(Chunk::from(chunk));
+                    if is_iend {
+                        if offset < bytes.len() {
+                            warnings.push(ParseWarning::DataAfterIend { offset, bytes: bytes.len() - offset });
+                        }
+                        break;
+                    }
+                },
+                Err(warning) => {
+                    warnings.push(warning);
+                    let resync = (offset + 1..bytes.len()).find(|start| {
+                        let Some(length) = plausible_chunk_at(bytes, *start) else {
+                            return false;
+                        };
+                        if length + 4 > crc_budget {
+                            return false;
+                        }
+                        crc_budget -= length + 4;
+                        intact_chunk_at(bytes, *start).is_ok()
+                    });
+                    match resync {
+                        Some(next) => offset = next,
+                        None => break
+                    }
+                }
+            }
+        }
+        if chunks.last().is_none_or(|chunk| chunk.chunk_type().bytes() != *b"IEND") {
+            warnings.push(ParseWarning::MissingIend);
         }
+        Ok((Png::parsed(Png::STANDARD_HEADER, chunks), warnings))
+    }
+    // Parses the file through a memory map instead of reading it first, so
+    // the chunks that `options` skips are never read from disk. The file must
+    // not be changed by another process while it is parsed.
+    #[cfg(feature = "mmap")]
+    pub fn from_mmap(path: impl AsRef<Path>) -> Result<Png, PngError> {
+        Png::from_mmap_with_options(path, &ParseOptions::default())
     }
+    #[cfg(feature = "mmap")]
+    pub fn from_mmap_with_options(path: impl AsRef<Path>, options: &ParseOptions) -> Result<Png, PngError> {
+        let file = File::open(path)?;
+        // SAFETY: the map is only read while parsing, and the chunks copy
+        // their data out of it.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Png::from_bytes_with_options(&map, options)
+    }
+    #[cfg(feature = "std")]
+    pub fn to_file(&self, path: impl AsRef<Path>) -> Result<(), PngError> {
+        self.write_to(BufWriter::new(File::create(path)?))?;
+        Ok(())
+    }
+    // Adds `chunk` immediately before IEND, or at the end when there is no
+    // IEND, so the file stays well formed without the caller picking a place.
     pub fn append_chunk(&mut self, chunk: Chunk) {
-        self.chunks.push(chunk);
+        self.chunks_changed(*chunk.chunk_type());
+        match self.chunks.iter().position(|x| x.chunk_type().bytes() == *b"IEND") {
+            Some(index) => self.chunks.insert(index, chunk),
+            None => self.chunks.push(chunk)
+        }
+    }
+    // Inserts `chunk` at `index`, shifting the chunks after it. `index` may be
+    // the number of chunks to add it at the very end.
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk) -> Result<(), PngError> {
+        if index > self.chunks.len() {
+            return Err(PngError::InvalidArgument("chunk index out of range"));
+        }
+        let chunk_type = *chunk.chunk_type();
+        self.chunks.insert(index, chunk);
+        self.chunks_changed(chunk_type);
+        Ok(())
     }
-    pub fn remove_chunk(&mut self, chunk_type: &str) -> std::io::Result<Chunk> {
-        let remove_chunk_err = std::io::Error::new(ErrorKind::Other, "could not remove chunk");
-        let chunk_str_as_raw = ChunkType::from_str(chunk_type).expect("could not parse chunk type.");
+    // Inserts `chunk` immediately before the first chunk of `chunk_type`.
+    pub fn insert_before(&mut self, chunk_type: &str, chunk: Chunk) -> Result<(), PngError> {
+        let before = ChunkType::from_str(chunk_type).map_err(|reason| PngError::InvalidChunkTypeName { name: chunk_type.to_string(), reason })?;
+        match self.chunks.iter().position(|x| x.chunk_type() == &before) {
+            Some(index) => self.insert_chunk(index, chunk),
+            None => Err(PngError::ChunkNotFound(chunk_type.to_string()))
+        }
+    }
+    // Puts `chunk` in place of the first chunk of the same type and drops any
+    // other chunks of that type, appending it when there is none.
+    pub fn replace_chunk(&mut self, chunk: Chunk) {
+        let chunk_type = *chunk.chunk_type();
+        match self.chunks.iter().position(|x| x.chunk_type() == &chunk_type) {
+            Some(index) => {
+                self.chunks[index] = chunk;
+                let mut seen = 0;
+                self.chunks.retain(|x| {
+                    if x.chunk_type() != &chunk_type {
+                        return true;
+                    }
+                    seen += 1;
+                    seen == 1
+                });
+            },
+            None => self.append_chunk(chunk)
+        }
+    }
+    // Replaces the chunk of the same type, or inserts `chunk` before the
+    // first chunk whose type is in `before`, appending it when there is none.
+    #[cfg(feature = "std")]
+    pub(crate) fn place_chunk(&mut self, chunk: Chunk, before: &ChunkTypeSet) {
+        if self.chunks.iter().any(|x| x.chunk_type() == chunk.chunk_type()) {
+            return self.replace_chunk(chunk);
+        }
+        match self.chunks.iter().position(|x| before.contains(*x.chunk_type())) {
+            Some(index) => self.insert_chunk(index, chunk).unwrap(),
+            None => self.append_chunk(chunk)
+        }
+    }
+    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, PngError> {
+        let chunk_str_as_raw = ChunkType::from_str(chunk_type).map_err(|reason| PngError::InvalidChunkTypeName { name: chunk_type.to_string(), reason })?;
         if let Some(index) = self.chunks().iter().position(|x| x.chunk_type() == &chunk_str_as_raw) {
-            Ok(self.chunks.swap_remove(index))
+            let chunk = self.chunks.remove(index);
+            self.chunks_changed(*chunk.chunk_type());
+            Ok(chunk)
         } else {
-            Err(remove_chunk_err)
+            Err(PngError::ChunkNotFound(chunk_type.to_string()))
         }
     }
+    // Removes every chunk of `chunk_type`, returning them in file order.
+    pub fn remove_chunks(&mut self, chunk_type: &str) -> Result<Vec<Chunk>, PngError> {
+        let raw = ChunkType::from_str(chunk_type).map_err(|reason| PngError::InvalidChunkTypeName { name: chunk_type.to_string(), reason })?;
+        let (removed, kept): (Vec<Chunk>, Vec<Chunk>) = core::mem::take(&mut self.chunks)
+            .into_iter()
+            .partition(|x| x.chunk_type() == &raw);
+        self.chunks = kept;
+        if removed.is_empty() {
+            return Err(PngError::ChunkNotFound(chunk_type.to_string()));
+        }
+        self.chunks_changed(raw);
+        Ok(removed)
+    }
+    // Removes the `n`th chunk of `chunk_type`, counting from 0.
+    pub fn remove_nth_chunk(&mut self, chunk_type: &str, n: usize) -> Result<Chunk, PngError> {
+        let raw = ChunkType::from_str(chunk_type).map_err(|reason| PngError::InvalidChunkTypeName { name: chunk_type.to_string(), reason })?;
+        let positions: Vec<usize> = self.chunks.iter().enumerate().filter(|(_, x)| x.chunk_type() == &raw).map(|(index, _)| index).collect();
+        if positions.is_empty() {
+            return Err(PngError::ChunkNotFound(chunk_type.to_string()));
+        }
+        let index = *positions.get(n).ok_or(PngError::InvalidArgument("chunk index out of range"))?;
+        let chunk = self.chunks.remove(index);
+        self.chunks_changed(raw);
+        Ok(chunk)
+    }
+    // Removes every chunk whose type is in `types`, returning how many were dropped.
+    pub fn strip_chunks(&mut self, types: &ChunkTypeSet) -> usize {
+        let before = self.chunks.len();
+        self.chunks.retain(|chunk| !types.contains(*chunk.chunk_type()));
+        let removed = before - self.chunks.len();
+        if removed > 0 && !types.contains(TIME_CHUNK_TYPE) {
+            self.refresh_modification_time();
+        }
+        removed
+    }
+    // Refreshes tIME after a chunk of `chunk_type` was added or removed, if enabled.
+    pub(crate) fn chunks_changed(&mut self, chunk_type: ChunkType) {
+        if chunk_type != TIME_CHUNK_TYPE {
+            self.refresh_modification_time();
+        }
+    }
+    #[cfg(feature = "std")]
+    fn refresh_modification_time(&mut self) {
+        if !self.refresh_time {
+            return;
+        }
+        let time: Chunk = crate::time::ModificationTime::now().into();
+        match self.chunks.iter().position(|x| x.chunk_type() == time.chunk_type()) {
+            Some(index) => self.chunks[index] = time,
+            None => match self.chunks.iter().position(|x| x.chunk_type().bytes() == *b"IEND") {
+                Some(index) => self.chunks.insert(index, time),
+                None => self.chunks.push(time)
+            }
+        }
+    }
+    // Without std there is no clock to read, so tIME is left as it is.
+    #[cfg(not(feature = "std"))]
+    fn refresh_modification_time(&mut self) {}
+    // Stably moves chunks that are out of place to where the spec puts them:
+    // color space chunks before PLTE, chunks that describe the image before
+    // IDAT, IDAT chunks together and merged, IEND last. Chunks without an
+    // ordering rule stay on the same side of PLTE and IDAT as they were.
+    pub fn normalize(&mut self) {
+        let mut region = 0;
+        let mut ranked: Vec<(u8, Chunk)> = Vec::with_capacity(self.chunks.len());
+        for chunk in self.chunks.drain(..) {
+            let rank = canonical_rank(*chunk.chunk_type(), region);
+            match &chunk.chunk_type().bytes() {
+                b"PLTE" => region = region.max(1),
+                b"IDAT" => region = 2,
+                _ => {}
+            }
+            ranked.push((rank, chunk));
+        }
+        ranked.sort_by_key(|(rank, _)| *rank);
+
+        let image_data = concat_image_data(ranked.iter().map(|(_, chunk)| chunk));
+        let mut merged = false;
+        for (_, chunk) in ranked {
+            if chunk.chunk_type().bytes() != *b"IDAT" {
+                self.chunks.push(chunk);
+            } else if !merged {
+                merged = true;
+                self.chunks.extend(image_data.chunks(MAX_CHUNK_LEN).map(|data| Chunk::new(*chunk.chunk_type(), data.to_vec())));
+            }
+        }
+    }
+    // Checks the standard chunks against the placement rules of the spec:
+    // IHDR first, PLTE before IDAT, tRNS and bKGD after PLTE, IDAT chunks
+    // consecutive, IEND last, and single chunks not repeated.
+    pub fn order_violations(&self) -> Vec<OrderViolation> {
+        let chunks = &self.chunks;
+        let has_palette = chunks.iter().any(|chunk| chunk.chunk_type().bytes() == *b"PLTE");
+        let (mut seen_palette, mut seen_idat, mut idat_ended) = (false, false, false);
+        let mut seen = ChunkTypeSet::new();
+        let mut violations = Vec::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            let is_idat = chunk.chunk_type().bytes() == *b"IDAT";
+            idat_ended |= seen_idat && !is_idat;
+            let Some(standard) = chunk.chunk_type().standard() else {
+                continue;
+            };
+            let problem = match standard.placement {
+                Placement::First if index != 0 => Some("must be the first chunk"),
+                Placement::BeforePlte if seen_palette || seen_idat => Some("must come before PLTE and IDAT"),
+                Placement::AfterPlte if has_palette && !seen_palette => Some("must come after PLTE"),
+                Placement::AfterPlte | Placement::BeforeIdat if seen_idat => Some("must come before IDAT"),
+                Placement::Idat if idat_ended => Some("chunks must be consecutive"),
+                Placement::AfterIdat if !seen_idat => Some("must come after IDAT"),
+                Placement::Last if index != chunks.len() - 1 => Some("must be the last chunk"),
+                _ => None
+            };
+            let repeated = !standard.multiple && seen.contains(standard.chunk_type);
+            for problem in problem.into_iter().chain(repeated.then_some("must not appear more than once")) {
+                violations.push(OrderViolation { index, chunk_type: *chunk.chunk_type(), problem });
+            }
+            // Capacity allows every standard type.
+            let _ = seen.insert(standard.chunk_type);
+            seen_palette |= chunk.chunk_type().bytes() == *b"PLTE";
+            seen_idat |= is_idat;
+        }
+        violations
+    }
+    // Fails with the first ordering rule the chunks break, see `order_violations`.
+    // `normalize` fixes everything but repeated chunks.
+    pub fn validate_order(&self) -> Result<(), PngError> {
+        match self.order_violations().into_iter().next() {
+            Some(violation) => Err(PngError::InvalidChunkOrder {
+                chunk_type: violation.chunk_type.to_string(),
+                index: violation.index,
+                reason: violation.problem
+            }),
+            None => Ok(())
+        }
+    }
+    // A name that is not a chunk type matches nothing.
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        let chunk_str_as_raw = &ChunkType::from_str(chunk_type).expect("cannot parse chunk type");
+        let chunk_str_as_raw = &ChunkType::from_str(chunk_type).ok()?;
         match self.chunks.iter().find(|x| x.chunk_type() == chunk_str_as_raw) {
            Some(val) => Some(val),
            None => None
         }
     }
+    // Every chunk of `chunk_type`, in file order.
+    pub fn chunks_by_type(&self, chunk_type: &str) -> Vec<&Chunk> {
+        let Ok(chunk_str_as_raw) = ChunkType::from_str(chunk_type) else {
+            return Vec::new();
+        };
+        self.chunks.iter().filter(|x| x.chunk_type() == &chunk_str_as_raw).collect()
+    }
+    // `chunks_by_type` for replacing chunks in place. tIME is not refreshed.
+    pub fn chunks_by_type_mut(&mut self, chunk_type: &str) -> Vec<&mut Chunk> {
+        let Ok(chunk_str_as_raw) = ChunkType::from_str(chunk_type) else {
+            return Vec::new();
+        };
+        self.chunks.iter_mut().filter(|x| x.chunk_type() == &chunk_str_as_raw).collect()
+    }
+    // Parses the first chunk of `chunk_type` into a typed value, if present.
+    pub fn chunk_as<'a, T>(&'a self, chunk_type: &str) -> Result<Option<T>, PngError>
+    where
+        T: TryFrom<&'a Chunk, Error = PngError>
+    {
+        self.chunk_by_type(chunk_type).map(T::try_from).transpose()
+    }
+    // True while the IDAT chunks are byte-for-byte the ones this Png was
+    // parsed or constructed with, including how the data is split.
+    pub fn is_pixel_data_untouched(&self) -> bool {
+        image_digest(&self.chunks) == self.original_image_digest
+    }
+    pub fn image_data(&self) -> Vec<u8> {
+        concat_image_data(self.chunks.iter())
+    }
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::<u8>::new();
-        bytes.extend(self.header().to_vec());
+        bytes.extend(self.signature().to_vec());
         for i in self.chunks().iter() {
             bytes.extend(i.as_bytes());
         };
@@ -53,46 +554,310 @@ impl Png {
     }
 }
 
+impl<'a> PngRef<'a> {
+    pub fn signature(&self) -> &[u8; 8] {
+        &self.signature
+    }
+    pub fn chunks(&self) -> &[ChunkRef<'a>] {
+        &self.chunks
+    }
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&ChunkRef<'a>> {
+        self.chunks_by_type(chunk_type).into_iter().next()
+    }
+    pub fn chunks_by_type(&self, chunk_type: &str) -> Vec<&ChunkRef<'a>> {
+        let Ok(chunk_str_as_raw) = ChunkType::from_str(chunk_type) else {
+            return Vec::new();
+        };
+        self.chunks.iter().filter(|x| x.chunk_type() == &chunk_str_as_raw).collect()
+    }
+    // Copies the chunks' data into an owned Png.
+    pub fn to_png(&self) -> Png {
+        Png::parsed(self.signature, self.chunks.iter().copied().map(Chunk::from).collect())
+    }
+    pub fn from_bytes_with_options(bytes: &'a [u8], options: &ParseOptions) -> Result<PngRef<'a>, PngError> {
+        if options.mode == ParseMode::Normalize {
+            return Err(PngError::InvalidArgument("a borrowed Png keeps the chunks in file order"));
+        }
+        parse_ref(bytes, options)
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = PngError;
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        parse_ref(value, &ParseOptions::default())
+    }
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseWarning::CrcMismatch { offset, chunk_type, stored, computed } => {
+                write!(f, "skipped {} at offset {}: stored CRC {:08x}, computed {:08x}", chunk_type, offset, stored, computed)
+            },
+            ParseWarning::BadLength { offset, length } => write!(f, "skipped the chunk at offset {}: length {} runs past the end of the file", offset, length),
+            ParseWarning::InvalidChunkType { offset } => write!(f, "skipped the chunk at offset {}: chunk type is not four ASCII letters", offset),
+            ParseWarning::Truncated { offset } => write!(f, "skipped the chunk at offset {}: cut off by the end of the file", offset),
+            ParseWarning::DataAfterIend { offset, bytes } => write!(f, "ignored {} bytes after IEND at offset {}", bytes, offset),
+            ParseWarning::MissingIend => write!(f, "no IEND chunk")
+        }
+    }
+}
+
+impl CrcCheck {
+    fn of(index: usize, chunk: &Chunk) -> CrcCheck {
+        CrcCheck { index, chunk_type: chunk.chunk_type, stored: chunk.crc, computed: chunk.computed_crc() }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.stored == self.computed
+    }
+}
+
+impl IntoIterator for Png {
+    type Item = Chunk;
+    type IntoIter = alloc::vec::IntoIter<Chunk>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Png {
+    type Item = &'a Chunk;
+    type IntoIter = core::slice::Iter<'a, Chunk>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Png {
+    type Item = &'a mut Chunk;
+    type IntoIter = core::slice::IterMut<'a, Chunk>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.iter_mut()
+    }
+}
+
+// Collects chunks into a Png with the standard signature, as `from_chunks`.
+impl FromIterator<Chunk> for Png {
+    fn from_iter<I: IntoIterator<Item = Chunk>>(iter: I) -> Self {
+        Png::from_chunks(iter.into_iter().collect())
+    }
+}
+
 impl TryFrom<&[u8]> for Png {
-    type Error = &'static str;
+    type Error = PngError;
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let mut counter = 0;
-        let mut chunks: Vec<Chunk> = Vec::new();
-        let std_header: [u8; 8] = value[0..8].try_into().expect("can not parse header");
-        if std_header != Png::STANDARD_HEADER {
-            return Err("Header is not valid STANDARD_HEADER for png file format.");
-        }
-        let bytes_without_header = &value[8..];
-        for (i, val) in bytes_without_header.iter().enumerate() {
-            if counter == bytes_without_header.len() {
-                break;
-            }
-            let header_bytes: [u8; 4] = bytes_without_header[counter..counter+4].try_into().expect("cannot get message size");
-            let message_size = u32::from_be_bytes(header_bytes);
-            chunks.push(Chunk::try_from(&bytes_without_header[counter..(message_size + 12) as usize + counter]).unwrap());
+        parse(value, &ParseOptions::default())
+    }
+}
 
-            counter += (message_size + 12) as usize;
+impl<'a> ParseOptions<'a> {
+    pub fn new() -> ParseOptions<'a> {
+        ParseOptions::default()
+    }
+
+    pub fn mode(mut self, mode: ParseMode) -> ParseOptions<'a> {
+        self.mode = mode;
+        self
+    }
+
+    // Called with each chunk's header before its data is read, e.g. to skip
+    // IDAT when only the metadata is wanted, or to stop at the first IDAT.
+    // The structure checks still see every chunk, skipped or not.
+    pub fn chunk_filter(mut self, filter: impl Fn(&ChunkHeader) -> FilterAction + 'a) -> ParseOptions<'a> {
+        self.chunk_filter = Some(Box::new(filter));
+        self
+    }
+
+    // Leaves every CRC unchecked, for callers that only list chunks or read a
+    // few small ones and do not want to checksum megabytes of IDAT. Check the
+    // chunks used with `Chunk::verify_crc`, or all of them with
+    // `Png::verify_all_crcs`.
+    pub fn defer_crc_check(mut self, defer: bool) -> ParseOptions<'a> {
+        self.defer_crc_check = defer;
+        self
+    }
+
+    fn filter(&self, header: &ChunkHeader) -> FilterAction {
+        self.chunk_filter.as_ref().map_or(FilterAction::Keep, |filter| filter(header))
+    }
+}
+
+fn parse(value: &[u8], options: &ParseOptions) -> Result<Png, PngError> {
+    Ok(parse_ref(value, options)?.to_png())
+}
+
+// CRCs are checked once the structure has been read, so they can be checked
+// in parallel, but an earlier chunk's bad CRC is still the error reported.
+fn parse_ref<'a>(value: &'a [u8], options: &ParseOptions) -> Result<PngRef<'a>, PngError> {
+    let mut chunks = Vec::new();
+    let structure = parse_structure(value, options, &mut chunks);
+    if !options.defer_crc_check {
+        verify_crcs(&chunks)?;
+    }
+    Ok(PngRef { signature: structure?, chunks })
+}
+
+// Reads the chunks into `chunks` without checking their CRCs and returns the
+// signature.
+fn parse_structure<'a>(value: &'a [u8], options: &ParseOptions, chunks: &mut Vec<ChunkRef<'a>>) -> Result<[u8; 8], PngError> {
+    let mut counter = 0;
+    // Every chunk seen, including skipped ones, so errors point at the file.
+    let mut chunk_index = 0;
+    let mut last_type = None;
+    let std_header: [u8; 8] = match value.get(0..8) {
+        Some(header) => header.try_into().unwrap(),
+        None => return Err(PngError::InvalidSignature)
+    };
+    if std_header != Png::STANDARD_HEADER {
+        return Err(PngError::InvalidSignature);
+    }
+    let bytes_without_header = &value[8..];
+    while counter < bytes_without_header.len() {
+        let truncated = PngError::TruncatedChunk { offset: counter + 8 };
+        let header_bytes: [u8; 4] = match bytes_without_header.get(counter..counter+4) {
+            Some(bytes) => bytes.try_into().unwrap(),
+            None => return Err(truncated)
+        };
+        let chunk_end = (u32::from_be_bytes(header_bytes) as usize)
+            .checked_add(12 + counter)
+            .filter(|end| *end <= bytes_without_header.len())
+            .ok_or(truncated)?;
+        let type_bytes: [u8; 4] = bytes_without_header[counter + 4..counter + 8].try_into().unwrap();
+        let header = ChunkHeader { offset: counter + 8, length: u32::from_be_bytes(header_bytes), chunk_type: ChunkType(u32::from_be_bytes(type_bytes)) };
+        let chunk = match options.filter(&header) {
+            FilterAction::Keep => Some(ChunkRef::from_bytes_unchecked(&bytes_without_header[counter..chunk_end])?),
+            FilterAction::Skip => None,
+            FilterAction::Stop => return Ok(std_header)
+        };
+        // Before the structure checks, so a bad CRC on this chunk wins.
+        chunks.extend(chunk);
+        let structure_error = |reason| PngError::InvalidStructure { offset: counter + 8, chunk_index, reason };
+        if chunk_index == 0 && type_bytes != *b"IHDR" {
+            return Err(structure_error("first chunk is not IHDR"));
+        }
+        if type_bytes == *b"IEND" && chunk_end != bytes_without_header.len() {
+            return Err(PngError::InvalidStructure {
+                offset: chunk_end + 8,
+                chunk_index: chunk_index + 1,
+                reason: "data after IEND"
+            });
         }
 
-        Ok(Png { header: std_header, chunks })
+        chunk_index += 1;
+        last_type = Some(type_bytes);
+        counter = chunk_end;
+    }
+
+    match last_type {
+        Some(last) if last == *b"IEND" => Ok(std_header),
+        _ => Err(PngError::InvalidStructure { offset: value.len(), chunk_index, reason: "missing IEND chunk" })
+    }
+}
+
+// The chunk starting at `offset`, if its length fits the file, its type is
+// four letters and its CRC matches.
+fn intact_chunk_at(bytes: &[u8], offset: usize) -> Result<ChunkRef<'_>, ParseWarning> {
+    let Some(header) = bytes.get(offset..offset + 8).filter(|_| bytes.len() - offset >= 12) else {
+        return Err(ParseWarning::Truncated { offset });
+    };
+    let length = u32::from_be_bytes(header[0..4].try_into().unwrap());
+    let end = (length as usize)
+        .checked_add(offset + 12)
+        .filter(|end| *end <= bytes.len())
+        .ok_or(ParseWarning::BadLength { offset, length })?;
+    let chunk = ChunkRef::from_bytes_unchecked(&bytes[offset..end]).map_err(|_| ParseWarning::InvalidChunkType { offset })?;
+    match chunk.crc() == chunk.computed_crc() {
+        true => Ok(chunk),
+        false => Err(ParseWarning::CrcMismatch { offset, chunk_type: *chunk.chunk_type(), stored: chunk.crc(), computed: chunk.computed_crc() })
+    }
+}
+
+// The data length of a chunk that could start at `offset`, judged without
+// hashing it: a valid chunk type, a length that fits in the file, and the end
+// of the file or another plausible chunk header right after it.
+fn plausible_chunk_at(bytes: &[u8], offset: usize) -> Option<usize> {
+    let header_at = |offset: usize| -> Option<usize> {
+        let header = bytes.get(offset..offset + 8)?;
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap());
+        let chunk_type = ChunkType::new_unchecked(header[4..8].try_into().unwrap());
+        (length <= i32::MAX as u32 && chunk_type.is_valid()).then_some(length as usize)
+    };
+    let length = header_at(offset)?;
+    let end = offset.checked_add(length + 12).filter(|end| *end <= bytes.len())?;
+    (bytes.len() - end < 12 || header_at(end).is_some()).then_some(length)
+}
+
+fn verify_crcs(chunks: &[ChunkRef]) -> Result<(), PngError> {
+    #[cfg(feature = "rayon")]
+    let first_mismatch = chunks.par_iter().map(ChunkRef::verify_crc).find_first(Result::is_err);
+    #[cfg(not(feature = "rayon"))]
+    let first_mismatch = chunks.iter().map(ChunkRef::verify_crc).find(Result::is_err);
+    first_mismatch.unwrap_or(Ok(()))
+}
+
+impl core::fmt::Debug for Png {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Png")
+            .field("signature", &self.signature)
+            .field("chunks", &self.chunks)
+            .finish()
     }
 }
 
 impl Display for Png {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
+// Chunk lengths are limited to 2^31 - 1 bytes.
+const MAX_CHUNK_LEN: usize = i32::MAX as usize;
+
+// Sort key for `Png::normalize`. `region` is 0 before PLTE, 1 between PLTE
+// and IDAT and 2 after IDAT.
+fn canonical_rank(chunk_type: ChunkType, region: u8) -> u8 {
+    let placement = chunk_type.standard().map_or(Placement::Anywhere, |standard| standard.placement);
+    match (&chunk_type.bytes(), placement) {
+        (b"PLTE", _) => 2,
+        // The first frame control of an animation stays in front of IDAT.
+        (b"fcTL", _) if region < 2 => 3,
+        (_, Placement::First) => 0,
+        (_, Placement::BeforePlte) => 1,
+        (_, Placement::AfterPlte | Placement::BeforeIdat) => 3,
+        (_, Placement::Idat) => 4,
+        (_, Placement::AfterIdat) => 5,
+        (_, Placement::Last) => 6,
+        (_, Placement::Anywhere) => [1, 3, 5][region as usize]
+    }
+}
+
+fn concat_image_data<'a>(chunks: impl Iterator<Item = &'a Chunk>) -> Vec<u8> {
+    chunks
+        .filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect()
+}
+
+fn image_digest(chunks: &[Chunk]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    for chunk in chunks.iter().filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT") {
+        hasher.update(&chunk.length().to_be_bytes());
+        hasher.update(chunk.data());
+    }
+    *hasher.finalize().as_bytes()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
     use crate::chunk::Chunk;
-    use std::num::ParseIntError;
-    use std::str::FromStr;
-    use std::convert::TryFrom;
+    use core::str::FromStr;
+    use core::convert::TryFrom;
 
+    #[allow(clippy::vec_init_then_push)]
     fn testing_chunks() -> Vec<Chunk> {
         let mut chunks = Vec::new();
 
@@ -103,13 +868,23 @@ mod tests {
         chunks
     }
 
+    fn framed_chunk_bytes() -> Vec<u8> {
+        let ihdr = Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]);
+        let iend = Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new());
+        std::iter::once(ihdr)
+            .chain(testing_chunks())
+            .chain(std::iter::once(iend))
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect()
+    }
+
     fn testing_png() -> Png {
         let chunks = testing_chunks();
         Png::from_chunks(chunks)
     }
 
-    fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk, ParseIntError> {
-        let chunk_type = ChunkType::from_str(chunk_type)?;
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Result<Chunk, PngError> {
+        let chunk_type = ChunkType::from_str(chunk_type).map_err(|reason| PngError::InvalidChunkTypeName { name: chunk_type.to_string(), reason })?;
         let data: Vec<u8> = data.bytes().collect();
 
         Ok(Chunk::new(chunk_type, data))
@@ -125,10 +900,7 @@ mod tests {
 
     #[test]
     fn test_valid_from_bytes() {
-        let chunk_bytes: Vec<u8> = testing_chunks()
-            .into_iter()
-            .flat_map(|chunk| chunk.as_bytes())
-            .collect();
+        let chunk_bytes = framed_chunk_bytes();
 
         let bytes: Vec<u8> = Png::STANDARD_HEADER
             .iter()
@@ -198,6 +970,22 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "first").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "second").unwrap());
+        let messages: Vec<String> = png.chunks_by_type("TeSt").iter().map(|chunk| chunk.data_as_string().unwrap()).collect();
+        assert_eq!(messages, ["first", "second"]);
+        assert!(png.chunks_by_type("NoNe").is_empty());
+
+        for chunk in png.chunks_by_type_mut("TeSt") {
+            *chunk = chunk_from_strings("TeSt", &chunk.data_as_string().unwrap().to_uppercase()).unwrap();
+        }
+        assert_eq!(&png.chunks()[4].data_as_string().unwrap(), "SECOND");
+        assert_eq!(png.chunks().len(), 5);
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -207,6 +995,19 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_replace_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "first").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "second").unwrap());
+        png.replace_chunk(chunk_from_strings("TeSt", "third").unwrap());
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(&png.chunks()[3].data_as_string().unwrap(), "third");
+
+        png.replace_chunk(chunk_from_strings("NeWw", "new").unwrap());
+        assert_eq!(png.chunks().len(), 5);
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -216,6 +1017,288 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_malformed_chunk_type_names() {
+        let mut png = testing_png();
+        for name in ["ab", "toolong", "", "aéb", "Te5t"] {
+            assert!(png.chunk_by_type(name).is_none());
+            assert!(png.chunks_by_type(name).is_empty());
+            assert!(matches!(png.remove_chunk(name), Err(PngError::InvalidChunkTypeName { .. })));
+            assert!(matches!(png.remove_chunks(name), Err(PngError::InvalidChunkTypeName { .. })));
+            assert!(matches!(png.remove_nth_chunk(name, 0), Err(PngError::InvalidChunkTypeName { .. })));
+        }
+        assert_eq!(png.chunks().len(), testing_png().chunks().len());
+    }
+
+    #[test]
+    fn test_first_chunk_must_be_ihdr() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+        let bytes: Vec<u8> = Png::STANDARD_HEADER.iter().chain(chunk_bytes.iter()).copied().collect();
+
+        let png = Png::try_from(bytes.as_ref());
+        assert!(matches!(png, Err(PngError::InvalidStructure { offset: 8, chunk_index: 0, .. })));
+    }
+
+    #[test]
+    fn test_missing_iend() {
+        let bytes = &PNG_FILE[..PNG_FILE.len() - 12];
+        let png = Png::try_from(bytes);
+        assert!(matches!(png, Err(PngError::InvalidStructure { offset: 4791, chunk_index: 6, .. })));
+    }
+
+    #[test]
+    fn test_data_after_iend() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend(chunk_from_strings("TeSt", "trailing").unwrap().as_bytes());
+        let png = Png::try_from(bytes.as_ref());
+        assert!(matches!(png, Err(PngError::InvalidStructure { offset: 4803, chunk_index: 7, .. })));
+    }
+
+    #[test]
+    fn test_earlier_bad_crc_wins_over_structure() {
+        let mut bytes = out_of_order_bytes();
+        // The last byte of tEXt's CRC, before the missing IEND is noticed.
+        bytes[8 + 25 + 18 - 1] ^= 1;
+        bytes.truncate(bytes.len() - 12);
+        let png = Png::try_from(bytes.as_ref());
+        assert!(matches!(png, Err(PngError::CrcMismatch { chunk_type, .. }) if chunk_type == "tEXt"));
+    }
+
+    #[test]
+    fn test_deferred_crc_check() {
+        let mut bytes = out_of_order_bytes();
+        bytes[8 + 25 + 18 - 1] ^= 1;
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+        let png = Png::from_bytes_with_options(&bytes, &ParseOptions::new().defer_crc_check(true)).unwrap();
+        assert!(png.chunk_by_type("IHDR").unwrap().verify_crc().is_ok());
+        assert!(matches!(png.chunk_by_type("tEXt").unwrap().verify_crc(), Err(PngError::CrcMismatch { .. })));
+        // Structure errors are still reported.
+        bytes.truncate(bytes.len() - 12);
+        assert!(matches!(
+            Png::from_bytes_with_options(&bytes, &ParseOptions::new().defer_crc_check(true)),
+            Err(PngError::InvalidStructure { .. })
+        ));
+    }
+
+    #[test]
+    fn test_lenient_parse_skips_corrupt_chunks() {
+        let mut bytes = out_of_order_bytes();
+        // tEXt's CRC, then a length running past the end of the file in pHYs.
+        bytes[8 + 25 + 18 - 1] ^= 1;
+        let phys = 8 + 25 + 18 + 15 + 16 + 14 + 14;
+        bytes[phys] = 0xff;
+        let (png, warnings) = Png::try_from_lenient(&bytes).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "PLTE", "gAMA", "IDAT", "IDAT", "zTXt", "IEND"]);
+        assert!(matches!(warnings[0], ParseWarning::CrcMismatch { offset: 33, .. }));
+        assert_eq!(warnings[1], ParseWarning::BadLength { offset: phys, length: 0xff00000a });
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[1].to_string(), format!("skipped the chunk at offset {}: length 4278190090 runs past the end of the file", phys));
+
+        let mut bytes = out_of_order_bytes();
+        bytes.truncate(bytes.len() - 14);
+        let (png, warnings) = Png::try_from_lenient(&bytes).unwrap();
+        assert_eq!(chunk_types(&png).len(), 7);
+        assert_eq!(warnings, [ParseWarning::BadLength { offset: bytes.len() - 15, length: 5 }, ParseWarning::MissingIend]);
+        assert!(Png::try_from_lenient(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn test_lenient_resync_is_linear() {
+        // Every 8 bytes claim a 1 MB chunk; each used to be hashed in full.
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        for _ in 0..(4 << 20) / 8 {
+            bytes.extend([0, 0x10, 0, 0]);
+            bytes.extend(b"aaAa");
+        }
+        let (png, warnings) = Png::try_from_lenient(&bytes).unwrap();
+        assert!(png.chunks().is_empty());
+        assert!(matches!(warnings[0], ParseWarning::CrcMismatch { offset: 8, .. }));
+        assert_eq!(warnings[1..], [ParseWarning::MissingIend]);
+    }
+
+    #[test]
+    fn test_chunk_iterators() {
+        let mut png = Png::try_from(out_of_order_bytes().as_ref()).unwrap();
+        assert_eq!((&png).into_iter().filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT").count(), 2);
+        for chunk in &mut png {
+            if chunk.chunk_type().bytes() == *b"tEXt" {
+                *chunk = chunk_from_strings("tEXt", "edited").unwrap();
+            }
+        }
+        assert_eq!(png.chunk_by_type("tEXt").unwrap().data(), b"edited");
+        png.chunks_mut()[3].crc = 0;
+        assert!(!png.verify_all_crcs()[3].is_ok());
+
+        let critical: Png = png.into_iter().filter(|chunk| chunk.chunk_type().is_critical()).collect();
+        assert_eq!(chunk_types(&critical), ["IHDR", "PLTE", "IDAT", "IDAT", "IEND"]);
+        let mut png = Png::try_from(out_of_order_bytes().as_ref()).unwrap();
+        assert_eq!(png.retain_chunks(|chunk| chunk.chunk_type().is_critical()), 4);
+        assert_eq!(png.as_bytes(), critical.as_bytes());
+    }
+
+    #[test]
+    fn test_verify_all_crcs() {
+        let mut png = Png::try_from(out_of_order_bytes().as_ref()).unwrap();
+        assert!(png.verify_all_crcs().iter().all(CrcCheck::is_ok));
+        png.chunks[3].message_bytes[0] ^= 1;
+        let checks = png.verify_all_crcs();
+        assert_eq!(checks.len(), 9);
+        let failed: Vec<&CrcCheck> = checks.iter().filter(|check| !check.is_ok()).collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!((failed[0].index, failed[0].chunk_type.to_string()), (3, String::from("gAMA")));
+        assert_eq!(failed[0].stored, png.chunks[3].crc());
+    }
+
+    #[test]
+    fn test_png_ref_borrows_chunks() {
+        let bytes = out_of_order_bytes();
+        let png_ref = PngRef::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png_ref.chunks().len(), 9);
+        let text = png_ref.chunk_by_type("tEXt").unwrap();
+        assert!(core::ptr::eq(text.data(), &bytes[8 + 25 + 8..8 + 25 + 14]));
+        assert_eq!(png_ref.chunks_by_type("IDAT").len(), 2);
+        assert_eq!(png_ref.to_png().as_bytes(), bytes);
+
+        let mut bad_crc = bytes.clone();
+        bad_crc[8 + 25 + 18 - 1] ^= 1;
+        assert!(matches!(PngRef::try_from(bad_crc.as_ref()), Err(PngError::CrcMismatch { .. })));
+        let normalize = ParseOptions::new().mode(ParseMode::Normalize);
+        assert!(PngRef::from_bytes_with_options(&bytes, &normalize).is_err());
+        let skip_text = ParseOptions::new().chunk_filter(|header| match &header.chunk_type.bytes() {
+            b"tEXt" => FilterAction::Skip,
+            _ => FilterAction::Keep
+        });
+        assert!(PngRef::from_bytes_with_options(&bytes, &skip_text).unwrap().chunk_by_type("tEXt").is_none());
+    }
+
+    #[test]
+    fn test_partition() {
+        let png = Png::try_from(out_of_order_bytes().as_ref()).unwrap();
+        let parts = png.partition();
+        let types = |chunks: &[Chunk]| chunks.iter().map(|chunk| chunk.chunk_type().to_string()).collect::<Vec<_>>();
+        assert_eq!(parts.header.as_ref().unwrap().data(), b"0123456789abc");
+        assert_eq!(parts.palette.as_ref().unwrap().data(), b"rgb");
+        assert_eq!(types(&parts.image_data), ["IDAT", "IDAT"]);
+        assert_eq!(types(&parts.metadata), ["tEXt", "gAMA", "pHYs", "zTXt"]);
+        assert!(parts.unknown.is_empty());
+
+        let mut parts = png.partition();
+        parts.metadata.retain(|chunk| chunk.chunk_type().to_string() != "zTXt");
+        parts.unknown.push(chunk_from_strings("ruSt", "hidden").unwrap());
+        let rebuilt = Png::from_parts(parts);
+        assert_eq!(chunk_types(&rebuilt), ["IHDR", "gAMA", "PLTE", "tEXt", "pHYs", "ruSt", "IDAT", "IDAT", "IEND"]);
+        assert!(rebuilt.order_violations().is_empty());
+        assert_eq!(rebuilt.image_data(), png.image_data());
+    }
+
+    #[test]
+    fn test_partition_repeated_critical_chunks() {
+        let png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "first").unwrap(),
+            chunk_from_strings("IHDR", "second").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        let parts = png.partition();
+        assert_eq!(parts.header.unwrap().data(), b"first");
+        assert_eq!(parts.unknown.len(), 1);
+        assert!(Png::from_parts(PngParts::default()).chunk_by_type("IEND").is_some());
+    }
+
+    #[test]
+    fn test_append_chunk_keeps_iend_last() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        let last = png.chunks().last().unwrap();
+        assert_eq!(&last.chunk_type().to_string(), "IEND");
+        assert!(Png::try_from(png.as_bytes().as_ref()).is_ok());
+    }
+
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "second").unwrap()).unwrap();
+        png.insert_chunk(4, chunk_from_strings("LaSt", "end").unwrap()).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["FrSt", "TeSt", "miDl", "LASt", "LaSt"]);
+        assert!(matches!(png.insert_chunk(6, chunk_from_strings("TeSt", "x").unwrap()), Err(PngError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_insert_before() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_before("IDAT", chunk_from_strings("teXt", "early").unwrap()).unwrap();
+        let index = png.chunks().iter().position(|chunk| chunk.chunk_type().to_string() == "teXt").unwrap();
+        assert_eq!(png.chunks()[index + 1].chunk_type().to_string(), "IDAT");
+        assert!(matches!(
+            png.insert_before("zzZz", chunk_from_strings("TeSt", "x").unwrap()),
+            Err(PngError::ChunkNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_append_chunk_before_trailing_chunks() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+            chunk_from_strings("teXt", "trailing").unwrap(),
+        ]);
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "TeSt");
+    }
+
+    #[test]
+    fn test_truncated_png() {
+        let bytes = &PNG_FILE[..PNG_FILE.len() - 3];
+        let png = Png::try_from(bytes);
+        assert!(matches!(png, Err(PngError::TruncatedChunk { offset: 4791 })));
+    }
+
+    #[test]
+    fn test_short_signature() {
+        let png = Png::try_from(&PNG_FILE[..4]);
+        assert!(matches!(png, Err(PngError::InvalidSignature)));
+    }
+
+    #[test]
+    fn test_every_prefix_is_rejected() {
+        for end in 0..PNG_FILE.len() {
+            assert!(Png::try_from(&PNG_FILE[..end]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_strip_chunks() {
+        let mut png = testing_png();
+        assert_eq!(png.strip_chunks(&"FrSt,LASt,tEXt".parse().unwrap()), 2);
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["miDl"]);
+    }
+
+    #[test]
+    fn test_remove_chunks() {
+        let mut png = testing_png();
+        for message in ["a", "b", "c"] {
+            png.append_chunk(chunk_from_strings("tEXt", message).unwrap());
+        }
+        assert_eq!(&png.remove_nth_chunk("tEXt", 1).unwrap().data_as_string().unwrap(), "b");
+        assert!(matches!(png.remove_nth_chunk("tEXt", 2), Err(PngError::InvalidArgument(_))));
+        let removed = png.remove_chunks("tEXt").unwrap();
+        let messages: Vec<String> = removed.iter().map(|chunk| chunk.data_as_string().unwrap()).collect();
+        assert_eq!(messages, ["a", "c"]);
+        assert_eq!(png.chunks().len(), 3);
+        assert!(matches!(png.remove_chunks("tEXt"), Err(PngError::ChunkNotFound(_))));
+        assert!(matches!(png.remove_nth_chunk("tEXt", 0), Err(PngError::ChunkNotFound(_))));
+    }
+
+    #[test]
+    fn test_remove_missing_chunk() {
+        let mut png = testing_png();
+        assert!(matches!(png.remove_chunk("TeSt"), Err(PngError::ChunkNotFound(_))));
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -223,6 +1306,55 @@ mod tests {
     }
 
     #[test]
+    fn test_header() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let header = png.header().unwrap();
+        assert_eq!((header.width, header.height), (50, 50));
+        assert_eq!(Png::from_chunks(testing_chunks()).header().unwrap_err().to_string(), "no IHDR chunk found");
+    }
+
+    #[test]
+    fn test_metadata_edits_keep_image_data() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let original: Vec<Vec<u8>> = png.chunks().iter()
+            .filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+            .map(|chunk| chunk.as_bytes())
+            .collect();
+        png.append_chunk(chunk_from_strings("RuSt", "hidden").unwrap());
+        png.replace_chunk(chunk_from_strings("RuSt", "replaced").unwrap());
+        png.set_comment("metadata only").unwrap();
+        png.remove_chunk("RuSt").unwrap();
+        assert!(png.is_pixel_data_untouched());
+
+        let reparsed = Png::try_from(png.write_to(Vec::new()).unwrap().as_ref()).unwrap();
+        let actual: Vec<Vec<u8>> = reparsed.chunks().iter()
+            .filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+            .map(|chunk| chunk.as_bytes())
+            .collect();
+        assert_eq!(actual, original);
+    }
+
+    #[test]
+    fn test_pixel_data_touched() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let idat = png.remove_chunk("IDAT").unwrap();
+        assert!(!png.is_pixel_data_untouched());
+        let (first, second) = idat.data().split_at(100);
+        png.append_chunk(Chunk::new(*idat.chunk_type(), first.to_vec()));
+        png.append_chunk(Chunk::new(*idat.chunk_type(), second.to_vec()));
+        assert_eq!(png.image_data(), idat.data());
+        assert!(!png.is_pixel_data_untouched());
+    }
+
+    #[test]
+    fn test_image_data() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let idat = png.chunk_by_type("IDAT").unwrap();
+        assert_eq!(png.image_data(), idat.data());
+    }
+
+    #[test]
+    #[allow(clippy::iter_cloned_collect)]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
@@ -231,11 +1363,131 @@ mod tests {
     }
 
     #[test]
-    fn test_png_trait_impls() {
-        let chunk_bytes: Vec<u8> = testing_chunks()
-            .into_iter()
-            .flat_map(|chunk| chunk.as_bytes())
+    fn test_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("png-file-round-trip-{}.png", std::process::id()));
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.to_file(&path).unwrap();
+        let actual = Png::from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(actual.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_from_missing_file() {
+        let png = Png::from_file(std::env::temp_dir().join("png-does-not-exist.png"));
+        assert!(matches!(png, Err(PngError::Io(_))));
+    }
+
+    fn out_of_order_bytes() -> Vec<u8> {
+        let chunks = [
+            ("IHDR", "0123456789abc"),
+            ("tEXt", "before"),
+            ("PLTE", "rgb"),
+            ("gAMA", "late"),
+            ("IDAT", "ab"),
+            ("IDAT", "cd"),
+            ("pHYs", "after data"),
+            ("zTXt", "after"),
+            ("IEND", ""),
+        ];
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        for (chunk_type, data) in chunks {
+            bytes.extend(chunk_from_strings(chunk_type, data).unwrap().as_bytes());
+        }
+        bytes
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_preserve() {
+        let bytes = out_of_order_bytes();
+        let png = Png::from_bytes_with(&bytes, ParseMode::Preserve).unwrap();
+        assert_eq!(png.as_bytes(), bytes);
+        assert_eq!(png.chunks().len(), 9);
+    }
+
+    #[test]
+    fn test_parse_normalize() {
+        let png = Png::from_bytes_with(&out_of_order_bytes(), ParseMode::Normalize).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "tEXt", "gAMA", "PLTE", "pHYs", "IDAT", "zTXt", "IEND"]);
+        assert_eq!(png.chunk_by_type("IDAT").unwrap().data(), b"abcd");
+        assert!(png.is_pixel_data_untouched());
+    }
+
+    #[test]
+    fn test_normalize_is_stable() {
+        let mut png = Png::from_bytes_with(&out_of_order_bytes(), ParseMode::Normalize).unwrap();
+        let before = png.as_bytes();
+        png.normalize();
+        assert_eq!(png.as_bytes(), before);
+    }
+
+    #[test]
+    fn test_chunk_filter_skip() {
+        let mut bytes = out_of_order_bytes();
+        // A bad CRC in a skipped chunk goes unnoticed.
+        let idat_crc_end = 8 + 25 + 18 + 15 + 16 + 14;
+        bytes[idat_crc_end - 1] ^= 0xff;
+        let offsets = core::cell::RefCell::new(Vec::new());
+        let options = ParseOptions::new().chunk_filter(|header| {
+            offsets.borrow_mut().push(header.offset);
+            match header.chunk_type.bytes() == *b"IDAT" {
+                true => FilterAction::Skip,
+                false => FilterAction::Keep
+            }
+        });
+        let png = Png::from_bytes_with_options(&bytes, &options).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "tEXt", "PLTE", "gAMA", "pHYs", "zTXt", "IEND"]);
+        assert_eq!(offsets.borrow()[..3], [8, 33, 51]);
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_chunk_filter_stop() {
+        let mut bytes = out_of_order_bytes();
+        bytes.truncate(bytes.len() - 12);
+        let options = ParseOptions::new()
+            .mode(ParseMode::Normalize)
+            .chunk_filter(|header| match header.chunk_type.bytes() == *b"IDAT" {
+                true => FilterAction::Stop,
+                false => FilterAction::Keep
+            });
+        let png = Png::from_bytes_with_options(&bytes, &options).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "tEXt", "gAMA", "PLTE"]);
+        // Without the filter the missing IEND is still an error.
+        assert!(Png::from_bytes_with_options(&bytes, &ParseOptions::new()).is_err());
+    }
+
+    #[test]
+    fn test_validate_order() {
+        let mut png = Png::from_bytes_with(&out_of_order_bytes(), ParseMode::Preserve).unwrap();
+        let violations: Vec<(usize, String)> = png
+            .order_violations()
+            .iter()
+            .map(|violation| (violation.index, format!("{} {}", violation.chunk_type, violation.problem)))
             .collect();
+        assert_eq!(violations, [(3, "gAMA must come before PLTE and IDAT".to_string()), (6, "pHYs must come before IDAT".to_string())]);
+        assert!(matches!(png.validate_order(), Err(PngError::InvalidChunkOrder { index: 3, .. })));
+        png.normalize();
+        assert!(png.validate_order().is_ok());
+    }
+
+    #[test]
+    fn test_validate_order_after_append() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("tRNS", "ab").unwrap());
+        png.append_chunk(chunk_from_strings("PLTE", "rgb").unwrap());
+        assert!(png.validate_order().is_err());
+        png.normalize();
+        assert!(png.validate_order().is_ok());
+    }
+
+    #[test]
+    fn test_png_trait_impls() {
+        let chunk_bytes = framed_chunk_bytes();
 
         let bytes: Vec<u8> = Png::STANDARD_HEADER
             .iter()
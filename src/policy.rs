@@ -0,0 +1,118 @@
+use std::str::FromStr;
+
+use glob::{MatchOptions, Pattern};
+
+use crate::{error::PngError, png::Png, text};
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions { case_sensitive: false, require_literal_separator: false, require_literal_leading_dot: false };
+
+// Which text keywords may be written. Patterns are globs matched without
+// regard to case, e.g. `X-Internal-*`. A denied keyword is refused even if it
+// is also allowed, and once anything is allowed, keywords that match no
+// allow pattern are refused too.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeywordPolicy {
+    allow: Vec<Pattern>,
+    deny: Vec<Pattern>
+}
+
+impl KeywordPolicy {
+    pub fn new() -> KeywordPolicy {
+        KeywordPolicy::default()
+    }
+
+    pub fn allow(&mut self, pattern: &str) -> Result<(), PngError> {
+        self.allow.push(pattern_from(pattern)?);
+        Ok(())
+    }
+
+    pub fn deny(&mut self, pattern: &str) -> Result<(), PngError> {
+        self.deny.push(pattern_from(pattern)?);
+        Ok(())
+    }
+
+    pub fn permits(&self, keyword: &str) -> bool {
+        let matches = |pattern: &Pattern| pattern.matches_with(keyword, MATCH_OPTIONS);
+        !self.deny.iter().any(matches) && (self.allow.is_empty() || self.allow.iter().any(matches))
+    }
+
+    // Fails on the first tEXt, zTXt or iTXt chunk whose keyword is not permitted.
+    pub fn check(&self, png: &Png) -> Result<(), PngError> {
+        match png.chunks().iter().filter_map(text::text_keyword).find(|keyword| !self.permits(keyword)) {
+            Some(keyword) => Err(PngError::KeywordNotAllowed(keyword)),
+            None => Ok(())
+        }
+    }
+}
+
+// One rule per line, "allow PATTERN" or "deny PATTERN". Blank lines and lines
+// starting with "#" are skipped.
+impl FromStr for KeywordPolicy {
+    type Err = PngError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = KeywordPolicy::new();
+        for (index, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason| PngError::InvalidPolicy { line: index + 1, reason };
+            let rule = match line.split_once(char::is_whitespace) {
+                Some(("allow", pattern)) => policy.allow(pattern.trim()),
+                Some(("deny", pattern)) => policy.deny(pattern.trim()),
+                _ => return Err(invalid("expected \"allow\" or \"deny\" and a keyword pattern"))
+            };
+            rule.map_err(|_| invalid("invalid keyword pattern"))?;
+        }
+        Ok(policy)
+    }
+}
+
+fn pattern_from(pattern: &str) -> Result<Pattern, PngError> {
+    Pattern::new(pattern).map_err(|_| PngError::InvalidArgument("invalid keyword pattern"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+    #[test]
+    fn test_deny() {
+        let mut policy = KeywordPolicy::new();
+        policy.deny("x-internal-*").unwrap();
+        assert!(policy.permits("Title"));
+        assert!(!policy.permits("X-Internal-Host"));
+    }
+
+    #[test]
+    fn test_allow_list() {
+        let mut policy = KeywordPolicy::new();
+        policy.allow("Title").unwrap();
+        policy.allow("Copyright").unwrap();
+        policy.deny("title").unwrap();
+        assert!(policy.permits("Copyright"));
+        assert!(!policy.permits("Comment"));
+        assert!(!policy.permits("Title"));
+    }
+
+    #[test]
+    fn test_parse() {
+        let policy = KeywordPolicy::from_str("# house rules\n\nallow Title\ndeny  Host*\n").unwrap();
+        assert!(policy.permits("title"));
+        assert!(!policy.permits("Hostname"));
+        assert!(matches!(KeywordPolicy::from_str("allow Title\nblock Host\n"), Err(PngError::InvalidPolicy { line: 2, .. })));
+        assert!(matches!(KeywordPolicy::from_str("deny [\n"), Err(PngError::InvalidPolicy { line: 1, .. })));
+    }
+
+    #[test]
+    fn test_check() {
+        let mut policy = KeywordPolicy::new();
+        policy.deny("Hostname").unwrap();
+        let mut png = Png::from_chunks(vec![Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new())]);
+        png.set_comment("fine").unwrap();
+        assert!(policy.check(&png).is_ok());
+        png.set_text("Hostname", "build-07.corp").unwrap();
+        assert_eq!(policy.check(&png).unwrap_err().to_string(), "text keyword \"Hostname\" is not allowed by the keyword policy");
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::PngError,
+    filter::FilterStrategy,
+    ihdr::ColorType,
+    palette::{Palette, Rgb},
+    pixels::{self, ImageBuffer},
+    png::Png,
+    transparency::Transparency,
+    zlib::CompressionLevel
+};
+
+// An image reduced to a palette of at most 256 RGBA colors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quantized {
+    pub width: u32,
+    pub height: u32,
+    pub palette: Palette,
+    // tRNS alpha for the leading palette entries; empty when every entry is opaque.
+    pub alpha: Vec<u8>,
+    pub indices: Vec<u8>
+}
+
+// Distinct colors with the number of pixels that use them.
+struct ColorBox {
+    colors: Vec<([u8; 4], u32)>
+}
+
+impl ColorBox {
+    // The RGBA channel with the largest spread of values, and that spread.
+    fn widest_channel(&self) -> (usize, u8) {
+        (0..4)
+            .map(|channel| {
+                let values = self.colors.iter().map(|(color, _)| color[channel]);
+                (channel, values.clone().max().unwrap_or(0) - values.min().unwrap_or(0))
+            })
+            .max_by_key(|(_, spread)| *spread)
+            .unwrap()
+    }
+
+    // Splits at the pixel-weighted median of the widest channel.
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+        self.colors.sort_by_key(|(color, _)| color[channel]);
+        let total: u64 = self.colors.iter().map(|(_, count)| *count as u64).sum();
+        let mut seen = 0;
+        let median = self.colors.iter().position(|(_, count)| {
+            seen += *count as u64;
+            seen * 2 >= total
+        });
+        let at = median.map_or(1, |i| i + 1).clamp(1, self.colors.len() - 1);
+        let upper = self.colors.split_off(at);
+        (self, ColorBox { colors: upper })
+    }
+
+    fn average(&self) -> [u8; 4] {
+        let total: u64 = self.colors.iter().map(|(_, count)| *count as u64).sum();
+        std::array::from_fn(|channel| {
+            let sum: u64 = self.colors.iter().map(|(color, count)| color[channel] as u64 * *count as u64).sum();
+            ((sum + total / 2) / total) as u8
+        })
+    }
+}
+
+impl Quantized {
+    // Encodes the indices as an 8-bit indexed PNG with PLTE and, when needed, tRNS.
+    pub fn to_png(&self, compression: CompressionLevel) -> Result<Png, PngError> {
+        let mut png = Png::from_pixels_with(
+            self.width,
+            self.height,
+            ColorType::Indexed,
+            8,
+            &self.indices,
+            FilterStrategy::None,
+            compression
+        )?;
+        png.set_palette(self.palette.clone());
+        if !self.alpha.is_empty() {
+            png.set_transparency(Transparency::Palette(self.alpha.clone()))?;
+        }
+        Ok(png)
+    }
+}
+
+// Reduces `image` to at most `max_colors` colors with median cut. Images that
+// already have few enough colors keep them exactly. Fully transparent pixels
+// all share one entry.
+pub fn quantize(image: &ImageBuffer, max_colors: usize) -> Result<Quantized, PngError> {
+    if !(1..=Palette::MAX_ENTRIES).contains(&max_colors) {
+        return Err(PngError::InvalidArgument("a palette can have 1 to 256 colors"));
+    }
+    if image.pixels.is_empty() {
+        return Err(PngError::InvalidArgument("image has no pixels"));
+    }
+    let mut counts: HashMap<[u8; 4], u32> = HashMap::new();
+    for pixel in image.pixels.iter() {
+        *counts.entry(clear_transparent(*pixel)).or_default() += 1;
+    }
+    let mut colors: Vec<([u8; 4], u32)> = counts.into_iter().collect();
+    colors.sort_unstable();
+
+    let mut boxes = vec![ColorBox { colors }];
+    while boxes.len() < max_colors {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, color_box)| color_box.colors.len() > 1)
+            .max_by_key(|(_, color_box)| color_box.widest_channel().1);
+        let Some((index, _)) = widest else {
+            break;
+        };
+        let (lower, upper) = boxes.swap_remove(index).split();
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+
+    // tRNS only covers leading entries, so translucent colors go first.
+    let mut entries: Vec<([u8; 4], &ColorBox)> = boxes.iter().map(|color_box| (color_box.average(), color_box)).collect();
+    entries.sort_by_key(|(color, _)| color[3] == 255);
+    let mut lookup: HashMap<[u8; 4], u8> = HashMap::new();
+    for (index, (_, color_box)) in entries.iter().enumerate() {
+        lookup.extend(color_box.colors.iter().map(|(color, _)| (*color, index as u8)));
+    }
+
+    Ok(Quantized {
+        width: image.width,
+        height: image.height,
+        palette: Palette::new(entries.iter().map(|([r, g, b, _], _)| Rgb::new(*r, *g, *b)).collect())?,
+        alpha: entries.iter().map(|(color, _)| color[3]).take_while(|alpha| *alpha != 255).collect(),
+        indices: image.pixels.iter().map(|pixel| lookup[&clear_transparent(*pixel)]).collect()
+    })
+}
+
+// Re-encodes `png` as an 8-bit indexed image of at most `max_colors` colors.
+// Chunks that describe the old colors are dropped, the rest are kept.
+pub fn quantize_png(png: &Png, max_colors: usize, compression: CompressionLevel) -> Result<Png, PngError> {
+    let quantized = quantize(&pixels::decode_rgba(png)?, max_colors)?.to_png(compression)?;
+    Ok(png.replace_image(&quantized))
+}
+
+fn clear_transparent(pixel: [u8; 4]) -> [u8; 4] {
+    if pixel[3] == 0 { [0; 4] } else { pixel }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+    fn image(pixels: Vec<[u8; 4]>, width: u32) -> ImageBuffer {
+        ImageBuffer { width, height: pixels.len() as u32 / width, pixels }
+    }
+
+    fn gradient() -> ImageBuffer {
+        image((0..=255).map(|i| [i, 255 - i, (i / 2), 255]).collect(), 16)
+    }
+
+    #[test]
+    fn test_few_colors_are_exact() {
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let quantized = quantize(&image(vec![red, blue, blue, red], 2), 256).unwrap();
+        assert_eq!(quantized.palette.len(), 2);
+        assert!(quantized.alpha.is_empty());
+        let colors: Vec<Rgb> = quantized.indices.iter().map(|index| quantized.palette.get(*index).unwrap()).collect();
+        assert_eq!(colors, [Rgb::new(255, 0, 0), Rgb::new(0, 0, 255), Rgb::new(0, 0, 255), Rgb::new(255, 0, 0)]);
+    }
+
+    #[test]
+    fn test_reduces_colors() {
+        let source = gradient();
+        let quantized = quantize(&source, 16).unwrap();
+        assert_eq!(quantized.palette.len(), 16);
+        for (pixel, index) in source.pixels.iter().zip(&quantized.indices) {
+            let color = quantized.palette.get(*index).unwrap();
+            assert!((pixel[0] as i32 - color.r as i32).abs() <= 16);
+        }
+    }
+
+    #[test]
+    fn test_translucent_entries_come_first() {
+        let pixels = vec![[10, 10, 10, 255], [1, 2, 3, 0], [50, 60, 70, 128], [9, 9, 9, 0]];
+        let quantized = quantize(&image(pixels, 2), 256).unwrap();
+        assert_eq!(quantized.palette.len(), 3);
+        assert_eq!(quantized.alpha, [0, 128]);
+        assert_eq!(quantized.indices[1], quantized.indices[3]);
+        assert_eq!(quantized.palette.get(quantized.indices[0]), Some(Rgb::new(10, 10, 10)));
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        assert!(quantize(&gradient(), 0).is_err());
+        assert!(quantize(&gradient(), 257).is_err());
+        assert!(quantize(&image(Vec::new(), 1), 16).is_err());
+    }
+
+    #[test]
+    fn test_to_png_round_trip() {
+        let pixels = vec![[10, 20, 30, 255], [40, 50, 60, 128], [0, 0, 0, 0], [10, 20, 30, 255]];
+        let png = quantize(&image(pixels.clone(), 2), 256).unwrap().to_png(CompressionLevel::Default).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "PLTE", "tRNS", "IDAT", "IEND"]);
+        assert_eq!(pixels::decode_rgba(&png).unwrap().pixels, pixels);
+    }
+
+    #[test]
+    fn test_quantize_png_keeps_metadata() {
+        let source = gradient();
+        let raw: Vec<u8> = source.pixels.iter().flat_map(|pixel| pixel[..3].to_vec()).collect();
+        let mut png = Png::from_pixels(16, 16, ColorType::Rgb, 8, &raw).unwrap();
+        png.append_chunk(Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Title\0art".to_vec()));
+        let quantized = quantize_png(&png, 8, CompressionLevel::Best).unwrap();
+        let types: Vec<String> = quantized.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "PLTE", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(quantized.header().unwrap().color_type, ColorType::Indexed);
+        assert_eq!(quantized.palette().unwrap().unwrap().len(), 8);
+    }
+}
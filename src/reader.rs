@@ -0,0 +1,186 @@
+use std::io::{self, Read};
+
+use crate::{chunk::Chunk, error::PngError, png::Png};
+
+// Yields chunks one at a time from any reader, so only a single chunk is
+// held in memory. Iteration stops after IEND or at the first error.
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    offset: usize,
+    finished: bool,
+    check_crc: bool
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(mut reader: R) -> Result<ChunkReader<R>, PngError> {
+        let mut signature = [0u8; 8];
+        reader.read_exact(&mut signature).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => PngError::InvalidSignature,
+            _ => PngError::Io(err)
+        })?;
+        if signature != Png::STANDARD_HEADER {
+            return Err(PngError::InvalidSignature);
+        }
+        Ok(ChunkReader { reader, offset: 8, finished: false, check_crc: true })
+    }
+
+    // Yields chunks whose stored CRC does not match instead of failing, for
+    // tools that report corruption.
+    pub fn without_crc_check(mut self) -> ChunkReader<R> {
+        self.check_crc = false;
+        self
+    }
+
+    // Byte offset of the next chunk in the underlying stream.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<Chunk>, PngError> {
+        let truncated = PngError::TruncatedChunk { offset: self.offset };
+        let mut header = [0u8; 8];
+        let read = read_up_to(&mut self.reader, &mut header)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if read < header.len() {
+            return Err(truncated);
+        }
+
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+        let mut bytes = header.to_vec();
+        (&mut self.reader).take(length + 4).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != length + 12 {
+            return Err(truncated);
+        }
+
+        let chunk = match self.check_crc {
+            true => Chunk::try_from(bytes.as_ref())?,
+            false => Chunk::from_bytes_unchecked(&bytes)?
+        };
+        self.offset += bytes.len();
+        Ok(Some(chunk))
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk, PngError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.read_chunk() {
+            Ok(Some(chunk)) => {
+                self.finished = chunk.chunk_type().bytes() == *b"IEND";
+                Some(Ok(chunk))
+            },
+            Ok(None) => {
+                self.finished = true;
+                None
+            },
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, PngError> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(PngError::Io(err))
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk_type::ChunkType;
+
+    fn testing_bytes() -> Vec<u8> {
+        let chunks = [
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![1, 2, 3, 4]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ];
+        Png::STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(chunks.iter().flat_map(|chunk| chunk.as_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn test_reads_every_chunk() {
+        let bytes = testing_bytes();
+        let reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let types: Vec<String> = reader.map(|chunk| chunk.unwrap().chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_tracks_offset() {
+        let bytes = testing_bytes();
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.offset(), 8);
+        reader.next().unwrap().unwrap();
+        assert_eq!(reader.offset(), 8 + 25);
+    }
+
+    #[test]
+    fn test_stops_after_iend() {
+        let mut bytes = testing_bytes();
+        bytes.extend([0xde, 0xad]);
+        let reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.count(), 3);
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        let bytes = testing_bytes();
+        let reader = ChunkReader::new(&bytes[..bytes.len() - 14]).unwrap();
+        let results: Vec<Result<Chunk, PngError>> = reader.collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[1], Err(PngError::TruncatedChunk { offset: 33 })));
+    }
+
+    #[test]
+    fn test_invalid_signature() {
+        assert!(matches!(ChunkReader::new(&b"GIF89a"[..]), Err(PngError::InvalidSignature)));
+    }
+
+    #[test]
+    fn test_huge_declared_length_is_truncated() {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend([0xff, 0xff, 0xff, 0xff]);
+        bytes.extend(b"IDAT");
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        assert!(matches!(reader.next(), Some(Err(PngError::TruncatedChunk { offset: 8 }))));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_without_crc_check() {
+        let mut bytes = testing_bytes();
+        bytes[8 + 8] ^= 1;
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        assert!(matches!(reader.next(), Some(Err(PngError::CrcMismatch { .. }))));
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap().without_crc_check();
+        let ihdr = reader.next().unwrap().unwrap();
+        assert_ne!(ihdr.crc(), ihdr.computed_crc());
+        assert_eq!(reader.count(), 2);
+    }
+}
@@ -0,0 +1,209 @@
+use std::str::FromStr;
+
+use crate::{
+    chunk_type::ChunkTypeSet,
+    error::PngError,
+    filter::FilterStrategy,
+    pixels::{self, ImageBuffer},
+    png::Png,
+    zlib::CompressionLevel
+};
+
+// Ancillary chunks that describe how to display colors but hold nothing
+// derived from the pixels. Every other ancillary chunk is dropped when
+// redacting: text, eXIf and private chunks can carry thumbnails or
+// descriptions, and sPLT, hIST and bKGD are computed from the old pixels.
+const REDACTION_SAFE_TYPES: ChunkTypeSet = ChunkTypeSet::of(&[b"gAMA", b"cHRM", b"sRGB", b"iCCP", b"pHYs"]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactMode {
+    // Opaque black.
+    Blackout,
+    // Each block of this many pixels becomes its average color. The averages
+    // are still visible in the output, so use Blackout for anything that must
+    // not be guessed from its rough shape.
+    Pixelate(u32)
+}
+
+impl FromStr for Rect {
+    type Err = PngError;
+    // "x,y,width,height"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values: Vec<u32> = s
+            .split(',')
+            .map(|value| value.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| PngError::InvalidArgument("rectangle must be x,y,width,height"))?;
+        match values.as_slice() {
+            [x, y, width, height] if *width > 0 && *height > 0 => Ok(Rect { x: *x, y: *y, width: *width, height: *height }),
+            [_, _, _, _] => Err(PngError::InvalidArgument("rectangle must not be empty")),
+            _ => Err(PngError::InvalidArgument("rectangle must be x,y,width,height"))
+        }
+    }
+}
+
+impl Rect {
+    // The part of this rectangle inside a `width` x `height` image.
+    fn clip(&self, width: u32, height: u32) -> Option<Rect> {
+        let (right, bottom) = (self.x.saturating_add(self.width).min(width), self.y.saturating_add(self.height).min(height));
+        (self.x < right && self.y < bottom).then(|| Rect { x: self.x, y: self.y, width: right - self.x, height: bottom - self.y })
+    }
+}
+
+// Overwrites `rects` in the decoded image and encodes the result as a new
+// 8-bit image of the smallest color type that holds it. Nothing of the original image data survives: the output
+// is encoded only from the redacted pixels, and every ancillary chunk except
+// the color space and resolution ones is dropped.
+pub fn redact(png: &Png, rects: &[Rect], mode: RedactMode) -> Result<Png, PngError> {
+    if mode == RedactMode::Pixelate(0) {
+        return Err(PngError::InvalidArgument("pixelate block size must be at least 1"));
+    }
+    let mut image = pixels::decode_rgba(png)?;
+    let clipped: Vec<Rect> = rects.iter().filter_map(|rect| rect.clip(image.width, image.height)).collect();
+    if clipped.len() != rects.len() {
+        return Err(PngError::InvalidArgument("rectangle is outside the image"));
+    }
+    for rect in clipped {
+        match mode {
+            RedactMode::Blackout => fill(&mut image, rect, [0, 0, 0, 255]),
+            RedactMode::Pixelate(size) => pixelate(&mut image, rect, size)
+        }
+    }
+
+    let color_type = image.min_color_type();
+    let samples = image.to_samples(color_type)?;
+    let encoded = Png::from_pixels_with(image.width, image.height, color_type, 8, &samples, FilterStrategy::Adaptive, CompressionLevel::Best)?;
+    // IHDR, IDAT, IEND
+    let mut idat = Some(encoded.chunks()[1].clone());
+    let mut chunks = Vec::new();
+    for chunk in png.chunks() {
+        match &chunk.chunk_type().bytes() {
+            b"IHDR" => chunks.push(encoded.chunks()[0].clone()),
+            b"IDAT" => chunks.extend(idat.take()),
+            b"IEND" => chunks.push(chunk.clone()),
+            _ if REDACTION_SAFE_TYPES.contains(*chunk.chunk_type()) => chunks.push(chunk.clone()),
+            _ => {}
+        }
+    }
+    Ok(png.with_chunks(chunks))
+}
+
+fn fill(image: &mut ImageBuffer, rect: Rect, color: [u8; 4]) {
+    for row in image.rows_mut().skip(rect.y as usize).take(rect.height as usize) {
+        row[rect.x as usize..(rect.x + rect.width) as usize].fill(color);
+    }
+}
+
+// Blocks are aligned to the rectangle and cut off at its edges.
+fn pixelate(image: &mut ImageBuffer, rect: Rect, size: u32) {
+    for top in (rect.y..rect.y + rect.height).step_by(size as usize) {
+        for left in (rect.x..rect.x + rect.width).step_by(size as usize) {
+            let block = Rect {
+                x: left,
+                y: top,
+                width: size.min(rect.x + rect.width - left),
+                height: size.min(rect.y + rect.height - top)
+            };
+            let mut sums = [0u64; 4];
+            for y in block.y..block.y + block.height {
+                for x in block.x..block.x + block.width {
+                    for (sum, sample) in sums.iter_mut().zip(image.pixel(x, y)) {
+                        *sum += sample as u64;
+                    }
+                }
+            }
+            let count = (block.width * block.height) as u64;
+            fill(image, block, sums.map(|sum| ((sum + count / 2) / count) as u8));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::Chunk,
+        chunk_type::ChunkType,
+        filter::{self, FilterType},
+        ihdr::{ColorType, Ihdr},
+        text::TextChunk,
+        zlib
+    };
+
+    fn testing_png() -> Png {
+        let raw: Vec<u8> = (0..4 * 4).flat_map(|i| [i as u8 * 10, 0, 0]).collect();
+        let filtered = filter::filter(&raw, 3, 12, FilterType::None);
+        Png::from_chunks(vec![
+            Ihdr::new(4, 4, 8, ColorType::Rgb).unwrap().into(),
+            Chunk::new(ChunkType::from_str("gAMA").unwrap(), vec![0, 0, 177, 143]),
+            TextChunk::new("Comment", "secret").unwrap().into(),
+            Chunk::new(ChunkType::from_str("prVw").unwrap(), vec![1, 2, 3]),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), zlib::deflate(&filtered, 6).unwrap()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_parse_rect() {
+        assert_eq!("1, 2,3,4".parse::<Rect>().unwrap(), Rect { x: 1, y: 2, width: 3, height: 4 });
+        assert!("1,2,3".parse::<Rect>().is_err());
+        assert!("1,2,0,4".parse::<Rect>().is_err());
+        assert!("1,2,-3,4".parse::<Rect>().is_err());
+    }
+
+    #[test]
+    fn test_blackout() {
+        let png = testing_png();
+        let redacted = redact(&png, &[Rect { x: 1, y: 1, width: 2, height: 9 }], RedactMode::Blackout).unwrap();
+        let image = pixels::decode_rgba(&redacted).unwrap();
+        assert_eq!(image.pixel(1, 1), [0, 0, 0, 255]);
+        assert_eq!(image.pixel(2, 3), [0, 0, 0, 255]);
+        assert_eq!(image.pixel(0, 1), [40, 0, 0, 255]);
+        assert_eq!(image.pixel(3, 0), [30, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_pixelate() {
+        let png = testing_png();
+        let redacted = redact(&png, &[Rect { x: 0, y: 0, width: 3, height: 2 }], RedactMode::Pixelate(2)).unwrap();
+        let image = pixels::decode_rgba(&redacted).unwrap();
+        // Average of 0, 10, 40 and 50.
+        assert_eq!(image.pixel(1, 1), [25, 0, 0, 255]);
+        // The cut-off block on the right averages 20 and 60.
+        assert_eq!(image.pixel(2, 0), [40, 0, 0, 255]);
+        assert_eq!(image.pixel(3, 0), [30, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_drops_unsafe_chunks() {
+        let png = testing_png();
+        let redacted = redact(&png, &[Rect { x: 0, y: 0, width: 1, height: 1 }], RedactMode::Blackout).unwrap();
+        let types: Vec<String> = redacted.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "gAMA", "IDAT", "IEND"]);
+        assert_eq!(redacted.image_data(), redacted.chunks()[2].data());
+    }
+
+    #[test]
+    fn test_output_color_type() {
+        let png = testing_png();
+        let redacted = redact(&png, &[Rect { x: 0, y: 0, width: 1, height: 1 }], RedactMode::Blackout).unwrap();
+        assert_eq!(redacted.header().unwrap().color_type, ColorType::Rgb);
+        let redacted = redact(&png, &[Rect { x: 0, y: 0, width: 4, height: 4 }], RedactMode::Blackout).unwrap();
+        assert_eq!(redacted.header().unwrap().color_type, ColorType::Grayscale);
+    }
+
+    #[test]
+    fn test_rect_outside_image() {
+        let png = testing_png();
+        assert!(redact(&png, &[Rect { x: 4, y: 0, width: 1, height: 1 }], RedactMode::Blackout).is_err());
+        assert!(redact(&png, &[Rect { x: 0, y: 0, width: 1, height: 1 }], RedactMode::Pixelate(0)).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use std::io::{self, Read};
+
+use flate2::read::ZlibDecoder;
+
+use crate::{
+    animation::AnimationControl,
+    chunk::Chunk,
+    color::{Chromaticities, Gamma, IccProfile, RenderingIntent},
+    extensions::{GifApplication, GifGraphicControl, ImageOffset, PhysicalDimensions, PhysicalScale, StereoMode},
+    histogram::Histogram,
+    ihdr::Ihdr,
+    palette::Palette,
+    suggested_palette::SuggestedPalette,
+    time::ModificationTime,
+    vendor,
+    zlib::ZlibHeader
+};
+
+const TEXT_PREVIEW_CHARS: usize = 200;
+const HEXDUMP_MAX_BYTES: usize = 64;
+// Streams are only inflated this far to report their size, so a small chunk
+// that inflates to gigabytes costs no more than this.
+const INFLATE_COUNT_MAX_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Payload {
+    Empty,
+    Utf8(String),
+    Latin1(String),
+    // `capped` when the stream inflates to more than `inflated` bytes.
+    Zlib { compressed: usize, inflated: usize, capped: bool },
+    Hex(Vec<u8>),
+    Binary(usize)
+}
+
+impl Payload {
+    // Picks the most readable representation for arbitrary chunk data.
+    pub fn sniff(data: &[u8]) -> Payload {
+        if data.is_empty() {
+            return Payload::Empty;
+        }
+        if let Ok(text) = std::str::from_utf8(data) {
+            if text.chars().all(is_text_char) && text.chars().any(|c| !c.is_control()) {
+                return Payload::Utf8(text.to_string());
+            }
+        }
+        if data.iter().all(|byte| is_latin1_text_byte(*byte)) && data.iter().any(|byte| *byte > 0x20) {
+            return Payload::Latin1(data.iter().map(|byte| *byte as char).collect());
+        }
+        if let Some(inflated) = inflated_len(data) {
+            let capped = inflated > INFLATE_COUNT_MAX_BYTES;
+            return Payload::Zlib { compressed: data.len(), inflated: inflated.min(INFLATE_COUNT_MAX_BYTES), capped };
+        }
+        if data.len() <= HEXDUMP_MAX_BYTES {
+            Payload::Hex(data.to_vec())
+        } else {
+            Payload::Binary(data.len())
+        }
+    }
+
+    pub fn render(&self) -> String {
+        match self {
+            Payload::Empty => String::from("(empty)"),
+            Payload::Utf8(text) => format!("{:?}", truncate(text)),
+            Payload::Latin1(text) => format!("latin-1 {:?}", truncate(text)),
+            Payload::Zlib { compressed, inflated, capped } => format!(
+                "zlib stream, {}, inflates to {}{}",
+                format_size(*compressed),
+                if *capped { "more than " } else { "" },
+                format_size(*inflated)
+            ),
+            Payload::Hex(bytes) => hexdump(bytes),
+            Payload::Binary(len) => format!("binary, {}", format_size(*len))
+        }
+    }
+}
+
+pub fn render_payload(data: &[u8]) -> String {
+    Payload::sniff(data).render()
+}
+
+// Decodes the payload of standard chunks that can be read without the rest
+// of the image. `None` for other chunks and for malformed data.
+pub fn decode_payload(chunk: &Chunk) -> Option<String> {
+    let decoded = match chunk.chunk_type().to_string().as_str() {
+        "IHDR" => Ihdr::try_from(chunk).ok()?.to_string(),
+        "PLTE" => format!("{} colors", Palette::try_from(chunk).ok()?.len()),
+        "gAMA" => {
+            let gamma = Gamma::try_from(chunk).ok()?;
+            match gamma.value() {
+                value if value > 0.0 => format!("{} (1/{:.1})", gamma, 1.0 / value),
+                _ => gamma.to_string()
+            }
+        },
+        "cHRM" => Chromaticities::try_from(chunk).ok()?.to_string(),
+        "sRGB" => format!("{} rendering intent", RenderingIntent::try_from(chunk).ok()?),
+        "iCCP" => IccProfile::try_from(chunk).ok()?.to_string(),
+        "hIST" => Histogram::try_from(chunk).ok()?.to_string(),
+        "pHYs" => PhysicalDimensions::try_from(chunk).ok()?.to_string(),
+        "sPLT" => SuggestedPalette::try_from(chunk).ok()?.to_string(),
+        "oFFs" => ImageOffset::try_from(chunk).ok()?.to_string(),
+        "sCAL" => PhysicalScale::try_from(chunk).ok()?.to_string(),
+        "sTER" => StereoMode::try_from(chunk).ok()?.to_string(),
+        "acTL" => AnimationControl::try_from(chunk).ok()?.to_string(),
+        "tIME" => ModificationTime::try_from(chunk).ok()?.to_string(),
+        "gIFg" => GifGraphicControl::try_from(chunk).ok()?.to_string(),
+        "gIFx" => GifApplication::try_from(chunk).ok()?.to_string(),
+        _ => vendor::decode_payload(chunk)?.ok()?
+    };
+    Some(decoded)
+}
+
+pub fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1048575 => format!("{} KB", (bytes + 512) / 1024),
+        _ => format!("{:.1} MB", bytes as f64 / 1048576.0)
+    }
+}
+
+pub fn hexdump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(i, line)| {
+            let hex: Vec<String> = line.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let ascii: String = line
+                .iter()
+                .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' })
+                .collect();
+            format!("{:08x}  {:<47}  |{}|", i * 16, hex.join(" "), ascii)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Text chunks separate keyword and value with NUL, so it counts as text.
+fn is_text_char(c: char) -> bool {
+    !c.is_control() || matches!(c, '\n' | '\r' | '\t' | '\0')
+}
+
+fn is_latin1_text_byte(byte: u8) -> bool {
+    matches!(byte, 0x20..=0x7e | 0xa0..=0xff | b'\n' | b'\r' | b'\t' | 0)
+}
+
+// Counts inflated bytes without keeping them, stopping one past the cap.
+fn inflated_len(data: &[u8]) -> Option<usize> {
+    ZlibHeader::parse(data)?;
+    let mut decoder = ZlibDecoder::new(data).take(INFLATE_COUNT_MAX_BYTES as u64 + 1);
+    io::copy(&mut decoder, &mut io::sink()).ok().map(|count| count as usize)
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= TEXT_PREVIEW_CHARS {
+        text.to_string()
+    } else {
+        let mut preview: String = text.chars().take(TEXT_PREVIEW_CHARS).collect();
+        preview.push('…');
+        preview
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Write, str::FromStr};
+    use flate2::{write::ZlibEncoder, Compression};
+    use crate::{chunk_type::ChunkType, extensions::DimensionUnit};
+
+    #[test]
+    fn test_sniff_utf8() {
+        let payload = Payload::sniff("Comment\0héllo".as_bytes());
+        assert_eq!(payload, Payload::Utf8(String::from("Comment\0héllo")));
+        assert_eq!(payload.render(), "\"Comment\\0héllo\"");
+    }
+
+    #[test]
+    fn test_sniff_latin1() {
+        let payload = Payload::sniff(&[b'c', b'a', b'f', 0xe9]);
+        assert_eq!(payload, Payload::Latin1(String::from("café")));
+    }
+
+    #[test]
+    fn test_sniff_zlib() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0u8; 4096]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let payload = Payload::sniff(&compressed);
+        assert_eq!(payload, Payload::Zlib { compressed: compressed.len(), inflated: 4096, capped: false });
+        assert!(payload.render().ends_with("inflates to 4 KB"));
+    }
+
+    #[test]
+    fn test_sniff_zlib_bomb_is_capped() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0u8; INFLATE_COUNT_MAX_BYTES + 4096]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let payload = Payload::sniff(&compressed);
+        assert_eq!(payload, Payload::Zlib { compressed: compressed.len(), inflated: INFLATE_COUNT_MAX_BYTES, capped: true });
+        assert!(payload.render().ends_with("inflates to more than 16.0 MB"));
+    }
+
+    #[test]
+    fn test_sniff_small_binary_is_hex() {
+        let payload = Payload::sniff(&[0, 0, 0, 50, 1, 2]);
+        assert_eq!(payload.render(), "00000000  00 00 00 32 01 02                                |...2..|");
+    }
+
+    #[test]
+    fn test_sniff_large_binary_is_summarized() {
+        let data: Vec<u8> = (0..2048).map(|i| (i % 7) as u8).collect();
+        assert_eq!(Payload::sniff(&data).render(), "binary, 2 KB");
+    }
+
+    #[test]
+    fn test_sniff_nul_bytes_are_not_text() {
+        assert_eq!(Payload::sniff(&[0, 0, 0, 0]), Payload::Hex(vec![0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_sniff_empty() {
+        assert_eq!(render_payload(&[]), "(empty)");
+    }
+
+    #[test]
+    fn test_long_text_is_truncated() {
+        let text = "a".repeat(500);
+        let rendered = render_payload(text.as_bytes());
+        assert_eq!(rendered.chars().count(), TEXT_PREVIEW_CHARS + 3);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(12), "12 B");
+        assert_eq!(format_size(49152), "48 KB");
+        assert_eq!(format_size(3 * 1048576), "3.0 MB");
+    }
+
+    #[test]
+    fn test_decode_payload() {
+        let gamma = Chunk::from(Gamma::from_f64(1.0 / 2.2).unwrap());
+        assert_eq!(decode_payload(&gamma).unwrap(), "0.45455 (1/2.2)");
+        let dimensions = Chunk::from(PhysicalDimensions { x: 2835, y: 2835, unit: DimensionUnit::Meter });
+        assert_eq!(decode_payload(&dimensions).unwrap(), "2835 px/m (72 dpi)");
+        let time = Chunk::from(ModificationTime::new(2023, 1, 2, 3, 4, 5).unwrap());
+        assert_eq!(decode_payload(&time).unwrap(), "2023-01-02T03:04:05Z");
+    }
+
+    #[test]
+    fn test_decode_payload_unknown_or_malformed() {
+        assert!(decode_payload(&Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![1])).is_none());
+        assert!(decode_payload(&Chunk::new(ChunkType::from_str("gAMA").unwrap(), vec![1])).is_none());
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt::Display;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::PngError, png::Png};
+
+// Something `repair` changed, with the byte offset in the original file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairAction {
+    RecomputedCrc { offset: usize, chunk_type: ChunkType },
+    DroppedChunk { offset: usize, reason: &'static str },
+    TruncatedAfterIend { offset: usize, bytes: usize },
+    AddedIend,
+    Reordered { violations: usize }
+}
+
+impl Display for RepairAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RepairAction::RecomputedCrc { offset, chunk_type } => write!(f, "recomputed the CRC of {} at offset {}", chunk_type, offset),
+            RepairAction::DroppedChunk { offset, reason } => write!(f, "dropped the chunk at offset {}: {}", offset, reason),
+            RepairAction::TruncatedAfterIend { offset, bytes } => write!(f, "removed {} bytes after IEND at offset {}", bytes, offset),
+            RepairAction::AddedIend => write!(f, "added the missing IEND chunk"),
+            RepairAction::Reordered { violations } => write!(f, "moved chunks into spec order ({} misplaced)", violations)
+        }
+    }
+}
+
+// Fixes what can be fixed without guessing at content: bad CRCs are
+// recomputed, misplaced chunks are moved, bytes after IEND are cut and a
+// missing IEND is added. Chunks that cannot be read at all, such as ones with
+// an invalid type or cut off by the end of the file, are only dropped when
+// `drop_unreadable` is set and they are not critical, otherwise they are an
+// error.
+pub fn repair(bytes: &[u8], drop_unreadable: bool) -> Result<(Png, Vec<RepairAction>), PngError> {
+    if !bytes.starts_with(&Png::STANDARD_HEADER) {
+        return Err(PngError::InvalidSignature);
+    }
+    let mut actions = Vec::new();
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut offset = Png::STANDARD_HEADER.len();
+    let unreadable = |offset: usize, chunk_index: usize, type_bytes: &[u8], reason: &'static str, actions: &mut Vec<RepairAction>| {
+        // An unreadable type might be a critical chunk with a flipped bit.
+        let critical = type_bytes.first().is_none_or(|byte| !byte.is_ascii_lowercase());
+        if !drop_unreadable || critical {
+            return Err(PngError::InvalidStructure { offset, chunk_index, reason });
+        }
+        actions.push(RepairAction::DroppedChunk { offset, reason });
+        Ok(())
+    };
+
+    while offset < bytes.len() {
+        let header = &bytes[offset..bytes.len().min(offset + 8)];
+        let end = header
+            .get(0..4)
+            .map(|length| offset + 12 + u32::from_be_bytes(length.try_into().unwrap()) as usize)
+            .filter(|end| header.len() == 8 && *end <= bytes.len());
+        let Some(end) = end else {
+            unreadable(offset, chunks.len(), header.get(4..).unwrap_or_default(), "chunk is cut off by the end of the file", &mut actions)?;
+            break;
+        };
+        let type_bytes: [u8; 4] = header[4..8].try_into().unwrap();
+        if !type_bytes.iter().all(u8::is_ascii_alphabetic) {
+            unreadable(offset, chunks.len(), &type_bytes, "chunk type is not four ASCII letters", &mut actions)?;
+            offset = end;
+            continue;
+        }
+        let chunk = Chunk::from_bytes_unchecked(&bytes[offset..end])?;
+        if chunk.crc() != chunk.computed_crc() {
+            actions.push(RepairAction::RecomputedCrc { offset, chunk_type: *chunk.chunk_type() });
+        }
+        let is_iend = type_bytes == *b"IEND";
+        chunks.push(Chunk::new(*chunk.chunk_type(), chunk.data().to_vec()));
+        offset = end;
+        if is_iend {
+            if offset < bytes.len() {
+                actions.push(RepairAction::TruncatedAfterIend { offset, bytes: bytes.len() - offset });
+            }
+            break;
+        }
+    }
+
+    if chunks.last().is_none_or(|chunk| chunk.chunk_type().bytes() != *b"IEND") {
+        chunks.push(Chunk::new(ChunkType(u32::from_be_bytes(*b"IEND")), Vec::new()));
+        actions.push(RepairAction::AddedIend);
+    }
+    let mut png = Png::from_chunks(chunks);
+    let violations = png.order_violations().len();
+    if violations > 0 {
+        png.normalize();
+        actions.push(RepairAction::Reordered { violations });
+    }
+    png.header()?;
+    Ok((png, actions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Vec<u8> {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).as_bytes()
+    }
+
+    fn testing_bytes(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend(chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]));
+        bytes.extend(chunks.concat());
+        bytes
+    }
+
+    #[test]
+    fn test_clean_file() {
+        let bytes = testing_bytes(&[chunk("IDAT", &[1]), chunk("IEND", &[])]);
+        let (png, actions) = repair(&bytes, false).unwrap();
+        assert!(actions.is_empty());
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_repairs() {
+        let mut bytes = testing_bytes(&[chunk("IDAT", &[1]), chunk("pHYs", &[0; 9]), chunk("IEND", &[]), b"junk".to_vec()]);
+        bytes[8 + 8] ^= 1;
+        let (png, actions) = repair(&bytes, false).unwrap();
+        assert_eq!(actions, [
+            RepairAction::RecomputedCrc { offset: 8, chunk_type: ChunkType::from_str("IHDR").unwrap() },
+            RepairAction::TruncatedAfterIend { offset: bytes.len() - 4, bytes: 4 },
+            RepairAction::Reordered { violations: 1 },
+        ]);
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "pHYs", "IDAT", "IEND"]);
+        assert!(png.validate_order().is_ok());
+        assert!(Png::try_from(png.as_bytes().as_slice()).is_ok());
+    }
+
+    #[test]
+    fn test_unreadable_chunks() {
+        let bytes = testing_bytes(&[chunk("IDAT", &[1]), chunk("tEXt", b"a\0b")]);
+        let cut = &bytes[..bytes.len() - 2];
+        assert!(repair(cut, false).is_err());
+        let (png, actions) = repair(cut, true).unwrap();
+        assert_eq!(actions, [
+            RepairAction::DroppedChunk { offset: 46, reason: "chunk is cut off by the end of the file" },
+            RepairAction::AddedIend,
+        ]);
+        assert_eq!(png.chunks().len(), 3);
+
+        let mut bytes = testing_bytes(&[chunk("IDAT", &[1]), chunk("tEXt", b"a\0b"), chunk("IEND", &[])]);
+        bytes[46 + 5] = b'1';
+        assert_eq!(repair(&bytes, true).unwrap().1, [RepairAction::DroppedChunk { offset: 46, reason: "chunk type is not four ASCII letters" }]);
+        // IDAT with a broken type might be critical, so it is never dropped.
+        bytes[33 + 5] = b'1';
+        assert!(repair(&bytes, true).is_err());
+    }
+}
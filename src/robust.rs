@@ -0,0 +1,208 @@
+use std::fmt::Display;
+
+use rand::Rng;
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    error::PngError,
+    lsb::{self, Carrier},
+    pixels,
+    png::Png,
+    spread::{self, Shard, SpreadManifest}
+};
+
+// Each frame bit is written this many times, once in each of as many equal
+// regions of the image, and read back by majority vote.
+const COPIES: usize = 5;
+// The payload is cut into Reed-Solomon shards, so half of them can be lost.
+const DATA_SHARDS: usize = 4;
+const PARITY_SHARDS: usize = 4;
+// Payload length, data and total shard counts, and the payload digest.
+const HEADER_LEN: usize = 4 + 1 + 1 + 32;
+// Each shard follows the first bytes of its blake3 digest, so a damaged
+// shard is known and dropped instead of spoiling the rebuild.
+const SHARD_CHECK_LEN: usize = 4;
+
+// Where `extract` found the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Chunk,
+    Pixels
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Chunk => write!(f, "chunk"),
+            Source::Pixels => write!(f, "pixels")
+        }
+    }
+}
+
+// Experimental. Stores `message` twice: in a `chunk_type` chunk, which must be
+// ancillary and safe to copy so optimizers that keep such chunks keep it, and
+// in the low bits of the pixels with repetition and Reed-Solomon coding, which
+// survives lossless re-encoding that drops every chunk or changes the color
+// type. Nothing survives lossy quantization.
+pub fn embed<R: Rng + ?Sized>(png: &Png, message: &[u8], chunk_type: ChunkType, rng: &mut R) -> Result<Png, PngError> {
+    if chunk_type.is_critical() || !chunk_type.is_safe_to_copy() {
+        return Err(PngError::InvalidArgument("robust messages need a safe-to-copy ancillary chunk type"));
+    }
+    let frame = frame(message)?;
+    let mut carrier = carrier(png)?;
+    let region = carrier.positions.len() / COPIES;
+    if frame.len() * 8 > region {
+        return Err(PngError::InvalidArgument("message and its error correction do not fit in the image's pixels"));
+    }
+    for (i, bit) in lsb::bits(&frame).enumerate() {
+        for copy in 0..COPIES {
+            let position = carrier.positions[copy * region + i];
+            carrier.set_bit(position, bit, rng);
+        }
+    }
+    let mut robust = carrier.into_png(png)?;
+    robust.append_chunk(Chunk::new(chunk_type, message.to_vec()));
+    Ok(robust)
+}
+
+// Reads a message hidden by `embed`, from the chunk if it is still there and
+// otherwise from the pixels.
+pub fn extract(png: &Png, chunk_type: ChunkType) -> Result<(Vec<u8>, Source), PngError> {
+    if let Some(chunk) = png.chunks().iter().find(|chunk| *chunk.chunk_type() == chunk_type) {
+        return Ok((chunk.data().to_vec(), Source::Chunk));
+    }
+    let image = pixels::decode_rgba(png)?;
+    // The file may have been re-encoded with another color type, so the bits
+    // are read from the RGBA pixels as a color image and as a grayscale one.
+    for channels in [3, 1] {
+        let bits: Vec<u8> = image
+            .pixels
+            .iter()
+            .filter(|pixel| pixel[3] != 0)
+            .flat_map(|pixel| pixel[..channels].iter().map(|sample| sample & 1))
+            .collect();
+        if let Some(message) = read_frame(&bits) {
+            return Ok((message, Source::Pixels));
+        }
+    }
+    Err(PngError::InvalidImageData("no robust message in the chunks or pixels"))
+}
+
+// The 8-bit color samples of the pixels that are not fully transparent, as
+// optimizers may change the color of those.
+fn carrier(png: &Png) -> Result<Carrier, PngError> {
+    let mut carrier = Carrier::new(png)?;
+    if carrier.raw.bit_depth != 8 {
+        return Err(PngError::InvalidArgument("robust messages need an 8-bit grayscale or truecolor image"));
+    }
+    if carrier.raw.color_type.has_alpha() {
+        let channels = carrier.raw.color_type.channels();
+        let data = &carrier.raw.data;
+        carrier.positions.retain(|position| data[position / channels * channels + channels - 1] != 0);
+    }
+    Ok(carrier)
+}
+
+// Layout: payload length, data and total shard counts, payload digest, then
+// each shard after its check bytes.
+fn frame(message: &[u8]) -> Result<Vec<u8>, PngError> {
+    let shards = spread::split(message, DATA_SHARDS, PARITY_SHARDS)?;
+    let manifest = shards[0].manifest;
+    let mut frame = manifest.payload_len.to_be_bytes().to_vec();
+    frame.extend([manifest.data_shards, manifest.total_shards]);
+    frame.extend(manifest.payload_digest);
+    for shard in shards {
+        frame.extend(&blake3::hash(&shard.data).as_bytes()[..SHARD_CHECK_LEN]);
+        frame.extend(shard.data);
+    }
+    Ok(frame)
+}
+
+fn read_frame(bits: &[u8]) -> Option<Vec<u8>> {
+    let region = bits.len() / COPIES;
+    let vote = |i: usize| ((0..COPIES).filter(|copy| bits[copy * region + i] == 1).count() * 2 > COPIES) as u8;
+    let read = |start: usize, len: usize| {
+        let bit_range = start.checked_mul(8)?..start.checked_add(len)?.checked_mul(8)?;
+        (bit_range.end <= region).then(|| lsb::from_bits(&bit_range.map(vote).collect::<Vec<u8>>()))
+    };
+    let header = read(0, HEADER_LEN)?;
+    let manifest = SpreadManifest {
+        payload_len: u32::from_be_bytes(header[0..4].try_into().unwrap()),
+        data_shards: header[4],
+        total_shards: header[5],
+        payload_digest: header[6..].try_into().unwrap()
+    };
+    if manifest.data_shards == 0 || manifest.total_shards < manifest.data_shards {
+        return None;
+    }
+    let record_len = SHARD_CHECK_LEN + (manifest.payload_len as usize).div_ceil(manifest.data_shards as usize).max(1);
+    let shards: Vec<Shard> = (0..manifest.total_shards)
+        .filter_map(|index| {
+            let record = read(HEADER_LEN + index as usize * record_len, record_len)?;
+            let (check, data) = record.split_at(SHARD_CHECK_LEN);
+            (blake3::hash(data).as_bytes()[..SHARD_CHECK_LEN] == *check).then(|| Shard { manifest, index, data: data.to_vec() })
+        })
+        .collect();
+    spread::join(&shards).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::str::FromStr;
+
+    fn testing_png(color_type: ColorType) -> Png {
+        let samples: Vec<u16> = (0..64 * 64 * color_type.channels()).map(|i| (i * 37 % 251) as u16 | 1).collect();
+        Png::from_samples(64, 64, color_type, 8, &samples).unwrap()
+    }
+
+    fn chunk_type() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    // What a lossless optimizer might do: drop every ancillary chunk and
+    // store the pixels with another color type.
+    fn reencode(png: &Png) -> Png {
+        let image = pixels::decode_rgba(png).unwrap();
+        let samples: Vec<u8> = image.pixels.iter().flatten().copied().collect();
+        Png::from_pixels(image.width, image.height, ColorType::Rgba, 8, &samples).unwrap()
+    }
+
+    #[test]
+    fn test_survives_reencoding() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for color_type in [ColorType::Rgb, ColorType::Grayscale, ColorType::Rgba] {
+            let robust = embed(&testing_png(color_type), b"meet at noon", chunk_type(), &mut rng).unwrap();
+            assert_eq!(extract(&robust, chunk_type()).unwrap(), (b"meet at noon".to_vec(), Source::Chunk));
+            assert_eq!(extract(&reencode(&robust), chunk_type()).unwrap(), (b"meet at noon".to_vec(), Source::Pixels));
+        }
+    }
+
+    #[test]
+    fn test_survives_damaged_pixels() {
+        let robust = embed(&testing_png(ColorType::Rgb), b"meet at noon", chunk_type(), &mut StdRng::seed_from_u64(2)).unwrap();
+        let mut image = pixels::decode_rgba(&robust).unwrap();
+        // Two of the five copies of the frame are wiped out entirely.
+        let region = image.pixels.len() / COPIES;
+        for pixel in image.pixels[..2 * region].iter_mut() {
+            *pixel = [0, 0, 0, 255];
+        }
+        let samples: Vec<u8> = image.pixels.iter().flatten().copied().collect();
+        let damaged = Png::from_pixels(image.width, image.height, ColorType::Rgba, 8, &samples).unwrap();
+        assert_eq!(extract(&damaged, chunk_type()).unwrap().0, b"meet at noon");
+    }
+
+    #[test]
+    fn test_requirements() {
+        let png = testing_png(ColorType::Rgb);
+        let mut rng = StdRng::seed_from_u64(3);
+        assert!(embed(&png, b"x", ChunkType::from_str("ruST").unwrap(), &mut rng).is_err());
+        assert!(embed(&png, &[0; 1000], chunk_type(), &mut rng).is_err());
+        assert!(extract(&png, chunk_type()).is_err());
+        let deep = Png::from_samples(48, 48, ColorType::Rgb, 16, &[300; 48 * 48 * 3]).unwrap();
+        assert!(embed(&deep, b"x", chunk_type(), &mut rng).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{
+    error::PngError,
+    filter::{self, FilterStrategy},
+    ihdr::Ihdr,
+    optimize,
+    pixels,
+    png::Png,
+    zlib
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleFilter {
+    // Every pixel becomes a factor x factor block.
+    Nearest,
+    // Scale2x/Scale3x edge smoothing for pixel art. Factor 4 is Scale2x twice.
+    Scale2x
+}
+
+// Upscales `png` by an integer factor without blending colors, so the result
+// keeps the original color type and bit depth and indexed images stay indexed.
+pub fn scale(png: &Png, factor: u32, scale_filter: ScaleFilter) -> Result<Png, PngError> {
+    let ihdr = png.header()?;
+    if factor == 0 {
+        return Err(PngError::InvalidArgument("scale factor must be at least 1"));
+    }
+    if scale_filter == ScaleFilter::Scale2x && !matches!(factor, 2..=4) {
+        return Err(PngError::InvalidArgument("scale2x only supports factors 2, 3 and 4"));
+    }
+    let (width, height) = match (ihdr.width.checked_mul(factor), ihdr.height.checked_mul(factor)) {
+        (Some(width), Some(height)) => (width, height),
+        _ => return Err(PngError::InvalidArgument("scaled image is too large"))
+    };
+    let scaled_ihdr = Ihdr::new(width, height, ihdr.bit_depth, ihdr.color_type)?;
+
+    let raw = pixels::decode_raw(png)?.data;
+    let image = Grid { width: ihdr.width as usize, height: ihdr.height as usize, pixels: unpack(&raw, &ihdr) };
+    let scaled = match (scale_filter, factor) {
+        (ScaleFilter::Nearest, _) => image.nearest(factor as usize),
+        (ScaleFilter::Scale2x, 2) => image.scale2x(),
+        (ScaleFilter::Scale2x, 3) => image.scale3x(),
+        _ => image.scale2x().scale2x()
+    };
+
+    let raw = pack(&scaled, &scaled_ihdr);
+    let strategy = FilterStrategy::for_image(&scaled_ihdr);
+    let filtered = filter::filter_with(&raw, scaled_ihdr.filter_bpp(), scaled_ihdr.row_len(width), strategy);
+    let mut scaled_png = optimize::replace_image_data(png, zlib::deflate(&filtered, 9)?);
+    scaled_png.replace_chunk(scaled_ihdr.into());
+    Ok(scaled_png)
+}
+
+// Pixels as opaque values: the packed bytes of each pixel, or the sample
+// itself below 8 bits. Scaling only ever compares and copies them.
+struct Grid {
+    width: usize,
+    height: usize,
+    pixels: Vec<u64>
+}
+
+impl Grid {
+    fn at(&self, x: isize, y: isize) -> u64 {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    fn nearest(&self, factor: usize) -> Grid {
+        let width = self.width * factor;
+        let pixels = (0..self.height * factor)
+            .flat_map(|y| (0..width).map(move |x| (x / factor, y / factor)))
+            .map(|(x, y)| self.pixels[y * self.width + x])
+            .collect();
+        Grid { width, height: self.height * factor, pixels }
+    }
+
+    fn scale2x(&self) -> Grid {
+        let width = self.width * 2;
+        let mut pixels = vec![0; width * self.height * 2];
+        for y in 0..self.height as isize {
+            for x in 0..self.width as isize {
+                let (b, d, e, f, h) = (self.at(x, y - 1), self.at(x - 1, y), self.at(x, y), self.at(x + 1, y), self.at(x, y + 1));
+                let block = if b != h && d != f {
+                    [
+                        if d == b { d } else { e },
+                        if b == f { f } else { e },
+                        if d == h { d } else { e },
+                        if h == f { f } else { e }
+                    ]
+                } else {
+                    [e; 4]
+                };
+                let (x, y) = (x as usize * 2, y as usize * 2);
+                pixels[y * width + x] = block[0];
+                pixels[y * width + x + 1] = block[1];
+                pixels[(y + 1) * width + x] = block[2];
+                pixels[(y + 1) * width + x + 1] = block[3];
+            }
+        }
+        Grid { width, height: self.height * 2, pixels }
+    }
+
+    fn scale3x(&self) -> Grid {
+        let width = self.width * 3;
+        let mut pixels = vec![0; width * self.height * 3];
+        for y in 0..self.height as isize {
+            for x in 0..self.width as isize {
+                let [a, b, c, d, e, f, g, h, i] = [
+                    self.at(x - 1, y - 1), self.at(x, y - 1), self.at(x + 1, y - 1),
+                    self.at(x - 1, y), self.at(x, y), self.at(x + 1, y),
+                    self.at(x - 1, y + 1), self.at(x, y + 1), self.at(x + 1, y + 1)
+                ];
+                let block = if b != h && d != f {
+                    [
+                        if d == b { d } else { e },
+                        if (d == b && e != c) || (b == f && e != a) { b } else { e },
+                        if b == f { f } else { e },
+                        if (d == b && e != g) || (d == h && e != a) { d } else { e },
+                        e,
+                        if (b == f && e != i) || (h == f && e != c) { f } else { e },
+                        if d == h { d } else { e },
+                        if (d == h && e != i) || (h == f && e != g) { h } else { e },
+                        if h == f { f } else { e }
+                    ]
+                } else {
+                    [e; 9]
+                };
+                let (x, y) = (x as usize * 3, y as usize * 3);
+                for (n, value) in block.into_iter().enumerate() {
+                    pixels[(y + n / 3) * width + x + n % 3] = value;
+                }
+            }
+        }
+        Grid { width, height: self.height * 3, pixels }
+    }
+}
+
+fn unpack(raw: &[u8], ihdr: &Ihdr) -> Vec<u64> {
+    let row_len = ihdr.row_len(ihdr.width);
+    let pixel_len = ihdr.bits_per_pixel() / 8;
+    let mut pixels = Vec::with_capacity(ihdr.width as usize * ihdr.height as usize);
+    for row in raw.chunks(row_len) {
+        for x in 0..ihdr.width as usize {
+            let value = if ihdr.bit_depth < 8 {
+                pixels::sample(row, x, ihdr.bit_depth) as u64
+            } else {
+                row[x * pixel_len..(x + 1) * pixel_len]
+                    .iter()
+                    .fold(0, |value, byte| value << 8 | *byte as u64)
+            };
+            pixels.push(value);
+        }
+    }
+    pixels
+}
+
+fn pack(grid: &Grid, ihdr: &Ihdr) -> Vec<u8> {
+    let row_len = ihdr.row_len(ihdr.width);
+    let bits = ihdr.bits_per_pixel();
+    let mut raw = vec![0u8; row_len * grid.height];
+    for (y, row) in raw.chunks_mut(row_len).enumerate() {
+        for x in 0..grid.width {
+            let value = grid.pixels[y * grid.width + x];
+            if bits < 8 {
+                row[x * bits / 8] |= (value as u8) << (8 - bits - x * bits % 8);
+            } else {
+                let pixel_len = bits / 8;
+                row[x * pixel_len..(x + 1) * pixel_len].copy_from_slice(&value.to_be_bytes()[8 - pixel_len..]);
+            }
+        }
+    }
+    raw
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType, filter::FilterType, ihdr::ColorType};
+
+    fn testing_png(ihdr: Ihdr, raw: &[u8], extra: Vec<Chunk>) -> Png {
+        let filtered = filter::filter(raw, ihdr.filter_bpp(), ihdr.row_len(ihdr.width), FilterType::None);
+        let mut chunks = vec![ihdr.into()];
+        chunks.extend(extra);
+        chunks.push(Chunk::new(ChunkType(u32::from_be_bytes(*b"IDAT")), zlib::deflate(&filtered, 6).unwrap()));
+        chunks.push(Chunk::new(ChunkType(u32::from_be_bytes(*b"IEND")), Vec::new()));
+        Png::from_chunks(chunks)
+    }
+
+    fn raw_pixels(png: &Png) -> Vec<u8> {
+        let ihdr = png.header().unwrap();
+        filter::unfilter(&zlib::inflate(&png.image_data()).unwrap(), ihdr.filter_bpp(), ihdr.row_len(ihdr.width)).unwrap()
+    }
+
+    #[test]
+    fn test_nearest_rgb() {
+        let png = testing_png(Ihdr::new(2, 1, 8, ColorType::Rgb).unwrap(), &[1, 2, 3, 4, 5, 6], Vec::new());
+        let scaled = scale(&png, 2, ScaleFilter::Nearest).unwrap();
+        assert_eq!((scaled.header().unwrap().width, scaled.header().unwrap().height), (4, 2));
+        assert_eq!(raw_pixels(&scaled), [1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6].repeat(2));
+    }
+
+    #[test]
+    fn test_nearest_keeps_packed_indexed() {
+        let plte = Chunk::new(ChunkType(u32::from_be_bytes(*b"PLTE")), vec![0, 0, 0, 255, 255, 255]);
+        let png = testing_png(Ihdr::new(3, 1, 1, ColorType::Indexed).unwrap(), &[0b1010_0000], vec![plte]);
+        let scaled = scale(&png, 3, ScaleFilter::Nearest).unwrap();
+        let ihdr = scaled.header().unwrap();
+        assert_eq!((ihdr.color_type, ihdr.bit_depth, ihdr.width), (ColorType::Indexed, 1, 9));
+        assert_eq!(raw_pixels(&scaled), [0b1110_0011, 0b1000_0000].repeat(3));
+        assert!(scaled.chunk_by_type("PLTE").is_some());
+    }
+
+    #[test]
+    fn test_nearest_interlaced() {
+        // 2x2 gray: Adam7 passes 1, 6 and 7 hold (0, 0), (1, 0) and row 1.
+        let mut ihdr = Ihdr::new(2, 2, 8, ColorType::Grayscale).unwrap();
+        ihdr.interlaced = true;
+        let png = Png::from_chunks(vec![
+            ihdr.into(),
+            Chunk::new(ChunkType(u32::from_be_bytes(*b"IDAT")), zlib::deflate(&[0, 1, 0, 2, 0, 3, 4], 6).unwrap()),
+            Chunk::new(ChunkType(u32::from_be_bytes(*b"IEND")), Vec::new()),
+        ]);
+        let scaled = scale(&png, 2, ScaleFilter::Nearest).unwrap();
+        assert!(!scaled.header().unwrap().interlaced);
+        assert_eq!(raw_pixels(&scaled), [1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]);
+    }
+
+    #[test]
+    fn test_scale2x_smooths_diagonal() {
+        let png = testing_png(Ihdr::new(2, 2, 8, ColorType::Grayscale).unwrap(), &[9, 0, 0, 9], Vec::new());
+        let scaled = scale(&png, 2, ScaleFilter::Scale2x).unwrap();
+        assert_eq!(raw_pixels(&scaled), [9, 9, 0, 0, 9, 0, 9, 0, 0, 9, 0, 9, 0, 0, 9, 9]);
+    }
+
+    #[test]
+    fn test_scale2x_flat_image_matches_nearest() {
+        let png = testing_png(Ihdr::new(2, 2, 8, ColorType::Grayscale).unwrap(), &[7; 4], Vec::new());
+        for factor in [2, 3, 4] {
+            let smooth = scale(&png, factor, ScaleFilter::Scale2x).unwrap();
+            let nearest = scale(&png, factor, ScaleFilter::Nearest).unwrap();
+            assert_eq!(raw_pixels(&smooth), raw_pixels(&nearest));
+        }
+    }
+
+    #[test]
+    fn test_invalid_factor() {
+        let png = testing_png(Ihdr::new(1, 1, 8, ColorType::Grayscale).unwrap(), &[0], Vec::new());
+        assert!(scale(&png, 0, ScaleFilter::Nearest).is_err());
+        assert!(scale(&png, 5, ScaleFilter::Scale2x).is_err());
+        assert!(scale(&png, u32::MAX, ScaleFilter::Nearest).is_err());
+    }
+}
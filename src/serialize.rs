@@ -0,0 +1,132 @@
+use std::fmt;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{
+    de::{self, MapAccess, Visitor},
+    ser::SerializeStruct,
+    Deserialize, Deserializer, Serialize, Serializer
+};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png};
+
+// A chunk type is its four ASCII letters, e.g. "IHDR".
+impl Serialize for ChunkType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for ChunkType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        let bytes: [u8; 4] = name
+            .as_bytes()
+            .try_into()
+            .map_err(|_| de::Error::invalid_length(name.len(), &"a chunk type of 4 letters"))?;
+        let chunk_type = ChunkType(u32::from_be_bytes(bytes));
+        if !chunk_type.is_valid() {
+            return Err(de::Error::invalid_value(de::Unexpected::Str(&name), &"a chunk type of 4 ASCII letters"));
+        }
+        Ok(chunk_type)
+    }
+}
+
+// A chunk is its type and base64 data. The length and CRC follow from the
+// data, so they are left out and recomputed when read back.
+impl Serialize for Chunk {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut chunk = serializer.serialize_struct("Chunk", 2)?;
+        chunk.serialize_field("type", self.chunk_type())?;
+        chunk.serialize_field("data", &STANDARD.encode(self.data()))?;
+        chunk.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Chunk {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("Chunk", &["type", "data"], ChunkVisitor)
+    }
+}
+
+struct ChunkVisitor;
+
+impl<'de> Visitor<'de> for ChunkVisitor {
+    type Value = Chunk;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a chunk with a type and base64 data")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Chunk, A::Error> {
+        let (mut chunk_type, mut data) = (None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "type" if chunk_type.is_none() => chunk_type = Some(map.next_value::<ChunkType>()?),
+                "data" if data.is_none() => {
+                    let encoded = map.next_value::<String>()?;
+                    data = Some(STANDARD.decode(&encoded).map_err(|_| de::Error::invalid_value(de::Unexpected::Str(&encoded), &"base64 data"))?);
+                },
+                "type" | "data" => return Err(de::Error::duplicate_field("type or data")),
+                _ => return Err(de::Error::unknown_field(&key, &["type", "data"]))
+            }
+        }
+        let chunk_type = chunk_type.ok_or_else(|| de::Error::missing_field("type"))?;
+        let data = data.ok_or_else(|| de::Error::missing_field("data"))?;
+        Ok(Chunk::new(chunk_type, data))
+    }
+}
+
+// A PNG is its chunks in file order; the signature is always the standard one.
+impl Serialize for Png {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut png = serializer.serialize_struct("Png", 1)?;
+        png.serialize_field("chunks", self.chunks())?;
+        png.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Png {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Chunks {
+            chunks: Vec<Chunk>
+        }
+        Chunks::deserialize(deserializer).map(|png| Png::from_chunks(png.chunks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_chunk_type_as_string() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        assert_eq!(serde_json::to_string(&chunk_type).unwrap(), "\"ruSt\"");
+        assert_eq!(serde_json::from_str::<ChunkType>("\"ruSt\"").unwrap(), chunk_type);
+        assert!(serde_json::from_str::<ChunkType>("\"rust!\"").is_err());
+        assert!(serde_json::from_str::<ChunkType>("\"ru1t\"").is_err());
+    }
+
+    #[test]
+    fn test_chunk_data_as_base64() {
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"a\0b".to_vec());
+        let json = serde_json::to_string(&chunk).unwrap();
+        assert_eq!(json, r#"{"type":"tEXt","data":"YQBi"}"#);
+        let read: Chunk = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.as_bytes(), chunk.as_bytes());
+        assert!(serde_json::from_str::<Chunk>(r#"{"type":"tEXt","data":"not base64!"}"#).is_err());
+        assert!(serde_json::from_str::<Chunk>(r#"{"type":"tEXt"}"#).is_err());
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let png = Png::from_pixels(2, 1, crate::ihdr::ColorType::Rgb, 8, &[1, 2, 3, 4, 5, 6]).unwrap();
+        let json = serde_json::to_string(&png).unwrap();
+        assert!(json.starts_with(r#"{"chunks":[{"type":"IHDR","data":"#));
+        let read: Png = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.as_bytes(), png.as_bytes());
+    }
+}
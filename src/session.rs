@@ -0,0 +1,390 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter, Cursor, IsTerminal, Read, Write},
+    rc::Rc
+};
+
+use crate::{
+    context::Context,
+    error::PngError,
+    optimize,
+    png::{ParseMode, ParseOptions, Png},
+    policy::KeywordPolicy,
+    text,
+    zlib::CompressionLevel
+};
+
+// How files that are modified are read and written back.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WriteOptions {
+    pub stamp_software: bool,
+    pub refresh_time: bool,
+    pub parse_mode: ParseMode,
+    // Re-deflate the image data at this level before writing.
+    pub compression: Option<CompressionLevel>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    // Files larger than this are refused before they are read.
+    pub max_input_bytes: u64,
+    // Images with more pixels than this are refused before they are decoded.
+    pub max_pixels: u64
+}
+
+// Where a session reads and writes files.
+pub trait Io {
+    fn open(&self, path: &str) -> io::Result<Box<dyn Read>>;
+    fn len(&self, path: &str) -> io::Result<u64>;
+    fn write(&mut self, path: &str, data: &[u8]) -> io::Result<()>;
+    // Paths matching `pattern`, in any order.
+    fn glob(&self, pattern: &glob::Pattern) -> io::Result<Vec<String>>;
+    // Parses `path` where it is, without reading it into memory first, for
+    // an Io that can. `None` to read it with `open` instead.
+    fn parse_in_place(&self, _path: &str, _options: &ParseOptions) -> Option<Result<Png, PngError>> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsIo;
+
+// Files kept in memory, shared between clones so a test can look at what a
+// session wrote.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryIo {
+    files: Rc<RefCell<BTreeMap<String, Vec<u8>>>>
+}
+
+// Output kept in memory, shared between clones like `MemoryIo`.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLog {
+    output: Rc<RefCell<Vec<u8>>>
+}
+
+// Everything a command needs besides its arguments: how to read and write
+// files, where to report, and the random source.
+pub struct Session {
+    pub options: WriteOptions,
+    pub limits: Limits,
+    pub context: Context,
+    pub logger: Box<dyn Write>,
+    // Set by checks that fail without an error, e.g. `verify --strong`.
+    pub failed: bool,
+    // Whether the logger is a terminal that shows ANSI colors.
+    pub color: bool,
+    // Checked against every text chunk of a file before it is written.
+    pub keyword_policy: KeywordPolicy,
+    io: Box<dyn Io>
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits { max_input_bytes: u64::MAX, max_pixels: u64::MAX }
+    }
+}
+
+// "-" is standard input when reading and standard output when writing.
+impl Io for FsIo {
+    fn open(&self, path: &str) -> io::Result<Box<dyn Read>> {
+        match path {
+            "-" => Ok(Box::new(io::stdin())),
+            _ => Ok(Box::new(File::open(path)?))
+        }
+    }
+
+    // The length of standard input is not known up front, so it is reported
+    // as 0 and only files are checked against the input limit.
+    fn len(&self, path: &str) -> io::Result<u64> {
+        match path {
+            "-" => Ok(0),
+            _ => Ok(fs::metadata(path)?.len())
+        }
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        let mut writer: Box<dyn Write> = match path {
+            "-" => Box::new(io::stdout().lock()),
+            _ => Box::new(BufWriter::new(File::create(path)?))
+        };
+        writer.write_all(data)?;
+        writer.flush()
+    }
+
+    fn glob(&self, pattern: &glob::Pattern) -> io::Result<Vec<String>> {
+        let paths = glob::glob(pattern.as_str()).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        paths
+            .map(|path| path.map(|path| path.to_string_lossy().into_owned()).map_err(io::Error::from))
+            .collect()
+    }
+
+    #[cfg(feature = "mmap")]
+    fn parse_in_place(&self, path: &str, options: &ParseOptions) -> Option<Result<Png, PngError>> {
+        (path != "-").then(|| Png::from_mmap_with_options(path, options))
+    }
+}
+
+impl MemoryIo {
+    pub fn new() -> MemoryIo {
+        MemoryIo::default()
+    }
+
+    pub fn insert(&self, path: &str, data: Vec<u8>) {
+        self.files.borrow_mut().insert(path.to_string(), data);
+    }
+
+    pub fn get(&self, path: &str) -> Option<Vec<u8>> {
+        self.files.borrow().get(path).cloned()
+    }
+
+    fn not_found(path: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::NotFound, format!("{}: no such file", path))
+    }
+}
+
+impl Io for MemoryIo {
+    fn open(&self, path: &str) -> io::Result<Box<dyn Read>> {
+        let data = self.get(path).ok_or_else(|| MemoryIo::not_found(path))?;
+        Ok(Box::new(Cursor::new(data)))
+    }
+
+    fn len(&self, path: &str) -> io::Result<u64> {
+        self.files.borrow().get(path).map(|data| data.len() as u64).ok_or_else(|| MemoryIo::not_found(path))
+    }
+
+    fn write(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        self.insert(path, data.to_vec());
+        Ok(())
+    }
+
+    fn glob(&self, pattern: &glob::Pattern) -> io::Result<Vec<String>> {
+        Ok(self.files.borrow().keys().filter(|path| pattern.matches(path)).cloned().collect())
+    }
+}
+
+impl MemoryLog {
+    pub fn new() -> MemoryLog {
+        MemoryLog::default()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.output.borrow()).into_owned()
+    }
+}
+
+impl Write for MemoryLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Session {
+    pub fn new(io: impl Io + 'static, logger: impl Write + 'static) -> Session {
+        Session {
+            options: WriteOptions::default(),
+            limits: Limits::default(),
+            context: Context::new(),
+            logger: Box::new(logger),
+            failed: false,
+            color: false,
+            keyword_policy: KeywordPolicy::default(),
+            io: Box::new(io)
+        }
+    }
+
+    // A session on the file system that reports to stdout.
+    pub fn stdio() -> Session {
+        let mut session = Session::new(FsIo, io::stdout());
+        session.color = io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+        session
+    }
+
+    pub fn open(&self, path: &str) -> Result<Box<dyn Read>, PngError> {
+        self.check_input_limit(path)?;
+        Ok(self.io.open(path)?)
+    }
+
+    fn check_input_limit(&self, path: &str) -> Result<(), PngError> {
+        if self.io.len(path)? > self.limits.max_input_bytes {
+            return Err(PngError::InvalidArgument("file is larger than the input limit"));
+        }
+        Ok(())
+    }
+
+    // A file without a readable header is left for the parse or decode to
+    // report.
+    pub fn check_pixel_limit(&self, png: &Png) -> Result<(), PngError> {
+        match png.header() {
+            Ok(header) if header.width as u64 * header.height as u64 > self.limits.max_pixels => {
+                Err(PngError::InvalidArgument("image has more pixels than the pixel limit"))
+            },
+            _ => Ok(())
+        }
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, PngError> {
+        let mut data = Vec::new();
+        self.open(path)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    pub fn read_to_string(&self, path: &str) -> Result<String, PngError> {
+        Ok(String::from_utf8(self.read(path)?)?)
+    }
+
+    pub fn write(&mut self, path: &str, data: &[u8]) -> Result<(), PngError> {
+        Ok(self.io.write(path, data)?)
+    }
+
+    pub fn read_png(&self, path: &str) -> Result<Png, PngError> {
+        self.read_png_with(path, &ParseOptions::default())
+    }
+
+    // Files are memory-mapped with the `mmap` feature, so the chunks that
+    // `options` skips are never read.
+    pub fn read_png_with(&self, path: &str, options: &ParseOptions) -> Result<Png, PngError> {
+        self.check_input_limit(path)?;
+        let png = match self.io.parse_in_place(path, options) {
+            Some(png) => png?,
+            None => Png::from_bytes_with_options(&self.read(path)?, options)?
+        };
+        self.check_pixel_limit(&png)?;
+        Ok(png)
+    }
+
+    // Reads a file that is about to be modified and written back out.
+    pub fn edit_png(&self, path: &str) -> Result<Png, PngError> {
+        let mut png = Png::from_bytes_with(&self.read(path)?, self.options.parse_mode)?;
+        self.check_pixel_limit(&png)?;
+        png.set_refresh_time(self.options.refresh_time);
+        Ok(png)
+    }
+
+    pub fn write_png(&mut self, png: &mut Png, path: &str) -> Result<(), PngError> {
+        self.write_png_with(png, path, self.options)
+    }
+
+    pub fn write_png_with(&mut self, png: &mut Png, path: &str, options: WriteOptions) -> Result<(), PngError> {
+        if let Some(level) = options.compression {
+            *png = optimize::recompress(png, level)?;
+        }
+        if options.stamp_software {
+            png.set_software(text::SOFTWARE)?;
+        }
+        self.keyword_policy.check(png)?;
+        self.write(path, &png.as_bytes())
+    }
+
+    // Files matching a glob pattern, in sorted order.
+    pub fn expand_pattern(&self, pattern: &str) -> Result<Vec<String>, PngError> {
+        let pattern = glob::Pattern::new(pattern).map_err(|_| PngError::InvalidArgument("invalid glob pattern"))?;
+        let mut files = self.io.glob(&pattern)?;
+        if files.is_empty() {
+            return Err(PngError::InvalidArgument("pattern does not match any files"));
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType, ihdr::ColorType, png::FilterAction};
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        Png::from_pixels(1, 1, ColorType::Grayscale, 8, &[7]).unwrap()
+    }
+
+    #[test]
+    fn test_memory_io_round_trip() {
+        let io = MemoryIo::new();
+        let mut session = Session::new(io.clone(), MemoryLog::new());
+        session.options.stamp_software = true;
+        let mut png = testing_png();
+        session.write_png(&mut png, "a.png").unwrap();
+        assert_eq!(io.get("a.png").unwrap(), png.as_bytes());
+        assert_eq!(session.read_png("a.png").unwrap().software().as_deref(), Some(text::SOFTWARE));
+        assert!(matches!(session.read_png("missing.png"), Err(PngError::Io(_))));
+    }
+
+    #[test]
+    fn test_edit_png_applies_options() {
+        let io = MemoryIo::new();
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(ChunkType::from_str("gAMA").unwrap(), vec![0, 0, 177, 143]));
+        io.insert("a.png", png.as_bytes());
+        let mut session = Session::new(io, MemoryLog::new());
+        session.options.parse_mode = ParseMode::Normalize;
+        let types: Vec<String> = session.edit_png("a.png").unwrap().chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "gAMA", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let io = MemoryIo::new();
+        io.insert("a.png", testing_png().as_bytes());
+        let mut session = Session::new(io, MemoryLog::new());
+        session.limits.max_input_bytes = 10;
+        assert!(matches!(session.read("a.png"), Err(PngError::InvalidArgument(_))));
+        session.limits = Limits { max_pixels: 0, ..Limits::default() };
+        assert!(session.read("a.png").is_ok());
+        assert!(matches!(session.read_png("a.png"), Err(PngError::InvalidArgument(_))));
+        assert!(matches!(session.edit_png("a.png"), Err(PngError::InvalidArgument(_))));
+        session.limits.max_pixels = 1;
+        assert!(session.read_png("a.png").is_ok());
+    }
+
+    #[test]
+    fn test_read_png_with_options() {
+        let io = MemoryIo::new();
+        io.insert("a.png", testing_png().as_bytes());
+        let session = Session::new(io, MemoryLog::new());
+        let options = ParseOptions::new().chunk_filter(|header| match &header.chunk_type.bytes() {
+            b"IDAT" => FilterAction::Skip,
+            _ => FilterAction::Keep
+        });
+        let png = session.read_png_with("a.png", &options).unwrap();
+        assert!(png.chunk_by_type("IHDR").is_some());
+        assert!(png.chunk_by_type("IDAT").is_none());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_read_png_through_mmap() {
+        let path = std::env::temp_dir().join(format!("png-mmap-{}.png", std::process::id()));
+        testing_png().to_file(&path).unwrap();
+        let session = Session::stdio();
+        let png = session.read_png(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(png.as_bytes(), testing_png().as_bytes());
+    }
+
+    #[test]
+    fn test_expand_pattern() {
+        let io = MemoryIo::new();
+        for path in ["b.png", "a.png", "c.txt"] {
+            io.insert(path, Vec::new());
+        }
+        let session = Session::new(io, MemoryLog::new());
+        assert_eq!(session.expand_pattern("*.png").unwrap(), ["a.png", "b.png"]);
+        assert!(session.expand_pattern("*.gif").is_err());
+        assert!(session.expand_pattern("[").is_err());
+    }
+
+    #[test]
+    fn test_memory_log() {
+        let log = MemoryLog::new();
+        let mut session = Session::new(MemoryIo::new(), log.clone());
+        writeln!(session.logger, "hello").unwrap();
+        assert_eq!(log.contents(), "hello\n");
+    }
+}
@@ -0,0 +1,175 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{chunk::Chunk, chunk_type::{ChunkType, ChunkTypeSet}, error::PngError, ihdr::{ColorType, Ihdr}, png::Png};
+
+// sBIT: how many bits of each sample were significant in the source data,
+// e.g. 5/6/5 for an image that came from a 16-bit framebuffer. The layout
+// depends on the IHDR color type; indexed images describe the palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignificantBits {
+    Gray(u8),
+    Rgb(u8, u8, u8),
+    GrayAlpha(u8, u8),
+    Rgba(u8, u8, u8, u8)
+}
+
+impl SignificantBits {
+    pub fn parse(chunk: &Chunk, color_type: ColorType) -> Result<SignificantBits, PngError> {
+        if chunk.chunk_type().bytes() != *b"sBIT" {
+            return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+        }
+        match (color_type, chunk.data()) {
+            (ColorType::Grayscale, [gray]) => Ok(SignificantBits::Gray(*gray)),
+            (ColorType::Rgb | ColorType::Indexed, [r, g, b]) => Ok(SignificantBits::Rgb(*r, *g, *b)),
+            (ColorType::GrayscaleAlpha, [gray, alpha]) => Ok(SignificantBits::GrayAlpha(*gray, *alpha)),
+            (ColorType::Rgba, [r, g, b, a]) => Ok(SignificantBits::Rgba(*r, *g, *b, *a)),
+            _ => Err(invalid("wrong number of bytes for the color type"))
+        }
+    }
+
+    pub fn values(&self) -> Vec<u8> {
+        match *self {
+            SignificantBits::Gray(gray) => vec![gray],
+            SignificantBits::Rgb(r, g, b) => vec![r, g, b],
+            SignificantBits::GrayAlpha(gray, alpha) => vec![gray, alpha],
+            SignificantBits::Rgba(r, g, b, a) => vec![r, g, b, a]
+        }
+    }
+
+    // Whether this kind of sBIT fits an image of `color_type`.
+    pub fn matches(&self, color_type: ColorType) -> bool {
+        matches!(
+            (self, color_type),
+            (SignificantBits::Gray(_), ColorType::Grayscale)
+                | (SignificantBits::Rgb(..), ColorType::Rgb | ColorType::Indexed)
+                | (SignificantBits::GrayAlpha(..), ColorType::GrayscaleAlpha)
+                | (SignificantBits::Rgba(..), ColorType::Rgba)
+        )
+    }
+
+    // The same values for the image once its alpha channel is dropped.
+    pub fn without_alpha(&self) -> SignificantBits {
+        match *self {
+            SignificantBits::GrayAlpha(gray, _) => SignificantBits::Gray(gray),
+            SignificantBits::Rgba(r, g, b, _) => SignificantBits::Rgb(r, g, b),
+            other => other
+        }
+    }
+
+    // Every value must be between 1 and the sample depth, which is 8 for palettes.
+    fn validate(&self, ihdr: &Ihdr) -> Result<(), PngError> {
+        if !self.matches(ihdr.color_type) {
+            return Err(invalid("does not match the image color type"));
+        }
+        let depth = match ihdr.color_type {
+            ColorType::Indexed => 8,
+            _ => ihdr.bit_depth
+        };
+        if self.values().iter().any(|bits| *bits == 0 || *bits > depth) {
+            return Err(invalid("significant bits must be between 1 and the sample depth"));
+        }
+        Ok(())
+    }
+}
+
+impl From<SignificantBits> for Chunk {
+    fn from(value: SignificantBits) -> Self {
+        Chunk::new(ChunkType::from_str("sBIT").unwrap(), value.values())
+    }
+}
+
+impl Display for SignificantBits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: &[&str] = match self {
+            SignificantBits::Gray(_) => &["gray"],
+            SignificantBits::Rgb(..) => &["r", "g", "b"],
+            SignificantBits::GrayAlpha(..) => &["gray", "a"],
+            SignificantBits::Rgba(..) => &["r", "g", "b", "a"]
+        };
+        let parts: Vec<String> = names.iter().zip(self.values()).map(|(name, bits)| format!("{}{}", name, bits)).collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl Png {
+    pub fn significant_bits(&self) -> Result<Option<SignificantBits>, PngError> {
+        match self.chunk_by_type("sBIT") {
+            Some(chunk) => SignificantBits::parse(chunk, self.header()?.color_type).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    // Replaces the sBIT chunk, or inserts one before PLTE and the image data.
+    pub fn set_significant_bits(&mut self, significant_bits: SignificantBits) -> Result<(), PngError> {
+        significant_bits.validate(&self.header()?)?;
+        self.place_chunk(significant_bits.into(), &ChunkTypeSet::of(&[b"PLTE", b"IDAT"]));
+        Ok(())
+    }
+
+    pub fn remove_significant_bits(&mut self) -> Option<Chunk> {
+        self.remove_chunk("sBIT").ok()
+    }
+}
+
+fn invalid(reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: String::from("sBIT"), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::{Palette, Rgb};
+
+    fn testing_png(bit_depth: u8, color_type: ColorType) -> Png {
+        Png::from_chunks(vec![
+            Ihdr::new(1, 1, bit_depth, color_type).unwrap().into(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_parse_by_color_type() {
+        let chunk = Chunk::new(ChunkType::from_str("sBIT").unwrap(), vec![5, 6, 5]);
+        assert_eq!(SignificantBits::parse(&chunk, ColorType::Rgb).unwrap(), SignificantBits::Rgb(5, 6, 5));
+        assert_eq!(SignificantBits::parse(&chunk, ColorType::Indexed).unwrap(), SignificantBits::Rgb(5, 6, 5));
+        assert!(SignificantBits::parse(&chunk, ColorType::Rgba).is_err());
+        assert!(SignificantBits::parse(&chunk, ColorType::Grayscale).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for (significant_bits, color_type) in [
+            (SignificantBits::Gray(3), ColorType::Grayscale),
+            (SignificantBits::GrayAlpha(12, 1), ColorType::GrayscaleAlpha),
+            (SignificantBits::Rgba(5, 5, 5, 1), ColorType::Rgba)
+        ] {
+            let chunk: Chunk = significant_bits.into();
+            assert_eq!(SignificantBits::parse(&chunk, color_type).unwrap(), significant_bits);
+        }
+        assert_eq!(SignificantBits::Rgba(5, 6, 5, 8).to_string(), "r5 g6 b5 a8");
+        assert_eq!(SignificantBits::Rgba(5, 6, 5, 8).without_alpha(), SignificantBits::Rgb(5, 6, 5));
+    }
+
+    #[test]
+    fn test_set_significant_bits() {
+        let mut png = testing_png(8, ColorType::Indexed);
+        png.set_palette(Palette::new(vec![Rgb::new(0, 0, 0)]).unwrap());
+        png.set_significant_bits(SignificantBits::Rgb(5, 6, 5)).unwrap();
+        assert_eq!(png.significant_bits().unwrap(), Some(SignificantBits::Rgb(5, 6, 5)));
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "sBIT", "PLTE", "IDAT", "IEND"]);
+        assert!(png.set_significant_bits(SignificantBits::Gray(5)).is_err());
+        assert!(png.remove_significant_bits().is_some());
+    }
+
+    #[test]
+    fn test_values_limited_by_sample_depth() {
+        let mut png = testing_png(4, ColorType::Grayscale);
+        assert!(png.set_significant_bits(SignificantBits::Gray(5)).is_err());
+        assert!(png.set_significant_bits(SignificantBits::Gray(0)).is_err());
+        png.set_significant_bits(SignificantBits::Gray(4)).unwrap();
+        // Palette entries are always 8 bits, whatever the index depth.
+        testing_png(2, ColorType::Indexed).set_significant_bits(SignificantBits::Rgb(8, 8, 8)).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+use std::fmt::Display;
+
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::PngError, png::Png};
+
+const SPREAD_VERSION: u8 = 1;
+const HEADER_LEN: usize = 1 + 32 + 4 + 1 + 1 + 1 + 32;
+
+// Describes the whole payload. Every shard carries a copy, so any
+// `data_shards` of the carriers are enough to put it back together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpreadManifest {
+    pub payload_digest: [u8; 32],
+    pub payload_len: u32,
+    pub data_shards: u8,
+    // Data shards plus Reed-Solomon parity shards.
+    pub total_shards: u8
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shard {
+    pub manifest: SpreadManifest,
+    pub index: u8,
+    pub data: Vec<u8>
+}
+
+impl Shard {
+    // Layout: version, payload digest, payload length, data and total shard
+    // counts, shard index, blake3 digest of the shard data, shard data.
+    pub fn to_chunk(&self, chunk_type: ChunkType) -> Chunk {
+        let mut data = Vec::with_capacity(HEADER_LEN + self.data.len());
+        data.push(SPREAD_VERSION);
+        data.extend(self.manifest.payload_digest);
+        data.extend(self.manifest.payload_len.to_be_bytes());
+        data.extend([self.manifest.data_shards, self.manifest.total_shards, self.index]);
+        data.extend(blake3::hash(&self.data).as_bytes());
+        data.extend(&self.data);
+        Chunk::new(chunk_type, data)
+    }
+
+    pub fn parse(chunk: &Chunk) -> Result<Shard, PngError> {
+        let invalid = |reason| PngError::InvalidChunkData { chunk_type: chunk.chunk_type().to_string(), reason };
+        let data = chunk.data();
+        if data.len() < HEADER_LEN || data[0] != SPREAD_VERSION {
+            return Err(invalid("not a payload shard"));
+        }
+        let manifest = SpreadManifest {
+            payload_digest: data[1..33].try_into().unwrap(),
+            payload_len: u32::from_be_bytes(data[33..37].try_into().unwrap()),
+            data_shards: data[37],
+            total_shards: data[38]
+        };
+        let index = data[39];
+        if manifest.data_shards == 0 || manifest.total_shards < manifest.data_shards || index >= manifest.total_shards {
+            return Err(invalid("shard counts are inconsistent"));
+        }
+        let shard_data = &data[HEADER_LEN..];
+        if blake3::hash(shard_data).as_bytes() != &data[40..72] {
+            return Err(invalid("shard data does not match its digest"));
+        }
+        Ok(Shard { manifest, index, data: shard_data.to_vec() })
+    }
+}
+
+impl Display for SpreadManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} bytes in {} shards, any {} recover it", self.payload_len, self.total_shards, self.data_shards)
+    }
+}
+
+// Cuts `payload` into `data_shards` equal pieces and adds `parity_shards`
+// Reed-Solomon shards, so it survives losing up to that many of them.
+pub fn split(payload: &[u8], data_shards: usize, parity_shards: usize) -> Result<Vec<Shard>, PngError> {
+    if data_shards == 0 || data_shards + parity_shards > u8::MAX as usize {
+        return Err(PngError::InvalidArgument("a payload can be spread over 1 to 255 shards"));
+    }
+    let payload_len = u32::try_from(payload.len())
+        .map_err(|_| PngError::InvalidArgument("payload is too large to spread"))?;
+    let manifest = SpreadManifest {
+        payload_digest: *blake3::hash(payload).as_bytes(),
+        payload_len,
+        data_shards: data_shards as u8,
+        total_shards: (data_shards + parity_shards) as u8
+    };
+
+    let shard_len = payload.len().div_ceil(data_shards).max(1);
+    let mut shards: Vec<Vec<u8>> = (0..data_shards + parity_shards)
+        .map(|i| {
+            let start = (i * shard_len).min(payload.len());
+            let mut shard = payload[start..(start + shard_len).min(payload.len())].to_vec();
+            shard.resize(shard_len, 0);
+            shard
+        })
+        .collect();
+    if parity_shards > 0 {
+        codec(data_shards, parity_shards)?.encode(&mut shards).map_err(|_| codec_failed())?;
+    }
+    Ok(shards
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| Shard { manifest, index: index as u8, data })
+        .collect())
+}
+
+// Rebuilds the payload from any `data_shards` distinct shards of the same
+// manifest, checking the result against the payload digest.
+pub fn join(shards: &[Shard]) -> Result<Vec<u8>, PngError> {
+    let manifest = shards.first().ok_or(PngError::InvalidArgument("no shards to join"))?.manifest;
+    if shards.iter().any(|shard| shard.manifest != manifest) {
+        return Err(PngError::InvalidArgument("shards belong to different payloads"));
+    }
+    let (data_shards, total_shards) = (manifest.data_shards as usize, manifest.total_shards as usize);
+    let mut slots: Vec<Option<Vec<u8>>> = vec![None; total_shards];
+    for shard in shards {
+        slots[shard.index as usize] = Some(shard.data.clone());
+    }
+    if slots.iter().flatten().count() < data_shards {
+        return Err(PngError::InvalidArgument("not enough shards to rebuild the payload"));
+    }
+    let shard_len = slots.iter().flatten().next().map_or(0, |shard| shard.len());
+    if slots.iter().flatten().any(|shard| shard.len() != shard_len) {
+        return Err(PngError::InvalidArgument("shards have different lengths"));
+    }
+    if slots[..data_shards].iter().any(Option::is_none) {
+        codec(data_shards, total_shards - data_shards)?.reconstruct_data(&mut slots).map_err(|_| codec_failed())?;
+    }
+
+    let mut payload: Vec<u8> = slots.into_iter().take(data_shards).flatten().flatten().collect();
+    payload.truncate(manifest.payload_len as usize);
+    if payload.len() != manifest.payload_len as usize || blake3::hash(&payload).as_bytes() != &manifest.payload_digest {
+        return Err(PngError::InvalidArgument("rebuilt payload does not match its digest"));
+    }
+    Ok(payload)
+}
+
+// Adds one shard of `payload` to each carrier as a `chunk_type` chunk.
+// `parity_shards` of the carriers may later go missing or be damaged.
+pub fn spread(carriers: &mut [Png], chunk_type: ChunkType, payload: &[u8], parity_shards: usize) -> Result<SpreadManifest, PngError> {
+    if parity_shards >= carriers.len() {
+        return Err(PngError::InvalidArgument("parity shards must be fewer than the carriers"));
+    }
+    let shards = split(payload, carriers.len() - parity_shards, parity_shards)?;
+    for (carrier, shard) in carriers.iter_mut().zip(&shards) {
+        carrier.append_chunk(shard.to_chunk(chunk_type));
+    }
+    Ok(shards[0].manifest)
+}
+
+// Collects the `chunk_type` shards of the carriers and rebuilds the payload.
+// Carriers without a shard, or with a damaged one, are skipped.
+pub fn gather(carriers: &[Png], chunk_type: ChunkType) -> Result<Vec<u8>, PngError> {
+    let shards: Vec<Shard> = carriers
+        .iter()
+        .filter_map(|carrier| carrier.chunks().iter().find(|chunk| *chunk.chunk_type() == chunk_type))
+        .filter_map(|chunk| Shard::parse(chunk).ok())
+        .collect();
+    join(&shards)
+}
+
+fn codec(data_shards: usize, parity_shards: usize) -> Result<ReedSolomon, PngError> {
+    ReedSolomon::new(data_shards, parity_shards).map_err(|_| codec_failed())
+}
+
+fn codec_failed() -> PngError {
+    PngError::InvalidArgument("shards could not be reed-solomon coded")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn carrier() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ])
+    }
+
+    fn chunk_type() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    const PAYLOAD: &[u8] = b"a secret message that is longer than any single shard";
+
+    #[test]
+    fn test_split_and_join() {
+        let shards = split(PAYLOAD, 3, 0).unwrap();
+        assert_eq!(shards.len(), 3);
+        assert!(shards.iter().all(|shard| shard.data.len() == PAYLOAD.len().div_ceil(3)));
+        assert_eq!(join(&shards).unwrap(), PAYLOAD);
+        assert!(join(&shards[1..]).is_err());
+    }
+
+    #[test]
+    fn test_join_with_missing_shards() {
+        let shards = split(PAYLOAD, 3, 2).unwrap();
+        assert_eq!(shards.len(), 5);
+        let survivors = [shards[1].clone(), shards[3].clone(), shards[4].clone()];
+        assert_eq!(join(&survivors).unwrap(), PAYLOAD);
+        assert!(join(&survivors[1..]).is_err());
+    }
+
+    #[test]
+    fn test_shard_chunk_round_trip() {
+        let shard = split(PAYLOAD, 2, 1).unwrap().remove(1);
+        let chunk = shard.to_chunk(chunk_type());
+        assert_eq!(Shard::parse(&chunk).unwrap(), shard);
+
+        let mut data = chunk.data().to_vec();
+        *data.last_mut().unwrap() ^= 1;
+        assert!(Shard::parse(&Chunk::new(chunk_type(), data)).is_err());
+        assert!(Shard::parse(&Chunk::new(chunk_type(), b"plain text".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_spread_and_gather() {
+        let mut carriers = vec![carrier(), carrier(), carrier(), carrier()];
+        let manifest = spread(&mut carriers, chunk_type(), PAYLOAD, 1).unwrap();
+        assert_eq!((manifest.data_shards, manifest.total_shards), (3, 4));
+        assert_eq!(manifest.to_string(), format!("{} bytes in 4 shards, any 3 recover it", PAYLOAD.len()));
+        assert_eq!(gather(&carriers, chunk_type()).unwrap(), PAYLOAD);
+
+        carriers.remove(2);
+        assert_eq!(gather(&carriers, chunk_type()).unwrap(), PAYLOAD);
+        carriers.remove(0);
+        assert!(gather(&carriers, chunk_type()).is_err());
+    }
+
+    #[test]
+    fn test_mixed_payloads() {
+        let mut shards = split(PAYLOAD, 2, 0).unwrap();
+        shards[1] = split(b"other", 2, 0).unwrap().remove(1);
+        assert!(join(&shards).is_err());
+    }
+
+    #[test]
+    fn test_invalid_counts() {
+        assert!(split(PAYLOAD, 0, 1).is_err());
+        assert!(split(PAYLOAD, 200, 56).is_err());
+        assert!(spread(&mut [carrier()], chunk_type(), PAYLOAD, 1).is_err());
+    }
+
+    #[test]
+    fn test_empty_payload() {
+        let shards = split(b"", 2, 1).unwrap();
+        assert_eq!(join(&shards[1..]).unwrap(), b"");
+    }
+}
@@ -0,0 +1,263 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::{ChunkType, ChunkTypeSet},
+    error::PngError,
+    palette::Rgb,
+    pixels::ImageBuffer,
+    png::Png,
+    text::{decode_latin1, encode_latin1, validate_keyword}
+};
+
+const SPLT_CHUNK_TYPE: ChunkType = ChunkType(u32::from_be_bytes(*b"sPLT"));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuggestedEntry {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+    pub alpha: u16,
+    // Relative usage, only meaningful compared with the other entries.
+    pub frequency: u16
+}
+
+// sPLT: a named palette suggested for displays with limited colors. Unlike
+// PLTE it is never used to decode the image, and there can be several.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuggestedPalette {
+    pub name: String,
+    // 8 or 16; samples must fit in it.
+    pub sample_depth: u8,
+    pub entries: Vec<SuggestedEntry>
+}
+
+impl SuggestedPalette {
+    pub fn new(name: &str, sample_depth: u8, entries: Vec<SuggestedEntry>) -> Result<SuggestedPalette, PngError> {
+        let palette = SuggestedPalette { name: name.to_string(), sample_depth, entries };
+        palette.validate()?;
+        Ok(palette)
+    }
+
+    // 8-bit opaque entries with no frequency information.
+    pub fn from_colors(name: &str, colors: &[Rgb]) -> Result<SuggestedPalette, PngError> {
+        let entries = colors
+            .iter()
+            .map(|color| SuggestedEntry { red: color.r as u16, green: color.g as u16, blue: color.b as u16, alpha: 255, frequency: 0 })
+            .collect();
+        SuggestedPalette::new(name, 8, entries)
+    }
+
+    // The `max_entries` most common colors of `image`, most common first, with
+    // frequencies scaled so the most common color is 65535.
+    pub fn from_image(name: &str, image: &ImageBuffer, max_entries: usize) -> Result<SuggestedPalette, PngError> {
+        let mut counts: HashMap<[u8; 4], u64> = HashMap::new();
+        for pixel in image.pixels.iter() {
+            *counts.entry(*pixel).or_default() += 1;
+        }
+        let mut counts: Vec<([u8; 4], u64)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        counts.truncate(max_entries);
+        let max = counts.first().map_or(1, |(_, count)| *count);
+        let entries = counts
+            .iter()
+            .map(|([r, g, b, a], count)| SuggestedEntry {
+                red: *r as u16,
+                green: *g as u16,
+                blue: *b as u16,
+                alpha: *a as u16,
+                frequency: (count * u16::MAX as u64).div_ceil(max) as u16
+            })
+            .collect();
+        SuggestedPalette::new(name, 8, entries)
+    }
+
+    fn validate(&self) -> Result<(), PngError> {
+        validate_keyword(&self.name).map_err(|_| invalid("name must be 1-79 Latin-1 characters"))?;
+        let max = match self.sample_depth {
+            8 => u8::MAX as u16,
+            16 => u16::MAX,
+            _ => return Err(invalid("sample depth must be 8 or 16"))
+        };
+        if self.entries.iter().any(|entry| [entry.red, entry.green, entry.blue, entry.alpha].iter().any(|sample| *sample > max)) {
+            return Err(invalid("sample value does not fit the sample depth"));
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&Chunk> for SuggestedPalette {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if *chunk.chunk_type() != SPLT_CHUNK_TYPE {
+            return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+        }
+        let data = chunk.data();
+        let separator = data.iter().position(|byte| *byte == 0).ok_or_else(|| invalid("missing null separator"))?;
+        let name = decode_latin1(&data[..separator]);
+        let sample_depth = *data.get(separator + 1).ok_or_else(|| invalid("missing sample depth"))?;
+        let entry_len = match sample_depth {
+            8 => 6,
+            16 => 10,
+            _ => return Err(invalid("sample depth must be 8 or 16"))
+        };
+        let entries = &data[separator + 2..];
+        if !entries.len().is_multiple_of(entry_len) {
+            return Err(invalid("entries are not a whole number of bytes"));
+        }
+        let entries = entries
+            .chunks(entry_len)
+            .map(|entry| {
+                let sample = |i: usize| match sample_depth {
+                    8 => entry[i] as u16,
+                    _ => u16::from_be_bytes([entry[i * 2], entry[i * 2 + 1]])
+                };
+                let frequency = u16::from_be_bytes([entry[entry_len - 2], entry[entry_len - 1]]);
+                SuggestedEntry { red: sample(0), green: sample(1), blue: sample(2), alpha: sample(3), frequency }
+            })
+            .collect();
+        SuggestedPalette::new(&name, sample_depth, entries)
+    }
+}
+
+impl From<SuggestedPalette> for Chunk {
+    fn from(value: SuggestedPalette) -> Self {
+        let mut data = encode_latin1(&value.name).expect("name was validated");
+        data.push(0);
+        data.push(value.sample_depth);
+        for entry in value.entries.iter() {
+            for sample in [entry.red, entry.green, entry.blue, entry.alpha] {
+                match value.sample_depth {
+                    8 => data.push(sample as u8),
+                    _ => data.extend(sample.to_be_bytes())
+                }
+            }
+            data.extend(entry.frequency.to_be_bytes());
+        }
+        Chunk::new(SPLT_CHUNK_TYPE, data)
+    }
+}
+
+impl Display for SuggestedPalette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} colors, {}-bit)", self.name, self.entries.len(), self.sample_depth)
+    }
+}
+
+impl Png {
+    pub fn suggested_palettes(&self) -> Result<Vec<SuggestedPalette>, PngError> {
+        self.chunks_by_type("sPLT").into_iter().map(SuggestedPalette::try_from).collect()
+    }
+
+    // Replaces the sPLT with the same name, or inserts one before the image data.
+    pub fn add_suggested_palette(&mut self, palette: SuggestedPalette) -> Result<(), PngError> {
+        palette.validate()?;
+        let name = palette.name.clone();
+        let chunk: Chunk = palette.into();
+        match self.chunks.iter().position(|x| is_named(x, &name)) {
+            Some(index) => self.chunks[index] = chunk,
+            None => match self.chunks.iter().position(|x| x.chunk_type().bytes() == *b"IDAT") {
+                Some(index) => {
+                    self.chunks.insert(index, chunk);
+                    self.chunks_changed(SPLT_CHUNK_TYPE);
+                },
+                None => self.append_chunk(chunk)
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_suggested_palette(&mut self, name: &str) -> bool {
+        let before = self.chunks.len();
+        self.chunks.retain(|chunk| !is_named(chunk, name));
+        let removed = before != self.chunks.len();
+        if removed {
+            self.chunks_changed(SPLT_CHUNK_TYPE);
+        }
+        removed
+    }
+
+    pub fn strip_suggested_palettes(&mut self) -> usize {
+        self.strip_chunks(&ChunkTypeSet::of(&[b"sPLT"]))
+    }
+}
+
+fn is_named(chunk: &Chunk, name: &str) -> bool {
+    let data = chunk.data();
+    let end = data.iter().position(|byte| *byte == 0).unwrap_or(data.len());
+    *chunk.chunk_type() == SPLT_CHUNK_TYPE && decode_latin1(&data[..end]) == name
+}
+
+fn invalid(reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: String::from("sPLT"), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn entry(red: u16, green: u16, blue: u16, alpha: u16, frequency: u16) -> SuggestedEntry {
+        SuggestedEntry { red, green, blue, alpha, frequency }
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0]),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_round_trip_8_bit() {
+        let palette = SuggestedPalette::new("web", 8, vec![entry(255, 0, 0, 255, 10), entry(0, 0, 0, 0, 1)]).unwrap();
+        let chunk: Chunk = palette.clone().into();
+        assert_eq!(chunk.data(), b"web\0\x08\xff\0\0\xff\0\x0a\0\0\0\0\0\x01");
+        assert_eq!(SuggestedPalette::try_from(&chunk).unwrap(), palette);
+    }
+
+    #[test]
+    fn test_round_trip_16_bit() {
+        let palette = SuggestedPalette::new("deep", 16, vec![entry(65535, 256, 1, 65535, 7)]).unwrap();
+        let chunk: Chunk = palette.clone().into();
+        assert_eq!(chunk.data().len(), 5 + 1 + 10);
+        assert_eq!(SuggestedPalette::try_from(&chunk).unwrap(), palette);
+    }
+
+    #[test]
+    fn test_invalid_palettes() {
+        assert!(SuggestedPalette::new("", 8, Vec::new()).is_err());
+        assert!(SuggestedPalette::new("web", 4, Vec::new()).is_err());
+        assert!(SuggestedPalette::new("web", 8, vec![entry(256, 0, 0, 0, 0)]).is_err());
+        let chunk = Chunk::new(SPLT_CHUNK_TYPE, b"web\0\x08\xff\0\0".to_vec());
+        assert!(SuggestedPalette::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_from_image() {
+        let pixels = vec![[1, 1, 1, 255], [2, 2, 2, 255], [1, 1, 1, 255], [3, 3, 3, 0]];
+        let image = ImageBuffer { width: 4, height: 1, pixels };
+        let palette = SuggestedPalette::from_image("common", &image, 2).unwrap();
+        assert_eq!(palette.entries, [entry(1, 1, 1, 255, 65535), entry(2, 2, 2, 255, 32768)]);
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let mut png = testing_png();
+        let colors = [Rgb::new(0, 0, 0), Rgb::new(255, 255, 255)];
+        png.add_suggested_palette(SuggestedPalette::from_colors("mono", &colors).unwrap()).unwrap();
+        png.add_suggested_palette(SuggestedPalette::from_colors("black", &colors[..1]).unwrap()).unwrap();
+        png.add_suggested_palette(SuggestedPalette::from_colors("mono", &colors[1..]).unwrap()).unwrap();
+        let palettes = png.suggested_palettes().unwrap();
+        assert_eq!(palettes.iter().map(|palette| palette.to_string()).collect::<Vec<String>>(), [
+            "mono (1 colors, 8-bit)",
+            "black (1 colors, 8-bit)"
+        ]);
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "sPLT", "sPLT", "IDAT", "IEND"]);
+        assert!(png.remove_suggested_palette("mono"));
+        assert!(!png.remove_suggested_palette("mono"));
+        assert_eq!(png.strip_suggested_palettes(), 1);
+    }
+}
@@ -0,0 +1,549 @@
+use std::str::FromStr;
+
+use crate::{chunk::Chunk, chunk_type::{ChunkType, ChunkTypeSet}, error::PngError, png::Png, zlib};
+
+pub const SOFTWARE: &str = concat!("png-decode-encode ", env!("CARGO_PKG_VERSION"));
+
+pub const TEXT_CHUNK_TYPES: ChunkTypeSet = ChunkTypeSet::of(&[b"tEXt", b"zTXt", b"iTXt"]);
+
+// The fields are only set through `new` or parsing, which both check them,
+// so turning one into a Chunk cannot fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    keyword: String,
+    text: String
+}
+
+// zTXt: Latin-1 text stored as a zlib stream. Private fields for the same
+// reason as TextChunk's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedTextChunk {
+    keyword: String,
+    text: String
+}
+
+// iTXt: UTF-8 text with an optional language tag and translated keyword.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalTextChunk {
+    keyword: String,
+    compressed: bool,
+    language_tag: String,
+    translated_keyword: String,
+    text: String
+}
+
+// Where `set_text_in_place` put the new text chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextPlacement {
+    // Over the chunk that used the keyword before, at the same size.
+    InPlace,
+    // Right before IEND, so only IEND moved.
+    AfterImageData
+}
+
+impl TextChunk {
+    pub fn new(keyword: &str, text: &str) -> Result<TextChunk, PngError> {
+        validate_keyword(keyword)?;
+        encode_latin1(text)?;
+        Ok(TextChunk { keyword: keyword.to_string(), text: text.to_string() })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let (keyword, text) = split_keyword(chunk, "tEXt")?;
+        Ok(TextChunk { keyword, text: decode_latin1(text) })
+    }
+}
+
+impl From<TextChunk> for Chunk {
+    fn from(value: TextChunk) -> Self {
+        let mut data = encode_latin1(&value.keyword).expect("keyword was validated");
+        data.push(0);
+        data.extend(encode_latin1(&value.text).expect("text was validated"));
+        Chunk::new(ChunkType::from_str("tEXt").unwrap(), data)
+    }
+}
+
+impl CompressedTextChunk {
+    pub fn new(keyword: &str, text: &str) -> Result<CompressedTextChunk, PngError> {
+        validate_keyword(keyword)?;
+        encode_latin1(text).map_err(|_| invalid("zTXt", "text is not representable in Latin-1"))?;
+        Ok(CompressedTextChunk { keyword: keyword.to_string(), text: text.to_string() })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl TryFrom<&Chunk> for CompressedTextChunk {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let (keyword, rest) = split_keyword(chunk, "zTXt")?;
+        match rest {
+            [0, compressed @ ..] => Ok(CompressedTextChunk { keyword, text: decode_latin1(&zlib::inflate(compressed)?) }),
+            [_, ..] => Err(invalid("zTXt", "unknown compression method")),
+            [] => Err(invalid("zTXt", "missing compression method"))
+        }
+    }
+}
+
+impl From<CompressedTextChunk> for Chunk {
+    fn from(value: CompressedTextChunk) -> Self {
+        let mut data = encode_latin1(&value.keyword).expect("keyword was validated");
+        data.extend([0, 0]);
+        let text = encode_latin1(&value.text).expect("text was validated");
+        data.extend(zlib::deflate(&text, 9).expect("compressing into memory cannot fail"));
+        Chunk::new(ChunkType::from_str("zTXt").unwrap(), data)
+    }
+}
+
+impl InternationalTextChunk {
+    pub fn new(keyword: &str, text: &str) -> Result<InternationalTextChunk, PngError> {
+        validate_keyword(keyword)?;
+        Ok(InternationalTextChunk {
+            keyword: keyword.to_string(),
+            compressed: false,
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+            text: text.to_string()
+        })
+    }
+
+    pub fn with_compression(self, compressed: bool) -> InternationalTextChunk {
+        InternationalTextChunk { compressed, ..self }
+    }
+
+    // The separator between fields cannot appear inside one.
+    pub fn with_translated_keyword(self, translated_keyword: &str) -> Result<InternationalTextChunk, PngError> {
+        if translated_keyword.contains('\0') {
+            return Err(invalid("iTXt", "translated keyword contains a null byte"));
+        }
+        Ok(InternationalTextChunk { translated_keyword: translated_keyword.to_string(), ..self })
+    }
+
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn language_tag(&self) -> &str {
+        &self.language_tag
+    }
+
+    pub fn translated_keyword(&self) -> &str {
+        &self.translated_keyword
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl TryFrom<&Chunk> for InternationalTextChunk {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let (keyword, rest) = split_keyword(chunk, "iTXt")?;
+        let (compressed, rest) = match rest {
+            [0, _, rest @ ..] => (false, rest),
+            [1, 0, rest @ ..] => (true, rest),
+            [1, _, ..] => return Err(invalid("iTXt", "unknown compression method")),
+            _ => return Err(invalid("iTXt", "invalid compression flag"))
+        };
+        let mut fields = rest.splitn(3, |byte| *byte == 0);
+        let (Some(language_tag), Some(translated_keyword), Some(text)) = (fields.next(), fields.next(), fields.next()) else {
+            return Err(invalid("iTXt", "missing language tag or translated keyword separator"));
+        };
+        if !language_tag.iter().all(|byte| byte.is_ascii_alphanumeric() || *byte == b'-') {
+            return Err(invalid("iTXt", "language tag is not ASCII"));
+        }
+        let text = if compressed { zlib::inflate(text)? } else { text.to_vec() };
+        Ok(InternationalTextChunk {
+            keyword,
+            compressed,
+            language_tag: decode_latin1(language_tag),
+            translated_keyword: String::from_utf8(translated_keyword.to_vec())?,
+            text: String::from_utf8(text)?
+        })
+    }
+}
+
+impl From<InternationalTextChunk> for Chunk {
+    fn from(value: InternationalTextChunk) -> Self {
+        let mut data = encode_latin1(&value.keyword).expect("keyword was validated");
+        data.extend([0, u8::from(value.compressed), 0]);
+        data.extend(value.language_tag.as_bytes());
+        data.push(0);
+        data.extend(value.translated_keyword.as_bytes());
+        data.push(0);
+        if value.compressed {
+            data.extend(zlib::deflate(value.text.as_bytes(), 9).expect("compressing into memory cannot fail"));
+        } else {
+            data.extend(value.text.as_bytes());
+        }
+        Chunk::new(ChunkType::from_str("iTXt").unwrap(), data)
+    }
+}
+
+impl Png {
+    pub fn text_chunks(&self) -> Vec<TextChunk> {
+        self.chunks()
+            .iter()
+            .filter_map(|chunk| TextChunk::try_from(chunk).ok())
+            .collect()
+    }
+
+    pub fn compressed_text_chunks(&self) -> Vec<CompressedTextChunk> {
+        self.chunks()
+            .iter()
+            .filter_map(|chunk| CompressedTextChunk::try_from(chunk).ok())
+            .collect()
+    }
+
+    pub fn international_text_chunks(&self) -> Vec<InternationalTextChunk> {
+        self.chunks()
+            .iter()
+            .filter_map(|chunk| InternationalTextChunk::try_from(chunk).ok())
+            .collect()
+    }
+
+    // Keyword and text of every tEXt, zTXt and iTXt chunk in file order,
+    // skipping chunks that do not parse.
+    pub fn text_entries(&self) -> Vec<(String, String)> {
+        self.chunks()
+            .iter()
+            .filter_map(|chunk| match &chunk.chunk_type().bytes() {
+                b"tEXt" => TextChunk::try_from(chunk).ok().map(|text| (text.keyword, text.text)),
+                b"zTXt" => CompressedTextChunk::try_from(chunk).ok().map(|text| (text.keyword, text.text)),
+                b"iTXt" => InternationalTextChunk::try_from(chunk).ok().map(|text| (text.keyword, text.text)),
+                _ => None
+            })
+            .collect()
+    }
+
+    pub fn text(&self, keyword: &str) -> Option<String> {
+        self.text_entries()
+            .into_iter()
+            .find(|(entry, _)| entry == keyword)
+            .map(|(_, text)| text)
+    }
+
+    // Replaces every text chunk using `keyword` with a single new tEXt chunk.
+    pub fn set_text(&mut self, keyword: &str, text: &str) -> Result<(), PngError> {
+        let chunk: Chunk = TextChunk::new(keyword, text)?.into();
+        self.remove_text(keyword);
+        self.append_chunk(chunk);
+        Ok(())
+    }
+
+    // `set_text` for files whose other chunks must stay byte-identical and at
+    // the same offsets, e.g. when a signature covers byte ranges of the image
+    // data. The chunk already using `keyword` is overwritten with one of the
+    // same size: tEXt when the length matches exactly, otherwise iTXt with
+    // its translated keyword padded with spaces to make up the difference.
+    // When there is none, or the text does not fit, the new chunk goes right
+    // before IEND, which only works if no chunk using `keyword` is left
+    // before it.
+    pub fn set_text_in_place(&mut self, keyword: &str, text: &str) -> Result<TextPlacement, PngError> {
+        let text_chunk = TextChunk::new(keyword, text);
+        let international = InternationalTextChunk::new(keyword, text)?;
+        let using_keyword: Vec<usize> = self
+            .chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| text_keyword(chunk).as_deref() == Some(keyword))
+            .map(|(index, _)| index)
+            .collect();
+        if let [index] = using_keyword[..] {
+            let len = self.chunks[index].data().len();
+            let fitted = match &text_chunk {
+                Ok(text_chunk) if Chunk::from(text_chunk.clone()).data().len() == len => Some(Chunk::from(text_chunk.clone())),
+                _ => {
+                    let unpadded = Chunk::from(international.clone()).data().len();
+                    match unpadded <= len {
+                        true => Some(Chunk::from(international.clone().with_translated_keyword(&" ".repeat(len - unpadded))?)),
+                        false => None
+                    }
+                }
+            };
+            if let Some(chunk) = fitted {
+                self.chunks[index] = chunk;
+                return Ok(TextPlacement::InPlace);
+            }
+        }
+
+        let iend = self.chunks.iter().position(|chunk| chunk.chunk_type().bytes() == *b"IEND").unwrap_or(self.chunks.len());
+        // Chunks between the last IDAT and IEND can go, they do not move anything else.
+        let last_idat = self.chunks.iter().rposition(|chunk| chunk.chunk_type().bytes() == *b"IDAT").unwrap_or(0);
+        if using_keyword.iter().any(|index| *index <= last_idat) {
+            return Err(PngError::InvalidArgument("the text does not fit where it is, and it cannot move without moving the image data"));
+        }
+        let chunk = match text_chunk {
+            Ok(text_chunk) => Chunk::from(text_chunk),
+            Err(_) => Chunk::from(international)
+        };
+        self.chunks.insert(iend, chunk);
+        for index in using_keyword.into_iter().rev() {
+            self.chunks.remove(index);
+        }
+        Ok(TextPlacement::AfterImageData)
+    }
+
+    // Removes the tEXt, zTXt and iTXt chunks using `keyword`.
+    pub fn remove_text(&mut self, keyword: &str) {
+        self.chunks.retain(|chunk| text_keyword(chunk).as_deref() != Some(keyword));
+    }
+}
+
+macro_rules! keyword_accessors {
+    ($($getter:ident, $setter:ident => $keyword:literal;)*) => {
+        impl Png {
+            $(
+                pub fn $getter(&self) -> Option<String> {
+                    self.text($keyword)
+                }
+
+                pub fn $setter(&mut self, value: &str) -> Result<(), PngError> {
+                    self.set_text($keyword, value)
+                }
+            )*
+        }
+    };
+}
+
+keyword_accessors! {
+    title, set_title => "Title";
+    author, set_author => "Author";
+    description, set_description => "Description";
+    copyright, set_copyright => "Copyright";
+    creation_time, set_creation_time => "Creation Time";
+    software, set_software => "Software";
+    disclaimer, set_disclaimer => "Disclaimer";
+    warning, set_warning => "Warning";
+    source, set_source => "Source";
+    comment, set_comment => "Comment";
+}
+
+// The keyword of a tEXt, zTXt or iTXt chunk, `None` for other chunks.
+pub(crate) fn text_keyword(chunk: &Chunk) -> Option<String> {
+    if !TEXT_CHUNK_TYPES.contains(*chunk.chunk_type()) {
+        return None;
+    }
+    let data = chunk.data();
+    let end = data.iter().position(|byte| *byte == 0).unwrap_or(data.len());
+    Some(decode_latin1(&data[..end]))
+}
+
+// Checks the chunk type and splits the data at the keyword separator.
+fn split_keyword<'a>(chunk: &'a Chunk, chunk_type: &str) -> Result<(String, &'a [u8]), PngError> {
+    if chunk.chunk_type().bytes() != chunk_type.as_bytes() {
+        return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+    }
+    let data = chunk.data();
+    let separator = data.iter().position(|byte| *byte == 0).ok_or(invalid(chunk_type, "missing keyword separator"))?;
+    let keyword = decode_latin1(&data[..separator]);
+    validate_keyword(&keyword).map_err(|err| match err {
+        PngError::InvalidChunkData { reason, .. } => invalid(chunk_type, reason),
+        err => err
+    })?;
+    Ok((keyword, &data[separator + 1..]))
+}
+
+pub(crate) fn validate_keyword(keyword: &str) -> Result<(), PngError> {
+    let bytes = encode_latin1(keyword)?;
+    if bytes.is_empty() || bytes.len() > 79 {
+        return Err(invalid("tEXt", "keyword must be 1-79 bytes"));
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return Err(invalid("tEXt", "keyword has leading, trailing or consecutive spaces"));
+    }
+    if !bytes.iter().all(|byte| matches!(byte, 0x20..=0x7e | 0xa1..=0xff)) {
+        return Err(invalid("tEXt", "keyword contains non-printable characters"));
+    }
+    Ok(())
+}
+
+pub(crate) fn encode_latin1(text: &str) -> Result<Vec<u8>, PngError> {
+    text.chars()
+        .map(|c| u8::try_from(u32::from(c)).map_err(|_| invalid("tEXt", "text is not representable in Latin-1")))
+        .collect()
+}
+
+pub(crate) fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| *byte as char).collect()
+}
+
+fn invalid(chunk_type: &str, reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: chunk_type.to_string(), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_text_chunk_round_trip() {
+        let text = TextChunk::new("Comment", "café").unwrap();
+        let chunk: Chunk = text.clone().into();
+        assert_eq!(chunk.data(), b"Comment\0caf\xe9");
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+        assert_eq!((text.keyword(), text.text()), ("Comment", "café"));
+    }
+
+    #[test]
+    fn test_invalid_keywords() {
+        assert!(TextChunk::new("", "value").is_err());
+        assert!(TextChunk::new(" Title", "value").is_err());
+        assert!(TextChunk::new("Two  spaces", "value").is_err());
+        assert!(TextChunk::new(&"k".repeat(80), "value").is_err());
+        assert!(TextChunk::new("Title", "snow ☃").is_err());
+    }
+
+    #[test]
+    fn test_text_chunk_without_separator() {
+        let chunk = Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Title".to_vec());
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_compressed_text_round_trip() {
+        let text = CompressedTextChunk::new("Description", &"dice ".repeat(40)).unwrap();
+        let chunk: Chunk = text.clone().into();
+        assert!(chunk.data().starts_with(b"Description\0\0"));
+        assert!(chunk.data().len() < 100);
+        assert_eq!(CompressedTextChunk::try_from(&chunk).unwrap(), text);
+    }
+
+    #[test]
+    fn test_invalid_compressed_text() {
+        let chunk = Chunk::new(ChunkType::from_str("zTXt").unwrap(), b"Title\0\x01data".to_vec());
+        assert!(CompressedTextChunk::try_from(&chunk).is_err());
+        let chunk = Chunk::new(ChunkType::from_str("zTXt").unwrap(), b"Title\0\0not zlib".to_vec());
+        assert!(matches!(CompressedTextChunk::try_from(&chunk), Err(PngError::Compression(_))));
+    }
+
+    #[test]
+    fn test_international_text_round_trip() {
+        let chunk = Chunk::new(ChunkType::from_str("iTXt").unwrap(), "Title\0\0\0ja\0タイトル\0雪だるま ☃".as_bytes().to_vec());
+        let text = InternationalTextChunk::try_from(&chunk).unwrap();
+        assert_eq!(text.language_tag(), "ja");
+        assert_eq!(text.translated_keyword(), "タイトル");
+        assert_eq!(text.text(), "雪だるま ☃");
+        let actual: Chunk = text.into();
+        assert_eq!(actual.data(), chunk.data());
+    }
+
+    #[test]
+    fn test_compressed_international_text() {
+        let text = InternationalTextChunk::new("Comment", "snow ☃").unwrap().with_compression(true);
+        let chunk: Chunk = text.clone().into();
+        assert_eq!(InternationalTextChunk::try_from(&chunk).unwrap(), text);
+        assert!(text.clone().with_translated_keyword("a\0b").is_err());
+        let chunk: Chunk = text.with_translated_keyword("Kommentar").unwrap().into();
+        assert_eq!(InternationalTextChunk::try_from(&chunk).unwrap().translated_keyword(), "Kommentar");
+    }
+
+    #[test]
+    fn test_invalid_international_text() {
+        let chunk = Chunk::new(ChunkType::from_str("iTXt").unwrap(), b"Title\0\0\0en".to_vec());
+        assert!(InternationalTextChunk::try_from(&chunk).is_err());
+        let chunk = Chunk::new(ChunkType::from_str("iTXt").unwrap(), b"Title\0\x02\0\0\0text".to_vec());
+        assert!(InternationalTextChunk::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_text_entries_include_every_kind() {
+        let mut png = testing_png();
+        png.set_title("plain").unwrap();
+        png.append_chunk(CompressedTextChunk::new("Comment", "packed").unwrap().into());
+        png.append_chunk(InternationalTextChunk::new("Author", "Zoë").unwrap().into());
+        assert_eq!(png.text_entries().len(), 3);
+        assert_eq!(png.comment().as_deref(), Some("packed"));
+        assert_eq!(png.international_text_chunks()[0].text(), "Zoë");
+        png.remove_text("Comment");
+        assert!(png.compressed_text_chunks().is_empty());
+        png.set_author("Zoe").unwrap();
+        assert_eq!(png.text_entries(), [(String::from("Title"), String::from("plain")), (String::from("Author"), String::from("Zoe"))]);
+    }
+
+    #[test]
+    fn test_keyword_accessors() {
+        let mut png = testing_png();
+        assert_eq!(png.author(), None);
+        png.set_author("Jane Doe").unwrap();
+        png.set_software(SOFTWARE).unwrap();
+        assert_eq!(png.author().as_deref(), Some("Jane Doe"));
+        assert_eq!(png.software().as_deref(), Some(SOFTWARE));
+        assert_eq!(&png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+    }
+
+    #[test]
+    fn test_set_text_replaces_existing() {
+        let mut png = testing_png();
+        png.set_title("First").unwrap();
+        png.set_title("Second").unwrap();
+        assert_eq!(png.text_chunks().len(), 1);
+        assert_eq!(png.title().as_deref(), Some("Second"));
+    }
+
+    #[test]
+    fn test_set_text_in_place() {
+        let mut png = testing_png();
+        png.set_title("A title").unwrap();
+        png.chunks.insert(2, Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![1, 2, 3]));
+        let before = png.as_bytes();
+
+        // Same length, so it stays tEXt.
+        assert_eq!(png.set_text_in_place("Title", "Z title").unwrap(), TextPlacement::InPlace);
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "tEXt");
+        // Other lengths turn into iTXt, padded with a translated keyword, as
+        // long as the text is at least 4 bytes shorter.
+        assert!(png.set_text_in_place("Title", "Longer title").is_err());
+        assert_eq!(png.set_text_in_place("Title", "Ti").unwrap(), TextPlacement::InPlace);
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "iTXt");
+        assert_eq!(png.title().as_deref(), Some("Ti"));
+        assert_eq!(png.as_bytes().len(), before.len());
+        assert_eq!(png.as_bytes()[..33], before[..33]);
+        assert_eq!(png.as_bytes()[58..], before[58..]);
+
+        assert_eq!(png.set_text_in_place("Comment", "new").unwrap(), TextPlacement::AfterImageData);
+        assert_eq!(png.chunks()[3].chunk_type().to_string(), "tEXt");
+        assert_eq!(png.set_text_in_place("Comment", "newer").unwrap(), TextPlacement::AfterImageData);
+        assert_eq!(png.text_entries().len(), 2);
+        assert_eq!(png.comment().as_deref(), Some("newer"));
+    }
+
+    #[test]
+    fn test_remove_text() {
+        let mut png = testing_png();
+        png.set_comment("note").unwrap();
+        png.set_copyright("mine").unwrap();
+        png.remove_text("Comment");
+        assert_eq!(png.comment(), None);
+        assert_eq!(png.copyright().as_deref(), Some("mine"));
+    }
+}
@@ -0,0 +1,273 @@
+use alloc::string::{String, ToString};
+use core::{fmt::Display, str::FromStr};
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, error::PngError, png::Png};
+
+pub(crate) const TIME_CHUNK_TYPE: ChunkType = ChunkType(u32::from_be_bytes(*b"tIME"));
+
+// tIME: last modification time, always in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ModificationTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    // Up to 60 to allow for leap seconds.
+    pub second: u8
+}
+
+impl ModificationTime {
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Result<ModificationTime, PngError> {
+        let time = ModificationTime { year, month, day, hour, minute, second };
+        time.validate()?;
+        Ok(time)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn now() -> ModificationTime {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+        ModificationTime::from_unix(seconds as i64)
+    }
+
+    pub fn from_unix(seconds: i64) -> ModificationTime {
+        let (days, rest) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+        let (year, month, day) = civil_from_days(days);
+        ModificationTime {
+            year: year.clamp(0, u16::MAX as i64) as u16,
+            month,
+            day,
+            hour: (rest / 3600) as u8,
+            minute: (rest % 3600 / 60) as u8,
+            second: (rest % 60) as u8
+        }
+    }
+
+    pub fn to_unix(&self) -> i64 {
+        days_from_civil(self.year as i64, self.month, self.day) * 86400
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64
+    }
+
+    // Accepts "YYYY-MM-DDTHH:MM:SS" with an optional fraction and a "Z" or
+    // "+HH:MM" offset, converting to UTC.
+    pub fn parse_rfc3339(value: &str) -> Result<ModificationTime, PngError> {
+        let invalid_time = || invalid("expected an RFC 3339 timestamp such as 2023-01-02T03:04:05Z");
+        let bytes = value.as_bytes();
+        if bytes.len() < 20 || bytes[4] != b'-' || bytes[7] != b'-' || !matches!(bytes[10], b'T' | b't' | b' ')
+            || bytes[13] != b':' || bytes[16] != b':' {
+            return Err(invalid_time());
+        }
+        let number = |range: core::ops::Range<usize>| -> Result<u16, PngError> {
+            let digits = &value[range];
+            if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+                return Err(invalid_time());
+            }
+            digits.parse().map_err(|_| invalid_time())
+        };
+        let time = ModificationTime::new(
+            number(0..4)?,
+            number(5..7)? as u8,
+            number(8..10)? as u8,
+            number(11..13)? as u8,
+            number(14..16)? as u8,
+            number(17..19)? as u8
+        )?;
+
+        let mut rest = &value[19..];
+        if let Some(fraction) = rest.strip_prefix('.') {
+            let digits = fraction.bytes().take_while(|byte| byte.is_ascii_digit()).count();
+            if digits == 0 {
+                return Err(invalid_time());
+            }
+            rest = &fraction[digits..];
+        }
+        let offset = match rest.as_bytes() {
+            [b'Z' | b'z'] => 0,
+            [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+                let minutes = number(value.len() - 5..value.len() - 3)? as i64 * 60 + number(value.len() - 2..value.len())? as i64;
+                if *sign == b'+' { minutes * 60 } else { -minutes * 60 }
+            },
+            _ => return Err(invalid_time())
+        };
+        Ok(if offset == 0 { time } else { ModificationTime::from_unix(time.to_unix() - offset) })
+    }
+
+    fn validate(&self) -> Result<(), PngError> {
+        let days_in_month = match self.month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if self.year.is_multiple_of(4) && (!self.year.is_multiple_of(100) || self.year.is_multiple_of(400)) => 29,
+            2 => 28,
+            _ => return Err(invalid("month must be 1-12"))
+        };
+        if self.day == 0 || self.day > days_in_month {
+            return Err(invalid("day is out of range for the month"));
+        }
+        if self.hour > 23 || self.minute > 59 || self.second > 60 {
+            return Err(invalid("time of day is out of range"));
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&Chunk> for ModificationTime {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if *chunk.chunk_type() != TIME_CHUNK_TYPE {
+            return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+        }
+        match chunk.data() {
+            [year_high, year_low, month, day, hour, minute, second] => ModificationTime::new(
+                u16::from_be_bytes([*year_high, *year_low]),
+                *month,
+                *day,
+                *hour,
+                *minute,
+                *second
+            ),
+            _ => Err(invalid("expected 7 bytes"))
+        }
+    }
+}
+
+impl From<ModificationTime> for Chunk {
+    fn from(value: ModificationTime) -> Self {
+        let mut data = value.year.to_be_bytes().to_vec();
+        data.extend([value.month, value.day, value.hour, value.minute, value.second]);
+        Chunk::new(TIME_CHUNK_TYPE, data)
+    }
+}
+
+impl FromStr for ModificationTime {
+    type Err = PngError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ModificationTime::parse_rfc3339(s)
+    }
+}
+
+// Formats as RFC 3339 in UTC.
+impl Display for ModificationTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+impl Png {
+    pub fn modification_time(&self) -> Result<Option<ModificationTime>, PngError> {
+        self.chunk_as("tIME")
+    }
+
+    pub fn set_modification_time(&mut self, time: ModificationTime) {
+        self.replace_chunk(time.into());
+    }
+
+    // Sets tIME to the current time.
+    #[cfg(feature = "std")]
+    pub fn touch(&mut self) {
+        self.set_modification_time(ModificationTime::now());
+    }
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u8;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u8;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn invalid(reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: String::from("tIME"), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkTypeSet;
+
+    #[test]
+    fn test_time_round_trip() {
+        let time = ModificationTime::new(2023, 1, 2, 3, 4, 5).unwrap();
+        let chunk: Chunk = time.into();
+        assert_eq!(chunk.data(), [0x07, 0xe7, 1, 2, 3, 4, 5]);
+        assert_eq!(ModificationTime::try_from(&chunk).unwrap(), time);
+        assert_eq!(time.to_string(), "2023-01-02T03:04:05Z");
+    }
+
+    #[test]
+    fn test_parse_rfc3339() {
+        let time = ModificationTime::new(2024, 2, 29, 23, 30, 0).unwrap();
+        assert_eq!("2024-02-29T23:30:00Z".parse::<ModificationTime>().unwrap(), time);
+        assert_eq!("2024-02-29T23:30:00.123z".parse::<ModificationTime>().unwrap(), time);
+        assert_eq!("2024-03-01T01:00:00+01:30".parse::<ModificationTime>().unwrap(), time);
+        assert_eq!("2024-02-29T20:30:00-03:00".parse::<ModificationTime>().unwrap(), time);
+    }
+
+    #[test]
+    fn test_parse_invalid_rfc3339() {
+        assert!("2023-02-29T00:00:00Z".parse::<ModificationTime>().is_err());
+        assert!("2023-01-02 03:04:05".parse::<ModificationTime>().is_err());
+        assert!("2023-01-02T24:00:00Z".parse::<ModificationTime>().is_err());
+        assert!("2023-01-02T03:04:05+0100".parse::<ModificationTime>().is_err());
+        assert!("+023-01-02T03:04:05Z".parse::<ModificationTime>().is_err());
+    }
+
+    #[test]
+    fn test_unix_conversion() {
+        assert_eq!(ModificationTime::from_unix(0).to_string(), "1970-01-01T00:00:00Z");
+        assert_eq!(ModificationTime::from_unix(951782400).to_string(), "2000-02-29T00:00:00Z");
+        let time = ModificationTime::new(2038, 1, 19, 3, 14, 8).unwrap();
+        assert_eq!(time.to_unix(), 1 << 31);
+        assert_eq!(ModificationTime::from_unix(time.to_unix()), time);
+    }
+
+    #[test]
+    fn test_touch() {
+        let mut png = Png::from_chunks(vec![Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new())]);
+        assert_eq!(png.modification_time().unwrap(), None);
+        png.touch();
+        png.touch();
+        assert_eq!(png.chunks().len(), 2);
+        assert!(png.modification_time().unwrap().unwrap().year >= 2024);
+    }
+
+    #[test]
+    fn test_refresh_time_on_chunk_changes() {
+        let iend = Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new());
+        let mut png = Png::from_chunks(vec![iend]);
+        png.append_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), Vec::new()));
+        assert_eq!(png.modification_time().unwrap(), None);
+
+        png.set_refresh_time(true);
+        let old = ModificationTime::new(2000, 1, 1, 0, 0, 0).unwrap();
+        png.set_modification_time(old);
+        png.remove_chunk("ruSt").unwrap();
+        assert!(png.modification_time().unwrap().unwrap() > old);
+        assert_eq!(png.chunks().len(), 2);
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+
+        png.strip_chunks(&ChunkTypeSet::of(&[b"tIME"]));
+        png.append_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), Vec::new()));
+        assert!(png.modification_time().unwrap().is_some());
+    }
+}
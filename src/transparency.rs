@@ -0,0 +1,291 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::{ChunkType, ChunkTypeSet},
+    error::PngError,
+    ihdr::ColorType,
+    palette::Rgb,
+    pixels,
+    png::Png
+};
+
+// tRNS: the layout depends on the IHDR color type, so it is parsed with
+// `Transparency::parse` rather than TryFrom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+    // Alpha for the first entries of the palette; the rest stay opaque.
+    Palette(Vec<u8>),
+    // Gray sample value that is fully transparent.
+    Gray(u16),
+    // RGB sample values that are fully transparent.
+    Rgb(u16, u16, u16)
+}
+
+impl Transparency {
+    pub fn parse(chunk: &Chunk, color_type: ColorType) -> Result<Transparency, PngError> {
+        if chunk.chunk_type().bytes() != *b"tRNS" {
+            return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+        }
+        let data = chunk.data();
+        let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+        match color_type {
+            ColorType::Indexed if data.len() <= 256 => Ok(Transparency::Palette(data.to_vec())),
+            ColorType::Indexed => Err(invalid("more alpha values than palette entries")),
+            ColorType::Grayscale if data.len() == 2 => Ok(Transparency::Gray(sample(0))),
+            ColorType::Grayscale => Err(invalid("expected 2 bytes")),
+            ColorType::Rgb if data.len() == 6 => Ok(Transparency::Rgb(sample(0), sample(2), sample(4))),
+            ColorType::Rgb => Err(invalid("expected 6 bytes")),
+            ColorType::GrayscaleAlpha | ColorType::Rgba => Err(invalid("not allowed for color types with alpha"))
+        }
+    }
+
+    // Whether this kind of tRNS fits an image of `color_type`.
+    pub fn matches(&self, color_type: ColorType) -> bool {
+        matches!(
+            (self, color_type),
+            (Transparency::Palette(_), ColorType::Indexed)
+                | (Transparency::Gray(_), ColorType::Grayscale)
+                | (Transparency::Rgb(..), ColorType::Rgb)
+        )
+    }
+}
+
+impl From<Transparency> for Chunk {
+    fn from(value: Transparency) -> Self {
+        let data = match value {
+            Transparency::Palette(alpha) => alpha,
+            Transparency::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Transparency::Rgb(r, g, b) => [r, g, b].iter().flat_map(|sample| sample.to_be_bytes()).collect()
+        };
+        Chunk::new(ChunkType::from_str("tRNS").unwrap(), data)
+    }
+}
+
+impl Display for Transparency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Transparency::Palette(alpha) => write!(f, "alpha for {} palette entries", alpha.len()),
+            Transparency::Gray(gray) => write!(f, "gray {}", gray),
+            Transparency::Rgb(r, g, b) => write!(f, "rgb({}, {}, {})", r, g, b)
+        }
+    }
+}
+
+impl Png {
+    pub fn transparency(&self) -> Result<Option<Transparency>, PngError> {
+        match self.chunk_by_type("tRNS") {
+            Some(chunk) => Transparency::parse(chunk, self.header()?.color_type).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    // Replaces the tRNS chunk, or inserts one after PLTE and before the image data.
+    pub fn set_transparency(&mut self, transparency: Transparency) -> Result<(), PngError> {
+        let ihdr = self.header()?;
+        if !transparency.matches(ihdr.color_type) {
+            return Err(invalid("does not match the image color type"));
+        }
+        if let Transparency::Palette(alpha) = &transparency {
+            let entries = self.palette()?.map_or(0, |palette| palette.len());
+            if alpha.len() > entries {
+                return Err(invalid("more alpha values than palette entries"));
+            }
+        }
+        self.place_chunk(transparency.into(), &ChunkTypeSet::of(&[b"IDAT"]));
+        Ok(())
+    }
+
+    pub fn remove_transparency(&mut self) -> Option<Chunk> {
+        self.remove_chunk("tRNS").ok()
+    }
+
+    // Makes every pixel of `color` transparent and counts them. Palette,
+    // grayscale and truecolor images get tRNS; images with an alpha channel
+    // have it cleared, and images whose tRNS keys another color gain one.
+    // `color` is scaled to the bit depth, so 16-bit samples must match it
+    // exactly.
+    pub fn with_color_key(&self, color: Rgb) -> Result<(Png, usize), PngError> {
+        let ihdr = self.header()?;
+        let samples = pixels::decode_raw(self)?.samples();
+        let mut keyed = self.with_chunks(self.chunks().to_vec());
+        if ihdr.color_type == ColorType::Indexed {
+            let palette = self.palette()?.ok_or(PngError::ChunkNotFound(String::from("PLTE")))?;
+            let mut alpha = match self.transparency()? {
+                Some(Transparency::Palette(alpha)) => alpha,
+                _ => Vec::new()
+            };
+            let entries: Vec<usize> = (0..palette.len()).filter(|i| palette.entries()[*i] == color).collect();
+            let count = samples.iter().filter(|index| entries.contains(&(**index as usize))).count();
+            if let Some(last) = entries.last() {
+                alpha.resize(alpha.len().max(last + 1), 255);
+                entries.iter().for_each(|i| alpha[*i] = 0);
+                keyed.set_transparency(Transparency::Palette(alpha))?;
+            }
+            return Ok((keyed, count));
+        }
+
+        let channels = ihdr.color_type.channels();
+        let Some(key) = key_samples(color, ihdr.color_type, ihdr.bit_depth) else {
+            return Ok((keyed, 0));
+        };
+        let is_key = |pixel: &[u16], key: &[u16]| !key.is_empty() && pixel[..key.len()] == *key;
+        let count = samples.chunks(channels).filter(|pixel| is_key(pixel, &key)).count();
+        if count == 0 {
+            return Ok((keyed, 0));
+        }
+        let existing = self.transparency()?;
+        let transparency = match key[..] {
+            [gray] if ihdr.color_type == ColorType::Grayscale => Some(Transparency::Gray(gray)),
+            [r, g, b] if ihdr.color_type == ColorType::Rgb => Some(Transparency::Rgb(r, g, b)),
+            _ => None
+        };
+        if let Some(transparency) = transparency.filter(|transparency| existing.is_none() || existing.as_ref() == Some(transparency)) {
+            keyed.set_transparency(transparency)?;
+            return Ok((keyed, count));
+        }
+
+        // Pixels of the old key stay transparent. Alpha needs 8 or 16 bits,
+        // so low bit depth grayscale is scaled up.
+        let old_key = match existing {
+            Some(Transparency::Gray(gray)) => vec![gray],
+            Some(Transparency::Rgb(r, g, b)) => vec![r, g, b],
+            _ => Vec::new()
+        };
+        let (color_type, bit_depth) = match ihdr.color_type {
+            ColorType::Grayscale => (ColorType::GrayscaleAlpha, ihdr.bit_depth.max(8)),
+            ColorType::Rgb => (ColorType::Rgba, ihdr.bit_depth),
+            color_type => (color_type, ihdr.bit_depth)
+        };
+        let (old_max, max) = ((1u32 << ihdr.bit_depth) - 1, (1u32 << bit_depth) - 1);
+        let mut with_alpha = Vec::with_capacity(samples.len() / channels * color_type.channels());
+        for pixel in samples.chunks(channels) {
+            let transparent = is_key(pixel, &key) || is_key(pixel, &old_key);
+            let (color, alpha) = match ihdr.color_type.has_alpha() {
+                true => (&pixel[..channels - 1], pixel[channels - 1] as u32),
+                false => (pixel, max)
+            };
+            with_alpha.extend(color.iter().map(|sample| (*sample as u32 * max / old_max) as u16));
+            with_alpha.push(if transparent { 0 } else { alpha as u16 });
+        }
+        let encoded = Png::from_samples(ihdr.width, ihdr.height, color_type, bit_depth, &with_alpha)?;
+        Ok((self.replace_image(&encoded), count))
+    }
+}
+
+// `color` as samples of an image of `color_type`, or None if no sample value
+// at `bit_depth` gives exactly that color.
+fn key_samples(color: Rgb, color_type: ColorType, bit_depth: u8) -> Option<Vec<u16>> {
+    let max = (1u32 << bit_depth) - 1;
+    let scale = |value: u8| (value as u32 * max).is_multiple_of(255).then(|| (value as u32 * max / 255) as u16);
+    match color_type {
+        ColorType::Grayscale | ColorType::GrayscaleAlpha if color.r == color.g && color.g == color.b => Some(vec![scale(color.r)?]),
+        ColorType::Rgb | ColorType::Rgba => Some(vec![scale(color.r)?, scale(color.g)?, scale(color.b)?]),
+        _ => None
+    }
+}
+
+fn invalid(reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: String::from("tRNS"), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ihdr::Ihdr, palette::{Palette, Rgb}};
+
+    fn testing_png(color_type: ColorType) -> Png {
+        Png::from_chunks(vec![
+            Ihdr::new(1, 1, 8, color_type).unwrap().into(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_parse_by_color_type() {
+        let chunk = Chunk::new(ChunkType::from_str("tRNS").unwrap(), vec![0, 1, 0, 2, 0, 3]);
+        assert_eq!(Transparency::parse(&chunk, ColorType::Rgb).unwrap(), Transparency::Rgb(1, 2, 3));
+        assert_eq!(Transparency::parse(&chunk, ColorType::Indexed).unwrap(), Transparency::Palette(vec![0, 1, 0, 2, 0, 3]));
+        assert!(Transparency::parse(&chunk, ColorType::Grayscale).is_err());
+        assert!(Transparency::parse(&chunk, ColorType::Rgba).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for transparency in [Transparency::Gray(257), Transparency::Rgb(1, 2, 65535), Transparency::Palette(vec![0, 128])] {
+            let color_type = match transparency {
+                Transparency::Gray(_) => ColorType::Grayscale,
+                Transparency::Rgb(..) => ColorType::Rgb,
+                Transparency::Palette(_) => ColorType::Indexed
+            };
+            let chunk: Chunk = transparency.clone().into();
+            assert_eq!(Transparency::parse(&chunk, color_type).unwrap(), transparency);
+        }
+    }
+
+    #[test]
+    fn test_set_transparency() {
+        let mut png = testing_png(ColorType::Grayscale);
+        assert_eq!(png.transparency().unwrap(), None);
+        png.set_transparency(Transparency::Gray(0)).unwrap();
+        png.set_transparency(Transparency::Gray(7)).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "tRNS", "IDAT", "IEND"]);
+        assert_eq!(png.transparency().unwrap(), Some(Transparency::Gray(7)));
+        assert!(png.set_transparency(Transparency::Rgb(0, 0, 0)).is_err());
+        assert!(png.remove_transparency().is_some());
+        assert_eq!(png.transparency().unwrap(), None);
+    }
+
+    fn pixel_alpha(png: &Png) -> Vec<u8> {
+        pixels::decode_rgba(png).unwrap().pixels.iter().map(|pixel| pixel[3]).collect()
+    }
+
+    #[test]
+    fn test_color_key_with_trns() {
+        let magenta = Rgb::new(255, 0, 255);
+        let rgb = Png::from_pixels(3, 1, ColorType::Rgb, 8, &[255, 0, 255, 1, 2, 3, 255, 0, 255]).unwrap();
+        let (keyed, count) = rgb.with_color_key(magenta).unwrap();
+        assert_eq!((count, keyed.transparency().unwrap()), (2, Some(Transparency::Rgb(255, 0, 255))));
+        assert_eq!(pixel_alpha(&keyed), [0, 255, 0]);
+
+        let gray = Png::from_samples(2, 1, ColorType::Grayscale, 2, &[3, 1]).unwrap();
+        let (keyed, count) = gray.with_color_key(Rgb::new(85, 85, 85)).unwrap();
+        assert_eq!((count, keyed.transparency().unwrap()), (1, Some(Transparency::Gray(1))));
+        // No 2-bit gray is exactly 90.
+        let (unchanged, count) = gray.with_color_key(Rgb::new(90, 90, 90)).unwrap();
+        assert_eq!((unchanged.as_bytes(), count), (gray.as_bytes(), 0));
+
+        let mut indexed = Png::from_samples(3, 1, ColorType::Indexed, 8, &[0, 1, 1]).unwrap();
+        indexed.set_palette(Palette::new(vec![Rgb::new(0, 0, 0), magenta]).unwrap());
+        let (keyed, count) = indexed.with_color_key(magenta).unwrap();
+        assert_eq!((count, keyed.transparency().unwrap()), (2, Some(Transparency::Palette(vec![255, 0]))));
+    }
+
+    #[test]
+    fn test_color_key_with_alpha_channel() {
+        let rgba = Png::from_pixels(2, 1, ColorType::Rgba, 8, &[255, 0, 255, 200, 1, 2, 3, 100]).unwrap();
+        let (keyed, count) = rgba.with_color_key(Rgb::new(255, 0, 255)).unwrap();
+        assert_eq!((count, pixel_alpha(&keyed)), (1, vec![0, 100]));
+
+        // The image already keys black, so both colors need an alpha channel.
+        let mut gray = Png::from_samples(3, 1, ColorType::Grayscale, 4, &[0, 15, 5]).unwrap();
+        gray.set_transparency(Transparency::Gray(0)).unwrap();
+        let (keyed, count) = gray.with_color_key(Rgb::new(255, 255, 255)).unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(keyed.header().unwrap().color_type, ColorType::GrayscaleAlpha);
+        assert_eq!(keyed.transparency().unwrap(), None);
+        assert_eq!(pixels::decode_rgba(&keyed).unwrap().pixels, [[0, 0, 0, 0], [255, 255, 255, 0], [85, 85, 85, 255]]);
+    }
+
+    #[test]
+    fn test_palette_alpha_limited_by_palette() {
+        let mut png = testing_png(ColorType::Indexed);
+        png.set_palette(Palette::new(vec![Rgb::new(0, 0, 0), Rgb::new(1, 1, 1)]).unwrap());
+        assert!(png.set_transparency(Transparency::Palette(vec![0, 0, 0])).is_err());
+        png.set_transparency(Transparency::Palette(vec![0])).unwrap();
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "PLTE", "tRNS", "IDAT", "IEND"]);
+    }
+}
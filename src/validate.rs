@@ -0,0 +1,174 @@
+use std::fmt::Display;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png};
+
+// Chunk lengths are limited to 2^31 - 1 bytes.
+const MAX_CHUNK_LEN: u32 = i32::MAX as u32;
+
+// What was found wrong with one chunk of a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkCheck {
+    pub offset: usize,
+    pub chunk_type: [u8; 4],
+    pub length: u32,
+    pub problems: Vec<String>
+}
+
+// The result of checking a file's structure without stopping at the first
+// problem.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validation {
+    pub signature_valid: bool,
+    pub chunks: Vec<ChunkCheck>,
+    // Problems with the file as a whole, with the byte offset they were found at.
+    pub problems: Vec<(usize, &'static str)>
+}
+
+impl ChunkCheck {
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl Validation {
+    pub fn passed(&self) -> bool {
+        self.signature_valid && self.problems.is_empty() && self.chunks.iter().all(ChunkCheck::passed)
+    }
+
+    pub fn problem_count(&self) -> usize {
+        !self.signature_valid as usize + self.problems.len() + self.chunks.iter().map(|chunk| chunk.problems.len()).sum::<usize>()
+    }
+}
+
+impl Display for ChunkCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let chunk_type: String = self.chunk_type.iter().map(|byte| if byte.is_ascii_graphic() { *byte as char } else { '?' }).collect();
+        write!(f, "{:>10}  {} {:>10} bytes  ", self.offset, chunk_type, self.length)?;
+        match self.passed() {
+            true => write!(f, "ok"),
+            false => write!(f, "FAILED: {}", self.problems.join("; "))
+        }
+    }
+}
+
+// Checks the signature, then each chunk's length, type and CRC, then the
+// chunk order, reading past problems wherever the chunk boundaries are still
+// known.
+pub fn validate(bytes: &[u8]) -> Validation {
+    let mut validation = Validation { signature_valid: bytes.starts_with(&Png::STANDARD_HEADER), ..Validation::default() };
+    if !validation.signature_valid {
+        return validation;
+    }
+    // Chunks with a readable type, with their index in `validation.chunks`.
+    let mut parsed: Vec<(usize, Chunk)> = Vec::new();
+    let mut offset = Png::STANDARD_HEADER.len();
+    while offset < bytes.len() {
+        let Some(header) = bytes.get(offset..offset + 8) else {
+            validation.problems.push((offset, "truncated chunk header"));
+            break;
+        };
+        let length = u32::from_be_bytes(header[0..4].try_into().unwrap());
+        let type_bytes: [u8; 4] = header[4..8].try_into().unwrap();
+        let chunk_type = ChunkType(u32::from_be_bytes(type_bytes));
+        let mut check = ChunkCheck { offset, chunk_type: type_bytes, length, problems: Vec::new() };
+        if length > MAX_CHUNK_LEN {
+            check.problems.push("length is over 2^31 - 1".to_string());
+        }
+        let readable = type_bytes.iter().all(u8::is_ascii_alphabetic);
+        if !readable {
+            check.problems.push("chunk type is not four ASCII letters".to_string());
+        } else if !chunk_type.is_reserved_bit_valid() {
+            check.problems.push("reserved bit is set in the chunk type".to_string());
+        }
+
+        let end = offset + 12 + length as usize;
+        let Some(data) = bytes.get(offset + 8..end - 4).filter(|_| end <= bytes.len()) else {
+            check.problems.push(format!("chunk runs {} bytes past the end of the file", end - bytes.len()));
+            validation.chunks.push(check);
+            break;
+        };
+        let stored = u32::from_be_bytes(bytes[end - 4..end].try_into().unwrap());
+        let chunk = Chunk::new(chunk_type, data.to_vec());
+        if stored != chunk.crc() {
+            check.problems.push(format!("crc mismatch: stored {:08x}, computed {:08x}", stored, chunk.crc()));
+        }
+        if readable {
+            parsed.push((validation.chunks.len(), chunk));
+        }
+        validation.chunks.push(check);
+        offset = end;
+        if type_bytes == *b"IEND" && offset != bytes.len() {
+            validation.problems.push((offset, "data after IEND"));
+            break;
+        }
+    }
+
+    match validation.chunks.first_mut() {
+        Some(first) if first.chunk_type != *b"IHDR" => first.problems.push("first chunk is not IHDR".to_string()),
+        None => validation.problems.push((offset, "no chunks")),
+        _ => {}
+    }
+    if validation.chunks.last().is_some_and(|last| last.chunk_type != *b"IEND") {
+        validation.problems.push((bytes.len(), "missing IEND chunk"));
+    }
+    let indices: Vec<usize> = parsed.iter().map(|(index, _)| *index).collect();
+    let png = Png::from_chunks(parsed.into_iter().map(|(_, chunk)| chunk).collect());
+    for violation in png.order_violations() {
+        validation.chunks[indices[violation.index]].problems.push(violation.problem.to_string());
+    }
+    validation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unchecked, so tests can write chunk types a parser must reject.
+    fn chunk(chunk_type: &str, data: &[u8]) -> Vec<u8> {
+        Chunk::new(ChunkType::new_unchecked(chunk_type.as_bytes().try_into().unwrap()), data.to_vec()).as_bytes()
+    }
+
+    fn testing_bytes(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        bytes.extend(chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]));
+        bytes.extend(chunks.concat());
+        bytes.extend(chunk("IEND", &[]));
+        bytes
+    }
+
+    #[test]
+    fn test_valid_file() {
+        let validation = validate(&testing_bytes(&[chunk("IDAT", &[1, 2])]));
+        assert!(validation.passed());
+        let offsets: Vec<usize> = validation.chunks.iter().map(|chunk| chunk.offset).collect();
+        assert_eq!(offsets, [8, 33, 47]);
+        assert_eq!(validation.chunks[1].to_string(), "        33  IDAT          2 bytes  ok");
+    }
+
+    #[test]
+    fn test_reads_past_chunk_problems() {
+        let mut bad_crc = chunk("tEXt", b"a\0b");
+        bad_crc[10] ^= 1;
+        let validation = validate(&testing_bytes(&[chunk("IDAT", &[1]), chunk("pHYs", &[0; 9]), bad_crc, chunk("ab1d", &[])]));
+        assert!(!validation.passed());
+        assert_eq!(validation.problem_count(), 3);
+        assert_eq!(validation.chunks[2].problems, ["must come before IDAT"]);
+        assert!(validation.chunks[3].problems[0].starts_with("crc mismatch"));
+        assert_eq!(validation.chunks[4].problems, ["chunk type is not four ASCII letters"]);
+        assert!(validation.chunks[5].passed());
+    }
+
+    #[test]
+    fn test_file_problems() {
+        assert!(!validate(b"GIF89a").signature_valid);
+
+        let mut bytes = testing_bytes(&[]);
+        bytes.extend([0, 1]);
+        assert_eq!(validate(&bytes).problems, [(bytes.len() - 2, "data after IEND")]);
+
+        let bytes = testing_bytes(&[chunk("IDAT", &[1, 2, 3])]);
+        let validation = validate(&bytes[..bytes.len() - 16]);
+        assert_eq!(validation.chunks[1].problems, ["chunk runs 4 bytes past the end of the file"]);
+        assert_eq!(validation.problems, [(bytes.len() - 16, "missing IEND chunk")]);
+    }
+}
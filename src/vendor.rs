@@ -0,0 +1,277 @@
+use std::fmt::Display;
+
+use crate::{chunk::Chunk, chunk_type::{ChunkType, ChunkTypeSet}, error::PngError, idot::Idot, png::Png};
+
+// An unregistered chunk type written by a well-known program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VendorType {
+    pub chunk_type: ChunkType,
+    pub vendor: &'static str,
+    pub description: &'static str
+}
+
+const fn vendor(name: &[u8; 4], vendor: &'static str, description: &'static str) -> VendorType {
+    VendorType { chunk_type: ChunkType(u32::from_be_bytes(*name)), vendor, description }
+}
+
+const VENDOR_TYPES: [VendorType; 12] = [
+    vendor(b"prVW", "Macromedia Fireworks", "preview thumbnail"),
+    vendor(b"mkBF", "Macromedia Fireworks", "editable document data"),
+    vendor(b"mkBS", "Macromedia Fireworks", "editable document data"),
+    vendor(b"mkBT", "Macromedia Fireworks", "editable document data"),
+    vendor(b"mkTS", "Macromedia Fireworks", "editable document data"),
+    vendor(b"vpAg", "ImageMagick", "virtual page size"),
+    vendor(b"caNv", "ImageMagick", "virtual canvas size and offset"),
+    vendor(b"orNT", "ImageMagick", "EXIF orientation"),
+    vendor(b"iDOT", "Apple", "parallel decoding hints"),
+    vendor(b"npTc", "Android", "nine-patch stretch regions"),
+    vendor(b"npLb", "Android", "nine-patch layout bounds"),
+    vendor(b"npOl", "Android", "nine-patch outline"),
+];
+
+// Every type in `VENDOR_TYPES`. They are all ancillary, so can be stripped.
+pub const VENDOR_CHUNK_TYPES: ChunkTypeSet = ChunkTypeSet::of(&[
+    b"prVW", b"mkBF", b"mkBS", b"mkBT", b"mkTS", b"vpAg", b"caNv", b"orNT", b"iDOT", b"npTc", b"npLb", b"npOl"
+]);
+
+// vpAg: the size of the page the image is placed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualPage {
+    pub width: u32,
+    pub height: u32,
+    // 0 for pixels.
+    pub unit: u8
+}
+
+// caNv: the canvas size and where the image sits on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualCanvas {
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32
+}
+
+// orNT: an EXIF orientation value, 1 to 8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Orientation(pub u8);
+
+// npTc: Android's Res_png_9patch header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NinePatch {
+    pub x_divs: u8,
+    pub y_divs: u8,
+    pub colors: u8,
+    // Left, right, top and bottom.
+    pub padding: [i32; 4]
+}
+
+pub fn vendor_types() -> impl Iterator<Item = &'static VendorType> {
+    VENDOR_TYPES.iter()
+}
+
+pub fn vendor_type(chunk_type: ChunkType) -> Option<&'static VendorType> {
+    VENDOR_TYPES.iter().find(|vendor| vendor.chunk_type == chunk_type)
+}
+
+// Reads the payload of a vendor chunk with a known layout. `None` for other
+// chunks.
+pub fn decode_payload(chunk: &Chunk) -> Option<Result<String, PngError>> {
+    let decoded = match &chunk.chunk_type().bytes() {
+        b"vpAg" => VirtualPage::try_from(chunk).map(|page| page.to_string()),
+        b"caNv" => VirtualCanvas::try_from(chunk).map(|canvas| canvas.to_string()),
+        b"orNT" => Orientation::try_from(chunk).map(|orientation| orientation.to_string()),
+        b"iDOT" => Idot::try_from(chunk).map(|idot| idot.to_string()),
+        b"npTc" => NinePatch::try_from(chunk).map(|patch| patch.to_string()),
+        _ => return None
+    };
+    Some(decoded)
+}
+
+impl TryFrom<&Chunk> for VirtualPage {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        match chunk_data(chunk, b"vpAg")? {
+            data if data.len() == 9 => Ok(VirtualPage { width: u32_at(data, 0), height: u32_at(data, 4), unit: data[8] }),
+            _ => Err(invalid("vpAg", "expected 9 bytes"))
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for VirtualCanvas {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        match chunk_data(chunk, b"caNv")? {
+            data if data.len() == 16 => Ok(VirtualCanvas {
+                width: u32_at(data, 0),
+                height: u32_at(data, 4),
+                x: u32_at(data, 8) as i32,
+                y: u32_at(data, 12) as i32
+            }),
+            _ => Err(invalid("caNv", "expected 16 bytes"))
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for Orientation {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        match chunk_data(chunk, b"orNT")? {
+            [value @ 1..=8] => Ok(Orientation(*value)),
+            [_] => Err(invalid("orNT", "orientation must be 1 to 8")),
+            _ => Err(invalid("orNT", "expected 1 byte"))
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for NinePatch {
+    type Error = PngError;
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let data = chunk_data(chunk, b"npTc")?;
+        if data.len() < 32 {
+            return Err(invalid("npTc", "expected at least 32 bytes"));
+        }
+        let patch = NinePatch {
+            x_divs: data[1],
+            y_divs: data[2],
+            colors: data[3],
+            padding: [12, 16, 20, 24].map(|offset| u32_at(data, offset) as i32)
+        };
+        // The divs and colors are 4 bytes each and follow the header.
+        if data.len() < 32 + 4 * (patch.x_divs as usize + patch.y_divs as usize + patch.colors as usize) {
+            return Err(invalid("npTc", "shorter than its divs and colors"));
+        }
+        Ok(patch)
+    }
+}
+
+impl Display for VirtualPage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.unit {
+            0 => write!(f, "{}x{} pixels", self.width, self.height),
+            unit => write!(f, "{}x{} (unit {})", self.width, self.height, unit)
+        }
+    }
+}
+
+impl Display for VirtualCanvas {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{} at {:+}{:+}", self.width, self.height, self.x, self.y)
+    }
+}
+
+impl Display for Orientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self.0 {
+            1 => "normal",
+            2 => "mirrored horizontally",
+            3 => "rotated 180°",
+            4 => "mirrored vertically",
+            5 => "mirrored horizontally, rotated 270° clockwise",
+            6 => "rotated 90° clockwise",
+            7 => "mirrored horizontally, rotated 90° clockwise",
+            _ => "rotated 270° clockwise"
+        };
+        write!(f, "{} ({})", self.0, name)
+    }
+}
+
+impl Display for NinePatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [left, right, top, bottom] = self.padding;
+        write!(f, "{} x divs, {} y divs, {} colors, padding {} {} {} {}", self.x_divs, self.y_divs, self.colors, left, top, right, bottom)
+    }
+}
+
+impl Png {
+    pub fn vendor_chunks(&self) -> Vec<&Chunk> {
+        self.chunks()
+            .iter()
+            .filter(|chunk| VENDOR_CHUNK_TYPES.contains(*chunk.chunk_type()))
+            .collect()
+    }
+
+    // Removes every chunk of a known vendor type, returning how many were dropped.
+    pub fn strip_vendor_chunks(&mut self) -> usize {
+        self.strip_chunks(&VENDOR_CHUNK_TYPES)
+    }
+}
+
+fn chunk_data<'a>(chunk: &'a Chunk, chunk_type: &[u8; 4]) -> Result<&'a [u8], PngError> {
+    if chunk.chunk_type().bytes() != *chunk_type {
+        return Err(PngError::InvalidChunkType(chunk.chunk_type().to_string()));
+    }
+    Ok(chunk.data())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn invalid(chunk_type: &str, reason: &'static str) -> PngError {
+    PngError::InvalidChunkData { chunk_type: chunk_type.to_string(), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    #[test]
+    fn test_vendor_types() {
+        assert_eq!(VENDOR_CHUNK_TYPES.len(), vendor_types().count());
+        for vendor in vendor_types() {
+            assert!(VENDOR_CHUNK_TYPES.contains(vendor.chunk_type));
+            assert!(!vendor.chunk_type.is_critical());
+        }
+        assert_eq!(vendor_type(ChunkType::from_str("vpAg").unwrap()).unwrap().vendor, "ImageMagick");
+        assert!(vendor_type(ChunkType::from_str("tEXt").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_decode_payloads() {
+        let mut page = vec![0, 0, 2, 128, 0, 0, 1, 224];
+        page.push(0);
+        assert_eq!(decode_payload(&chunk("vpAg", page)).unwrap().unwrap(), "640x480 pixels");
+        let mut canvas = [640u32, 480, 10, (-20i32) as u32].iter().flat_map(|value| value.to_be_bytes()).collect::<Vec<u8>>();
+        assert_eq!(decode_payload(&chunk("caNv", canvas.clone())).unwrap().unwrap(), "640x480 at +10-20");
+        canvas.pop();
+        assert!(decode_payload(&chunk("caNv", canvas)).unwrap().is_err());
+        assert_eq!(decode_payload(&chunk("orNT", vec![6])).unwrap().unwrap(), "6 (rotated 90° clockwise)");
+        assert!(decode_payload(&chunk("orNT", vec![9])).unwrap().is_err());
+        let mut idot = vec![0, 0, 0, 2];
+        idot.extend([0; 24]);
+        assert_eq!(decode_payload(&chunk("iDOT", idot)).unwrap().unwrap(), "2 segments of 0, 0 rows");
+        assert!(decode_payload(&chunk("prVW", vec![1, 2])).is_none());
+    }
+
+    #[test]
+    fn test_nine_patch() {
+        let mut data = vec![1, 2, 2, 1];
+        data.extend([0u32, 0, 1, 2, 3, 4, 0].iter().flat_map(|value| value.to_be_bytes()));
+        let header = data.clone();
+        data.extend([0; 4 * 5]);
+        let patch = NinePatch::try_from(&chunk("npTc", data)).unwrap();
+        assert_eq!(patch, NinePatch { x_divs: 2, y_divs: 2, colors: 1, padding: [1, 2, 3, 4] });
+        assert_eq!(patch.to_string(), "2 x divs, 2 y divs, 1 colors, padding 1 3 2 4");
+        assert!(NinePatch::try_from(&chunk("npTc", header)).is_err());
+    }
+
+    #[test]
+    fn test_strip_vendor_chunks() {
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", vec![0; 13]),
+            chunk("vpAg", vec![0; 9]),
+            chunk("tEXt", b"a\0b".to_vec()),
+            chunk("mkBF", vec![1]),
+            chunk("IEND", Vec::new()),
+        ]);
+        assert_eq!(png.vendor_chunks().len(), 2);
+        assert_eq!(png.strip_vendor_chunks(), 2);
+        assert_eq!(png.chunks().len(), 3);
+    }
+}
@@ -0,0 +1,95 @@
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, envelope::Envelope, error::PngError, png::Png};
+
+// A parsed file for JavaScript, edited in place and written back out with
+// `toBytes`, so a dropped file never has to leave the browser.
+#[wasm_bindgen(js_name = Png)]
+pub struct WasmPng {
+    png: Png
+}
+
+#[wasm_bindgen(js_class = Png)]
+impl WasmPng {
+    #[wasm_bindgen(constructor)]
+    pub fn new(bytes: &[u8]) -> Result<WasmPng, JsError> {
+        Ok(WasmPng { png: Png::try_from(bytes)? })
+    }
+
+    // A JSON array of {type, length, crc} in file order.
+    #[wasm_bindgen(js_name = listChunks)]
+    pub fn list_chunks(&self) -> String {
+        let chunks: Vec<Value> = self
+            .png
+            .chunks()
+            .iter()
+            .map(|chunk| json!({ "type": chunk.chunk_type().to_string(), "length": chunk.length(), "crc": chunk.crc() }))
+            .collect();
+        Value::Array(chunks).to_string()
+    }
+
+    // Hides `message` in a new chunk of `chunk_type` before IEND.
+    pub fn encode(&mut self, chunk_type: &str, message: &str) -> Result<(), JsError> {
+        self.png.append_chunk(Chunk::new(parse_chunk_type(chunk_type)?, message.as_bytes().to_vec()));
+        Ok(())
+    }
+
+    // The message in the first chunk of `chunk_type`, unwrapping an envelope
+    // if it has one.
+    pub fn decode(&self, chunk_type: &str) -> Result<String, JsError> {
+        let chunk = self.png.chunk_by_type(chunk_type).ok_or_else(|| PngError::ChunkNotFound(chunk_type.to_string()))?;
+        Ok(std::str::from_utf8(Envelope::open(chunk.data())?).map_err(PngError::from)?.to_string())
+    }
+
+    pub fn remove(&mut self, chunk_type: &str) -> Result<(), JsError> {
+        self.png.remove_chunk(chunk_type)?;
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.png.as_bytes()
+    }
+}
+
+fn parse_chunk_type(chunk_type: &str) -> Result<ChunkType, PngError> {
+    let parsed = ChunkType::from_str(chunk_type).map_err(|reason| PngError::InvalidChunkTypeName { name: chunk_type.to_string(), reason })?;
+    match parsed.is_valid() {
+        true => Ok(parsed),
+        false => Err(PngError::InvalidChunkType(chunk_type.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::ColorType;
+
+    // Only the success paths run natively; a JsError needs a JavaScript host.
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let bytes = Png::from_pixels(1, 1, ColorType::Rgb, 8, &[1, 2, 3]).unwrap().as_bytes();
+        let mut png = WasmPng::new(&bytes).unwrap();
+        png.encode("ruSt", "hello").unwrap();
+        let mut png = WasmPng::new(&png.to_bytes()).unwrap();
+        assert_eq!(png.decode("ruSt").unwrap(), "hello");
+
+        let chunks: Value = serde_json::from_str(&png.list_chunks()).unwrap();
+        let types: Vec<&str> = chunks.as_array().unwrap().iter().map(|chunk| chunk["type"].as_str().unwrap()).collect();
+        assert_eq!(types, ["IHDR", "IDAT", "ruSt", "IEND"]);
+        assert_eq!(chunks[2]["length"], 5);
+
+        png.remove("ruSt").unwrap();
+        assert_eq!(png.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_parse_chunk_type() {
+        assert!(parse_chunk_type("ruSt").is_ok());
+        assert!(parse_chunk_type("rust!").is_err());
+        assert!(parse_chunk_type("ru").is_err());
+    }
+}
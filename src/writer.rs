@@ -0,0 +1,104 @@
+use std::io::Write;
+
+use crate::{checksum::{Crc32, DefaultCrc32}, chunk::Chunk, chunk_type::ChunkType, error::PngError, png::Png};
+
+// Writes the signature up front and then one chunk at a time, computing each
+// CRC while the data streams out.
+pub struct PngWriter<W: Write> {
+    writer: W,
+    wrote_iend: bool
+}
+
+impl<W: Write> PngWriter<W> {
+    pub fn new(mut writer: W) -> Result<PngWriter<W>, PngError> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(PngWriter { writer, wrote_iend: false })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<(), PngError> {
+        self.write_raw_chunk(*chunk.chunk_type(), chunk.data())
+    }
+
+    pub fn write_raw_chunk(&mut self, chunk_type: ChunkType, data: &[u8]) -> Result<(), PngError> {
+        if self.wrote_iend {
+            return Err(PngError::InvalidArgument("cannot write chunks after IEND"));
+        }
+        let length = u32::try_from(data.len())
+            .map_err(|_| PngError::InvalidArgument("chunk data is longer than 2^32 - 1 bytes"))?;
+        let mut digest = DefaultCrc32::default();
+        digest.update(&chunk_type.bytes());
+        digest.update(data);
+
+        self.writer.write_all(&length.to_be_bytes())?;
+        self.writer.write_all(&chunk_type.bytes())?;
+        self.writer.write_all(data)?;
+        self.writer.write_all(&digest.finalize().to_be_bytes())?;
+        self.wrote_iend = chunk_type.bytes() == *b"IEND";
+        Ok(())
+    }
+
+    // Writes IEND unless it was already written, then flushes.
+    pub fn finish(mut self) -> Result<W, PngError> {
+        if !self.wrote_iend {
+            self.write_raw_chunk(ChunkType(u32::from_be_bytes(*b"IEND")), &[])?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl Png {
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<W, PngError> {
+        let mut png_writer = PngWriter::new(writer)?;
+        for chunk in self.chunks() {
+            png_writer.write_chunk(chunk)?;
+        }
+        png_writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"This is where your secret message will be!".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_write_to_matches_as_bytes() {
+        let png = testing_png();
+        let bytes = png.write_to(Vec::new()).unwrap();
+        assert_eq!(bytes, png.as_bytes());
+    }
+
+    #[test]
+    fn test_raw_chunk_crc() {
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        writer.write_raw_chunk(ChunkType::from_str("RuSt").unwrap(), b"This is where your secret message will be!").unwrap();
+        let bytes = writer.finish().unwrap();
+        let crc = u32::from_be_bytes(bytes[8 + 8 + 42..8 + 12 + 42].try_into().unwrap());
+        assert_eq!(crc, 2882656334);
+    }
+
+    #[test]
+    fn test_finish_appends_iend() {
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        writer.write_chunk(&testing_png().chunks()[0]).unwrap();
+        let bytes = writer.finish().unwrap();
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.chunks().len(), 2);
+    }
+
+    #[test]
+    fn test_no_chunks_after_iend() {
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        writer.write_raw_chunk(ChunkType::from_str("IEND").unwrap(), &[]).unwrap();
+        assert!(writer.write_raw_chunk(ChunkType::from_str("RuSt").unwrap(), &[]).is_err());
+    }
+}
@@ -0,0 +1,443 @@
+use std::{fmt::Display, io::{Read, Write}, str::FromStr};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::{cancel::CancellationToken, error::PngError};
+
+// zlib effort used when writing image data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompressionLevel {
+    Fast,
+    #[default]
+    Default,
+    Best,
+    // 0 (stored, no compression) to 9; higher values are clamped to 9.
+    Level(u8)
+}
+
+impl CompressionLevel {
+    pub fn level(&self) -> u32 {
+        match self {
+            CompressionLevel::Fast => 1,
+            CompressionLevel::Default => 6,
+            CompressionLevel::Best => 9,
+            CompressionLevel::Level(level) => (*level).min(9) as u32
+        }
+    }
+}
+
+// "fast", "default", "best" or a level from 0 to 9.
+impl FromStr for CompressionLevel {
+    type Err = PngError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fast" => Ok(CompressionLevel::Fast),
+            "default" => Ok(CompressionLevel::Default),
+            "best" => Ok(CompressionLevel::Best),
+            level => match level.parse::<u8>() {
+                Ok(level) if level <= 9 => Ok(CompressionLevel::Level(level)),
+                _ => Err(PngError::InvalidArgument("compression must be fast, default, best or 0 to 9"))
+            }
+        }
+    }
+}
+
+impl Display for CompressionLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressionLevel::Fast => f.pad("fast"),
+            CompressionLevel::Default => f.pad("default"),
+            CompressionLevel::Best => f.pad("best"),
+            CompressionLevel::Level(level) => f.pad(&level.to_string())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZlibHeader {
+    pub method: u8,
+    pub window_size: usize,
+    pub preset_dictionary: bool,
+    // FLEVEL: 0 fastest, 1 fast, 2 default, 3 maximum compression.
+    pub level: u8
+}
+
+impl ZlibHeader {
+    pub fn parse(data: &[u8]) -> Option<ZlibHeader> {
+        let (cmf, flg) = (*data.first()?, *data.get(1)?);
+        if cmf & 0x0f != 8 || (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+            return None;
+        }
+        Some(ZlibHeader {
+            method: cmf & 0x0f,
+            window_size: 1 << ((cmf >> 4) + 8),
+            preset_dictionary: flg & 0x20 != 0,
+            level: flg >> 6
+        })
+    }
+}
+
+// How a deflate block is coded, RFC 1951 section 3.2.3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    Stored,
+    Fixed,
+    Dynamic
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeflateBlock {
+    pub block_type: BlockType,
+    pub is_final: bool,
+    // Including the block header, so the sizes of all blocks add up to the
+    // deflate stream.
+    pub compressed_bits: usize,
+    pub output_bytes: usize
+}
+
+// The layout of a zlib stream, for checking it against decoders that only
+// handle some of what zlib allows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZlibStream {
+    pub header: ZlibHeader,
+    // DICTID, the Adler-32 of the preset dictionary, when the header has FDICT.
+    pub dictionary_id: Option<u32>,
+    pub blocks: Vec<DeflateBlock>,
+    // None when the stream ends before the checksum.
+    pub adler32: Option<u32>,
+    // Bytes after the checksum.
+    pub trailing_bytes: usize
+}
+
+impl Display for BlockType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockType::Stored => f.pad("stored"),
+            BlockType::Fixed => f.pad("fixed"),
+            BlockType::Dynamic => f.pad("dynamic")
+        }
+    }
+}
+
+// Walks the deflate blocks of a zlib stream without keeping the output.
+pub fn analyze(data: &[u8]) -> Result<ZlibStream, PngError> {
+    let header = ZlibHeader::parse(data).ok_or(PngError::InvalidImageData("image data does not start with a zlib header"))?;
+    let mut start = 2;
+    let dictionary_id = match header.preset_dictionary {
+        true => {
+            let id = data.get(2..6).ok_or(truncated())?;
+            start = 6;
+            Some(u32::from_be_bytes(id.try_into().unwrap()))
+        },
+        false => None
+    };
+    let mut reader = BitReader { data: &data[start..], bit: 0 };
+    let mut blocks = Vec::new();
+    loop {
+        let block_start = reader.bit;
+        let is_final = reader.bits(1)? == 1;
+        let (block_type, output_bytes) = match reader.bits(2)? {
+            0 => (BlockType::Stored, stored_block(&mut reader)?),
+            1 => (BlockType::Fixed, huffman_block(&mut reader, &Huffman::fixed_literals()?, &Huffman::fixed_distances()?)?),
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                (BlockType::Dynamic, huffman_block(&mut reader, &literals, &distances)?)
+            },
+            _ => return Err(PngError::InvalidImageData("deflate block has the reserved type 3"))
+        };
+        blocks.push(DeflateBlock { block_type, is_final, compressed_bits: reader.bit - block_start, output_bytes });
+        if is_final {
+            break;
+        }
+    }
+    let end = start + reader.bit.div_ceil(8);
+    let adler32 = data.get(end..end + 4).map(|checksum| u32::from_be_bytes(checksum.try_into().unwrap()));
+    let trailing_bytes = data.len().saturating_sub(end + 4);
+    Ok(ZlibStream { header, dictionary_id, blocks, adler32, trailing_bytes })
+}
+
+// Extra bits after each length and distance symbol.
+const LENGTH_BASE: [usize; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u32; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_EXTRA: [u32; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// The order code length code lengths are stored in.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// Deflate packs bits from the least significant end of each byte.
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit: usize
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u32) -> Result<u32, PngError> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self.data.get(self.bit / 8).ok_or(truncated())?;
+            value |= u32::from(byte >> (self.bit % 8) & 1) << i;
+            self.bit += 1;
+        }
+        Ok(value)
+    }
+}
+
+// A canonical Huffman code, decoded a bit at a time.
+struct Huffman {
+    // How many codes have each length, from 0 to 15 bits.
+    counts: [u16; 16],
+    // Symbols ordered by code.
+    symbols: Vec<u16>
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, PngError> {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        let mut left: i32 = 1;
+        for count in &counts[1..] {
+            left = (left << 1) - i32::from(*count);
+            if left < 0 {
+                return Err(PngError::InvalidImageData("deflate Huffman code is over-subscribed"));
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate().filter(|(_, length)| **length != 0) {
+            symbols[offsets[*length as usize] as usize] = symbol as u16;
+            offsets[*length as usize] += 1;
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn fixed_literals() -> Result<Huffman, PngError> {
+        let lengths: Vec<u8> = (0..288).map(|symbol| match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8
+        }).collect();
+        Huffman::new(&lengths)
+    }
+
+    fn fixed_distances() -> Result<Huffman, PngError> {
+        Huffman::new(&[5; 30])
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, PngError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = i32::from(*count);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(PngError::InvalidImageData("deflate stream has an invalid Huffman code"))
+    }
+}
+
+// Skips the block's bytes and returns how many there are.
+fn stored_block(reader: &mut BitReader) -> Result<usize, PngError> {
+    reader.bit = reader.bit.div_ceil(8) * 8;
+    let (length, complement) = (reader.bits(16)?, reader.bits(16)?);
+    if length != !complement & 0xffff {
+        return Err(PngError::InvalidImageData("stored deflate block length does not match its complement"));
+    }
+    reader.bit += length as usize * 8;
+    if reader.bit > reader.data.len() * 8 {
+        return Err(truncated());
+    }
+    Ok(length as usize)
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), PngError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    let mut code_length_lengths = [0u8; 19];
+    for index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[*index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths)?;
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (length, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or(PngError::InvalidImageData("deflate code lengths repeat before the first"))?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?)
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(PngError::InvalidImageData("deflate code lengths overrun the table"));
+    }
+    if lengths[256] == 0 {
+        return Err(PngError::InvalidImageData("deflate block has no end-of-block code"));
+    }
+    Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+}
+
+// Reads symbols up to the end of the block and returns how many bytes they
+// stand for.
+fn huffman_block(reader: &mut BitReader, literals: &Huffman, distances: &Huffman) -> Result<usize, PngError> {
+    let mut output_bytes = 0;
+    loop {
+        match literals.decode(reader)? {
+            0..=255 => output_bytes += 1,
+            256 => return Ok(output_bytes),
+            symbol => {
+                let index = symbol as usize - 257;
+                let (base, extra) = LENGTH_BASE.get(index).zip(LENGTH_EXTRA.get(index))
+                    .ok_or(PngError::InvalidImageData("deflate stream has an invalid length code"))?;
+                output_bytes += base + reader.bits(*extra)? as usize;
+                let distance = distances.decode(reader)? as usize;
+                let extra = DISTANCE_EXTRA.get(distance).ok_or(PngError::InvalidImageData("deflate stream has an invalid distance code"))?;
+                reader.bits(*extra)?;
+            }
+        }
+    }
+}
+
+fn truncated() -> PngError {
+    PngError::InvalidImageData("zlib stream ends early")
+}
+
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, PngError> {
+    inflate_with(data, &CancellationToken::new())
+}
+
+// Inflates in blocks, checking `token` between them.
+pub fn inflate_with(data: &[u8], token: &CancellationToken) -> Result<Vec<u8>, PngError> {
+    inflate_limited(data, usize::MAX, token)
+}
+
+// `inflate_with` that fails as soon as the output passes `max_output` bytes,
+// so a small stream cannot inflate to more than its caller expects.
+pub fn inflate_limited(data: &[u8], max_output: usize, token: &CancellationToken) -> Result<Vec<u8>, PngError> {
+    let mut decoder = ZlibDecoder::new(data);
+    let mut inflated = Vec::new();
+    let mut block = [0u8; 64 * 1024];
+    loop {
+        token.check()?;
+        match decoder.read(&mut block) {
+            Ok(0) => return Ok(inflated),
+            Ok(n) if n > max_output - inflated.len() => {
+                return Err(PngError::InvalidImageData("zlib stream inflates to more than expected"));
+            },
+            Ok(n) => inflated.extend_from_slice(&block[..n]),
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(PngError::Compression(err))
+        }
+    }
+}
+
+pub fn deflate(data: &[u8], level: u32) -> Result<Vec<u8>, PngError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level.min(9)));
+    encoder.write_all(data).map_err(PngError::Compression)?;
+    encoder.finish().map_err(PngError::Compression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..1000).map(|i| (i % 13) as u8).collect();
+        for level in [0, 1, 6, 9] {
+            assert_eq!(inflate(&deflate(&data, level).unwrap()).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_compression_level() {
+        assert_eq!("fast".parse::<CompressionLevel>().unwrap().level(), 1);
+        assert_eq!("BEST".parse::<CompressionLevel>().unwrap(), CompressionLevel::Best);
+        assert_eq!("0".parse::<CompressionLevel>().unwrap(), CompressionLevel::Level(0));
+        assert!("10".parse::<CompressionLevel>().is_err());
+        assert!("quick".parse::<CompressionLevel>().is_err());
+        assert_eq!(CompressionLevel::default().level(), 6);
+        assert_eq!(CompressionLevel::Level(12).level(), 9);
+        assert_eq!(CompressionLevel::Level(3).to_string(), "3");
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let header = ZlibHeader::parse(&deflate(b"data", 9).unwrap()).unwrap();
+        assert_eq!(header.method, 8);
+        assert_eq!(header.window_size, 32768);
+        assert!(!header.preset_dictionary);
+        assert_eq!(header.level, 3);
+        assert_eq!(ZlibHeader::parse(&deflate(b"data", 1).unwrap()).unwrap().level, 0);
+    }
+
+    #[test]
+    fn test_analyze() {
+        let data: Vec<u8> = (0..100_000u64).map(|i| (i * i % 251) as u8).collect();
+        for level in [0, 1, 9] {
+            let compressed = deflate(&data, level).unwrap();
+            let stream = analyze(&compressed).unwrap();
+            assert_eq!(stream.blocks.iter().map(|block| block.output_bytes).sum::<usize>(), data.len());
+            assert!(stream.blocks.last().unwrap().is_final);
+            assert_eq!(stream.blocks.iter().filter(|block| block.is_final).count(), 1);
+            let bits: usize = stream.blocks.iter().map(|block| block.compressed_bits).sum();
+            assert_eq!(2 + bits.div_ceil(8) + 4, compressed.len());
+            assert_eq!(stream.adler32, Some(u32::from_be_bytes(compressed[compressed.len() - 4..].try_into().unwrap())));
+            assert_eq!(stream.trailing_bytes, 0);
+            let expected = if level == 0 { BlockType::Stored } else { BlockType::Dynamic };
+            assert!(stream.blocks.iter().all(|block| block.block_type == expected));
+        }
+        // Short inputs get a fixed Huffman block.
+        assert_eq!(analyze(&deflate(b"a", 6).unwrap()).unwrap().blocks[0].block_type, BlockType::Fixed);
+    }
+
+    #[test]
+    fn test_analyze_damaged_stream() {
+        let compressed = deflate(&[7; 1000], 6).unwrap();
+        assert!(analyze(&compressed[..compressed.len() - 6]).is_err());
+        let mut dictionary = compressed.clone();
+        // FDICT set, FCHECK adjusted so the header still checks out.
+        dictionary[1] = 0xbb;
+        dictionary.splice(2..2, [0, 0, 0, 1]);
+        assert_eq!(analyze(&dictionary).unwrap().dictionary_id, Some(1));
+        let mut reserved = compressed;
+        reserved[2] |= 0b110;
+        assert!(analyze(&reserved).is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid_header() {
+        assert!(ZlibHeader::parse(&[0x78]).is_none());
+        assert!(ZlibHeader::parse(&[0x78, 0x00]).is_none());
+        assert!(ZlibHeader::parse(b"text").is_none());
+    }
+
+    #[test]
+    fn test_inflate_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(matches!(inflate_with(&deflate(b"data", 6).unwrap(), &token), Err(PngError::Cancelled)));
+    }
+
+    #[test]
+    fn test_inflate_limited() {
+        let compressed = deflate(&vec![0; 1 << 20], 9).unwrap();
+        let token = CancellationToken::new();
+        assert_eq!(inflate_limited(&compressed, 1 << 20, &token).unwrap().len(), 1 << 20);
+        assert!(matches!(inflate_limited(&compressed, 1000, &token), Err(PngError::InvalidImageData(_))));
+    }
+
+    #[test]
+    fn test_inflate_invalid_stream() {
+        assert!(matches!(inflate(&[0x78, 0x9c, 0xff, 0xff]), Err(PngError::Compression(_))));
+    }
+}
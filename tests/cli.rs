@@ -0,0 +1,372 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+use tempfile::TempDir;
+
+// Runs the CLI against the files in tests/corpus. Commands that write are
+// pointed at copies in a temporary directory. Transcripts in tests/golden
+// can be regenerated with UPDATE_GOLDEN=1.
+
+fn corpus(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus").join(name).to_string_lossy().into_owned()
+}
+
+fn cli() -> Command {
+    Command::cargo_bin("png-decode-encode").unwrap()
+}
+
+// A temporary directory holding copies of the named corpus files.
+fn workspace(names: &[&str]) -> TempDir {
+    let dir = TempDir::new().unwrap();
+    for name in names {
+        fs::copy(corpus(name), dir.path().join(name)).unwrap();
+    }
+    dir
+}
+
+fn path(dir: &TempDir, name: &str) -> String {
+    dir.path().join(name).to_string_lossy().into_owned()
+}
+
+// Compares stdout with tests/golden/<name>.txt, after replacing the corpus
+// directory with a placeholder.
+fn assert_golden(name: &str, args: &[&str]) {
+    let output = cli().args(args).output().unwrap();
+    assert!(output.status.success(), "{} failed: {}", name, String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap().replace(&corpus(""), "<corpus>/");
+    let golden: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        fs::write(&golden, &stdout).unwrap();
+    }
+    assert_eq!(stdout, fs::read_to_string(&golden).unwrap(), "transcript {} changed", name);
+}
+
+#[test]
+fn test_info() {
+    assert_golden("info_rgb", &["info", &corpus("rgb.png")]);
+    assert_golden("info_indexed", &["info", &corpus("indexed.png")]);
+}
+
+#[test]
+fn test_print() {
+    assert_golden("print_describe", &["print", &corpus("rgb.png"), "--describe"]);
+    cli().args(["print", &corpus("indexed.png")]).assert().success().stdout(predicate::str::contains("PLTE"));
+    cli()
+        .args(["print", &corpus("bad_crc.png"), "--verify"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("MISMATCH").and(predicate::str::contains("\x1b").not()));
+    cli().args(["print", &corpus("rgb.png"), "--hash"]).assert().code(2);
+}
+
+#[test]
+fn test_lint_and_fingerprint() {
+    assert_golden("lint_rgb", &["lint", &corpus("rgb.png")]);
+    assert_golden("fingerprint_rgb", &["fingerprint", &corpus("rgb.png")]);
+}
+
+#[test]
+fn test_analyze() {
+    assert_golden("analyze_gray16", &["analyze", &corpus("gray16.png"), "--filters"]);
+    assert_golden("analyze_zlib_rgb", &["analyze", &corpus("rgb.png"), "--zlib"]);
+    cli()
+        .args(["-v", "analyze", &corpus("rgb.png")])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("decode:"));
+}
+
+#[test]
+fn test_signature() {
+    assert_golden("signature_rgb", &["signature", &corpus("rgb.png")]);
+    cli().args(["signature", &corpus("not_a_png.txt")]).assert().code(1);
+}
+
+#[test]
+fn test_features() {
+    cli().arg("features").assert().success().stdout(predicate::str::contains("\"crypto\":true"));
+}
+
+#[test]
+fn test_encode_and_decode() {
+    let dir = workspace(&["rgb.png"]);
+    let output = path(&dir, "out.png");
+    cli().args(["encode", &path(&dir, "rgb.png"), "ruSt", "hello", &output, "--envelope"]).assert().success();
+    cli().args(["decode", &output, "ruSt"]).assert().success().stdout("\"hello\"\n");
+    cli()
+        .args(["decode", &output, "ruSt", "--inspect"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("sharded:       no"));
+    cli()
+        .args(["decode", &output, "miSs"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("no miSs chunk found"));
+    cli()
+        .args(["encode", &path(&dir, "rgb.png"), "ru", "hello", &output])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("invalid chunk type \"ru\": a chunk type is 4 letters, not 2 bytes"));
+    cli()
+        .args(["encode", &path(&dir, "rgb.png"), "ru5t", "hello", &output])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("'5' is not an ASCII letter"));
+}
+
+#[test]
+fn test_encode_random_chunk_is_seeded() {
+    let run = || {
+        let output = cli().args(["--seed", "9", "encode", &corpus("rgb.png"), "random", "hello"]).output().unwrap();
+        String::from_utf8(output.stdout).unwrap().lines().next().unwrap().to_string()
+    };
+    assert!(run().starts_with("[CHUNK] "));
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn test_spread_and_gather() {
+    let dir = workspace(&[]);
+    for name in ["a.png", "b.png", "c.png"] {
+        fs::copy(corpus("rgb.png"), dir.path().join(name)).unwrap();
+    }
+    let pattern = path(&dir, "*.png");
+    cli()
+        .args(["encode", &pattern, "ruSt", "a message in pieces", "--spread", "--parity", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("[SPREAD]"));
+    fs::remove_file(dir.path().join("b.png")).unwrap();
+    cli().args(["decode", &pattern, "ruSt", "--gather"]).assert().success().stdout("\"a message in pieces\"\n");
+}
+
+#[test]
+fn test_remove_and_strip() {
+    let dir = workspace(&["rgb.png"]);
+    let file = path(&dir, "rgb.png");
+    cli().args(["remove", &file, "tIME"]).assert().success().stdout(predicate::str::starts_with("[REMOVED]"));
+    cli().args(["remove", &file, "tIME"]).assert().code(1);
+    cli().args(["strip", &file, "tEXt,gAMA"]).assert().success();
+    cli().args(["print", &file]).assert().success().stdout(predicate::str::contains("gAMA").not());
+    cli()
+        .args(["strip", &file, "IDAT"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("critical chunks cannot be stripped"));
+}
+
+#[test]
+fn test_optimize() {
+    let dir = workspace(&["rgb.png"]);
+    let output = path(&dir, "small.png");
+    cli()
+        .args(["optimize", &path(&dir, "rgb.png"), &output, "--json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"original_size\":178"));
+    cli().args(["compare", &corpus("rgb.png"), &output]).assert().success().stdout("differing pixels: 0 of 16\n");
+}
+
+#[test]
+fn test_optimize_emit_idot() {
+    let dir = workspace(&["rgb.png"]);
+    let output = path(&dir, "idot.png");
+    cli()
+        .args(["optimize", &path(&dir, "rgb.png"), &output, "--emit-idot"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[IDOT] 2 segments of 2, 2 rows"));
+    cli().args(["print", &output]).assert().success().stdout(predicate::str::contains("iDOT"));
+    cli().args(["compare", &corpus("rgb.png"), &output]).assert().success().stdout("differing pixels: 0 of 16\n");
+}
+
+#[test]
+fn test_verify() {
+    let dir = workspace(&["rgb.png"]);
+    let file = path(&dir, "rgb.png");
+    cli().args(["verify", &file]).assert().success().stdout(predicate::str::ends_with(format!("[VERIFIED] {}\n", file)));
+    cli()
+        .args(["verify", &corpus("bad_crc.png")])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("         8  IHDR         13 bytes  FAILED: crc mismatch"));
+    cli().args(["verify", &file, "--strong"]).assert().code(1).stdout(predicate::str::contains("Missing"));
+    let signed = path(&dir, "signed.png");
+    cli().args(["encode", &file, "ruSt", "x", &signed, "--integrity"]).assert().success();
+    cli().args(["verify", &signed, "--strong"]).assert().success();
+}
+
+#[test]
+fn test_metadata() {
+    let dir = workspace(&["rgb.png", "palette.gpl"]);
+    let file = path(&dir, "rgb.png");
+    cli()
+        .args(["metadata", &file, "--dpi", "300", "--time", "2024-05-06T07:08:09Z"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[UPDATED] resolution 11811 px/m (300 dpi)"));
+    cli().args(["metadata", &file, "--suggested-palette", &path(&dir, "palette.gpl")]).assert().success();
+    cli()
+        .args(["info", &file])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2024-05-06T07:08:09Z").and(predicate::str::contains("suggested:")));
+    cli().args(["metadata", &file, "--time", "yesterday"]).assert().code(1);
+}
+
+#[test]
+fn test_convert_and_quantize() {
+    let dir = workspace(&["rgb.png", "palette.gpl"]);
+    let file = path(&dir, "rgb.png");
+    let converted = path(&dir, "converted.png");
+    cli().args(["convert", &file, &converted, "--palette", &path(&dir, "palette.gpl")]).assert().code(2);
+    cli().args(["convert", &file, &converted, "--palette", &path(&dir, "palette.gpl"), "--exact"]).assert().code(1);
+    cli().args(["convert", &file, &converted, "--palette", &path(&dir, "palette.gpl"), "--exact", "--nearest"]).assert().code(2);
+    cli()
+        .args(["convert", &file, &converted, "--palette", &path(&dir, "palette.gpl"), "--nearest"])
+        .assert()
+        .success()
+        .stdout("[CONVERTED] remapped onto 2 palette colors\n");
+    let keyed = path(&dir, "keyed.png");
+    cli()
+        .args(["convert", &file, &keyed, "--colorkey", "#FF00FF"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("[CONVERTED]").and(predicate::str::contains("of #ff00ff made transparent")));
+    cli().args(["convert", &file, &keyed, "--colorkey", "magenta"]).assert().code(1);
+    cli().args(["convert", &file, &keyed, "--colorkey", "#FF00FF", "--nearest"]).assert().code(2);
+    let quantized = path(&dir, "quantized.png");
+    cli()
+        .args(["quantize", &file, &quantized, "--colors", "4"])
+        .assert()
+        .success()
+        .stdout("[QUANTIZED] 4x4 8-bit rgb -> 4 palette colors\n");
+    cli().args(["quantize", &file, &quantized, "--colors", "0"]).assert().code(1);
+}
+
+#[test]
+fn test_scale() {
+    let dir = workspace(&["indexed.png"]);
+    cli()
+        .args(["scale", &path(&dir, "indexed.png"), &path(&dir, "big.png"), "--factor", "2", "--scale2x"])
+        .assert()
+        .success()
+        .stdout("[SCALED] 4x2 2-bit indexed -> 8x4 2-bit indexed\n");
+    cli()
+        .args(["scale", &path(&dir, "indexed.png"), &path(&dir, "big.png"), "--factor", "3", "--nearest"])
+        .assert()
+        .success()
+        .stdout("[SCALED] 4x2 2-bit indexed -> 12x6 2-bit indexed\n");
+    cli().args(["scale", &path(&dir, "indexed.png"), &path(&dir, "big.png"), "--factor", "2"]).assert().code(2);
+}
+
+#[test]
+fn test_compare() {
+    cli()
+        .args(["compare", &corpus("rgb.png"), &corpus("rgb_changed.png"), "--metric", "psnr"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("differing pixels: 1 of 16\npsnr:"));
+    cli()
+        .args(["compare", &corpus("rgb.png"), &corpus("rgb_changed.png"), "--metric", "psnr", "--min", "100"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("[FAILED] psnr is below 100"));
+}
+
+#[test]
+fn test_encrypt_and_decrypt() {
+    let dir = workspace(&["rgb.png"]);
+    let (file, encrypted, decrypted) = (path(&dir, "rgb.png"), path(&dir, "enc.png"), path(&dir, "dec.png"));
+    cli().args(["encrypt-image", &file, &encrypted, "--password", "hunter2"]).assert().success();
+    cli()
+        .args(["decrypt-image", &encrypted, &decrypted, "--password", "wrong"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("wrong password"));
+    cli().args(["decrypt-image", &encrypted, &decrypted, "--password", "hunter2"]).assert().success();
+    cli().args(["compare", &file, &decrypted]).assert().success().stdout("differing pixels: 0 of 16\n");
+}
+
+#[test]
+fn test_redact() {
+    let dir = workspace(&["rgb.png"]);
+    let output = path(&dir, "redacted.png");
+    cli()
+        .args(["redact", &path(&dir, "rgb.png"), &output, "--rect", "0,0,2,2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("[REDACTED] 1 regions"));
+    cli()
+        .args(["compare", &corpus("rgb.png"), &output])
+        .assert()
+        .success()
+        .stdout("differing pixels: 4 of 16\n");
+    cli().args(["redact", &path(&dir, "rgb.png"), "--rect", "9,9,1,1"]).assert().code(1);
+}
+
+#[test]
+fn test_chain_through_pipes() {
+    let output = cli()
+        .args(["chain", "encode", "ruSt", "piped", ",", "strip", "tEXt,tIME", ",", "optimize", "--level", "9"])
+        .pipe_stdin(corpus("rgb.png"))
+        .unwrap()
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let dir = workspace(&[]);
+    let file = path(&dir, "out.png");
+    fs::write(&file, &output.stdout).unwrap();
+    cli().args(["decode", &file, "ruSt"]).assert().success().stdout("\"piped\"\n");
+    cli().args(["print", &file]).assert().success().stdout(predicate::str::contains("tIME").not());
+    cli().args(["chain", "-i", &corpus("rgb.png"), "strip", "IDAT"]).assert().code(1);
+    cli().args(["chain", "-i", &corpus("rgb.png"), "optimize", "--levle", "9"]).assert().code(1);
+}
+
+#[test]
+fn test_repair() {
+    let dir = workspace(&["bad_crc.png"]);
+    let output = path(&dir, "fixed.png");
+    cli()
+        .args(["repair", &path(&dir, "bad_crc.png"), &output])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("  recomputed the CRC of IHDR at offset 8\n[REPAIRED] 1 action(s)"));
+    cli().args(["verify", &output]).assert().success();
+    cli().args(["repair", &corpus("not_a_png.txt"), &output]).assert().code(1);
+}
+
+#[test]
+fn test_invalid_input() {
+    cli()
+        .args(["info", &corpus("bad_crc.png")])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("crc mismatch in IHDR chunk"));
+    cli()
+        .args(["info", &corpus("not_a_png.txt")])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("PNG signature"));
+    cli().args(["info", &corpus("missing.png")]).assert().code(1).stderr(predicate::str::starts_with("error: io error"));
+    cli().args(["encode", &corpus("rgb.png"), "toolong", "x"]).assert().code(1);
+    cli().arg("frobnicate").assert().code(2);
+}
+
+#[test]
+fn test_limits() {
+    cli()
+        .args(["info", &corpus("rgb.png"), "--max-input-bytes", "10"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("larger than the input limit"));
+    cli()
+        .args(["--max-pixels", "1", "signature", &corpus("rgb.png")])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("more pixels than the pixel limit"));
+    cli().args(["signature", &corpus("rgb.png"), "--max-pixels", "1000000"]).assert().success();
+}
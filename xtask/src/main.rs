@@ -0,0 +1,236 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command
+};
+
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use flate2::{write::GzEncoder, Compression};
+use zip::{write::FileOptions, ZipWriter};
+
+// The CLI definition is shared with the binary so completions and man pages
+// always match it.
+#[allow(dead_code)]
+#[path = "../../src/args.rs"]
+mod args;
+
+const BIN: &str = "png-decode-encode";
+const WASM_TARGET: &str = "wasm32-unknown-unknown";
+const SHELLS: [Shell; 5] = [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell, Shell::Elvish];
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Parser, Debug)]
+#[command(name = "xtask")]
+struct Xtask {
+    #[command(subcommand)]
+    task: Task
+}
+
+#[derive(Subcommand, Debug)]
+enum Task {
+    // Builds a stripped release binary for a target triple (the host by
+    // default) and packages it with completions and man pages in target/dist
+    Dist {
+        #[arg(long)]
+        target: Option<String>
+    },
+    // Checks that the library builds for the browser with the wasm feature
+    // and without the binary's dependencies
+    CheckWasm
+}
+
+fn main() {
+    let result = match Xtask::parse().task {
+        Task::Dist { target } => dist(target),
+        Task::CheckWasm => check_wasm()
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn dist(target: Option<String>) -> Result<()> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().to_path_buf();
+    let target = match target {
+        Some(target) => target,
+        None => host_target()?
+    };
+    let version = package_version(&root)?;
+
+    let mut build = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()));
+    build
+        .current_dir(&root)
+        .args(["build", "--release", "--bin", BIN, "--target", &target])
+        .env("CARGO_PROFILE_RELEASE_STRIP", "symbols");
+    if let Some(flags) = static_rustflags(&target) {
+        build.env("RUSTFLAGS", flags);
+    }
+    if !build.status()?.success() {
+        return Err(format!("release build for {} failed", target).into());
+    }
+
+    let name = format!("{}-{}-{}", BIN, version, target);
+    let dist = root.join("target").join("dist");
+    let staging = dist.join(&name);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(staging.join("completions"))?;
+    fs::create_dir_all(staging.join("man"))?;
+
+    let binary = format!("{}{}", BIN, exe_suffix(&target));
+    fs::copy(root.join("target").join(&target).join("release").join(&binary), staging.join(&binary))?;
+    fs::copy(root.join("README.md"), staging.join("README.md"))?;
+    let mut cli = args::Cli::command().name(BIN).bin_name(BIN).version(version);
+    for shell in SHELLS {
+        clap_complete::generate_to(shell, &mut cli, BIN, staging.join("completions"))?;
+    }
+    write_man_pages(&cli, &staging.join("man"))?;
+
+    let archive = dist.join(archive_name(&name, &target));
+    if archive_name(&name, &target).ends_with(".zip") {
+        write_zip(&staging, &name, &archive)?;
+    } else {
+        write_tar_gz(&staging, &name, &archive)?;
+    }
+    println!("[DIST] {}", archive.display());
+    Ok(())
+}
+
+fn check_wasm() -> Result<()> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().to_path_buf();
+    let status = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+        .current_dir(&root)
+        .args(["check", "--lib", "--target", WASM_TARGET, "--no-default-features", "--features", "wasm,crc32fast"])
+        .status()?;
+    if !status.success() {
+        return Err(format!("the wasm feature does not build for {}", WASM_TARGET).into());
+    }
+    println!("[CHECKED] {}", WASM_TARGET);
+    Ok(())
+}
+
+fn host_target() -> Result<String> {
+    let output = Command::new(env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string())).arg("-vV").output()?;
+    String::from_utf8(output.stdout)?
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(str::to_string)
+        .ok_or_else(|| "could not determine the host target".into())
+}
+
+fn package_version(root: &Path) -> Result<String> {
+    let output = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+        .current_dir(root)
+        .args(["metadata", "--no-deps", "--format-version", "1"])
+        .output()?;
+    let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|package| package["name"] == BIN)
+        .and_then(|package| package["version"].as_str())
+        .map(str::to_string)
+        .ok_or_else(|| format!("no {} package in cargo metadata", BIN).into())
+}
+
+// The C runtime is linked statically where the target supports it, so the
+// binary runs without a matching libc or Visual C++ runtime installed.
+fn static_rustflags(target: &str) -> Option<&'static str> {
+    (target.ends_with("-musl") || target.ends_with("-windows-msvc")).then_some("-C target-feature=+crt-static")
+}
+
+fn exe_suffix(target: &str) -> &'static str {
+    if target.contains("-windows-") { ".exe" } else { "" }
+}
+
+fn archive_name(name: &str, target: &str) -> String {
+    if target.contains("-windows-") { format!("{}.zip", name) } else { format!("{}.tar.gz", name) }
+}
+
+// One page for the tool and one per subcommand, named like git's.
+fn write_man_pages(cli: &clap::Command, dir: &Path) -> Result<()> {
+    let mut pages = vec![(BIN.to_string(), cli.clone())];
+    for subcommand in cli.get_subcommands() {
+        let name = format!("{}-{}", BIN, subcommand.get_name());
+        pages.push((name.clone(), subcommand.clone().name(name)));
+    }
+    for (name, command) in pages {
+        let mut page = Vec::new();
+        clap_mangen::Man::new(command).render(&mut page)?;
+        fs::write(dir.join(format!("{}.1", name)), page)?;
+    }
+    Ok(())
+}
+
+// Files under `dir`, relative to it, in sorted order so archives are
+// reproducible.
+fn files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        for entry in fs::read_dir(dir.join(&relative))? {
+            let entry = entry?;
+            let path = relative.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn write_tar_gz(staging: &Path, name: &str, archive: &Path) -> Result<()> {
+    let mut tar = tar::Builder::new(GzEncoder::new(File::create(archive)?, Compression::best()));
+    for path in files(staging)? {
+        tar.append_path_with_name(staging.join(&path), Path::new(name).join(&path))?;
+    }
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn write_zip(staging: &Path, name: &str, archive: &Path) -> Result<()> {
+    let mut zip = ZipWriter::new(File::create(archive)?);
+    for path in files(staging)? {
+        let entry = Path::new(name).join(&path).to_string_lossy().replace('\\', "/");
+        zip.start_file(entry, FileOptions::default().unix_permissions(0o755))?;
+        zip.write_all(&fs::read(staging.join(&path))?)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_specifics() {
+        assert_eq!(archive_name("a", "x86_64-pc-windows-msvc"), "a.zip");
+        assert_eq!(archive_name("a", "aarch64-apple-darwin"), "a.tar.gz");
+        assert_eq!(exe_suffix("x86_64-pc-windows-gnu"), ".exe");
+        assert_eq!(exe_suffix("x86_64-unknown-linux-musl"), "");
+        assert!(static_rustflags("x86_64-unknown-linux-musl").is_some());
+        assert!(static_rustflags("x86_64-unknown-linux-gnu").is_none());
+    }
+
+    #[test]
+    fn test_man_pages() {
+        let dir = env::temp_dir().join(format!("xtask-man-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        write_man_pages(&args::Cli::command().name(BIN), &dir).unwrap();
+        let pages = files(&dir).unwrap();
+        assert!(pages.contains(&PathBuf::from(format!("{}.1", BIN))));
+        assert!(pages.contains(&PathBuf::from(format!("{}-encode.1", BIN))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}